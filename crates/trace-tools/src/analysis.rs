@@ -0,0 +1,16 @@
+/// Ordering-based analysis of lock contention
+pub mod contention;
+/// Wait-for graph based detection of deadlocks
+pub mod deadlock;
+/// Happens-before based detection of data races
+pub mod happens_before;
+/// Eraser-style lockset analysis
+pub mod lockset;
+/// Summary statistics of execution traces
+pub mod statistics;
+
+pub use contention::{ContentionAnalyzer, ContentionReport, LockContention};
+pub use deadlock::{DeadlockDetector, DeadlockReport};
+pub use happens_before::{HappensBefore, RaceReport};
+pub use lockset::{LocksetAnalyzer, LocksetReport};
+pub use statistics::{TraceStats, TraceStatsCollector};
@@ -0,0 +1,350 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Display,
+};
+
+use anyhow::Error;
+use serde::Serialize;
+
+use crate::generic::{Event, EventResult, Operation};
+
+/// The contention of a single lock.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize)]
+pub struct LockContention {
+    pub lock: u64,
+    /// The name of the lock, e.g., the static it is stored in, if known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The number of times the lock was acquired
+    pub n_acquisitions: u64,
+    /// The number of acquisitions that had to wait for another thread
+    pub n_contended: u64,
+    /// The maximum number of threads that waited for the lock at the same time
+    pub max_waiters: u64,
+    /// The locations of contended requests with their number, most frequent first
+    pub top_locations: Vec<(u64, u64)>,
+}
+
+/// The contention of all locks of an execution trace.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize)]
+pub struct ContentionReport {
+    /// The contention of every acquired lock ordered by the lock ID
+    pub locks: Vec<LockContention>,
+}
+
+impl ContentionReport {
+    /// Returns the locks that have been contended at least once, most contended first.
+    pub fn contended_locks(&self) -> Vec<&LockContention> {
+        let mut locks: Vec<&LockContention> = self
+            .locks
+            .iter()
+            .filter(|lock| lock.n_contended > 0)
+            .collect();
+        locks.sort_by_key(|lock| std::cmp::Reverse(lock.n_contended));
+        locks
+    }
+
+    /// Names the locks by the given names instead of their IDs.
+    ///
+    /// Locks without a name keep being reported by their ID.
+    pub fn with_lock_names(mut self, lock_names: &HashMap<u64, String>) -> Self {
+        for lock in self.locks.iter_mut() {
+            lock.name = lock_names.get(&lock.lock).cloned();
+        }
+        self
+    }
+}
+
+impl Display for ContentionReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:<8} {:>12} {:>12} {:>12}  Top locations",
+            "Lock", "Acquisitions", "Contended", "Max waiters"
+        )?;
+        for lock in &self.locks {
+            let locations = lock
+                .top_locations
+                .iter()
+                .map(|(location, n)| format!("{location} ({n}x)"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            write!(
+                f,
+                "\n{:<8} {:>12} {:>12} {:>12}  {}",
+                lock.name
+                    .clone()
+                    .unwrap_or_else(|| format!("L{}", lock.lock)),
+                lock.n_acquisitions,
+                lock.n_contended,
+                lock.max_waiters,
+                locations
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A request that has not been followed by its acquisition yet.
+struct PendingRequest {
+    location: u64,
+    contended: bool,
+}
+
+#[derive(Default)]
+struct LockState {
+    /// The thread holding the lock and how often it acquired the lock recursively
+    holder: Option<(u64, u64)>,
+    /// The threads that requested but not yet acquired the lock
+    waiters: HashSet<u64>,
+    n_acquisitions: u64,
+    n_contended: u64,
+    max_waiters: u64,
+    locations: HashMap<u64, u64>,
+}
+
+/// An analysis of how often threads had to wait for locks.
+///
+/// Each request of a lock is paired with the subsequent acquisition of the same lock
+/// by the same thread. The acquisition is contended if another thread held the lock
+/// or waited for it at the time of the request, or if another thread acquired the
+/// lock in between. The analysis is purely based on the order of the events.
+/// Acquisitions without a preceding request, e.g., of a successful `try_lock`, are
+/// never contended.
+#[derive(Default)]
+pub struct ContentionAnalyzer {
+    locks: BTreeMap<u64, LockState>,
+    pending: HashMap<(u64, u64), PendingRequest>,
+}
+
+impl ContentionAnalyzer {
+    /// The number of locations reported per lock
+    pub const TOP_LOCATIONS: usize = 5;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the analysis over a whole execution trace.
+    pub fn analyze<I: IntoIterator<Item = EventResult>>(
+        events: I,
+    ) -> Result<ContentionReport, Error> {
+        let mut analyzer = Self::new();
+        for event in events {
+            analyzer.process(&event?);
+        }
+
+        Ok(analyzer.finish())
+    }
+
+    /// Processes the next event of the trace.
+    pub fn process(&mut self, event: &Event) {
+        let (tid, operation, location) = event.get_fields();
+
+        match operation {
+            Operation::Request { lock } => {
+                let state = self.locks.entry(*lock).or_default();
+                let held_by_other = state.holder.is_some_and(|(holder, _)| holder != *tid);
+                let contended = held_by_other || state.waiters.iter().any(|t| t != tid);
+
+                state.waiters.insert(*tid);
+                state.max_waiters = state.max_waiters.max(state.waiters.len() as u64);
+                self.pending.insert(
+                    (*tid, *lock),
+                    PendingRequest {
+                        location: *location,
+                        contended,
+                    },
+                );
+            }
+            Operation::Aquire { lock } => {
+                let state = self.locks.entry(*lock).or_default();
+                state.n_acquisitions += 1;
+                state.waiters.remove(tid);
+                state.holder = match state.holder {
+                    Some((holder, depth)) if holder == *tid => Some((holder, depth + 1)),
+                    _ => Some((*tid, 1)),
+                };
+
+                // Threads still waiting for the lock are overtaken by this acquisition
+                for waiter in &state.waiters {
+                    if let Some(request) = self.pending.get_mut(&(*waiter, *lock)) {
+                        request.contended = true;
+                    }
+                }
+
+                if let Some(request) = self.pending.remove(&(*tid, *lock))
+                    && request.contended
+                {
+                    state.n_contended += 1;
+                    *state.locations.entry(request.location).or_default() += 1;
+                }
+            }
+            Operation::Release { lock } => {
+                let state = self.locks.entry(*lock).or_default();
+                state.holder = match state.holder {
+                    Some((holder, depth)) if holder == *tid && depth > 1 => {
+                        Some((holder, depth - 1))
+                    }
+                    Some((holder, _)) if holder == *tid => None,
+                    holder => holder,
+                };
+            }
+            Operation::Read { memory: _ }
+            | Operation::Write { memory: _ }
+            | Operation::Fork { tid: _ }
+            | Operation::Join { tid: _ }
+            | Operation::Wait { cond: _ }
+            | Operation::Notify { cond: _ }
+            | Operation::Call { fidx: _ }
+            | Operation::Return { fidx: _ } => {}
+        }
+    }
+
+    /// Consumes the analyzer and returns the contention of every lock.
+    pub fn finish(self) -> ContentionReport {
+        let locks = self
+            .locks
+            .into_iter()
+            .map(|(lock, state)| {
+                let mut top_locations: Vec<(u64, u64)> = state.locations.into_iter().collect();
+                top_locations.sort_by_key(|(location, n)| (std::cmp::Reverse(*n), *location));
+                top_locations.truncate(Self::TOP_LOCATIONS);
+
+                LockContention {
+                    lock,
+                    name: None,
+                    n_acquisitions: state.n_acquisitions,
+                    n_contended: state.n_contended,
+                    max_waiters: state.max_waiters,
+                    top_locations,
+                }
+            })
+            .collect();
+
+        ContentionReport { locks }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use anyhow::Error;
+
+    use super::{ContentionAnalyzer, ContentionReport, LockContention};
+    use crate::generic::{Event, Operation::*};
+
+    fn analyze(trace: Vec<Event>) -> Result<ContentionReport, Error> {
+        ContentionAnalyzer::analyze(trace.into_iter().map(Ok))
+    }
+
+    #[test]
+    fn report_no_contention_for_sequential_locking() -> Result<(), Error> {
+        let report = analyze(vec![
+            Event::new(0, Request { lock: 0 }, 1),
+            Event::new(0, Aquire { lock: 0 }, 1),
+            Event::new(0, Release { lock: 0 }, 2),
+            Event::new(1, Request { lock: 0 }, 3),
+            Event::new(1, Aquire { lock: 0 }, 3),
+            Event::new(1, Release { lock: 0 }, 4),
+        ])?;
+
+        assert_eq!(
+            report.locks,
+            vec![LockContention {
+                lock: 0,
+                name: None,
+                n_acquisitions: 2,
+                n_contended: 0,
+                max_waiters: 1,
+                top_locations: vec![],
+            }]
+        );
+        assert!(report.contended_locks().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn report_known_contention() -> Result<(), Error> {
+        let report = analyze(vec![
+            // T0 holds L0 while T1 and T2 wait for it
+            Event::new(0, Request { lock: 0 }, 1),
+            Event::new(0, Aquire { lock: 0 }, 1),
+            Event::new(1, Request { lock: 0 }, 10),
+            Event::new(2, Request { lock: 0 }, 20),
+            Event::new(0, Release { lock: 0 }, 2),
+            Event::new(2, Aquire { lock: 0 }, 20),
+            Event::new(2, Release { lock: 0 }, 21),
+            Event::new(1, Aquire { lock: 0 }, 10),
+            Event::new(1, Release { lock: 0 }, 11),
+            // T1 requests L0 before T0, which overtakes it
+            Event::new(1, Request { lock: 0 }, 10),
+            Event::new(0, Request { lock: 0 }, 1),
+            Event::new(0, Aquire { lock: 0 }, 1),
+            Event::new(0, Release { lock: 0 }, 2),
+            Event::new(1, Aquire { lock: 0 }, 10),
+            Event::new(1, Release { lock: 0 }, 11),
+            // L1 is only acquired without requests
+            Event::new(0, Aquire { lock: 1 }, 30),
+            Event::new(0, Aquire { lock: 1 }, 30),
+            Event::new(0, Release { lock: 1 }, 31),
+            Event::new(0, Release { lock: 1 }, 31),
+        ])?;
+
+        assert_eq!(
+            report.locks,
+            vec![
+                LockContention {
+                    lock: 0,
+                    name: None,
+                    n_acquisitions: 5,
+                    n_contended: 4,
+                    max_waiters: 2,
+                    top_locations: vec![(10, 2), (1, 1), (20, 1)],
+                },
+                LockContention {
+                    lock: 1,
+                    name: None,
+                    n_acquisitions: 2,
+                    n_contended: 0,
+                    max_waiters: 0,
+                    top_locations: vec![],
+                },
+            ]
+        );
+        assert_eq!(report.contended_locks().len(), 1);
+
+        let json = serde_json::to_value(&report)?;
+        assert_eq!(json["locks"][0]["n_contended"], 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn report_lock_names() -> Result<(), Error> {
+        let report = analyze(vec![
+            Event::new(0, Aquire { lock: 0 }, 1),
+            Event::new(0, Release { lock: 0 }, 2),
+            Event::new(0, Aquire { lock: 1 }, 3),
+            Event::new(0, Release { lock: 1 }, 4),
+        ])?
+        .with_lock_names(&HashMap::from([(1, "COUNTER".to_string())]));
+
+        let rows = report.to_string();
+        let rows = rows
+            .lines()
+            .skip(1)
+            .map(|row| row.split_whitespace().next())
+            .collect::<Vec<_>>();
+        assert_eq!(rows, [Some("L0"), Some("COUNTER")]);
+
+        let json = serde_json::to_value(&report)?;
+        assert!(json["locks"][0].get("name").is_none());
+        assert_eq!(json["locks"][1]["name"], "COUNTER");
+
+        Ok(())
+    }
+}
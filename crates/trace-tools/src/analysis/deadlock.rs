@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+
+use anyhow::Error;
+
+use crate::generic::{Event, EventResult, Operation};
+
+/// A cycle in the wait-for graph, i.e., a set of threads that wait for each other.
+///
+/// The `i`-th thread waits for the `i`-th lock at the `i`-th location while that
+/// lock is held by the `(i + 1)`-th thread. The last lock is held by the first thread.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DeadlockReport<L = u64> {
+    /// The threads involved in the deadlock
+    pub threads: Vec<u64>,
+    /// The locks each of the threads is waiting for
+    pub locks: Vec<u64>,
+    /// The locations at which each of the threads requested its lock
+    pub locations: Vec<L>,
+}
+
+/// An online deadlock detection based on a wait-for graph.
+///
+/// The detector tracks which thread holds which lock and which lock each thread
+/// is currently waiting for. Whenever a thread requests a lock that is held by
+/// another thread, the chain of lock owners is followed. If it leads back to the
+/// requesting thread, all threads along the chain are deadlocked.
+///
+/// The generic parameter `L` is the type of the locations attached to lock requests.
+/// It defaults to the location IDs of the generic trace representation.
+pub struct DeadlockDetector<L = u64> {
+    owners: HashMap<u64, u64>,
+    waiting: HashMap<u64, (u64, L)>,
+    reports: Vec<DeadlockReport<L>>,
+}
+
+impl<L: Clone> DeadlockDetector<L> {
+    pub fn new() -> Self {
+        Self {
+            owners: HashMap::new(),
+            waiting: HashMap::new(),
+            reports: Vec::new(),
+        }
+    }
+
+    /// Records that `tid` started to wait for `lock` at `location`.
+    ///
+    /// Returns the deadlock this request completes, if any. Detected deadlocks
+    /// are also retained and can be retrieved via [`DeadlockDetector::reports`].
+    pub fn request(&mut self, tid: u64, lock: u64, location: L) -> Option<&DeadlockReport<L>> {
+        // A free lock may still be acquired by another thread before `tid` gets it
+        self.waiting.insert(tid, (lock, location));
+        match self.owners.get(&lock) {
+            Some(owner) if *owner != tid => {}
+            _ => return None,
+        }
+
+        let mut report = DeadlockReport {
+            threads: Vec::new(),
+            locks: Vec::new(),
+            locations: Vec::new(),
+        };
+
+        let mut current = tid;
+        loop {
+            let (lock, location) = self.waiting.get(&current)?;
+            let owner = *self.owners.get(lock)?;
+
+            report.threads.push(current);
+            report.locks.push(*lock);
+            report.locations.push(location.clone());
+
+            if owner == tid {
+                break;
+            }
+            if report.threads.contains(&owner) {
+                // The chain runs into a cycle that does not involve `tid`.
+                // That cycle has already been reported when it was closed.
+                return None;
+            }
+
+            current = owner;
+        }
+
+        self.reports.push(report);
+        self.reports.last()
+    }
+
+    /// Records that `tid` stopped waiting without acquiring the requested lock.
+    pub fn cancel(&mut self, tid: u64) {
+        self.waiting.remove(&tid);
+    }
+
+    /// Records that `tid` has acquired `lock`.
+    pub fn acquire(&mut self, tid: u64, lock: u64) {
+        self.waiting.remove(&tid);
+        self.owners.insert(lock, tid);
+    }
+
+    /// Records that `tid` has released `lock`.
+    pub fn release(&mut self, tid: u64, lock: u64) {
+        if self.owners.get(&lock) == Some(&tid) {
+            self.owners.remove(&lock);
+        }
+    }
+
+    /// Returns all deadlocks that have been detected so far.
+    pub fn reports(&self) -> &[DeadlockReport<L>] {
+        &self.reports
+    }
+
+    /// Consumes the detector and returns all detected deadlocks.
+    pub fn finish(self) -> Vec<DeadlockReport<L>> {
+        self.reports
+    }
+}
+
+impl<L: Clone> Default for DeadlockDetector<L> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeadlockDetector {
+    /// Runs the detection over a whole execution trace and returns all deadlocks.
+    ///
+    /// Since a deadlocked program never acquires the requested locks, the
+    /// trace of such a program ends with the requests that close the cycle.
+    pub fn analyze<I: IntoIterator<Item = EventResult>>(
+        events: I,
+    ) -> Result<Vec<DeadlockReport>, Error> {
+        let mut detector = Self::new();
+        for event in events {
+            detector.process(&event?);
+        }
+
+        Ok(detector.finish())
+    }
+
+    /// Processes the next event of the trace.
+    pub fn process(&mut self, event: &Event) {
+        let (tid, operation, location) = event.get_fields();
+
+        match operation {
+            Operation::Request { lock } => {
+                self.request(*tid, *lock, *location);
+            }
+            Operation::Aquire { lock } => self.acquire(*tid, *lock),
+            Operation::Release { lock } => self.release(*tid, *lock),
+            Operation::Read { memory: _ }
+            | Operation::Write { memory: _ }
+            | Operation::Fork { tid: _ }
+            | Operation::Join { tid: _ }
+            | Operation::Wait { cond: _ }
+            | Operation::Notify { cond: _ }
+            | Operation::Call { fidx: _ }
+            | Operation::Return { fidx: _ } => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use crate::generic::{Event, Operation};
+
+    use super::{DeadlockDetector, DeadlockReport};
+
+    #[test]
+    fn detect_lock_order_inversion() -> Result<(), Error> {
+        let trace = [
+            Event::new(0, Operation::Fork { tid: 1 }, 1),
+            Event::new(0, Operation::Request { lock: 10 }, 2),
+            Event::new(0, Operation::Aquire { lock: 10 }, 2),
+            Event::new(1, Operation::Request { lock: 20 }, 3),
+            Event::new(1, Operation::Aquire { lock: 20 }, 3),
+            Event::new(0, Operation::Request { lock: 20 }, 4),
+            Event::new(1, Operation::Request { lock: 10 }, 5),
+        ];
+
+        let reports = DeadlockDetector::analyze(trace.into_iter().map(Ok))?;
+
+        assert_eq!(
+            reports,
+            vec![DeadlockReport {
+                threads: vec![1, 0],
+                locks: vec![10, 20],
+                locations: vec![5, 4],
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn detect_deadlock_after_request_on_free_lock() -> Result<(), Error> {
+        let trace = [
+            Event::new(0, Operation::Request { lock: 20 }, 1),
+            Event::new(0, Operation::Aquire { lock: 20 }, 1),
+            // The lock is free when requested, but thread 1 acquires it first
+            Event::new(0, Operation::Request { lock: 10 }, 2),
+            Event::new(1, Operation::Request { lock: 10 }, 3),
+            Event::new(1, Operation::Aquire { lock: 10 }, 3),
+            Event::new(1, Operation::Request { lock: 20 }, 4),
+        ];
+
+        let reports = DeadlockDetector::analyze(trace.into_iter().map(Ok))?;
+
+        assert_eq!(
+            reports,
+            vec![DeadlockReport {
+                threads: vec![1, 0],
+                locks: vec![20, 10],
+                locations: vec![4, 2],
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn consistent_lock_order() -> Result<(), Error> {
+        let trace = [
+            Event::new(0, Operation::Request { lock: 10 }, 1),
+            Event::new(0, Operation::Aquire { lock: 10 }, 1),
+            Event::new(1, Operation::Request { lock: 10 }, 2),
+            Event::new(0, Operation::Request { lock: 20 }, 3),
+            Event::new(0, Operation::Aquire { lock: 20 }, 3),
+            Event::new(0, Operation::Release { lock: 20 }, 4),
+            Event::new(0, Operation::Release { lock: 10 }, 5),
+            Event::new(1, Operation::Aquire { lock: 10 }, 2),
+            Event::new(1, Operation::Request { lock: 20 }, 6),
+            Event::new(1, Operation::Aquire { lock: 20 }, 6),
+        ];
+
+        assert!(DeadlockDetector::analyze(trace.into_iter().map(Ok))?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn detect_three_thread_cycle() {
+        let mut detector = DeadlockDetector::new();
+        detector.acquire(0, 10);
+        detector.acquire(1, 20);
+        detector.acquire(2, 30);
+
+        assert!(detector.request(0, 20, "a").is_none());
+        assert!(detector.request(1, 30, "b").is_none());
+
+        let report = detector.request(2, 10, "c").cloned();
+        assert_eq!(
+            report,
+            Some(DeadlockReport {
+                threads: vec![2, 0, 1],
+                locks: vec![10, 20, 30],
+                locations: vec!["c", "a", "b"],
+            })
+        );
+        assert_eq!(detector.reports().len(), 1);
+    }
+}
@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+
+use anyhow::Error;
+
+use crate::generic::{Event, EventResult, Operation};
+
+/// A vector clock mapping thread IDs to their logical time.
+#[derive(Clone, Debug, Default)]
+struct VectorClock(HashMap<u64, u64>);
+
+impl VectorClock {
+    fn get(&self, tid: u64) -> u64 {
+        self.0.get(&tid).copied().unwrap_or(0)
+    }
+
+    fn increment(&mut self, tid: u64) {
+        *self.0.entry(tid).or_default() += 1;
+    }
+
+    fn join(&mut self, other: &VectorClock) {
+        for (tid, time) in other.0.iter() {
+            let entry = self.0.entry(*tid).or_default();
+            *entry = (*entry).max(*time);
+        }
+    }
+}
+
+/// A memory access recorded at logical time `epoch` of its thread.
+#[derive(Clone, Copy)]
+struct Access {
+    epoch: u64,
+    event: u64,
+    location: u64,
+}
+
+/// The most recent accesses of each thread to a single variable.
+#[derive(Default)]
+struct VariableState {
+    reads: HashMap<u64, Access>,
+    writes: HashMap<u64, Access>,
+}
+
+/// A pair of conflicting memory accesses that are not ordered by happens-before.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RaceReport {
+    /// Index of the earlier event in the trace
+    pub first_event: u64,
+    /// Thread that issued the earlier event
+    pub first_thread: u64,
+    /// Location of the earlier event
+    pub first_location: u64,
+    /// Index of the later event in the trace
+    pub second_event: u64,
+    /// Thread that issued the later event
+    pub second_thread: u64,
+    /// Location of the later event
+    pub second_location: u64,
+    /// The variable accessed by both events
+    pub variable: u64,
+}
+
+/// A data-race detector based on the happens-before relation.
+///
+/// The analyzer maintains a vector clock per thread, per lock and per
+/// condition variable. Clocks are updated on `Fork`, `Join`, `Aquire`,
+/// `Release`, `Wait` and `Notify` operations.
+/// Two accesses to the same variable, at least one of them being a write,
+/// are reported as a race if neither of them happens before the other.
+///
+/// For every variable, only the most recent read and write of each thread is
+/// remembered. Hence, a racy access is reported against the latest conflicting
+/// access of each other thread rather than against all of them.
+#[derive(Default)]
+pub struct HappensBefore {
+    threads: HashMap<u64, VectorClock>,
+    locks: HashMap<u64, VectorClock>,
+    conds: HashMap<u64, VectorClock>,
+    variables: HashMap<u64, VariableState>,
+    n_events: u64,
+    races: Vec<RaceReport>,
+}
+
+impl HappensBefore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the analysis over a whole execution trace and returns all detected races.
+    pub fn analyze<I: IntoIterator<Item = EventResult>>(
+        events: I,
+    ) -> Result<Vec<RaceReport>, Error> {
+        let mut analyzer = Self::new();
+        for event in events {
+            analyzer.process(&event?);
+        }
+
+        Ok(analyzer.finish())
+    }
+
+    fn clock(&mut self, tid: u64) -> &mut VectorClock {
+        self.threads.entry(tid).or_insert_with(|| {
+            let mut clock = VectorClock::default();
+            clock.increment(tid);
+            clock
+        })
+    }
+
+    /// Processes the next event of the trace.
+    pub fn process(&mut self, event: &Event) {
+        let (tid, operation, location) = event.get_fields();
+        let (tid, location) = (*tid, *location);
+        let index = self.n_events;
+        self.n_events += 1;
+
+        match operation {
+            Operation::Fork { tid: child } => {
+                let parent_clock = self.clock(tid).clone();
+                self.clock(*child).join(&parent_clock);
+                self.clock(tid).increment(tid);
+            }
+            Operation::Join { tid: child } => {
+                let child_clock = self.clock(*child).clone();
+                self.clock(tid).join(&child_clock);
+                self.clock(*child).increment(*child);
+            }
+            Operation::Aquire { lock } => {
+                if let Some(lock_clock) = self.locks.get(lock).cloned() {
+                    self.clock(tid).join(&lock_clock);
+                }
+            }
+            Operation::Release { lock } => {
+                let clock = self.clock(tid).clone();
+                self.locks.insert(*lock, clock);
+                self.clock(tid).increment(tid);
+            }
+            Operation::Notify { cond } => {
+                let clock = self.clock(tid).clone();
+                self.conds.entry(*cond).or_default().join(&clock);
+                self.clock(tid).increment(tid);
+            }
+            Operation::Wait { cond } => {
+                if let Some(cond_clock) = self.conds.get(cond).cloned() {
+                    self.clock(tid).join(&cond_clock);
+                }
+            }
+            Operation::Request { lock: _ }
+            | Operation::Call { fidx: _ }
+            | Operation::Return { fidx: _ } => (),
+            Operation::Read { memory } => self.access(tid, index, location, *memory, false),
+            Operation::Write { memory } => self.access(tid, index, location, *memory, true),
+        }
+    }
+
+    fn access(&mut self, tid: u64, index: u64, location: u64, variable: u64, is_write: bool) {
+        let clock = self.clock(tid).clone();
+        let state = self.variables.entry(variable).or_default();
+
+        let mut conflicts: Vec<(u64, Access)> = state
+            .writes
+            .iter()
+            .filter(|(other, access)| **other != tid && access.epoch > clock.get(**other))
+            .map(|(other, access)| (*other, *access))
+            .collect();
+
+        if is_write {
+            conflicts.extend(
+                state
+                    .reads
+                    .iter()
+                    .filter(|(other, access)| **other != tid && access.epoch > clock.get(**other))
+                    .map(|(other, access)| (*other, *access)),
+            );
+        }
+
+        conflicts.sort_by_key(|(_, access)| access.event);
+        self.races
+            .extend(conflicts.into_iter().map(|(other, access)| RaceReport {
+                first_event: access.event,
+                first_thread: other,
+                first_location: access.location,
+                second_event: index,
+                second_thread: tid,
+                second_location: location,
+                variable,
+            }));
+
+        let access = Access {
+            epoch: clock.get(tid),
+            event: index,
+            location,
+        };
+        if is_write {
+            state.writes.insert(tid, access);
+        } else {
+            state.reads.insert(tid, access);
+        }
+    }
+
+    /// Returns all races that have been detected so far.
+    pub fn races(&self) -> &[RaceReport] {
+        &self.races
+    }
+
+    /// Consumes the analyzer and returns all detected races.
+    pub fn finish(self) -> Vec<RaceReport> {
+        self.races
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use super::{HappensBefore, RaceReport};
+    use crate::generic::{Event, Operation::*};
+
+    fn analyze(trace: Vec<Event>) -> Result<Vec<RaceReport>, Error> {
+        HappensBefore::analyze(trace.into_iter().map(Ok))
+    }
+
+    #[test]
+    fn detect_unsynchronized_writes() -> Result<(), Error> {
+        let races = analyze(vec![
+            Event::new(0, Fork { tid: 1 }, 0),
+            Event::new(0, Write { memory: 7 }, 1),
+            Event::new(1, Write { memory: 7 }, 2),
+        ])?;
+
+        assert_eq!(
+            races,
+            vec![RaceReport {
+                first_event: 1,
+                first_thread: 0,
+                first_location: 1,
+                second_event: 2,
+                second_thread: 1,
+                second_location: 2,
+                variable: 7,
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn ignore_concurrent_reads() -> Result<(), Error> {
+        let races = analyze(vec![
+            Event::new(0, Fork { tid: 1 }, 0),
+            Event::new(0, Read { memory: 7 }, 1),
+            Event::new(1, Read { memory: 7 }, 2),
+        ])?;
+
+        assert!(races.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn respect_fork_and_join() -> Result<(), Error> {
+        let races = analyze(vec![
+            Event::new(0, Write { memory: 7 }, 0),
+            Event::new(0, Fork { tid: 1 }, 1),
+            Event::new(1, Write { memory: 7 }, 2),
+            Event::new(0, Join { tid: 1 }, 3),
+            Event::new(0, Read { memory: 7 }, 4),
+        ])?;
+
+        assert!(races.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn respect_locks() -> Result<(), Error> {
+        let races = analyze(vec![
+            Event::new(0, Fork { tid: 1 }, 0),
+            Event::new(0, Request { lock: 3 }, 1),
+            Event::new(0, Aquire { lock: 3 }, 1),
+            Event::new(0, Write { memory: 7 }, 2),
+            Event::new(0, Release { lock: 3 }, 3),
+            Event::new(1, Request { lock: 3 }, 4),
+            Event::new(1, Aquire { lock: 3 }, 4),
+            Event::new(1, Read { memory: 7 }, 5),
+            Event::new(1, Release { lock: 3 }, 6),
+            Event::new(0, Read { memory: 7 }, 7),
+        ])?;
+
+        assert!(races.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn respect_notify_and_wait() -> Result<(), Error> {
+        let races = analyze(vec![
+            Event::new(0, Fork { tid: 1 }, 0),
+            Event::new(0, Write { memory: 7 }, 1),
+            Event::new(0, Notify { cond: 5 }, 2),
+            Event::new(1, Wait { cond: 5 }, 3),
+            Event::new(1, Read { memory: 7 }, 4),
+            Event::new(1, Write { memory: 7 }, 5),
+        ])?;
+
+        assert!(races.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn detect_race_outside_of_lock() -> Result<(), Error> {
+        let races = analyze(vec![
+            Event::new(0, Fork { tid: 1 }, 0),
+            Event::new(0, Aquire { lock: 3 }, 1),
+            Event::new(0, Read { memory: 7 }, 2),
+            Event::new(0, Release { lock: 3 }, 3),
+            Event::new(1, Write { memory: 7 }, 4),
+        ])?;
+
+        assert_eq!(
+            races,
+            vec![RaceReport {
+                first_event: 2,
+                first_thread: 0,
+                first_location: 2,
+                second_event: 4,
+                second_thread: 1,
+                second_location: 4,
+                variable: 7,
+            }]
+        );
+
+        Ok(())
+    }
+}
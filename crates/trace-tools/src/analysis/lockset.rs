@@ -0,0 +1,229 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::Error;
+
+use crate::generic::{Event, EventResult, Operation};
+
+/// The states of a variable in the Eraser state machine.
+#[derive(Debug, PartialEq, Eq)]
+enum VariableState {
+    /// The variable has not been accessed yet.
+    Virgin,
+    /// The variable has only been accessed by a single thread.
+    Exclusive { tid: u64 },
+    /// The variable has been read by multiple threads.
+    Shared { candidates: HashSet<u64> },
+    /// The variable has been written while being shared amongst threads.
+    SharedModified { candidates: HashSet<u64> },
+}
+
+/// A variable that is not consistently protected by any lock.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LocksetReport {
+    /// The unprotected variable
+    pub variable: u64,
+    /// Indices of the events that accessed the variable without common lock
+    pub events: Vec<u64>,
+}
+
+/// An Eraser-style lockset analysis.
+///
+/// For each variable, the analyzer keeps a set of candidate locks, which is
+/// the intersection of the locks held at every access. Variables pass through
+/// the states _virgin_, _exclusive_, _shared_ and _shared-modified_ such that
+/// initialization by a single thread and read-only sharing are not flagged.
+/// A variable is reported once its candidate set becomes empty in the
+/// _shared-modified_ state.
+///
+/// See: S. Savage et al., "Eraser: a dynamic data race detector for multithreaded programs,"
+/// ACM Transactions on Computer Systems 15(4), 1997, pp. 391-411, doi: 10.1145/265924.265927.
+#[derive(Default)]
+pub struct LocksetAnalyzer {
+    held: HashMap<u64, HashSet<u64>>,
+    variables: HashMap<u64, VariableState>,
+    reports: BTreeMap<u64, Vec<u64>>,
+    n_events: u64,
+}
+
+impl LocksetAnalyzer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the analysis over a whole execution trace and returns all unprotected variables.
+    pub fn analyze<I: IntoIterator<Item = EventResult>>(
+        events: I,
+    ) -> Result<Vec<LocksetReport>, Error> {
+        let mut analyzer = Self::new();
+        for event in events {
+            analyzer.process(&event?);
+        }
+
+        Ok(analyzer.finish())
+    }
+
+    /// Processes the next event of the trace.
+    pub fn process(&mut self, event: &Event) {
+        let (tid, operation, _) = event.get_fields();
+        let index = self.n_events;
+        self.n_events += 1;
+
+        match operation {
+            Operation::Aquire { lock } => {
+                self.held.entry(*tid).or_default().insert(*lock);
+            }
+            Operation::Release { lock } => {
+                self.held.entry(*tid).or_default().remove(lock);
+            }
+            Operation::Read { memory } => self.access(*tid, index, *memory, false),
+            Operation::Write { memory } => self.access(*tid, index, *memory, true),
+            Operation::Fork { tid: _ }
+            | Operation::Join { tid: _ }
+            | Operation::Request { lock: _ }
+            | Operation::Wait { cond: _ }
+            | Operation::Notify { cond: _ }
+            | Operation::Call { fidx: _ }
+            | Operation::Return { fidx: _ } => {}
+        }
+    }
+
+    fn access(&mut self, tid: u64, index: u64, variable: u64, is_write: bool) {
+        let held = self.held.get(&tid).cloned().unwrap_or_default();
+        let state = self
+            .variables
+            .entry(variable)
+            .or_insert(VariableState::Virgin);
+
+        let next = match std::mem::replace(state, VariableState::Virgin) {
+            VariableState::Virgin => VariableState::Exclusive { tid },
+            VariableState::Exclusive { tid: owner } if owner == tid => {
+                VariableState::Exclusive { tid: owner }
+            }
+            VariableState::Exclusive { tid: _ } if is_write => {
+                VariableState::SharedModified { candidates: held }
+            }
+            VariableState::Exclusive { tid: _ } => VariableState::Shared { candidates: held },
+            VariableState::Shared { mut candidates } => {
+                candidates.retain(|lock| held.contains(lock));
+                if is_write {
+                    VariableState::SharedModified { candidates }
+                } else {
+                    VariableState::Shared { candidates }
+                }
+            }
+            VariableState::SharedModified { mut candidates } => {
+                candidates.retain(|lock| held.contains(lock));
+                VariableState::SharedModified { candidates }
+            }
+        };
+
+        if let VariableState::SharedModified { candidates } = &next
+            && candidates.is_empty()
+        {
+            self.reports.entry(variable).or_default().push(index);
+        }
+
+        *state = next;
+    }
+
+    /// Consumes the analyzer and returns all unprotected variables ordered by their ID.
+    pub fn finish(self) -> Vec<LocksetReport> {
+        self.reports
+            .into_iter()
+            .map(|(variable, events)| LocksetReport { variable, events })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use super::{LocksetAnalyzer, LocksetReport, VariableState};
+    use crate::generic::{Event, Operation::*};
+
+    fn analyze(trace: Vec<Event>) -> Result<Vec<LocksetReport>, Error> {
+        LocksetAnalyzer::analyze(trace.into_iter().map(Ok))
+    }
+
+    #[test]
+    fn state_machine_transitions() {
+        let mut analyzer = LocksetAnalyzer::new();
+        let state = |analyzer: &LocksetAnalyzer| match analyzer.variables.get(&7) {
+            Some(VariableState::Virgin) | None => "virgin",
+            Some(VariableState::Exclusive { tid: _ }) => "exclusive",
+            Some(VariableState::Shared { candidates: _ }) => "shared",
+            Some(VariableState::SharedModified { candidates: _ }) => "shared-modified",
+        };
+
+        assert_eq!(state(&analyzer), "virgin");
+        analyzer.process(&Event::new(0, Write { memory: 7 }, 0));
+        assert_eq!(state(&analyzer), "exclusive");
+        analyzer.process(&Event::new(0, Read { memory: 7 }, 1));
+        assert_eq!(state(&analyzer), "exclusive");
+        analyzer.process(&Event::new(1, Read { memory: 7 }, 2));
+        assert_eq!(state(&analyzer), "shared");
+        analyzer.process(&Event::new(2, Read { memory: 7 }, 3));
+        assert_eq!(state(&analyzer), "shared");
+        analyzer.process(&Event::new(2, Write { memory: 7 }, 4));
+        assert_eq!(state(&analyzer), "shared-modified");
+    }
+
+    #[test]
+    fn ignore_initialization_and_read_sharing() -> Result<(), Error> {
+        let reports = analyze(vec![
+            Event::new(0, Write { memory: 7 }, 0),
+            Event::new(0, Write { memory: 7 }, 1),
+            Event::new(1, Read { memory: 7 }, 2),
+            Event::new(2, Read { memory: 7 }, 3),
+        ])?;
+
+        assert!(reports.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn detect_missing_lock() -> Result<(), Error> {
+        let reports = analyze(vec![
+            Event::new(0, Aquire { lock: 3 }, 0),
+            Event::new(0, Write { memory: 7 }, 1),
+            Event::new(0, Release { lock: 3 }, 2),
+            Event::new(1, Aquire { lock: 3 }, 3),
+            Event::new(1, Write { memory: 7 }, 4),
+            Event::new(1, Release { lock: 3 }, 5),
+            Event::new(2, Write { memory: 7 }, 6),
+        ])?;
+
+        assert_eq!(
+            reports,
+            vec![LocksetReport {
+                variable: 7,
+                events: vec![6],
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn accept_consistently_locked_variable() -> Result<(), Error> {
+        let reports = analyze(vec![
+            Event::new(0, Aquire { lock: 3 }, 0),
+            Event::new(0, Write { memory: 7 }, 1),
+            Event::new(0, Release { lock: 3 }, 2),
+            Event::new(1, Aquire { lock: 4 }, 3),
+            Event::new(1, Aquire { lock: 3 }, 4),
+            Event::new(1, Write { memory: 7 }, 5),
+            Event::new(1, Release { lock: 3 }, 6),
+            Event::new(1, Release { lock: 4 }, 7),
+            Event::new(2, Aquire { lock: 3 }, 8),
+            Event::new(2, Read { memory: 7 }, 9),
+            Event::new(2, Release { lock: 3 }, 10),
+        ])?;
+
+        assert!(reports.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
+
+use anyhow::Error;
+
+use crate::generic::{Event, EventResult, Operation};
+
+/// A summary of an execution trace.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct TraceStats {
+    pub n_events: u64,
+    pub n_reads: u64,
+    pub n_writes: u64,
+    pub n_aquires: u64,
+    pub n_requests: u64,
+    pub n_releases: u64,
+    pub n_forks: u64,
+    pub n_joins: u64,
+    pub n_waits: u64,
+    pub n_notifies: u64,
+    pub n_calls: u64,
+    pub n_returns: u64,
+    pub n_threads: u64,
+    pub n_locks: u64,
+    pub n_variables: u64,
+    pub n_locations: u64,
+    /// The maximum number of locks held by a single thread at the same time
+    pub max_lock_depth: u64,
+    /// The total number of bytes read and written
+    ///
+    /// The generic trace representation does not carry the widths of memory
+    /// accesses. This is only available if the statistics have been enriched
+    /// with the metadata of the trace.
+    pub bytes_accessed: Option<u64>,
+}
+
+impl TraceStats {
+    /// Computes the statistics of a whole execution trace in a single pass.
+    pub fn collect<I: IntoIterator<Item = EventResult>>(events: I) -> Result<Self, Error> {
+        let mut collector = TraceStatsCollector::new();
+        for event in events {
+            collector.process(&event?);
+        }
+
+        Ok(collector.finish())
+    }
+}
+
+impl Display for TraceStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Events:          {}", self.n_events)?;
+        writeln!(f, "  Reads:         {}", self.n_reads)?;
+        writeln!(f, "  Writes:        {}", self.n_writes)?;
+        writeln!(f, "  Requests:      {}", self.n_requests)?;
+        writeln!(f, "  Aquires:       {}", self.n_aquires)?;
+        writeln!(f, "  Releases:      {}", self.n_releases)?;
+        writeln!(f, "  Forks:         {}", self.n_forks)?;
+        writeln!(f, "  Joins:         {}", self.n_joins)?;
+        writeln!(f, "  Waits:         {}", self.n_waits)?;
+        writeln!(f, "  Notifies:      {}", self.n_notifies)?;
+        writeln!(f, "  Calls:         {}", self.n_calls)?;
+        writeln!(f, "  Returns:       {}", self.n_returns)?;
+        writeln!(f, "Threads:         {}", self.n_threads)?;
+        writeln!(f, "Locks:           {}", self.n_locks)?;
+        writeln!(f, "Variables:       {}", self.n_variables)?;
+        writeln!(f, "Locations:       {}", self.n_locations)?;
+        write!(f, "Max lock depth:  {}", self.max_lock_depth)?;
+        if let Some(bytes_accessed) = self.bytes_accessed {
+            write!(f, "\nBytes accessed:  {bytes_accessed}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Incrementally computes the [`TraceStats`] of an execution trace.
+#[derive(Default)]
+pub struct TraceStatsCollector {
+    stats: TraceStats,
+    threads: HashSet<u64>,
+    locks: HashSet<u64>,
+    variables: HashSet<u64>,
+    locations: HashSet<u64>,
+    lock_depths: HashMap<u64, u64>,
+}
+
+impl TraceStatsCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Processes the next event of the trace.
+    pub fn process(&mut self, event: &Event) {
+        let (tid, operation, location) = event.get_fields();
+
+        self.stats.n_events += 1;
+        self.threads.insert(*tid);
+        self.locations.insert(*location);
+
+        match operation {
+            Operation::Read { memory } => {
+                self.stats.n_reads += 1;
+                self.variables.insert(*memory);
+            }
+            Operation::Write { memory } => {
+                self.stats.n_writes += 1;
+                self.variables.insert(*memory);
+            }
+            Operation::Request { lock } => {
+                self.stats.n_requests += 1;
+                self.locks.insert(*lock);
+            }
+            Operation::Aquire { lock } => {
+                self.stats.n_aquires += 1;
+                self.locks.insert(*lock);
+
+                let depth = self.lock_depths.entry(*tid).or_default();
+                *depth += 1;
+                self.stats.max_lock_depth = self.stats.max_lock_depth.max(*depth);
+            }
+            Operation::Release { lock } => {
+                self.stats.n_releases += 1;
+                self.locks.insert(*lock);
+
+                let depth = self.lock_depths.entry(*tid).or_default();
+                *depth = depth.saturating_sub(1);
+            }
+            Operation::Fork { tid } => {
+                self.stats.n_forks += 1;
+                self.threads.insert(*tid);
+            }
+            Operation::Join { tid } => {
+                self.stats.n_joins += 1;
+                self.threads.insert(*tid);
+            }
+            Operation::Wait { cond: _ } => self.stats.n_waits += 1,
+            Operation::Notify { cond: _ } => self.stats.n_notifies += 1,
+            Operation::Call { fidx: _ } => self.stats.n_calls += 1,
+            Operation::Return { fidx: _ } => self.stats.n_returns += 1,
+        }
+    }
+
+    /// Consumes the collector and returns the statistics of all processed events.
+    pub fn finish(self) -> TraceStats {
+        TraceStats {
+            n_threads: self.threads.len() as u64,
+            n_locks: self.locks.len() as u64,
+            n_variables: self.variables.len() as u64,
+            n_locations: self.locations.len() as u64,
+            ..self.stats
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use crate::generic::{Event, Operation};
+
+    use super::TraceStats;
+
+    #[test]
+    fn collect_statistics() -> Result<(), Error> {
+        let trace = [
+            Event::new(0, Operation::Fork { tid: 1 }, 0),
+            Event::new(1, Operation::Request { lock: 0 }, 1),
+            Event::new(1, Operation::Aquire { lock: 0 }, 1),
+            Event::new(1, Operation::Request { lock: 1 }, 2),
+            Event::new(1, Operation::Aquire { lock: 1 }, 2),
+            Event::new(1, Operation::Write { memory: 7 }, 3),
+            Event::new(1, Operation::Release { lock: 1 }, 4),
+            Event::new(1, Operation::Release { lock: 0 }, 5),
+            Event::new(0, Operation::Request { lock: 0 }, 1),
+            Event::new(0, Operation::Aquire { lock: 0 }, 1),
+            Event::new(0, Operation::Read { memory: 7 }, 6),
+            Event::new(0, Operation::Read { memory: 8 }, 6),
+            Event::new(0, Operation::Release { lock: 0 }, 5),
+            Event::new(0, Operation::Join { tid: 1 }, 7),
+        ];
+
+        let stats = TraceStats::collect(trace.into_iter().map(Ok))?;
+
+        assert_eq!(
+            stats,
+            TraceStats {
+                n_events: 14,
+                n_reads: 2,
+                n_writes: 1,
+                n_aquires: 3,
+                n_requests: 3,
+                n_releases: 3,
+                n_forks: 1,
+                n_joins: 1,
+                n_waits: 0,
+                n_notifies: 0,
+                n_calls: 0,
+                n_returns: 0,
+                n_threads: 2,
+                n_locks: 2,
+                n_variables: 2,
+                n_locations: 8,
+                max_lock_depth: 2,
+                bytes_accessed: None,
+            }
+        );
+
+        Ok(())
+    }
+}
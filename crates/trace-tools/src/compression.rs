@@ -0,0 +1,207 @@
+use std::io::{self, Cursor, Read, Write};
+
+use anyhow::{Error, bail};
+
+/// Identifies compressed execution traces.
+const MAGIC: [u8; 4] = *b"WGTZ";
+
+/// The compression codec of an execution trace.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    /// The trace is stored as is
+    None,
+    /// The trace is compressed with Zstandard
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Codec {
+    fn id(self) -> u8 {
+        match self {
+            Codec::None => 0,
+            #[cfg(feature = "zstd")]
+            Codec::Zstd => 1,
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Self, Error> {
+        match id {
+            0 => Ok(Codec::None),
+            #[cfg(feature = "zstd")]
+            1 => Ok(Codec::Zstd),
+            #[cfg(not(feature = "zstd"))]
+            1 => bail!("Trace is compressed with Zstandard, which requires the `zstd` feature"),
+            _ => bail!("Trace is compressed with an unknown codec ({id})"),
+        }
+    }
+}
+
+/// Compresses the execution trace read from `input` into `output`.
+///
+/// The compressed trace is preceded by a frame header, consisting of a magic number,
+/// the codec and the uncompressed length of the trace. Hence, it can be opened
+/// without knowing the codec in advance (see [`open_trace`]).
+pub fn compress<I: Read, O: Write>(
+    codec: Codec,
+    mut input: I,
+    uncompressed_len: u64,
+    mut output: O,
+) -> Result<(), Error> {
+    output.write_all(&MAGIC)?;
+    output.write_all(&[codec.id()])?;
+    output.write_all(&uncompressed_len.to_le_bytes())?;
+
+    let copied = match codec {
+        Codec::None => io::copy(&mut input, &mut output)?,
+        #[cfg(feature = "zstd")]
+        Codec::Zstd => {
+            let mut encoder = zstd::Encoder::new(&mut output, 0)?;
+            let copied = io::copy(&mut input, &mut encoder)?;
+            encoder.finish()?;
+            copied
+        }
+    };
+    if copied != uncompressed_len {
+        bail!("Expected a trace of {uncompressed_len} bytes but compressed {copied} bytes");
+    }
+
+    output.flush()?;
+    Ok(())
+}
+
+/// Opens an execution trace that may have been compressed by [`compress`].
+///
+/// Compressed traces are decompressed transparently, whereas traces without a frame
+/// header are passed through unchanged. The returned reader can be handed to any
+/// [`crate::generic::Parser`].
+pub fn open_trace<'a, R: Read + 'a>(mut reader: R) -> Result<Box<dyn Read + 'a>, Error> {
+    let mut magic = Vec::with_capacity(MAGIC.len());
+    (&mut reader)
+        .take(MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+    if magic != MAGIC {
+        return Ok(Box::new(Cursor::new(magic).chain(reader)));
+    }
+
+    let mut codec = [0; 1];
+    reader.read_exact(&mut codec)?;
+    let mut uncompressed_len = [0; 8];
+    reader.read_exact(&mut uncompressed_len)?;
+    let remaining = u64::from_le_bytes(uncompressed_len);
+
+    let inner: Box<dyn Read + 'a> = match Codec::from_id(codec[0])? {
+        Codec::None => Box::new(reader),
+        #[cfg(feature = "zstd")]
+        Codec::Zstd => Box::new(zstd::Decoder::new(reader)?),
+    };
+
+    Ok(Box::new(FramedReader { inner, remaining }))
+}
+
+/// Ensures that exactly the uncompressed length of a frame is read.
+struct FramedReader<'a> {
+    inner: Box<dyn Read + 'a>,
+    remaining: u64,
+}
+
+impl Read for FramedReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let len = buf
+            .len()
+            .min(usize::try_from(self.remaining).unwrap_or(usize::MAX));
+        let n = self.inner.read(&mut buf[..len])?;
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("Compressed trace ended {} bytes early", self.remaining),
+            ));
+        }
+
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor, Read as _};
+
+    use anyhow::Error;
+
+    use super::{Codec, compress, open_trace};
+    use crate::{
+        RapidBinEncoder, RapidBinParser,
+        generic::{Encoder, Event, Operation::*, Parser},
+    };
+
+    fn example_trace() -> Result<Vec<u8>, Error> {
+        let events = vec![
+            Event::new(0, Fork { tid: 1 }, 0),
+            Event::new(0, Write { memory: 7 }, 1),
+            Event::new(1, Aquire { lock: 3 }, 2),
+            Event::new(1, Read { memory: 7 }, 3),
+            Event::new(1, Release { lock: 3 }, 4),
+            Event::new(0, Join { tid: 1 }, 5),
+        ];
+
+        let mut trace = Cursor::new(Vec::new());
+        RapidBinEncoder::new().encode(events.into_iter().map(Ok), &mut trace)?;
+        Ok(trace.into_inner())
+    }
+
+    fn parse(reader: impl io::Read) -> Result<Vec<Event>, Error> {
+        RapidBinParser::new()
+            .parse(open_trace(reader)?)?
+            .collect::<Result<Vec<Event>, Error>>()
+    }
+
+    #[test]
+    fn roundtrip_compressed_traces() -> Result<(), Error> {
+        let trace = example_trace()?;
+        let expected = parse(trace.as_slice())?;
+
+        let codecs = [
+            Codec::None,
+            #[cfg(feature = "zstd")]
+            Codec::Zstd,
+        ];
+        for codec in codecs {
+            let mut compressed = Vec::new();
+            compress(codec, trace.as_slice(), trace.len() as u64, &mut compressed)?;
+            assert_eq!(parse(compressed.as_slice())?, expected);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_truncated_frames() -> Result<(), Error> {
+        let trace = example_trace()?;
+        let mut compressed = Vec::new();
+        compress(
+            Codec::None,
+            trace.as_slice(),
+            trace.len() as u64,
+            &mut compressed,
+        )?;
+        compressed.truncate(compressed.len() - 1);
+
+        let mut decompressed = Vec::new();
+        assert!(
+            open_trace(compressed.as_slice())?
+                .read_to_end(&mut decompressed)
+                .is_err()
+        );
+
+        // Traces shorter than the magic number are passed through as well
+        let mut short = Vec::new();
+        open_trace([1u8, 2].as_slice())?.read_to_end(&mut short)?;
+        assert_eq!(short, [1, 2]);
+
+        Ok(())
+    }
+}
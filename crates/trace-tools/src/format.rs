@@ -0,0 +1,172 @@
+use std::io::{BufRead, Read, Seek, Write};
+
+use anyhow::Error;
+
+use crate::{
+    JsonFormatEncoder, JsonFormatParser, RapidBinEncoder, RapidBinParser, StdFormatEncoder,
+    StdFormatParser,
+    generic::{Encoder, EventResult, Parser},
+};
+
+/// An execution trace format that is selected at runtime.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+    RapidBin,
+    Std,
+    Json,
+}
+
+impl TraceFormat {
+    /// Guesses the format of the trace read by `input` without consuming it.
+    ///
+    /// STD traces start with a thread identifier (`T...`) and JSON traces with an
+    /// object. Any other trace is assumed to be a RapidBin trace, whose header starts
+    /// with a thread count that is far too small to be mistaken for either of them.
+    pub fn detect<R: BufRead>(input: &mut R) -> Result<Self, Error> {
+        let format = match input
+            .fill_buf()?
+            .iter()
+            .find(|byte| !byte.is_ascii_whitespace())
+        {
+            Some(b'T') => TraceFormat::Std,
+            Some(b'{') => TraceFormat::Json,
+            _ => TraceFormat::RapidBin,
+        };
+
+        Ok(format)
+    }
+
+    /// Parses an execution trace in this format.
+    pub fn parse<'a, R: Read + 'a>(
+        self,
+        input: R,
+    ) -> Result<Box<dyn Iterator<Item = EventResult> + 'a>, Error> {
+        Ok(match self {
+            TraceFormat::RapidBin => Box::new(RapidBinParser::new().parse(input)?),
+            TraceFormat::Std => Box::new(StdFormatParser::new().parse(input)?),
+            TraceFormat::Json => Box::new(JsonFormatParser::new().parse(input)?),
+        })
+    }
+
+    /// Encodes an execution trace into this format.
+    pub fn encode<W: Write + Seek, I: IntoIterator<Item = EventResult>>(
+        self,
+        input: I,
+        output: W,
+    ) -> Result<(), Error> {
+        match self {
+            TraceFormat::RapidBin => RapidBinEncoder::new().encode(input, output),
+            TraceFormat::Std => StdFormatEncoder::new().encode(input, output),
+            TraceFormat::Json => JsonFormatEncoder::new().encode(input, output),
+        }
+    }
+}
+
+/// Converts an execution trace between two formats that are selected at runtime.
+///
+/// See [`crate::convert`].
+pub fn convert_dynamic<I: Read, O: Write + Seek>(
+    from: TraceFormat,
+    to: TraceFormat,
+    input: I,
+    mut output: O,
+) -> Result<(), Error> {
+    to.encode(from.parse(input)?, &mut output)?;
+
+    output.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Cursor};
+
+    use anyhow::Error;
+
+    use crate::{
+        RapidBinEncoder,
+        generic::{Encoder, Event, Operation},
+    };
+
+    use super::{TraceFormat, convert_dynamic};
+
+    fn example_trace() -> Vec<Event> {
+        vec![
+            Event::new(0, Operation::Fork { tid: 1 }, 0),
+            Event::new(1, Operation::Request { lock: 0 }, 1),
+            Event::new(1, Operation::Aquire { lock: 0 }, 1),
+            Event::new(1, Operation::Write { memory: 0 }, 2),
+            Event::new(1, Operation::Release { lock: 0 }, 1),
+            Event::new(0, Operation::Join { tid: 1 }, 3),
+        ]
+    }
+
+    fn rapidbin_trace() -> Result<Vec<u8>, Error> {
+        let mut buffer = Cursor::new(Vec::new());
+        RapidBinEncoder::new().encode(example_trace().into_iter().map(Ok), &mut buffer)?;
+        Ok(buffer.into_inner())
+    }
+
+    fn convert(from: TraceFormat, to: TraceFormat, input: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut output = Cursor::new(Vec::new());
+        convert_dynamic(from, to, input, &mut output)?;
+        Ok(output.into_inner())
+    }
+
+    fn parse(format: TraceFormat, input: &[u8]) -> Result<Vec<Event>, Error> {
+        format.parse(input)?.collect()
+    }
+
+    #[test]
+    fn convert_rapidbin_to_std() -> Result<(), Error> {
+        let std = convert(TraceFormat::RapidBin, TraceFormat::Std, &rapidbin_trace()?)?;
+
+        assert_eq!(
+            String::from_utf8(std.clone())?.lines().next(),
+            Some("T0|fork(T1)|0")
+        );
+        assert_eq!(parse(TraceFormat::Std, &std)?, example_trace());
+
+        Ok(())
+    }
+
+    #[test]
+    fn convert_std_to_rapidbin() -> Result<(), Error> {
+        let std = convert(TraceFormat::RapidBin, TraceFormat::Std, &rapidbin_trace()?)?;
+        let rapidbin = convert(TraceFormat::Std, TraceFormat::RapidBin, &std)?;
+
+        assert_eq!(rapidbin, rapidbin_trace()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn convert_rapidbin_to_json() -> Result<(), Error> {
+        let json = convert(TraceFormat::RapidBin, TraceFormat::Json, &rapidbin_trace()?)?;
+
+        assert_eq!(parse(TraceFormat::Json, &json)?, example_trace());
+
+        Ok(())
+    }
+
+    #[test]
+    fn detect_trace_formats() -> Result<(), Error> {
+        let rapidbin = rapidbin_trace()?;
+        let std = convert(TraceFormat::RapidBin, TraceFormat::Std, &rapidbin)?;
+        let json = convert(TraceFormat::RapidBin, TraceFormat::Json, &rapidbin)?;
+
+        for (trace, format) in [
+            (rapidbin, TraceFormat::RapidBin),
+            (std, TraceFormat::Std),
+            (json, TraceFormat::Json),
+        ] {
+            let mut reader = BufReader::new(trace.as_slice());
+            assert_eq!(TraceFormat::detect(&mut reader)?, format);
+            // Detection does not consume the trace
+            assert_eq!(parse(format, reader.buffer())?, example_trace());
+        }
+
+        Ok(())
+    }
+}
@@ -12,6 +12,10 @@ pub enum Operation {
     Fork { tid: u64 },
     Join { tid: u64 },
     Request { lock: u64 },
+    Wait { cond: u64 },
+    Notify { cond: u64 },
+    Call { fidx: u64 },
+    Return { fidx: u64 },
 }
 
 impl Operation {
@@ -25,6 +29,10 @@ impl Operation {
             Operation::Fork { tid: _ } => 4,
             Operation::Join { tid: _ } => 5,
             Operation::Request { lock: _ } => 8,
+            Operation::Wait { cond: _ } => 10,
+            Operation::Notify { cond: _ } => 11,
+            Operation::Call { fidx: _ } => 12,
+            Operation::Return { fidx: _ } => 13,
         }
     }
 
@@ -37,6 +45,10 @@ impl Operation {
             4 => Ok(Operation::Fork { tid: decor }),
             5 => Ok(Operation::Join { tid: decor }),
             8 => Ok(Operation::Request { lock: decor }),
+            10 => Ok(Operation::Wait { cond: decor }),
+            11 => Ok(Operation::Notify { cond: decor }),
+            12 => Ok(Operation::Call { fidx: decor }),
+            13 => Ok(Operation::Return { fidx: decor }),
             _ => Err(anyhow!("Operation-ID was not recognized")),
         }
     }
@@ -105,7 +117,7 @@ mod tests {
     #[test]
     fn fail_on_invalid_operation_id() {
         let valid_decor = 42;
-        let valid_ids = [0, 1, 2, 3, 4, 5, 8];
+        let valid_ids = [0, 1, 2, 3, 4, 5, 8, 10, 11, 12, 13];
 
         for id in (-100..100).filter(|id| !valid_ids.contains(id)) {
             Operation::try_from_id(id, valid_decor).unwrap_err();
@@ -117,7 +129,7 @@ mod tests {
         use super::Operation::*;
 
         let valid_decor = 42;
-        let valid_ids = [0, 1, 2, 3, 4, 5, 8];
+        let valid_ids = [0, 1, 2, 3, 4, 5, 8, 10, 11, 12, 13];
         let valid_ops = [
             Aquire { lock: valid_decor },
             Release { lock: valid_decor },
@@ -130,6 +142,10 @@ mod tests {
             Fork { tid: valid_decor },
             Join { tid: valid_decor },
             Request { lock: valid_decor },
+            Wait { cond: valid_decor },
+            Notify { cond: valid_decor },
+            Call { fidx: valid_decor },
+            Return { fidx: valid_decor },
         ];
 
         for (idx, id) in valid_ids.into_iter().enumerate() {
@@ -0,0 +1,234 @@
+use crate::generic::{Encoder, Event, EventResult, Operation, Parser};
+use anyhow::{Error, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::{Deserializer, StreamDeserializer, de::IoRead};
+use std::io::{Read, Seek, Write};
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum JsonOperation {
+    Acquire,
+    Release,
+    Read,
+    Write,
+    Fork,
+    Join,
+    Request,
+    Wait,
+    Notify,
+    Call,
+    Return,
+}
+
+/// The representation of a single event in a _JSON_ trace
+#[derive(Serialize, Deserialize)]
+struct JsonEvent {
+    thread: u64,
+    op: JsonOperation,
+    decor: u64,
+    location: u64,
+}
+
+impl From<Event> for JsonEvent {
+    fn from(event: Event) -> Self {
+        let (thread, operation, location) = event.into_fields();
+        let (op, decor) = match operation {
+            Operation::Aquire { lock } => (JsonOperation::Acquire, lock),
+            Operation::Release { lock } => (JsonOperation::Release, lock),
+            Operation::Read { memory } => (JsonOperation::Read, memory),
+            Operation::Write { memory } => (JsonOperation::Write, memory),
+            Operation::Fork { tid } => (JsonOperation::Fork, tid),
+            Operation::Join { tid } => (JsonOperation::Join, tid),
+            Operation::Request { lock } => (JsonOperation::Request, lock),
+            Operation::Wait { cond } => (JsonOperation::Wait, cond),
+            Operation::Notify { cond } => (JsonOperation::Notify, cond),
+            Operation::Call { fidx } => (JsonOperation::Call, fidx),
+            Operation::Return { fidx } => (JsonOperation::Return, fidx),
+        };
+
+        Self {
+            thread,
+            op,
+            decor,
+            location,
+        }
+    }
+}
+
+impl From<JsonEvent> for Event {
+    fn from(event: JsonEvent) -> Self {
+        let decor = event.decor;
+        let operation = match event.op {
+            JsonOperation::Acquire => Operation::Aquire { lock: decor },
+            JsonOperation::Release => Operation::Release { lock: decor },
+            JsonOperation::Read => Operation::Read { memory: decor },
+            JsonOperation::Write => Operation::Write { memory: decor },
+            JsonOperation::Fork => Operation::Fork { tid: decor },
+            JsonOperation::Join => Operation::Join { tid: decor },
+            JsonOperation::Request => Operation::Request { lock: decor },
+            JsonOperation::Wait => Operation::Wait { cond: decor },
+            JsonOperation::Notify => Operation::Notify { cond: decor },
+            JsonOperation::Call => Operation::Call { fidx: decor },
+            JsonOperation::Return => Operation::Return { fidx: decor },
+        };
+
+        Event::new(event.thread, operation, event.location)
+    }
+}
+
+/// An encoder to emit execution traces in _JSON_ format
+///
+/// Every event is emitted as a JSON object on its own line, e.g.,
+/// `{"thread":0,"op":"fork","decor":1,"location":42}`.
+pub struct JsonFormatEncoder;
+
+impl JsonFormatEncoder {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Default for JsonFormatEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Encoder for JsonFormatEncoder {
+    const EVENT_SIZE_HINT: usize = 1;
+
+    fn encode<W: Write + Seek, I: IntoIterator<Item = EventResult>>(
+        &mut self,
+        input: I,
+        mut output: W,
+    ) -> Result<(), Error> {
+        for event in input {
+            serde_json::to_writer(&mut output, &JsonEvent::from(event?))?;
+            writeln!(output)?;
+        }
+
+        Ok(())
+    }
+
+    fn format(&self) -> &'static str {
+        "JSON"
+    }
+}
+
+/// A parser for execution traces in _JSON_ format
+pub struct JsonFormatParser;
+
+impl JsonFormatParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Default for JsonFormatParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser for JsonFormatParser {
+    type Iter<R: Read> = JsonFormatIterator<R>;
+
+    fn parse<R: Read>(&mut self, input: R) -> Result<Self::Iter<R>, Error> {
+        Ok(JsonFormatIterator {
+            events: Deserializer::from_reader(input).into_iter(),
+        })
+    }
+
+    fn format(&self) -> &'static str {
+        "JSON"
+    }
+}
+
+pub struct JsonFormatIterator<R: Read> {
+    events: StreamDeserializer<'static, IoRead<R>, JsonEvent>,
+}
+
+impl<R: Read> Iterator for JsonFormatIterator<R> {
+    type Item = EventResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.events.next().map(|event| {
+            event
+                .map(Event::from)
+                .map_err(|e| anyhow!("Malformed JSON event: {e}"))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use anyhow::Error;
+
+    use crate::generic::{Encoder, Event, Operation, Parser};
+
+    use super::{JsonFormatEncoder, JsonFormatParser};
+
+    fn example_trace() -> Vec<Event> {
+        vec![
+            Event::new(0, Operation::Fork { tid: 1 }, 42),
+            Event::new(1, Operation::Request { lock: 0 }, 362),
+            Event::new(1, Operation::Aquire { lock: 0 }, 362),
+            Event::new(1, Operation::Write { memory: 200 }, 923),
+            Event::new(1, Operation::Release { lock: 0 }, 362),
+            Event::new(0, Operation::Call { fidx: 7 }, 500),
+            Event::new(0, Operation::Return { fidx: 7 }, 501),
+            Event::new(0, Operation::Join { tid: 1 }, 7382),
+        ]
+    }
+
+    #[test]
+    fn encode_valid_trace() -> Result<(), Error> {
+        let mut buffer = Cursor::new(Vec::new());
+        let mut encoder = JsonFormatEncoder::new();
+        encoder.encode(example_trace().into_iter().take(2).map(Ok), &mut buffer)?;
+
+        assert_eq!(
+            String::from_utf8(buffer.into_inner())?,
+            concat!(
+                "{\"thread\":0,\"op\":\"fork\",\"decor\":1,\"location\":42}\n",
+                "{\"thread\":1,\"op\":\"request\",\"decor\":0,\"location\":362}\n",
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn json_format_roundtrip() -> Result<(), Error> {
+        let mut buffer = Cursor::new(Vec::new());
+        let mut encoder = JsonFormatEncoder::new();
+        encoder.encode(example_trace().into_iter().map(Ok), &mut buffer)?;
+
+        let mut parser = JsonFormatParser::new();
+        let parsed_trace = parser
+            .parse(buffer.into_inner().as_slice())?
+            .collect::<Result<Vec<Event>, Error>>()?;
+
+        assert_eq!(example_trace(), parsed_trace);
+
+        Ok(())
+    }
+
+    #[test]
+    fn fail_on_malformed_events() {
+        let malformed = [
+            "{\"thread\":0,\"op\":\"lock\",\"decor\":1,\"location\":42}",
+            "{\"thread\":0,\"op\":\"fork\",\"location\":42}",
+            "{\"thread\":-1,\"op\":\"fork\",\"decor\":1,\"location\":42}",
+            "T0|fork(T1)|42",
+        ];
+
+        for event in malformed {
+            let mut parser = JsonFormatParser::new();
+            let mut iter = parser.parse(event.as_bytes()).unwrap();
+            iter.next().unwrap().unwrap_err();
+        }
+    }
+}
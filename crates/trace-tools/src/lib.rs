@@ -4,14 +4,27 @@ use anyhow::Error;
 
 use crate::generic::{Encoder, Parser};
 
+/// Analyses operating on execution traces in the generic representation
+pub mod analysis;
+/// Optional compression of execution traces
+pub mod compression;
+mod format;
 /// Generic traits and structs for parsing and encoding of execution traces
 pub mod generic;
+mod json_format;
+#[cfg(feature = "parallel")]
+mod parallel;
 /// Specific parser/encoder implementations for the RapidBin trace format
 pub mod rapidbin;
 mod std_format;
 
-pub use rapidbin::{encoder::RapidBinEncoder, parser::RapidBinParser};
-pub use std_format::StdFormatEncoder;
+pub use compression::{Codec, open_trace};
+pub use format::{TraceFormat, convert_dynamic};
+pub use json_format::{JsonFormatEncoder, JsonFormatParser};
+#[cfg(feature = "parallel")]
+pub use parallel::convert_parallel;
+pub use rapidbin::{encoder::RapidBinEncoder, parser::RapidBinParser, writer::RapidBinFileWriter};
+pub use std_format::{StdFormatEncoder, StdFormatParser};
 
 /// Converts an execution trace from one format into another
 pub fn convert<P: Parser, E: Encoder, I: Read, O: Write + Seek>(
@@ -1,40 +1,191 @@
 use std::{
+    collections::HashMap,
     fs::{File, OpenOptions},
     io::{BufRead, BufReader, BufWriter},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
-use anyhow::Error;
-use clap::Parser;
-use trace_tools::{RapidBinParser, StdFormatEncoder};
+use anyhow::{Error, bail};
+use clap::{Parser, ValueEnum};
+use serde::Deserialize;
+use trace_tools::{
+    StdFormatEncoder, TraceFormat,
+    analysis::{HappensBefore, TraceStats},
+    generic::Encoder,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Std,
+    Rapidbin,
+    Json,
+}
+
+impl From<Format> for TraceFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Std => TraceFormat::Std,
+            Format::Rapidbin => TraceFormat::RapidBin,
+            Format::Json => TraceFormat::Json,
+        }
+    }
+}
 
 #[derive(Parser)]
 struct Cli {
     input: PathBuf,
-    output: PathBuf,
+    #[arg(required_unless_present_any = ["stats", "detect_races"])]
+    output: Option<PathBuf>,
+
+    /// Print summary statistics of the input trace instead of converting it
+    #[arg(long)]
+    stats: bool,
+
+    /// Report data races in the input trace instead of converting it
+    #[arg(long)]
+    detect_races: bool,
+
+    /// The format of the input trace. Detected from its first bytes if omitted
+    #[arg(long, value_enum)]
+    from: Option<Format>,
+
+    /// The format of the output trace
+    #[arg(long, value_enum, default_value_t = Format::Std)]
+    to: Format,
+
+    /// The metadata file of a Wasmgrind trace whose locations have been resolved to
+    /// source positions. STD output then names the locations by `file:line` and the
+    /// locks by their symbol or address
+    #[arg(long, value_name = "METADATA")]
+    source_map: Option<PathBuf>,
+
+    /// The number of threads converting a RapidBin trace to STD format
+    #[cfg(feature = "parallel")]
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: u16,
+}
+
+/// The part of the Wasmgrind trace metadata that names the locations and locks of the trace.
+#[derive(Deserialize)]
+struct SourceMetadata {
+    location_records: Vec<SourceRecord>,
+    #[serde(default)]
+    lock_records: Vec<LockRecord>,
+}
+
+#[derive(Deserialize)]
+struct SourceRecord {
+    trace_id: u64,
+    #[serde(default)]
+    source: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LockRecord {
+    trace_id: u64,
+    #[serde(default)]
+    address: Option<u32>,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+/// Creates an STD encoder naming locations and locks as in the metadata of a Wasmgrind trace.
+///
+/// Locks without a symbol are named by their address.
+fn named_encoder(metadata: &Path) -> Result<StdFormatEncoder, Error> {
+    let metadata: SourceMetadata = serde_json::from_reader(BufReader::new(File::open(metadata)?))?;
+    let locations: HashMap<u64, String> = metadata
+        .location_records
+        .into_iter()
+        .filter_map(|record| Some((record.trace_id, record.source?)))
+        .collect();
+    let locks = metadata
+        .lock_records
+        .into_iter()
+        .filter_map(|record| {
+            let name = record
+                .name
+                .or_else(|| Some(format!("{:#x}", record.address?)))?;
+            Some((record.trace_id, name))
+        })
+        .collect();
+
+    Ok(StdFormatEncoder::with_location_names(locations).with_lock_names(locks))
 }
 
 fn main() -> Result<(), Error> {
     let args = Cli::parse();
 
-    let reader = BufReader::new(File::open(&args.input)?);
+    let mut reader = BufReader::new(trace_tools::open_trace(BufReader::new(File::open(
+        &args.input,
+    )?))?);
+    let from = match args.from {
+        Some(format) => TraceFormat::from(format),
+        None => TraceFormat::detect(&mut reader)?,
+    };
+
+    if args.stats {
+        let stats = TraceStats::collect(from.parse(reader)?)?;
+        println!("{stats}");
+
+        return Ok(());
+    }
+
+    if args.detect_races {
+        let races = HappensBefore::analyze(from.parse(reader)?)?;
+        for race in &races {
+            println!(
+                "Data race on variable {} between thread {} at location {} and thread {} at location {}",
+                race.variable,
+                race.first_thread,
+                race.first_location,
+                race.second_thread,
+                race.second_location
+            );
+        }
+        println!("Found {} data races", races.len());
+
+        return Ok(());
+    }
+
+    let mut encoder = match &args.source_map {
+        Some(_) if args.to != Format::Std => bail!("--source-map is only supported for STD output"),
+        Some(metadata) => named_encoder(metadata)?,
+        None => StdFormatEncoder::new(),
+    };
+
+    let output = args
+        .output
+        .expect("Output is required unless --stats or --detect-races is given");
     let writer = BufWriter::new(
         OpenOptions::new()
             .truncate(true)
             .write(true)
             .create(true)
-            .open(&args.output)?,
+            .open(&output)?,
     );
 
-    let mut parser = RapidBinParser::new();
-    let mut encoder = StdFormatEncoder::new();
+    #[cfg(feature = "parallel")]
+    let jobs = usize::from(args.jobs);
+    #[cfg(not(feature = "parallel"))]
+    let jobs = 1;
 
-    trace_tools::convert(&mut parser, &mut encoder, reader, writer)?;
+    match (from, TraceFormat::from(args.to)) {
+        #[cfg(feature = "parallel")]
+        (TraceFormat::RapidBin, TraceFormat::Std) if jobs > 1 => {
+            trace_tools::convert_parallel(&encoder, reader, writer, jobs)?
+        }
+        _ if jobs > 1 => bail!("--jobs is only supported when converting from RapidBin to STD"),
+        (from, TraceFormat::Std) => encoder.encode(from.parse(reader)?, writer)?,
+        (from, to) => trace_tools::convert_dynamic(from, to, reader, writer)?,
+    }
 
-    println!("Trace Output: ");
-    let reader = BufReader::new(File::open(args.output)?);
-    for line in reader.lines() {
-        println!("{}", line?)
+    if args.to == Format::Std {
+        println!("Trace Output: ");
+        let reader = BufReader::new(File::open(output)?);
+        for line in reader.lines() {
+            println!("{}", line?)
+        }
     }
 
     Ok(())
@@ -0,0 +1,226 @@
+use std::io::{Read, Write};
+
+use anyhow::{Context, Error, ensure};
+use rayon::prelude::*;
+
+use crate::{
+    RapidBinParser, StdFormatEncoder,
+    rapidbin::{
+        RapidBinLayout,
+        parser::{TraceIds, decode_event},
+    },
+};
+
+/// The number of events that are converted by a single task.
+const CHUNK_EVENTS: usize = 1 << 16;
+
+/// Converts an execution trace from RapidBin into STD format using `jobs` threads.
+///
+/// The trace is read sequentially in chunks of events, which are decoded and encoded
+/// in parallel and written to `output` in their original order. Since the chunks are
+/// converted independently, the numbers of threads, locks and variables specified in
+/// the header are only verified after all events have been converted. Hence, `output`
+/// may contain a partial trace if the conversion fails.
+///
+/// The output is identical to the one of [`crate::convert`].
+pub fn convert_parallel<I: Read, O: Write>(
+    encoder: &StdFormatEncoder,
+    mut input: I,
+    mut output: O,
+    jobs: usize,
+) -> Result<(), Error> {
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs).build()?;
+
+    let header = RapidBinParser::parse_header(&mut input)?;
+    let mut remaining = header.n_events.unsigned_abs();
+    let mut ids = TraceIds::default();
+    // The index of the first event of the next chunk, which offsets its timestamps
+    let mut first = 0;
+
+    loop {
+        let mut chunks = Vec::with_capacity(jobs.max(1));
+        while chunks.len() < jobs.max(1) && remaining > 0 {
+            let n_events = usize::try_from(remaining)
+                .unwrap_or(usize::MAX)
+                .min(CHUNK_EVENTS);
+            let mut chunk = vec![0; n_events * 8];
+            input
+                .read_exact(&mut chunk)
+                .context("Found fewer events than specified!")?;
+            remaining -= n_events as u64;
+            chunks.push((first, chunk));
+            first += n_events;
+        }
+
+        if chunks.is_empty() {
+            break;
+        }
+
+        let converted = pool.install(|| {
+            chunks
+                .par_iter()
+                .map(|(first, chunk)| convert_chunk(encoder, header.layout, *first, chunk))
+                .collect::<Vec<_>>()
+        });
+
+        for result in converted {
+            let (text, chunk_ids) = result?;
+            output.write_all(text.as_bytes())?;
+            ids.merge(chunk_ids);
+        }
+    }
+
+    ensure!(
+        input.read(&mut [0; 1])? == 0,
+        "Found more events than specified!"
+    );
+    ids.ensure_within(header.n_threads, header.n_locks, header.n_variables)?;
+    ensure!(
+        ids.matches(header.n_threads, header.n_locks, header.n_variables)?,
+        "Found fewer threads, locks or variables than specified!"
+    );
+
+    output.flush()?;
+
+    Ok(())
+}
+
+/// Converts the events of `chunk`, the first of which is the event with index `first`.
+fn convert_chunk(
+    encoder: &StdFormatEncoder,
+    layout: RapidBinLayout,
+    first: usize,
+    chunk: &[u8],
+) -> Result<(String, TraceIds), Error> {
+    let mut text = String::new();
+    let mut ids = TraceIds::default();
+
+    for (index, bytes) in (first..).zip(chunk.chunks_exact(8)) {
+        let event = decode_event(layout, bytes.try_into()?)?;
+        ids.record(&event);
+        text.push_str(&encoder.encode_line(index, event));
+        text.push('\n');
+    }
+
+    Ok((text, ids))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use anyhow::Error;
+    use rand_xoshiro::{
+        Xoshiro256PlusPlus,
+        rand_core::{RngCore, SeedableRng},
+    };
+
+    use super::{CHUNK_EVENTS, convert_parallel};
+    use crate::{
+        RapidBinEncoder, RapidBinParser, StdFormatEncoder, convert,
+        generic::{Encoder, Event, Operation},
+    };
+
+    fn random_trace(n_events: usize) -> Result<Vec<u8>, Error> {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(42);
+        let events = (0..n_events).map(|_| {
+            let decor = u64::from(rng.next_u32() % 64);
+            let operation = match rng.next_u32() % 4 {
+                0 => Operation::Aquire { lock: decor },
+                1 => Operation::Release { lock: decor },
+                2 => Operation::Read { memory: decor },
+                _ => Operation::Write { memory: decor },
+            };
+            Ok(Event::new(
+                u64::from(rng.next_u32() % 8),
+                operation,
+                u64::from(rng.next_u32() % 1024),
+            ))
+        });
+
+        let mut trace = Cursor::new(Vec::new());
+        RapidBinEncoder::new().encode(events, &mut trace)?;
+        Ok(trace.into_inner())
+    }
+
+    #[test]
+    fn convert_like_sequential() -> Result<(), Error> {
+        let trace = random_trace(3 * CHUNK_EVENTS + 17)?;
+
+        let mut sequential = Cursor::new(Vec::new());
+        convert(
+            &mut RapidBinParser::new(),
+            &mut StdFormatEncoder::new(),
+            trace.as_slice(),
+            &mut sequential,
+        )?;
+
+        for jobs in [1, 2, 8] {
+            let mut parallel = Vec::new();
+            convert_parallel(
+                &StdFormatEncoder::new(),
+                trace.as_slice(),
+                &mut parallel,
+                jobs,
+            )?;
+            assert_eq!(parallel, *sequential.get_ref());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn convert_timestamps_like_sequential() -> Result<(), Error> {
+        let n_events = 2 * CHUNK_EVENTS + 5;
+        let trace = random_trace(n_events)?;
+        let timestamps = (0..n_events as u64).map(|idx| 3 * idx).collect::<Vec<_>>();
+
+        let mut sequential = Cursor::new(Vec::new());
+        convert(
+            &mut RapidBinParser::new(),
+            &mut StdFormatEncoder::new().with_timestamps(timestamps.clone()),
+            trace.as_slice(),
+            &mut sequential,
+        )?;
+
+        let mut parallel = Vec::new();
+        convert_parallel(
+            &StdFormatEncoder::new().with_timestamps(timestamps),
+            trace.as_slice(),
+            &mut parallel,
+            4,
+        )?;
+        assert_eq!(parallel, *sequential.get_ref());
+        // The last event of the trace is emitted with its own timestamp
+        let last_line = String::from_utf8(parallel)?
+            .lines()
+            .last()
+            .map(String::from);
+        assert!(last_line.is_some_and(|line| line.ends_with(&format!("@{}", 3 * (n_events - 1)))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn validate_header_counts() -> Result<(), Error> {
+        let trace = random_trace(100)?;
+        let convert =
+            |trace: &[u8]| convert_parallel(&StdFormatEncoder::new(), trace, Vec::new(), 2);
+        convert(&trace)?;
+
+        // Missing events
+        convert(&trace[..trace.len() - 8]).unwrap_err();
+
+        // Additional events
+        let mut extended = trace.clone();
+        extended.extend_from_within(trace.len() - 8..);
+        convert(&extended).unwrap_err();
+
+        // Fewer threads than specified
+        let mut header = trace.clone();
+        header[..2].copy_from_slice(&9_i16.to_be_bytes());
+        convert(&header).unwrap_err();
+
+        Ok(())
+    }
+}
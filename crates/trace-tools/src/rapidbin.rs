@@ -4,28 +4,233 @@ pub mod parser;
 /// Utilities to encode execution traces to RapidBin format.
 pub mod encoder;
 
-// ============================================================================
-// Statics, which are relevant for reading and writing traces in RapidBin format:
-const THREAD_NUM_BITS: i16 = 10;
-const THREAD_BIT_OFFSET: i16 = 0;
-
-const OP_NUM_BITS: i16 = 4;
-const OP_BIT_OFFSET: i16 = THREAD_BIT_OFFSET + THREAD_NUM_BITS;
+/// Utilities to write execution traces in RapidBin format event by event.
+pub mod writer;
 
-const DECOR_NUM_BITS: i16 = 34;
-const DECOR_BIT_OFFSET: i16 = OP_BIT_OFFSET + OP_NUM_BITS;
+use anyhow::{Error, ensure};
 
-const LOC_NUM_BITS: i16 = 15;
-const LOC_BIT_OFFSET: i16 = DECOR_BIT_OFFSET + DECOR_NUM_BITS;
 // ============================================================================
 // Only relevant for reading traces:
 const NUMBER_OF_TRHEADS_MASK: i16 = 0x7FFF;
 const NUMBER_OF_LOCKS_MASK: i32 = 0x7FFFFFFF;
 const NUMBER_OF_VARS_MASK: i32 = 0x7FFFFFFF;
 const NUMBER_OF_EVENTS_MASK: i64 = 0x7FFFFFFFFFFFFFFF;
-
-const THREAD_MASK: i64 = ((1 << THREAD_NUM_BITS) - 1) << THREAD_BIT_OFFSET;
-const OP_MASK: i64 = ((1 << OP_NUM_BITS) - 1) << OP_BIT_OFFSET;
-const DECOR_MASK: i64 = ((1 << DECOR_NUM_BITS) - 1) << DECOR_BIT_OFFSET;
-const LOC_MASK: i64 = ((1 << LOC_NUM_BITS) - 1) << LOC_BIT_OFFSET;
 // ============================================================================
+// The most significant bit of the thread count is never set in traces using the
+// default layout. We use it to signal that a layout descriptor follows the header.
+const LAYOUT_DESCRIPTOR_FLAG: i16 = i16::MIN;
+const LAYOUT_DESCRIPTOR_LEN: usize = 4;
+// ============================================================================
+
+/// The bit widths of the fields of an encoded RapidBin event.
+///
+/// From the least to the most significant bit, an event consists of the thread,
+/// the operation, the decoration, and the location. The [`RapidBinLayout::DEFAULT`]
+/// layout is the one understood by RAPID. Traces using any other layout carry a
+/// layout descriptor in their header and can only be read by [`crate::RapidBinParser`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RapidBinLayout {
+    thread_bits: u8,
+    op_bits: u8,
+    decor_bits: u8,
+    location_bits: u8,
+}
+
+impl RapidBinLayout {
+    /// The layout used by RAPID.
+    pub const DEFAULT: Self = Self {
+        thread_bits: 10,
+        op_bits: 4,
+        decor_bits: 34,
+        location_bits: 15,
+    };
+
+    /// A layout for traces with more distinct locations than [`RapidBinLayout::DEFAULT`]
+    /// can represent.
+    ///
+    /// The decoration is narrowed to 32 bits, which still covers all lock and
+    /// variable IDs since their counts are stored as `i32` in the header.
+    pub const WIDE_LOCATIONS: Self = Self {
+        thread_bits: 10,
+        op_bits: 4,
+        decor_bits: 32,
+        location_bits: 18,
+    };
+
+    /// Creates a new layout from the bit widths of the individual fields.
+    ///
+    /// The fields may occupy fewer than 64 bits, just like [`RapidBinLayout::DEFAULT`],
+    /// which leaves the sign bit unused. Unused bits are the most significant bits of
+    /// an event. They are always written as zero and ignored when an event is unpacked.
+    ///
+    /// # Errors
+    ///
+    /// Fails if any field is empty, if the operation field cannot represent all
+    /// operations, or if the fields do not fit into 64 bits.
+    pub fn new(
+        thread_bits: u8,
+        op_bits: u8,
+        decor_bits: u8,
+        location_bits: u8,
+    ) -> Result<Self, Error> {
+        ensure!(
+            thread_bits > 0 && decor_bits > 0 && location_bits > 0,
+            "RapidBin fields must be at least one bit wide"
+        );
+        ensure!(
+            op_bits >= Self::DEFAULT.op_bits,
+            "The operation field must be at least {} bits wide",
+            Self::DEFAULT.op_bits
+        );
+
+        let total_bits = u32::from(thread_bits)
+            + u32::from(op_bits)
+            + u32::from(decor_bits)
+            + u32::from(location_bits);
+        ensure!(
+            total_bits <= 64,
+            "RapidBin fields occupy {total_bits} bits but events are only 64 bits wide"
+        );
+
+        Ok(Self {
+            thread_bits,
+            op_bits,
+            decor_bits,
+            location_bits,
+        })
+    }
+
+    /// Returns the number of distinct locations that can be represented.
+    pub fn max_locations(&self) -> u64 {
+        1 << self.location_bits
+    }
+
+    fn op_offset(&self) -> u8 {
+        self.thread_bits
+    }
+
+    fn decor_offset(&self) -> u8 {
+        self.op_offset() + self.op_bits
+    }
+
+    fn location_offset(&self) -> u8 {
+        self.decor_offset() + self.decor_bits
+    }
+
+    fn field(event: u64, offset: u8, bits: u8) -> u64 {
+        (event >> offset) & (u64::MAX >> (64 - bits))
+    }
+
+    fn fits(value: u64, bits: u8) -> bool {
+        bits == 64 || value < (1 << bits)
+    }
+
+    fn to_descriptor(self) -> [u8; LAYOUT_DESCRIPTOR_LEN] {
+        [
+            self.thread_bits,
+            self.op_bits,
+            self.decor_bits,
+            self.location_bits,
+        ]
+    }
+
+    fn from_descriptor(descriptor: [u8; LAYOUT_DESCRIPTOR_LEN]) -> Result<Self, Error> {
+        let [thread_bits, op_bits, decor_bits, location_bits] = descriptor;
+
+        Self::new(thread_bits, op_bits, decor_bits, location_bits)
+    }
+
+    /// Splits an encoded RapidBin event into its bit fields.
+    pub fn unpack(&self, event: i64) -> RawEvent {
+        let event = u64::from_ne_bytes(event.to_ne_bytes());
+
+        RawEvent {
+            thread: Self::field(event, 0, self.thread_bits),
+            op: Self::field(event, self.op_offset(), self.op_bits)
+                .try_into()
+                .expect("Operation field is narrower than 64 bits"),
+            decor: Self::field(event, self.decor_offset(), self.decor_bits),
+            location: Self::field(event, self.location_offset(), self.location_bits),
+        }
+    }
+
+    /// Combines the bit fields into an encoded RapidBin event.
+    ///
+    /// # Errors
+    ///
+    /// Fails if any of the fields does not fit into its bit field.
+    pub fn pack(&self, event: &RawEvent) -> Result<i64, Error> {
+        ensure!(
+            Self::fits(event.thread, self.thread_bits),
+            "Thread-ID {} exceeds the RapidBin bit width",
+            event.thread
+        );
+        ensure!(
+            u64::try_from(event.op).is_ok_and(|op| Self::fits(op, self.op_bits)),
+            "Operation-ID {} exceeds the RapidBin bit width",
+            event.op
+        );
+        ensure!(
+            Self::fits(event.decor, self.decor_bits),
+            "Decoration {} exceeds the RapidBin bit width",
+            event.decor
+        );
+        ensure!(
+            Self::fits(event.location, self.location_bits),
+            "Location-ID {} exceeds the RapidBin bit width",
+            event.location
+        );
+
+        let packed = event.thread
+            | (event.op.unsigned_abs() << self.op_offset())
+            | (event.decor << self.decor_offset())
+            | (event.location << self.location_offset());
+
+        Ok(i64::from_ne_bytes(packed.to_ne_bytes()))
+    }
+}
+
+impl Default for RapidBinLayout {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The header of an execution trace in RapidBin format.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RapidBinHeader {
+    pub n_threads: i16,
+    pub n_locks: i32,
+    pub n_variables: i32,
+    pub n_events: i64,
+    pub layout: RapidBinLayout,
+}
+
+/// An event in RapidBin format that has been split into its bit fields.
+///
+/// Contrary to [`crate::generic::Event`], the fields are not validated or
+/// interpreted in any way. This allows to copy events between RapidBin traces
+/// without altering their binary representation.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RawEvent {
+    pub thread: u64,
+    pub op: i64,
+    pub decor: u64,
+    pub location: u64,
+}
+
+impl RawEvent {
+    /// Splits an event encoded with the default layout into its bit fields.
+    pub fn unpack(event: i64) -> Self {
+        RapidBinLayout::DEFAULT.unpack(event)
+    }
+
+    /// Combines the bit fields into an event encoded with the default layout.
+    ///
+    /// # Errors
+    ///
+    /// Fails if any of the fields does not fit into its bit field.
+    pub fn pack(&self) -> Result<i64, Error> {
+        RapidBinLayout::DEFAULT.pack(self)
+    }
+}
@@ -3,18 +3,18 @@ use std::{
     io::{Seek, SeekFrom, Write},
 };
 
-use anyhow::Error;
+use anyhow::{Error, ensure};
 
 use crate::{
     generic::{Encoder, Event, EventResult, Operation},
     rapidbin::{
-        DECOR_BIT_OFFSET, DECOR_NUM_BITS, LOC_BIT_OFFSET, LOC_NUM_BITS, OP_BIT_OFFSET, OP_NUM_BITS,
-        THREAD_BIT_OFFSET, THREAD_NUM_BITS,
+        LAYOUT_DESCRIPTOR_FLAG, LAYOUT_DESCRIPTOR_LEN, RapidBinHeader, RapidBinLayout, RawEvent,
     },
 };
 
 /// An encoder to emit execution traces in _RapidBin_ format
 pub struct RapidBinEncoder {
+    pub(super) layout: RapidBinLayout,
     threads: HashSet<i64>,
     locks: HashSet<i64>,
     variables: HashSet<i64>,
@@ -25,13 +25,31 @@ impl RapidBinEncoder {
         std::mem::size_of::<i16>() + 2 * std::mem::size_of::<i32>() + std::mem::size_of::<i64>();
 
     pub fn new() -> Self {
+        Self::with_layout(RapidBinLayout::DEFAULT)
+    }
+
+    /// Creates an encoder that packs events according to the given `layout`.
+    ///
+    /// Unless `layout` is [`RapidBinLayout::DEFAULT`], a layout descriptor is written
+    /// into the header of the trace. Such traces can be read by [`crate::RapidBinParser`]
+    /// but not by RAPID itself.
+    pub fn with_layout(layout: RapidBinLayout) -> Self {
         Self {
+            layout,
             threads: HashSet::new(),
             locks: HashSet::new(),
             variables: HashSet::new(),
         }
     }
 
+    pub(super) fn header_len(layout: &RapidBinLayout) -> usize {
+        if *layout == RapidBinLayout::DEFAULT {
+            Self::HEADER_LEN
+        } else {
+            Self::HEADER_LEN + LAYOUT_DESCRIPTOR_LEN
+        }
+    }
+
     fn get_n_threads(&self) -> Result<i16, Error> {
         let n_threads = i16::try_from(self.threads.len())?;
 
@@ -50,39 +68,136 @@ impl RapidBinEncoder {
         Ok(n_variables)
     }
 
-    fn encode_event(&mut self, event: Event) -> Result<i64, Error> {
-        let (thread_id, operation, location) = event.into_fields();
+    pub(super) fn write_header<W: Write>(
+        &self,
+        output: &mut W,
+        n_events: i64,
+    ) -> Result<(), Error> {
+        Self::write_raw_header(
+            output,
+            &RapidBinHeader {
+                n_threads: self.get_n_threads()?,
+                n_locks: self.get_n_locks()?,
+                n_variables: self.get_n_variables()?,
+                n_events,
+                layout: self.layout,
+            },
+        )
+    }
+
+    fn write_raw_header<W: Write>(output: &mut W, header: &RapidBinHeader) -> Result<(), Error> {
+        let has_descriptor = header.layout != RapidBinLayout::DEFAULT;
+
+        let mut n_threads = header.n_threads;
+        if has_descriptor {
+            n_threads |= LAYOUT_DESCRIPTOR_FLAG;
+        }
+
+        output.write_all(&n_threads.to_be_bytes())?;
+        output.write_all(&header.n_locks.to_be_bytes())?;
+        output.write_all(&header.n_variables.to_be_bytes())?;
+        output.write_all(&header.n_events.to_be_bytes())?;
+
+        if has_descriptor {
+            output.write_all(&header.layout.to_descriptor())?;
+        }
+
+        Ok(())
+    }
+
+    /// Encodes an execution trace into RapidBin format without seeking in the `output`.
+    ///
+    /// The RapidBin header contains counts that are only known after all events
+    /// have been processed. While [`Encoder::encode`] reserves space for the header
+    /// and seeks back to fill it in, this function buffers the encoded events in memory
+    /// and writes them after the header. This makes it suitable for outputs like
+    /// pipes or sockets at the cost of holding the whole encoded trace in memory,
+    /// i.e., [`RapidBinEncoder::EVENT_SIZE_HINT`] bytes per event.
+    ///
+    /// The emitted bytes are identical to those emitted by [`Encoder::encode`].
+    pub fn encode_streaming<W: Write, I: IntoIterator<Item = EventResult>>(
+        &mut self,
+        input: I,
+        mut output: W,
+    ) -> Result<(), Error> {
+        let input = input.into_iter();
+        let mut events = Vec::with_capacity(input.size_hint().0 * Self::EVENT_SIZE_HINT);
+
+        let mut n_events = 0_i64;
+        for event in input {
+            events.extend_from_slice(&self.encode_event(event?)?.to_be_bytes());
+            n_events += 1;
+        }
+
+        self.write_header(&mut output, n_events)?;
+        output.write_all(&events)?;
+
+        Ok(())
+    }
+
+    /// Encodes raw RapidBin events without reassigning any of their IDs.
+    ///
+    /// The given `header` is written as is, followed by the `input` events packed
+    /// according to the layout of the `header`.
+    /// Combined with [`crate::RapidBinParser::parse_raw`], this results in a
+    /// byte-exact copy of the original trace.
+    pub fn encode_raw<W: Write, I: IntoIterator<Item = Result<RawEvent, Error>>>(
+        &mut self,
+        header: &RapidBinHeader,
+        input: I,
+        mut output: W,
+    ) -> Result<(), Error> {
+        Self::write_raw_header(&mut output, header)?;
+
+        let mut n_events = 0_i64;
+        for event in input {
+            output.write_all(&header.layout.pack(&event?)?.to_be_bytes())?;
+            n_events += 1;
+        }
 
-        let tid = i64::from(i16::try_from(thread_id)?) & ((1 << THREAD_NUM_BITS) - 1);
-        let oid = i64::from(operation.id()) & ((1 << OP_NUM_BITS) - 1);
-        let lid = i64::from(i16::try_from(location)?) & ((1 << LOC_NUM_BITS) - 1);
+        ensure!(
+            n_events == header.n_events,
+            "Header specified {} events but {n_events} were encoded",
+            header.n_events
+        );
+
+        Ok(())
+    }
+
+    pub(super) fn encode_event(&mut self, event: Event) -> Result<i64, Error> {
+        let (thread_id, operation, location) = event.into_fields();
 
         let decor = match operation {
             Operation::Aquire { lock: decor }
             | Operation::Request { lock: decor }
             | Operation::Release { lock: decor } => {
-                let decor = i64::try_from(decor)?;
-                self.locks.insert(decor);
+                self.locks.insert(i64::try_from(decor)?);
                 decor
             }
             Operation::Read { memory: decor } | Operation::Write { memory: decor } => {
-                let decor = i64::try_from(decor)?;
-                self.variables.insert(decor);
+                self.variables.insert(i64::try_from(decor)?);
                 decor
             }
             Operation::Fork { tid: decor } | Operation::Join { tid: decor } => {
-                let decor = i64::try_from(decor)?;
-                self.threads.insert(decor);
+                self.threads.insert(i64::try_from(decor)?);
                 decor
             }
-        } & ((1 << DECOR_NUM_BITS) - 1);
-
-        self.threads.insert(tid);
-
-        Ok((tid << THREAD_BIT_OFFSET)
-            | (oid << OP_BIT_OFFSET)
-            | (decor << DECOR_BIT_OFFSET)
-            | (lid << LOC_BIT_OFFSET))
+            Operation::Wait { cond: decor }
+            | Operation::Notify { cond: decor }
+            | Operation::Call { fidx: decor }
+            | Operation::Return { fidx: decor } => decor,
+        };
+
+        let packed = self.layout.pack(&RawEvent {
+            thread: thread_id,
+            op: i64::from(operation.id()),
+            decor,
+            location,
+        })?;
+
+        self.threads.insert(i64::try_from(thread_id)?);
+
+        Ok(packed)
     }
 }
 
@@ -101,7 +216,7 @@ impl Encoder for RapidBinEncoder {
         mut output: W,
     ) -> Result<(), Error> {
         // Reserve empty space for the header information
-        output.write_all(&[0u8; Self::HEADER_LEN])?;
+        output.write_all(&vec![0u8; Self::header_len(&self.layout)])?;
 
         // Write the events of the trace
         let mut n_events = 0_i64;
@@ -112,10 +227,7 @@ impl Encoder for RapidBinEncoder {
 
         // Now we can write the header information
         output.seek(SeekFrom::Start(0))?;
-        output.write_all(&self.get_n_threads()?.to_be_bytes())?;
-        output.write_all(&self.get_n_locks()?.to_be_bytes())?;
-        output.write_all(&self.get_n_variables()?.to_be_bytes())?;
-        output.write_all(&n_events.to_be_bytes())?;
+        self.write_header(&mut output, n_events)?;
 
         Ok(())
     }
@@ -135,7 +247,10 @@ mod tests {
         rand_core::{RngCore, SeedableRng},
     };
 
-    use crate::generic::{Encoder, Event, EventResult, Operation};
+    use crate::{
+        generic::{Encoder, Event, EventResult, Operation},
+        rapidbin::{RapidBinLayout, RawEvent},
+    };
 
     use super::RapidBinEncoder;
 
@@ -214,9 +329,8 @@ mod tests {
         }
     }
 
-    #[test]
-    fn encode_valid_trace() -> Result<(), Error> {
-        let generic_trace: Vec<EventResult> = [
+    fn example_trace() -> Vec<EventResult> {
+        [
             Event::new(0, Operation::Fork { tid: 1 }, 42),
             Event::new(0, Operation::Fork { tid: 2 }, 42),
             Event::new(2, Operation::Fork { tid: 3 }, 123),
@@ -229,11 +343,14 @@ mod tests {
         ]
         .into_iter()
         .map(Ok)
-        .collect();
+        .collect()
+    }
 
+    #[test]
+    fn encode_valid_trace() -> Result<(), Error> {
         let mut buffer = Cursor::new(Vec::new());
         let mut encoder = RapidBinEncoder::new();
-        encoder.encode(generic_trace, &mut buffer)?;
+        encoder.encode(example_trace(), &mut buffer)?;
 
         let encoded_trace = buffer.into_inner();
         let mut binary_trace = Vec::new();
@@ -272,6 +389,73 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn encode_streaming_equals_seeking() -> Result<(), Error> {
+        let mut seeking = Cursor::new(Vec::new());
+        RapidBinEncoder::new().encode(example_trace(), &mut seeking)?;
+
+        let mut streaming = Vec::new();
+        RapidBinEncoder::new().encode_streaming(example_trace(), &mut streaming)?;
+
+        assert_eq!(seeking.into_inner(), streaming);
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::unusual_byte_groupings)]
+    fn encode_custom_layout() -> Result<(), Error> {
+        let layout = RapidBinLayout::new(8, 4, 20, 32)?;
+        let mut buffer = Cursor::new(Vec::new());
+        let mut encoder = RapidBinEncoder::with_layout(layout);
+        encoder.encode(
+            [Ok(Event::new(1, Operation::Write { memory: 200 }, 100_000))],
+            &mut buffer,
+        )?;
+
+        let mut binary_trace = Vec::new();
+        binary_trace.extend((1_i16 | i16::MIN).to_be_bytes());
+        binary_trace.extend(0_i32.to_be_bytes());
+        binary_trace.extend(1_i32.to_be_bytes());
+        binary_trace.extend(1_i64.to_be_bytes());
+        binary_trace.extend([8_u8, 4, 20, 32]);
+        binary_trace.extend(
+            0b00000000000000011000011010100000_00000000000011001000_0011_00000001_i64.to_be_bytes(),
+        );
+
+        assert_eq!(binary_trace, buffer.into_inner());
+
+        Ok(())
+    }
+
+    #[test]
+    fn encode_layout_with_unused_bits() -> Result<(), Error> {
+        // The fields only occupy the lower 40 bits of an event
+        let layout = RapidBinLayout::new(8, 4, 16, 12)?;
+        let event = RawEvent {
+            thread: 0xFF,
+            op: 0xF,
+            decor: 0xFFFF,
+            location: 0xFFF,
+        };
+
+        let packed = layout.pack(&event)?;
+        assert_eq!(packed, (1 << 40) - 1);
+        assert_eq!(layout.unpack(packed), event);
+        // Bits beyond the fields do not leak into the fields
+        assert_eq!(layout.unpack(packed | i64::MIN | (1 << 40)), event);
+
+        Ok(())
+    }
+
+    #[test]
+    fn fail_on_invalid_layout() {
+        RapidBinLayout::new(16, 4, 30, 15).unwrap_err();
+        RapidBinLayout::new(0, 4, 34, 15).unwrap_err();
+        RapidBinLayout::new(10, 3, 34, 15).unwrap_err();
+        RapidBinLayout::new(16, 4, 28, 16).unwrap();
+    }
+
     #[test]
     fn fail_on_invalid_trace() {
         let mut encoder = RapidBinEncoder::new();
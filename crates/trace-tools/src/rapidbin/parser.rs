@@ -1,13 +1,15 @@
-use std::{collections::HashSet, io::Read};
+use std::{
+    collections::HashSet,
+    io::{Read, Seek, SeekFrom},
+};
 
 use anyhow::{Error, bail, ensure};
 
 use crate::generic::{Event, EventResult, Operation, Parser};
 
 use super::{
-    DECOR_BIT_OFFSET, DECOR_MASK, LOC_BIT_OFFSET, LOC_MASK, NUMBER_OF_EVENTS_MASK,
-    NUMBER_OF_LOCKS_MASK, NUMBER_OF_TRHEADS_MASK, NUMBER_OF_VARS_MASK, OP_BIT_OFFSET, OP_MASK,
-    THREAD_BIT_OFFSET, THREAD_MASK,
+    LAYOUT_DESCRIPTOR_FLAG, LAYOUT_DESCRIPTOR_LEN, NUMBER_OF_EVENTS_MASK, NUMBER_OF_LOCKS_MASK,
+    NUMBER_OF_TRHEADS_MASK, NUMBER_OF_VARS_MASK, RapidBinHeader, RapidBinLayout, RawEvent,
 };
 
 /// A parser for execution traces in _RapidBin_ format.
@@ -29,10 +31,23 @@ impl Parser for RapidBinParser {
     type Iter<R: Read> = RapidBinIterator<R>;
 
     fn parse<R: Read>(&mut self, mut input: R) -> Result<Self::Iter<R>, Error> {
-        // Parse header info
+        let header = Self::parse_header(&mut input)?;
+
+        Ok(RapidBinIterator::from_header(input, &header))
+    }
+
+    fn format(&self) -> &'static str {
+        "RapidBin"
+    }
+}
+
+impl RapidBinParser {
+    pub(crate) fn parse_header<R: Read>(input: &mut R) -> Result<RapidBinHeader, Error> {
         let mut n_threads = [0; 2];
         input.read_exact(&mut n_threads)?;
-        let n_threads = NUMBER_OF_TRHEADS_MASK & i16::from_be_bytes(n_threads);
+        let n_threads = i16::from_be_bytes(n_threads);
+        let has_descriptor = n_threads & LAYOUT_DESCRIPTOR_FLAG != 0;
+        let n_threads = NUMBER_OF_TRHEADS_MASK & n_threads;
 
         let mut n_locks = [0; 4];
         input.read_exact(&mut n_locks)?;
@@ -40,48 +55,163 @@ impl Parser for RapidBinParser {
 
         let mut n_vars = [0; 4];
         input.read_exact(&mut n_vars)?;
-        let n_vars = NUMBER_OF_VARS_MASK & i32::from_be_bytes(n_vars);
+        let n_variables = NUMBER_OF_VARS_MASK & i32::from_be_bytes(n_vars);
 
         let mut n_events = [0; 8];
         input.read_exact(&mut n_events)?;
         let n_events = NUMBER_OF_EVENTS_MASK & i64::from_be_bytes(n_events);
 
-        Ok(RapidBinIterator::new(
-            input, n_threads, n_locks, n_vars, n_events,
+        let layout = if has_descriptor {
+            let mut descriptor = [0; LAYOUT_DESCRIPTOR_LEN];
+            input.read_exact(&mut descriptor)?;
+            RapidBinLayout::from_descriptor(descriptor)?
+        } else {
+            RapidBinLayout::DEFAULT
+        };
+
+        Ok(RapidBinHeader {
+            n_threads,
+            n_locks,
+            n_variables,
+            n_events,
+            layout,
+        })
+    }
+
+    /// Parses an execution trace in RapidBin format without interpreting its events.
+    ///
+    /// The returned iterator yields the bit fields of each event as they are stored
+    /// in the trace. Together with [`crate::RapidBinEncoder::encode_raw`], this
+    /// allows to copy RapidBin traces without reassigning any of their IDs.
+    pub fn parse_raw<R: Read>(
+        &mut self,
+        mut input: R,
+    ) -> Result<(RapidBinHeader, RapidBinRawIterator<R>), Error> {
+        let header = Self::parse_header(&mut input)?;
+
+        Ok((
+            header,
+            RapidBinRawIterator {
+                input,
+                layout: header.layout,
+                n_events: header.n_events,
+                event_counter: 0,
+            },
         ))
     }
 
-    fn format(&self) -> &'static str {
-        "RapidBin"
+    /// Parses an execution trace in RapidBin format, beginning at the event with index `first_event`.
+    ///
+    /// All preceding events are skipped by seeking in `input`. Since these events are never
+    /// read, the returned iterator can not verify the numbers of threads, locks and variables
+    /// specified in the header. Only the number of events is verified.
+    pub fn parse_seekable<R: Read + Seek>(
+        &mut self,
+        mut input: R,
+        first_event: u64,
+    ) -> Result<RapidBinIterator<R>, Error> {
+        let header = Self::parse_header(&mut input)?;
+        ensure!(
+            first_event <= header.n_events.unsigned_abs(),
+            "Trace contains only {} events, can not skip to event {first_event}",
+            header.n_events
+        );
+        input.seek(SeekFrom::Current(i64::try_from(first_event * 8)?))?;
+
+        let mut iter = RapidBinIterator::from_header(input, &header);
+        iter.event_counter = i64::try_from(first_event)?;
+        iter.check_ids = false;
+        Ok(iter)
+    }
+
+    /// Parses an execution trace in RapidBin format while reporting the parsing progress.
+    ///
+    /// The `callback` is invoked with `(events_read, total_events)` every `interval` events
+    /// as well as once after the last event has been read. The total number of events is
+    /// taken from the header of the trace.
+    pub fn parse_with_progress<R: Read, F: FnMut(u64, u64)>(
+        &mut self,
+        input: R,
+        interval: u64,
+        callback: F,
+    ) -> Result<RapidBinProgressIterator<R, F>, Error> {
+        Ok(self.parse(input)?.with_progress(interval, callback))
     }
 }
 
 pub struct RapidBinIterator<R: Read> {
     input: R,
+    layout: RapidBinLayout,
     n_threads: i16,
     n_locks: i32,
     n_variables: i32,
     n_events: i64,
     buffer: [u8; 8],
     event_counter: i64,
-    threads: HashSet<u64>,
-    locks: HashSet<u64>,
-    variables: HashSet<u64>,
+    ids: TraceIds,
+    check_ids: bool,
 }
 
 impl<R: Read> RapidBinIterator<R> {
+    #[cfg(test)]
     fn new(input: R, n_threads: i16, n_locks: i32, n_variables: i32, n_events: i64) -> Self {
+        Self::from_header(
+            input,
+            &RapidBinHeader {
+                n_threads,
+                n_locks,
+                n_variables,
+                n_events,
+                layout: RapidBinLayout::DEFAULT,
+            },
+        )
+    }
+
+    fn from_header(input: R, header: &RapidBinHeader) -> Self {
+        let RapidBinHeader {
+            n_threads,
+            n_locks,
+            n_variables,
+            n_events,
+            layout,
+        } = *header;
+
         Self {
             input,
+            layout,
             n_threads,
             n_locks,
             n_variables,
             n_events,
             buffer: [0; 8],
             event_counter: 0,
-            threads: HashSet::new(),
-            locks: HashSet::new(),
-            variables: HashSet::new(),
+            ids: TraceIds::default(),
+            check_ids: true,
+        }
+    }
+
+    /// Returns the number of events as specified in the header of the trace.
+    pub fn n_events(&self) -> u64 {
+        self.n_events.unsigned_abs()
+    }
+
+    /// Returns the number of events that have been read so far.
+    pub fn events_read(&self) -> u64 {
+        self.event_counter.unsigned_abs()
+    }
+
+    /// Wraps this iterator such that `callback` is invoked with `(events_read, total_events)`
+    /// every `interval` events and once after the last event has been read.
+    pub fn with_progress<F: FnMut(u64, u64)>(
+        self,
+        interval: u64,
+        callback: F,
+    ) -> RapidBinProgressIterator<R, F> {
+        RapidBinProgressIterator {
+            inner: self,
+            interval: interval.max(1),
+            callback,
+            finished: false,
         }
     }
 
@@ -90,9 +220,10 @@ impl<R: Read> RapidBinIterator<R> {
             match e.kind() {
                 std::io::ErrorKind::UnexpectedEof => {
                     if self.event_counter == self.n_events
-                        && u64::try_from(self.threads.len())? == u64::try_from(self.n_threads)?
-                        && u64::try_from(self.locks.len())? == u64::try_from(self.n_locks)?
-                        && u64::try_from(self.variables.len())? == u64::try_from(self.n_variables)?
+                        && (!self.check_ids
+                            || self
+                                .ids
+                                .matches(self.n_threads, self.n_locks, self.n_variables)?)
                     {
                         return Ok(None);
                     } else {
@@ -103,15 +234,53 @@ impl<R: Read> RapidBinIterator<R> {
             }
         }
 
-        let event_integer = i64::from_be_bytes(self.buffer);
-        let t = u64::try_from((event_integer & THREAD_MASK) >> THREAD_BIT_OFFSET)?;
-        let op = (event_integer & OP_MASK) >> OP_BIT_OFFSET;
-        let decor = u64::try_from((event_integer & DECOR_MASK) >> DECOR_BIT_OFFSET)?;
-        let operation = Operation::try_from_id(op, decor)?;
-        let loc = u64::try_from((event_integer & LOC_MASK) >> LOC_BIT_OFFSET)?;
+        let event = decode_event(self.layout, self.buffer)?;
+        self.ids.record(&event);
+
+        self.event_counter += 1;
+
+        if self.check_ids {
+            self.ids
+                .ensure_within(self.n_threads, self.n_locks, self.n_variables)?;
+        }
+        ensure!(
+            self.event_counter <= self.n_events,
+            "Found more events than specified!"
+        );
+
+        Ok(Some(event))
+    }
+}
+
+/// Decodes a single event of a RapidBin trace.
+pub(crate) fn decode_event(layout: RapidBinLayout, bytes: [u8; 8]) -> Result<Event, Error> {
+    let RawEvent {
+        thread: t,
+        op,
+        decor,
+        location: loc,
+    } = layout.unpack(i64::from_be_bytes(bytes));
+    let operation = Operation::try_from_id(op, decor)?;
+
+    Ok(Event::new(t, operation, loc))
+}
 
-        self.threads.insert(t);
-        match operation {
+/// The distinct threads, locks and variables seen in a RapidBin trace.
+///
+/// These are compared against the counts specified in the header of the trace.
+#[derive(Default)]
+pub(crate) struct TraceIds {
+    threads: HashSet<u64>,
+    locks: HashSet<u64>,
+    variables: HashSet<u64>,
+}
+
+impl TraceIds {
+    pub(crate) fn record(&mut self, event: &Event) {
+        let (t, operation, _) = event.get_fields();
+
+        self.threads.insert(*t);
+        match *operation {
             Operation::Aquire { lock: decor }
             | Operation::Request { lock: decor }
             | Operation::Release { lock: decor } => {
@@ -123,50 +292,153 @@ impl<R: Read> RapidBinIterator<R> {
             Operation::Fork { tid: decor } | Operation::Join { tid: decor } => {
                 self.threads.insert(decor);
             }
+            Operation::Wait { cond: _ }
+            | Operation::Notify { cond: _ }
+            | Operation::Call { fidx: _ }
+            | Operation::Return { fidx: _ } => {}
         }
+    }
 
-        let event = Event::new(t, operation, loc);
+    #[cfg(feature = "parallel")]
+    pub(crate) fn merge(&mut self, other: TraceIds) {
+        self.threads.extend(other.threads);
+        self.locks.extend(other.locks);
+        self.variables.extend(other.variables);
+    }
 
-        self.event_counter += 1;
+    /// Returns whether exactly the given numbers of threads, locks and variables have been seen.
+    pub(crate) fn matches(
+        &self,
+        n_threads: i16,
+        n_locks: i32,
+        n_variables: i32,
+    ) -> Result<bool, Error> {
+        Ok(
+            u64::try_from(self.threads.len())? == u64::try_from(n_threads)?
+                && u64::try_from(self.locks.len())? == u64::try_from(n_locks)?
+                && u64::try_from(self.variables.len())? == u64::try_from(n_variables)?,
+        )
+    }
 
+    /// Fails if more threads, locks or variables have been seen than given.
+    pub(crate) fn ensure_within(
+        &self,
+        n_threads: i16,
+        n_locks: i32,
+        n_variables: i32,
+    ) -> Result<(), Error> {
         ensure!(
-            u64::try_from(self.threads.len())? <= u64::try_from(self.n_threads)?,
+            u64::try_from(self.threads.len())? <= u64::try_from(n_threads)?,
             "Found more threads than specified!"
         );
         ensure!(
-            u64::try_from(self.locks.len())? <= u64::try_from(self.n_locks)?,
+            u64::try_from(self.locks.len())? <= u64::try_from(n_locks)?,
             "Found more locks than specified!"
         );
         ensure!(
-            u64::try_from(self.variables.len())? <= u64::try_from(self.n_variables)?,
+            u64::try_from(self.variables.len())? <= u64::try_from(n_variables)?,
             "Found more variables than specified!"
         );
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for RapidBinIterator<R> {
+    type Item = EventResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner_next().transpose()
+    }
+}
+
+/// An iterator over the raw events of a trace in RapidBin format.
+pub struct RapidBinRawIterator<R: Read> {
+    input: R,
+    layout: RapidBinLayout,
+    n_events: i64,
+    event_counter: i64,
+}
+
+impl<R: Read> RapidBinRawIterator<R> {
+    fn inner_next(&mut self) -> Result<Option<RawEvent>, Error> {
+        let mut buffer = [0; 8];
+        if let Err(e) = self.input.read_exact(&mut buffer) {
+            match e.kind() {
+                std::io::ErrorKind::UnexpectedEof if self.event_counter == self.n_events => {
+                    return Ok(None);
+                }
+                _ => bail!(e),
+            }
+        }
+
+        self.event_counter += 1;
         ensure!(
             self.event_counter <= self.n_events,
             "Found more events than specified!"
         );
 
-        Ok(Some(event))
+        Ok(Some(self.layout.unpack(i64::from_be_bytes(buffer))))
     }
 }
 
-impl<R: Read> Iterator for RapidBinIterator<R> {
-    type Item = EventResult;
+impl<R: Read> Iterator for RapidBinRawIterator<R> {
+    type Item = Result<RawEvent, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
         self.inner_next().transpose()
     }
 }
 
+/// A [`RapidBinIterator`] that reports its progress to a callback.
+pub struct RapidBinProgressIterator<R: Read, F: FnMut(u64, u64)> {
+    inner: RapidBinIterator<R>,
+    interval: u64,
+    callback: F,
+    finished: bool,
+}
+
+impl<R: Read, F: FnMut(u64, u64)> Iterator for RapidBinProgressIterator<R, F> {
+    type Item = EventResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.inner.next();
+
+        match &item {
+            Some(Ok(_)) => {
+                let events_read = self.inner.events_read();
+                if events_read.is_multiple_of(self.interval) {
+                    (self.callback)(events_read, self.inner.n_events());
+                }
+            }
+            None if !self.finished => {
+                self.finished = true;
+                let events_read = self.inner.events_read();
+                if !events_read.is_multiple_of(self.interval) {
+                    (self.callback)(events_read, self.inner.n_events());
+                }
+            }
+            _ => (),
+        }
+
+        item
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::io::Cursor;
+
     use anyhow::Error;
 
     use super::{RapidBinIterator, RapidBinParser};
-    use crate::generic::{Event, Operation, Parser};
+    use crate::{
+        RapidBinEncoder,
+        generic::{Event, Operation, Parser},
+        rapidbin::RapidBinLayout,
+    };
 
-    #[test]
-    fn parse_valid_trace() -> Result<(), Error> {
+    fn example_binary_trace() -> Vec<u8> {
         let mut binary_trace = Vec::new();
         binary_trace.extend(4_i16.to_be_bytes());
         binary_trace.extend(1_i32.to_be_bytes());
@@ -198,6 +470,13 @@ mod tests {
             .concat(),
         );
 
+        binary_trace
+    }
+
+    #[test]
+    fn parse_valid_trace() -> Result<(), Error> {
+        let binary_trace = example_binary_trace();
+
         let mut parser = RapidBinParser::new();
         let parsed_trace: Result<Vec<Event>, Error> =
             parser.parse(binary_trace.as_slice())?.collect();
@@ -218,6 +497,102 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn raw_roundtrip() -> Result<(), Error> {
+        let binary_trace = example_binary_trace();
+
+        let mut parser = RapidBinParser::new();
+        let (header, events) = parser.parse_raw(binary_trace.as_slice())?;
+
+        let mut output = Vec::new();
+        RapidBinEncoder::new().encode_raw(&header, events, &mut output)?;
+
+        assert_eq!(binary_trace, output);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_custom_layout() -> Result<(), Error> {
+        let generic_trace = || {
+            vec![
+                Event::new(0, Operation::Fork { tid: 1 }, 40_000),
+                Event::new(1, Operation::Aquire { lock: 3 }, 70_000),
+                Event::new(1, Operation::Write { memory: 200 }, 1_000_000),
+                Event::new(1, Operation::Release { lock: 3 }, 70_000),
+                Event::new(0, Operation::Join { tid: 1 }, 40_000),
+            ]
+        };
+
+        let layout = RapidBinLayout::new(8, 4, 20, 32)?;
+        let mut binary_trace = Vec::new();
+        RapidBinEncoder::with_layout(layout)
+            .encode_streaming(generic_trace().into_iter().map(Ok), &mut binary_trace)?;
+
+        let (header, _) = RapidBinParser::new().parse_raw(binary_trace.as_slice())?;
+        assert_eq!(header.layout, layout);
+        assert_eq!(header.n_threads, 2);
+
+        let parsed_trace: Result<Vec<Event>, Error> = RapidBinParser::new()
+            .parse(binary_trace.as_slice())?
+            .collect();
+
+        assert_eq!(generic_trace(), parsed_trace?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_from_event_offset() -> Result<(), Error> {
+        let binary_trace = example_binary_trace();
+        let full: Vec<Event> = RapidBinParser::new()
+            .parse(binary_trace.as_slice())?
+            .collect::<Result<_, Error>>()?;
+
+        for first_event in 0..=full.len() {
+            let parsed: Vec<Event> = RapidBinParser::new()
+                .parse_seekable(Cursor::new(&binary_trace), first_event as u64)?
+                .collect::<Result<_, Error>>()?;
+            assert_eq!(parsed, full[first_event..]);
+        }
+
+        RapidBinParser::new()
+            .parse_seekable(Cursor::new(&binary_trace), full.len() as u64 + 1)
+            .err()
+            .expect("Skipped beyond the last event");
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::unusual_byte_groupings)]
+    fn report_parsing_progress() -> Result<(), Error> {
+        let event = 0b0_000000000101010_0000000000000000000000000000000001_0100_0000000000__i64
+            .to_be_bytes();
+        let mut binary_trace = Vec::new();
+        binary_trace.extend(2_i16.to_be_bytes());
+        binary_trace.extend(0_i32.to_be_bytes());
+        binary_trace.extend(0_i32.to_be_bytes());
+        binary_trace.extend(5_i64.to_be_bytes());
+        for _ in 0..5 {
+            binary_trace.extend(event);
+        }
+
+        let mut progress = Vec::new();
+        let mut parser = RapidBinParser::new();
+        let n_parsed = parser
+            .parse_with_progress(binary_trace.as_slice(), 2, |read, total| {
+                progress.push((read, total))
+            })?
+            .collect::<Result<Vec<Event>, Error>>()?
+            .len();
+
+        assert_eq!(n_parsed, 5);
+        assert_eq!(progress, vec![(2, 5), (4, 5), (5, 5)]);
+
+        Ok(())
+    }
+
     #[test]
     #[allow(clippy::unusual_byte_groupings)]
     fn parse_valid_event() -> Result<(), Error> {
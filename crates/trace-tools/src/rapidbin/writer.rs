@@ -0,0 +1,253 @@
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use anyhow::{Error, ensure};
+
+use crate::{
+    generic::{Encoder, Event},
+    rapidbin::{RapidBinLayout, encoder::RapidBinEncoder, parser::decode_event},
+};
+
+/// Writes an execution trace in RapidBin format to a file event by event.
+///
+/// In contrast to [`RapidBinEncoder`], the events do not have to be known upfront:
+/// each appended event is packed and written right away, so the trace is never held
+/// in memory. Space for the header is reserved when the file is created and the
+/// header is filled in by [`RapidBinFileWriter::finish`].
+///
+/// Appended events can be invalidated until the trace is finished. Invalidated
+/// events are removed from the file and do not contribute to the counts of the header.
+pub struct RapidBinFileWriter {
+    encoder: RapidBinEncoder,
+    output: BufWriter<File>,
+    n_events: u64,
+    invalid: BTreeSet<u64>,
+}
+
+impl RapidBinFileWriter {
+    /// The number of events that are moved at once when invalidated events are removed.
+    const COMPACTION_CHUNK_EVENTS: u64 = 1 << 16;
+
+    /// Creates (or truncates) the file at `path` to write a trace with the default layout.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::create_with_layout(path, RapidBinLayout::DEFAULT)
+    }
+
+    /// Creates (or truncates) the file at `path` to write a trace with the given `layout`.
+    ///
+    /// See [`RapidBinEncoder::with_layout`].
+    pub fn create_with_layout<P: AsRef<Path>>(
+        path: P,
+        layout: RapidBinLayout,
+    ) -> Result<Self, Error> {
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        let mut output = BufWriter::new(file);
+
+        // Reserve empty space for the header information
+        output.write_all(&vec![0u8; RapidBinEncoder::header_len(&layout)])?;
+
+        Ok(Self {
+            encoder: RapidBinEncoder::with_layout(layout),
+            output,
+            n_events: 0,
+            invalid: BTreeSet::new(),
+        })
+    }
+
+    /// Appends `event` to the trace and returns its index.
+    ///
+    /// Fails if the event can not be represented by the layout of the trace.
+    pub fn append(&mut self, event: Event) -> Result<u64, Error> {
+        let packed = self.encoder.encode_event(event)?;
+        self.output.write_all(&packed.to_be_bytes())?;
+        self.n_events += 1;
+
+        Ok(self.n_events - 1)
+    }
+
+    /// Removes the event with the given `index` from the trace once it is finished.
+    ///
+    /// Fails if no event has been appended with this index or if it has already
+    /// been invalidated.
+    pub fn invalidate(&mut self, index: u64) -> Result<(), Error> {
+        ensure!(
+            index < self.n_events,
+            "Tried to invalidate event {index}, but only {} events have been appended",
+            self.n_events
+        );
+        ensure!(
+            self.invalid.insert(index),
+            "Event {index} was already invalidated once"
+        );
+
+        Ok(())
+    }
+
+    /// Returns the number of events that have been appended, including invalidated ones.
+    pub fn n_events(&self) -> u64 {
+        self.n_events
+    }
+
+    /// Removes all invalidated events and writes the header of the trace.
+    ///
+    /// Returns the number of events in the finished trace.
+    pub fn finish(self) -> Result<u64, Error> {
+        let Self {
+            mut encoder,
+            output,
+            n_events,
+            invalid,
+        } = self;
+        let mut file = output.into_inner().map_err(|e| e.into_error())?;
+
+        if !invalid.is_empty() {
+            // The IDs of invalidated events must not be counted in the header
+            encoder = Self::compact(&mut file, encoder.layout, n_events, &invalid)?;
+        }
+
+        let n_events = n_events - u64::try_from(invalid.len())?;
+        file.seek(SeekFrom::Start(0))?;
+        encoder.write_header(&mut file, i64::try_from(n_events)?)?;
+        file.flush()?;
+
+        Ok(n_events)
+    }
+
+    /// Moves all valid events to the front of the trace and truncates the file after them.
+    ///
+    /// Returns an encoder that has seen all valid events.
+    fn compact(
+        file: &mut File,
+        layout: RapidBinLayout,
+        n_events: u64,
+        invalid: &BTreeSet<u64>,
+    ) -> Result<RapidBinEncoder, Error> {
+        let mut encoder = RapidBinEncoder::with_layout(layout);
+        let header_len = u64::try_from(RapidBinEncoder::header_len(&layout))?;
+        let event_len = u64::try_from(RapidBinEncoder::EVENT_SIZE_HINT)?;
+
+        let mut chunk = Vec::new();
+        let mut kept = Vec::new();
+        let mut write_offset = header_len;
+        let mut first = 0;
+        while first < n_events {
+            let len = Self::COMPACTION_CHUNK_EVENTS.min(n_events - first);
+            chunk.resize(usize::try_from(len * event_len)?, 0);
+            file.seek(SeekFrom::Start(header_len + first * event_len))?;
+            file.read_exact(&mut chunk)?;
+
+            kept.clear();
+            for (index, bytes) in (first..).zip(chunk.chunks_exact(8)) {
+                if invalid.contains(&index) {
+                    continue;
+                }
+
+                let bytes: [u8; 8] = bytes.try_into()?;
+                encoder.encode_event(decode_event(layout, bytes)?)?;
+                kept.extend_from_slice(&bytes);
+            }
+
+            // Valid events never move behind their original position,
+            // so no event is overwritten before it has been read.
+            file.seek(SeekFrom::Start(write_offset))?;
+            file.write_all(&kept)?;
+            write_offset += u64::try_from(kept.len())?;
+            first += len;
+        }
+
+        file.set_len(write_offset)?;
+
+        Ok(encoder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, io::Cursor};
+
+    use anyhow::Error;
+    use tempfile::tempdir;
+
+    use crate::{
+        RapidBinEncoder, RapidBinParser,
+        generic::{Encoder, Event, Operation, Parser},
+    };
+
+    use super::RapidBinFileWriter;
+
+    fn example_events() -> Vec<Event> {
+        vec![
+            Event::new(0, Operation::Fork { tid: 1 }, 1),
+            Event::new(1, Operation::Request { lock: 0 }, 2),
+            Event::new(1, Operation::Aquire { lock: 0 }, 2),
+            Event::new(1, Operation::Write { memory: 0 }, 3),
+            Event::new(1, Operation::Release { lock: 0 }, 4),
+            Event::new(0, Operation::Request { lock: 1 }, 5),
+            Event::new(0, Operation::Read { memory: 1 }, 6),
+            Event::new(0, Operation::Join { tid: 1 }, 7),
+        ]
+    }
+
+    #[test]
+    fn write_like_encoder() -> Result<(), Error> {
+        let tmp = tempdir()?;
+        let path = tmp.path().join("trace.data");
+
+        let mut writer = RapidBinFileWriter::create(&path)?;
+        for event in example_events() {
+            writer.append(event)?;
+        }
+        assert_eq!(writer.finish()?, 8);
+
+        let mut encoded = Cursor::new(Vec::new());
+        RapidBinEncoder::new().encode(example_events().into_iter().map(Ok), &mut encoded)?;
+        assert_eq!(std::fs::read(&path)?, encoded.into_inner());
+
+        Ok(())
+    }
+
+    #[test]
+    fn remove_invalidated_events() -> Result<(), Error> {
+        let tmp = tempdir()?;
+        let path = tmp.path().join("trace.data");
+
+        let mut writer = RapidBinFileWriter::create(&path)?;
+        let mut indices = Vec::new();
+        for event in example_events() {
+            indices.push(writer.append(event)?);
+        }
+        // The request of lock 1 is the only event with that lock
+        writer.invalidate(indices[1])?;
+        writer.invalidate(indices[5])?;
+        assert!(writer.invalidate(indices[5]).is_err());
+        assert!(writer.invalidate(8).is_err());
+        assert_eq!(writer.n_events(), 8);
+        assert_eq!(writer.finish()?, 6);
+
+        let expected = example_events()
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| ![1, 5].contains(index))
+            .map(|(_, event)| event)
+            .collect::<Vec<_>>();
+        let parsed = RapidBinParser::new()
+            .parse(File::open(&path)?)?
+            .collect::<Result<Vec<_>, Error>>()?;
+        assert_eq!(parsed, expected);
+
+        let mut encoded = Cursor::new(Vec::new());
+        RapidBinEncoder::new().encode(expected.into_iter().map(Ok), &mut encoded)?;
+        assert_eq!(std::fs::read(&path)?, encoded.into_inner());
+
+        Ok(())
+    }
+}
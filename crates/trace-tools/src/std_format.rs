@@ -1,28 +1,93 @@
-use crate::generic::{Encoder, Event, EventResult, Operation};
-use std::io::{Seek, Write};
+use crate::generic::{Encoder, Event, EventResult, Operation, Parser};
+use anyhow::{Error, anyhow, bail};
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Lines, Read, Seek, Write},
+};
 
 /// An encoder to emit execution traces in _STD_ format
-pub struct StdFormatEncoder;
+pub struct StdFormatEncoder {
+    location_names: HashMap<u64, String>,
+    lock_names: HashMap<u64, String>,
+    timestamps: Vec<u64>,
+}
 
 impl StdFormatEncoder {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            location_names: HashMap::new(),
+            lock_names: HashMap::new(),
+            timestamps: Vec::new(),
+        }
+    }
+
+    /// Emits the given names, e.g., `file:line`, instead of the numeric locations.
+    ///
+    /// Locations without a name are still emitted as numbers. Note that traces
+    /// containing location names can not be parsed by [`StdFormatParser`] anymore.
+    pub fn with_location_names(location_names: HashMap<u64, String>) -> Self {
+        Self {
+            location_names,
+            lock_names: HashMap::new(),
+            timestamps: Vec::new(),
+        }
+    }
+
+    /// Emits the given names, e.g., of the statics holding the locks, instead of `L<id>`.
+    ///
+    /// Locks without a name are still emitted by their ID. Like location names, lock
+    /// names can not be parsed by [`StdFormatParser`].
+    pub fn with_lock_names(mut self, lock_names: HashMap<u64, String>) -> Self {
+        self.lock_names = lock_names;
+        self
+    }
+
+    /// Appends `@<timestamp>` to each line, taking the timestamps in the order of the events.
+    ///
+    /// Events beyond the end of `timestamps` are emitted without a timestamp.
+    /// [`StdFormatParser`] ignores the timestamps.
+    pub fn with_timestamps(mut self, timestamps: Vec<u64>) -> Self {
+        self.timestamps = timestamps;
+        self
     }
 
-    fn encode_event(&self, event: Event) -> String {
+    pub(crate) fn encode_event(&self, event: Event) -> String {
         let (thread_id, operation, location) = event.into_fields();
 
         let op_and_decor = match operation {
-            Operation::Aquire { lock } => format!("acq(L{})", lock),
-            Operation::Release { lock } => format!("rel(L{})", lock),
+            Operation::Aquire { lock } => format!("acq({})", self.lock_name(lock)),
+            Operation::Release { lock } => format!("rel({})", self.lock_name(lock)),
             Operation::Read { memory } => format!("r(V{})", memory),
             Operation::Write { memory } => format!("w(V{})", memory),
             Operation::Fork { tid } => format!("fork(T{})", tid),
             Operation::Join { tid } => format!("join(T{})", tid),
-            Operation::Request { lock } => format!("req(L{})", lock),
+            Operation::Request { lock } => format!("req({})", self.lock_name(lock)),
+            Operation::Wait { cond } => format!("wait(C{})", cond),
+            Operation::Notify { cond } => format!("notify(C{})", cond),
+            Operation::Call { fidx } => format!("call(F{})", fidx),
+            Operation::Return { fidx } => format!("ret(F{})", fidx),
         };
 
-        format!("T{}|{}|{}", thread_id, op_and_decor, location)
+        match self.location_names.get(&location) {
+            Some(name) => format!("T{}|{}|{}", thread_id, op_and_decor, name),
+            None => format!("T{}|{}|{}", thread_id, op_and_decor, location),
+        }
+    }
+
+    fn lock_name(&self, lock: u64) -> String {
+        match self.lock_names.get(&lock) {
+            Some(name) => name.clone(),
+            None => format!("L{}", lock),
+        }
+    }
+
+    /// Encodes the event at position `index` of the trace, including its timestamp.
+    pub(crate) fn encode_line(&self, index: usize, event: Event) -> String {
+        let line = self.encode_event(event);
+        match self.timestamps.get(index) {
+            Some(timestamp) => format!("{line}@{timestamp}"),
+            None => line,
+        }
     }
 }
 
@@ -40,8 +105,8 @@ impl Encoder for StdFormatEncoder {
         input: I,
         mut output: W,
     ) -> Result<(), anyhow::Error> {
-        for event in input {
-            writeln!(output, "{}", self.encode_event(event?))?
+        for (idx, event) in input.into_iter().enumerate() {
+            writeln!(output, "{}", self.encode_line(idx, event?))?;
         }
 
         Ok(())
@@ -52,19 +117,156 @@ impl Encoder for StdFormatEncoder {
     }
 }
 
+/// A parser for execution traces in _STD_ format
+pub struct StdFormatParser;
+
+impl StdFormatParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Default for StdFormatParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser for StdFormatParser {
+    type Iter<R: Read> = StdFormatIterator<R>;
+
+    fn parse<R: Read>(&mut self, input: R) -> Result<Self::Iter<R>, Error> {
+        Ok(StdFormatIterator {
+            lines: BufReader::new(input).lines(),
+            line_number: 0,
+        })
+    }
+
+    fn format(&self) -> &'static str {
+        "STD"
+    }
+}
+
+pub struct StdFormatIterator<R: Read> {
+    lines: Lines<BufReader<R>>,
+    line_number: usize,
+}
+
+impl<R: Read> StdFormatIterator<R> {
+    fn parse_id(value: &str, prefix: char) -> Result<u64, Error> {
+        value
+            .trim()
+            .strip_prefix(prefix)
+            .ok_or_else(|| anyhow!("Expected identifier with prefix '{prefix}', found '{value}'"))?
+            .trim()
+            .parse()
+            .map_err(|e| anyhow!("Invalid identifier '{value}': {e}"))
+    }
+
+    fn parse_operation(value: &str) -> Result<Operation, Error> {
+        let (name, decor) = value
+            .trim()
+            .strip_suffix(')')
+            .and_then(|op| op.split_once('('))
+            .ok_or_else(|| anyhow!("Malformed operation '{value}'"))?;
+
+        let operation = match name.trim() {
+            "acq" => Operation::Aquire {
+                lock: Self::parse_id(decor, 'L')?,
+            },
+            "rel" => Operation::Release {
+                lock: Self::parse_id(decor, 'L')?,
+            },
+            "req" => Operation::Request {
+                lock: Self::parse_id(decor, 'L')?,
+            },
+            "r" => Operation::Read {
+                memory: Self::parse_id(decor, 'V')?,
+            },
+            "w" => Operation::Write {
+                memory: Self::parse_id(decor, 'V')?,
+            },
+            "fork" => Operation::Fork {
+                tid: Self::parse_id(decor, 'T')?,
+            },
+            "join" => Operation::Join {
+                tid: Self::parse_id(decor, 'T')?,
+            },
+            "wait" => Operation::Wait {
+                cond: Self::parse_id(decor, 'C')?,
+            },
+            "notify" => Operation::Notify {
+                cond: Self::parse_id(decor, 'C')?,
+            },
+            "call" => Operation::Call {
+                fidx: Self::parse_id(decor, 'F')?,
+            },
+            "ret" => Operation::Return {
+                fidx: Self::parse_id(decor, 'F')?,
+            },
+            name => bail!("Unknown operation '{name}'"),
+        };
+
+        Ok(operation)
+    }
+
+    fn parse_event(line: &str) -> Result<Event, Error> {
+        let mut fields = line.split('|');
+        let (Some(thread), Some(operation), Some(location), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            bail!("Expected three fields separated by '|'");
+        };
+
+        let thread_id = Self::parse_id(thread, 'T')?;
+        let operation = Self::parse_operation(operation)?;
+        let location = location
+            .split_once('@')
+            .map_or(location, |(location, _timestamp)| location)
+            .trim()
+            .parse()
+            .map_err(|e| anyhow!("Invalid location '{location}': {e}"))?;
+
+        Ok(Event::new(thread_id, operation, location))
+    }
+
+    fn inner_next(&mut self) -> Result<Option<Event>, Error> {
+        for line in self.lines.by_ref() {
+            self.line_number += 1;
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            return Self::parse_event(&line)
+                .map(Some)
+                .map_err(|e| anyhow!("Line {}: {e}", self.line_number));
+        }
+
+        Ok(None)
+    }
+}
+
+impl<R: Read> Iterator for StdFormatIterator<R> {
+    type Item = EventResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner_next().transpose()
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::io::Cursor;
+    use std::{collections::HashMap, io::Cursor};
 
     use anyhow::Error;
 
-    use crate::generic::{Encoder, Event, EventResult, Operation};
+    use crate::generic::{Encoder, Event, EventResult, Operation, Parser};
 
-    use super::StdFormatEncoder;
+    use super::{StdFormatEncoder, StdFormatParser};
 
-    #[test]
-    fn encode_valid_trace() -> Result<(), Error> {
-        let generic_trace: Vec<EventResult> = vec![
+    fn example_trace() -> Vec<Event> {
+        vec![
             Event::new(0, Operation::Fork { tid: 1 }, 42),
             Event::new(0, Operation::Fork { tid: 2 }, 42),
             Event::new(2, Operation::Fork { tid: 3 }, 123),
@@ -73,11 +275,15 @@ mod tests {
             Event::new(0, Operation::Read { memory: 200 }, 436),
             Event::new(0, Operation::Write { memory: 200 }, 923),
             Event::new(0, Operation::Release { lock: 0 }, 362),
+            Event::new(0, Operation::Notify { cond: 5 }, 512),
+            Event::new(1, Operation::Wait { cond: 5 }, 640),
             Event::new(0, Operation::Join { tid: 1 }, 7382),
         ]
-        .into_iter()
-        .map(Ok)
-        .collect();
+    }
+
+    #[test]
+    fn encode_valid_trace() -> Result<(), Error> {
+        let generic_trace: Vec<EventResult> = example_trace().into_iter().map(Ok).collect();
 
         let mut buffer = Cursor::new(Vec::new());
         let mut encoder = StdFormatEncoder::new();
@@ -93,6 +299,8 @@ mod tests {
             "T0|r(V200)|436",
             "T0|w(V200)|923",
             "T0|rel(L0)|362",
+            "T0|notify(C5)|512",
+            "T1|wait(C5)|640",
             "T0|join(T1)|7382\n",
         ]
         .join("\n");
@@ -101,4 +309,118 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn encode_location_names() -> Result<(), Error> {
+        let mut buffer = Cursor::new(Vec::new());
+        let mut encoder =
+            StdFormatEncoder::with_location_names(HashMap::from([(42, "main.rs:7".to_string())]));
+        encoder.encode(example_trace().into_iter().take(3).map(Ok), &mut buffer)?;
+
+        let encoded_trace = String::from_utf8(buffer.into_inner())?;
+        assert_eq!(
+            encoded_trace,
+            "T0|fork(T1)|main.rs:7\nT0|fork(T2)|main.rs:7\nT2|fork(T3)|123\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn encode_lock_names() -> Result<(), Error> {
+        let mut buffer = Cursor::new(Vec::new());
+        let mut encoder =
+            StdFormatEncoder::new().with_lock_names(HashMap::from([(0, "QUEUE".to_string())]));
+        encoder.encode(
+            example_trace()
+                .into_iter()
+                .filter(|event| matches!(event.get_fields().1, Operation::Aquire { .. }))
+                .chain([Event::new(1, Operation::Release { lock: 3 }, 5)])
+                .map(Ok),
+            &mut buffer,
+        )?;
+
+        let encoded_trace = String::from_utf8(buffer.into_inner())?;
+        assert_eq!(encoded_trace, "T0|acq(QUEUE)|362\nT1|rel(L3)|5\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn encode_timestamps() -> Result<(), Error> {
+        let mut buffer = Cursor::new(Vec::new());
+        let mut encoder = StdFormatEncoder::new().with_timestamps(vec![100, 250]);
+        encoder.encode(example_trace().into_iter().take(3).map(Ok), &mut buffer)?;
+
+        let encoded_trace = String::from_utf8(buffer.into_inner())?;
+        assert_eq!(
+            encoded_trace,
+            "T0|fork(T1)|42@100\nT0|fork(T2)|42@250\nT2|fork(T3)|123\n"
+        );
+
+        let mut parser = StdFormatParser::new();
+        let parsed_trace = parser
+            .parse(encoded_trace.as_bytes())?
+            .collect::<Result<Vec<Event>, Error>>()?;
+        assert_eq!(parsed_trace, example_trace()[..3]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn std_format_roundtrip() -> Result<(), Error> {
+        let mut buffer = Cursor::new(Vec::new());
+        let mut encoder = StdFormatEncoder::new();
+        encoder.encode(example_trace().into_iter().map(Ok), &mut buffer)?;
+
+        let mut parser = StdFormatParser::new();
+        let parsed_trace = parser
+            .parse(buffer.into_inner().as_slice())?
+            .collect::<Result<Vec<Event>, Error>>()?;
+
+        assert_eq!(example_trace(), parsed_trace);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_with_whitespace() -> Result<(), Error> {
+        let input = " T0 | fork( T1 ) | 42 \n\n\tT2|acq(L3)|7\n";
+
+        let mut parser = StdFormatParser::new();
+        let parsed_trace = parser
+            .parse(input.as_bytes())?
+            .collect::<Result<Vec<Event>, Error>>()?;
+
+        assert_eq!(
+            parsed_trace,
+            vec![
+                Event::new(0, Operation::Fork { tid: 1 }, 42),
+                Event::new(2, Operation::Aquire { lock: 3 }, 7),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn fail_on_malformed_lines() {
+        let malformed = [
+            "T0|lock(L1)|42",
+            "T0|acq(L1)",
+            "T0|acq(L1)|42|7",
+            "T0 acq(L1) 42",
+            "Tx|acq(L1)|42",
+            "T0|acq(V1)|42",
+            "T0|wait(L1)|42",
+            "T0|acq(L1|42",
+            "T0|acq(L1)|loc",
+        ];
+
+        for line in malformed {
+            let mut parser = StdFormatParser::new();
+            let mut iter = parser.parse(line.as_bytes()).unwrap();
+            iter.next().unwrap().unwrap_err();
+        }
+    }
 }
@@ -0,0 +1,346 @@
+use std::fmt::{self, Display};
+
+use anyhow::{Error, bail};
+use serde::{Deserialize, Serialize};
+use walrus::{ExportItem, ImportKind, Module};
+
+use crate::threadify::get_stack_pointer;
+
+/// The import module of the host functions required by the standalone runtime
+pub const STANDALONE_MODULE: &str = "wasmgrind_standalone";
+
+/// The import module of the tracing hooks
+pub const TRACING_MODULE: &str = "wasmgrind_tracing";
+
+const STANDALONE_IMPORTS: &[&str] = &[
+    "clone_instance",
+    "thread_is_finished",
+    "get_tls_size",
+    "get_tls_align",
+    "exit",
+];
+
+const TRACING_IMPORTS: &[&str] = &[
+    "initialize",
+    "thread_ignore_begin",
+    "thread_ignore_end",
+    "thread_create",
+    "thread_register",
+    "thread_consume",
+    "thread_join",
+    "thread_detach",
+    "mutex_register",
+    "mutex_unregister",
+    "mutex_start_lock",
+    "mutex_finish_lock",
+    "mutex_unlock",
+    "condvar_wait",
+    "condvar_notify",
+    "thread_set_name",
+    "mutex_repair",
+    "mutex_invalid_access",
+    "read_hook",
+    "write_hook",
+    "call_hook",
+    "return_hook",
+];
+
+/// Hooks that are imported by every instrumented module, regardless of the selected hooks
+const INSTRUMENTATION_IMPORTS: &[&str] = &["initialize"];
+
+/// The ABI a module is validated against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AbiFlavor {
+    /// Modules executed by the standalone runtime
+    Standalone,
+    /// Instrumented modules executed by the standalone runtime with tracing enabled
+    Tracing,
+}
+
+/// A single violation of the Wasmgrind ABI.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AbiIssue {
+    MissingExport {
+        name: &'static str,
+        kind: &'static str,
+    },
+    MismatchedExport {
+        name: &'static str,
+        expected: &'static str,
+    },
+    MissingStackPointer,
+    MissingMemory,
+    Memory64,
+    UnsharedMemoryImport {
+        module: String,
+        name: String,
+    },
+    MissingImport {
+        module: &'static str,
+        name: &'static str,
+    },
+    UnknownImport {
+        module: String,
+        name: String,
+    },
+    MismatchedImport {
+        module: String,
+        name: String,
+    },
+}
+
+impl Display for AbiIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AbiIssue::MissingExport { name, kind } => write!(f, "missing {kind} export `{name}`"),
+            AbiIssue::MismatchedExport { name, expected } => {
+                write!(f, "export `{name}` must be {expected}")
+            }
+            AbiIssue::MissingStackPointer => write!(f, "failed to find the stack pointer"),
+            AbiIssue::MissingMemory => write!(f, "module neither defines nor imports a memory"),
+            AbiIssue::Memory64 => write!(f, "64bit memories are unsupported"),
+            AbiIssue::UnsharedMemoryImport { module, name } => {
+                write!(f, "imported memory `{module}.{name}` must be shared")
+            }
+            AbiIssue::MissingImport { module, name } => {
+                write!(f, "missing import `{module}.{name}`")
+            }
+            AbiIssue::UnknownImport { module, name } => {
+                write!(f, "unknown import `{module}.{name}`")
+            }
+            AbiIssue::MismatchedImport { module, name } => {
+                write!(f, "import `{module}.{name}` must be a function")
+            }
+        }
+    }
+}
+
+/// The result of validating a module against the Wasmgrind ABI.
+#[derive(Clone, Debug, Default)]
+pub struct AbiReport {
+    /// All ABI violations that were found in the module
+    pub issues: Vec<AbiIssue>,
+}
+
+impl AbiReport {
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Turns the report into a single error that lists all violations.
+    pub fn ensure_valid(self) -> Result<(), Error> {
+        if self.is_valid() {
+            return Ok(());
+        }
+
+        let issues = self
+            .issues
+            .iter()
+            .map(|issue| format!("  - {issue}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        bail!(
+            "Module does not conform to the Wasmgrind ABI. Was it compiled for Wasmgrind?\n{issues}"
+        )
+    }
+}
+
+/// Parses a binary WebAssembly module and validates it against the Wasmgrind ABI.
+///
+/// # Errors
+///
+/// This function only fails if `wasm` is not a valid WebAssembly module.
+/// ABI violations are listed in the returned [`AbiReport`].
+pub fn validate(wasm: &[u8], flavor: AbiFlavor) -> Result<AbiReport, Error> {
+    let module = Module::from_buffer(wasm)?;
+    Ok(validate_module(&module, flavor))
+}
+
+/// Validates a module against the Wasmgrind ABI.
+///
+/// This checks all exports that are consumed by [`crate::threadify`], the
+/// imports of the standalone runtime and the memory of the module. With the
+/// [`AbiFlavor::Tracing`] flavor, the imported tracing hooks are checked as well.
+pub fn validate_module(module: &Module, flavor: AbiFlavor) -> AbiReport {
+    let mut report = AbiReport::default();
+
+    check_exports(module, &mut report);
+    check_memories(module, &mut report);
+    check_imports(module, flavor, &mut report);
+
+    report
+}
+
+fn check_exports(module: &Module, report: &mut AbiReport) {
+    let find = |name| module.exports.iter().find(|e| e.name == name);
+
+    for name in ["__wasmgrind_thread_start", "__wasm_init_tls"] {
+        match find(name).map(|e| e.item) {
+            Some(ExportItem::Function(_)) => {}
+            Some(_) => report.issues.push(AbiIssue::MismatchedExport {
+                name,
+                expected: "a function",
+            }),
+            None => report.issues.push(AbiIssue::MissingExport {
+                name,
+                kind: "function",
+            }),
+        }
+    }
+
+    for name in ["__tls_size", "__tls_align"] {
+        match find(name).map(|e| e.item) {
+            Some(ExportItem::Global(id)) => {
+                if !matches!(
+                    module.globals.get(id).kind,
+                    walrus::GlobalKind::Local(walrus::ConstExpr::Value(walrus::ir::Value::I32(_)))
+                ) {
+                    report.issues.push(AbiIssue::MismatchedExport {
+                        name,
+                        expected: "a local `i32` constant",
+                    });
+                }
+            }
+            Some(_) => report.issues.push(AbiIssue::MismatchedExport {
+                name,
+                expected: "a global",
+            }),
+            None => report.issues.push(AbiIssue::MissingExport {
+                name,
+                kind: "global",
+            }),
+        }
+    }
+
+    if get_stack_pointer(module).is_none() {
+        report.issues.push(AbiIssue::MissingStackPointer);
+    }
+}
+
+fn check_memories(module: &Module, report: &mut AbiReport) {
+    if module.memories.iter().next().is_none() {
+        report.issues.push(AbiIssue::MissingMemory);
+    }
+
+    if module.memories.iter().any(|memory| memory.memory64) {
+        report.issues.push(AbiIssue::Memory64);
+    }
+
+    for import in module.imports.iter() {
+        if let ImportKind::Memory(id) = import.kind
+            && !module.memories.get(id).shared
+        {
+            report.issues.push(AbiIssue::UnsharedMemoryImport {
+                module: import.module.clone(),
+                name: import.name.clone(),
+            });
+        }
+    }
+}
+
+fn check_imports(module: &Module, flavor: AbiFlavor, report: &mut AbiReport) {
+    let mut checked = vec![(STANDALONE_MODULE, STANDALONE_IMPORTS)];
+    if flavor == AbiFlavor::Tracing {
+        checked.push((TRACING_MODULE, TRACING_IMPORTS));
+    }
+
+    for import in module.imports.iter() {
+        let Some((_, known)) = checked.iter().find(|(m, _)| *m == import.module) else {
+            continue;
+        };
+
+        if !known.contains(&import.name.as_str()) {
+            report.issues.push(AbiIssue::UnknownImport {
+                module: import.module.clone(),
+                name: import.name.clone(),
+            });
+        } else if !matches!(import.kind, ImportKind::Function(_)) {
+            report.issues.push(AbiIssue::MismatchedImport {
+                module: import.module.clone(),
+                name: import.name.clone(),
+            });
+        }
+    }
+
+    if flavor == AbiFlavor::Tracing {
+        for name in INSTRUMENTATION_IMPORTS {
+            if module.imports.find(TRACING_MODULE, name).is_none() {
+                report.issues.push(AbiIssue::MissingImport {
+                    module: TRACING_MODULE,
+                    name,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use walrus::{Module, ModuleConfig};
+
+    use super::{AbiFlavor, AbiIssue, validate, validate_module};
+    use crate::{instrumentation::instrument, testing::abi_module};
+
+    #[test]
+    fn accept_valid_module() -> Result<(), Error> {
+        let mut module = abi_module().module;
+        validate_module(&module, AbiFlavor::Standalone).ensure_valid()?;
+
+        let module = Module::from_buffer(&instrument(&mut module)?.emit_wasm())?;
+        validate_module(&module, AbiFlavor::Tracing).ensure_valid()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn report_all_issues_at_once() -> Result<(), Error> {
+        let wasm = Module::with_config(ModuleConfig::new()).emit_wasm();
+        let report = validate(&wasm, AbiFlavor::Tracing)?;
+
+        assert!(!report.is_valid());
+        assert!(report.issues.contains(&AbiIssue::MissingExport {
+            name: "__wasmgrind_thread_start",
+            kind: "function"
+        }));
+        assert!(report.issues.contains(&AbiIssue::MissingExport {
+            name: "__tls_align",
+            kind: "global"
+        }));
+        assert!(report.issues.contains(&AbiIssue::MissingStackPointer));
+        assert!(report.issues.contains(&AbiIssue::MissingMemory));
+        assert!(report.issues.contains(&AbiIssue::MissingImport {
+            module: "wasmgrind_tracing",
+            name: "initialize"
+        }));
+
+        let message = report.ensure_valid().unwrap_err().to_string();
+        assert!(message.contains("__wasm_init_tls"));
+        assert!(message.contains("__tls_size"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn report_unknown_imports() {
+        let mut module = abi_module().module;
+        let ty = module.types.add(&[], &[]);
+        module.add_import_func("wasmgrind_standalone", "spawn", ty);
+        module.add_import_func("wasmgrind_tracing", "unknown_hook", ty);
+
+        let report = validate_module(&module, AbiFlavor::Standalone);
+        assert_eq!(
+            report.issues,
+            vec![AbiIssue::UnknownImport {
+                module: "wasmgrind_standalone".to_string(),
+                name: "spawn".to_string()
+            }]
+        );
+
+        let report = validate_module(&module, AbiFlavor::Tracing);
+        assert!(report.issues.contains(&AbiIssue::UnknownImport {
+            module: "wasmgrind_tracing".to_string(),
+            name: "unknown_hook".to_string()
+        }));
+    }
+}
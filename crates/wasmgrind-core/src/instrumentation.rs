@@ -1,5 +1,7 @@
 use std::{
     collections::{HashMap, HashSet},
+    fmt::{self, Display},
+    ops::Range,
     sync::Mutex,
 };
 
@@ -7,10 +9,11 @@ use anyhow::{Error, bail};
 use rayon::iter::ParallelIterator;
 use walrus::{
     FunctionBuilder, FunctionId, Import, InstrLocId, InstrSeqBuilder, LocalFunction, LocalId,
-    Module, ModuleLocals, ModuleTypes, TypeId, ValType,
+    Module, ModuleLocals, ModuleTypes, RawCustomSection, TypeId, ValType,
     ir::{
-        AtomicRmw, AtomicWait, BinaryOp, Block, Call, Cmpxchg, Const, IfElse, Instr, Load, Loop,
-        MemoryCopy, MemoryFill, MemoryInit, Store, Value,
+        AtomicRmw, AtomicWait, BinaryOp, Block, Br, BrIf, BrTable, Call, Cmpxchg, Const, IfElse,
+        Instr, InstrSeqId, InstrSeqType, Load, Loop, MemoryCopy, MemoryFill, MemoryInit, Return,
+        ReturnCall, ReturnCallIndirect, Store, Value,
     },
 };
 
@@ -84,6 +87,7 @@ struct WasmgrindInstrumentation<'mutex, 'context, 'module> {
     /// of the function that is currently instrumented
     function_loc: InstrLocId,
     local_provider: ReusableLocalProvider<'mutex, 'module>,
+    report: InstrumentationReport,
 }
 
 impl<'mutex, 'context, 'module> WasmgrindInstrumentation<'mutex, 'context, 'module> {
@@ -100,10 +104,125 @@ impl<'mutex, 'context, 'module> WasmgrindInstrumentation<'mutex, 'context, 'modu
             context,
             function_loc: InstrLocId::default(),
             local_provider: ReusableLocalProvider::new(locals),
+            report: InstrumentationReport::default(),
         }
     }
 
-    fn process_function(&mut self, func: &mut LocalFunction) {
+    /// Records an instrumented instruction and counts the hook calls
+    /// that were inserted around it.
+    fn record(&mut self, instr: Instr, loc: InstrLocId, seq: &[(Instr, InstrLocId)]) {
+        self.report.hooks += seq
+            .iter()
+            .filter(|(instr, _)| {
+                matches!(instr, Instr::Call(Call { func })
+                    if Some(*func) == self.context.read_hook
+                        || Some(*func) == self.context.write_hook)
+            })
+            .count();
+        self.report
+            .locations
+            .insert((self.function_loc.data(), loc.data()), instr);
+    }
+
+    /// Returns the instructions that call `hook` with the index of the instrumented function.
+    fn function_hook(
+        &self,
+        hook: FunctionId,
+        fidx: u32,
+        loc: InstrLocId,
+    ) -> [(Instr, InstrLocId); 4] {
+        let constant = |value: u32| {
+            (
+                Instr::Const(Const {
+                    value: Value::I32(value as i32),
+                }),
+                loc,
+            )
+        };
+
+        [
+            constant(fidx),
+            constant(self.function_loc.data()),
+            constant(loc.data()),
+            (Instr::Call(Call { func: hook }), loc),
+        ]
+    }
+
+    /// Calls the `call_hook` when the function is entered and the `return_hook`
+    /// whenever it is left.
+    ///
+    /// The original body is moved into a block, such that falling off its end and
+    /// branching to its outermost label both pass the `return_hook` afterwards.
+    /// Explicit returns and tail calls are preceded by their own `return_hook`.
+    fn instrument_function_calls(
+        &mut self,
+        func: &mut LocalFunction,
+        fidx: u32,
+        ty: InstrSeqType,
+        (call_hook, return_hook): (FunctionId, FunctionId),
+    ) {
+        let entry_id = func.entry_block();
+        let entry = func.block(entry_id);
+        let end = entry.end;
+        self.function_loc = entry.first().map(|(_, loc)| *loc).unwrap_or(end);
+
+        let body_instrs = std::mem::take(func.builder_mut().instr_seq(entry_id).instrs_mut());
+        let mut body = func.builder_mut().dangling_instr_seq(ty);
+        *body.instrs_mut() = body_instrs;
+        let body_id = body.id();
+
+        let retarget = |block: &mut InstrSeqId| {
+            if *block == entry_id {
+                *block = body_id;
+            }
+        };
+
+        let mut stack = vec![body_id];
+        while let Some(seq_id) = stack.pop() {
+            let mut seq = func.builder_mut().instr_seq(seq_id);
+            let instrs = seq.instrs_mut();
+
+            let mut i = 0;
+            while i < instrs.len() {
+                let loc = instrs[i].1;
+                match &mut instrs[i].0 {
+                    Instr::Block(Block { seq }) | Instr::Loop(Loop { seq }) => {
+                        stack.push(*seq);
+                    }
+                    Instr::IfElse(IfElse {
+                        consequent,
+                        alternative,
+                    }) => {
+                        stack.push(*alternative);
+                        stack.push(*consequent);
+                    }
+                    Instr::Br(Br { block }) | Instr::BrIf(BrIf { block }) => retarget(block),
+                    Instr::BrTable(BrTable { blocks, default }) => {
+                        blocks.iter_mut().for_each(retarget);
+                        retarget(default);
+                    }
+                    Instr::Return(Return {})
+                    | Instr::ReturnCall(ReturnCall { .. })
+                    | Instr::ReturnCallIndirect(ReturnCallIndirect { .. }) => {
+                        let hook = self.function_hook(return_hook, fidx, loc);
+                        instrs.splice(i..i, hook);
+                        i += 4; // We added 4 instructions in total
+                    }
+                    _ => {}
+                }
+
+                i += 1;
+            }
+        }
+
+        let function_loc = self.function_loc;
+        let mut instrs = Vec::from(self.function_hook(call_hook, fidx, function_loc));
+        instrs.push((Instr::Block(Block { seq: body_id }), function_loc));
+        instrs.extend(self.function_hook(return_hook, fidx, end));
+        *func.builder_mut().instr_seq(entry_id).instrs_mut() = instrs;
+    }
+
+    fn process_function(&mut self, func: &mut LocalFunction, memory_hooks: bool) {
         let start_seq_id = func.entry_block();
         let start_seq = func.block(start_seq_id);
         let func_loc = start_seq
@@ -128,7 +247,9 @@ impl<'mutex, 'context, 'module> WasmgrindInstrumentation<'mutex, 'context, 'modu
                     break;
                 }
 
+                let start = i;
                 let (instr, loc) = &seq.instrs()[i];
+                let original = (instr.clone(), *loc);
                 match instr {
                     Instr::Block(Block { seq }) | Instr::Loop(Loop { seq }) => {
                         stack.push(*seq);
@@ -150,6 +271,8 @@ impl<'mutex, 'context, 'module> WasmgrindInstrumentation<'mutex, 'context, 'modu
                         // How do we find out whether the called function is part of 'self.context.external_hooks'?
                         // Will this be a type signature mismatch error at runtime?
                     }
+                    // Calls to external hooks are patched regardless, as their signatures have changed
+                    _ if !memory_hooks => {}
                     Instr::MemoryInit(memory_init) => {
                         self.instrument_memory_init(memory_init.clone(), *loc, &mut seq, &mut i);
                     }
@@ -178,6 +301,11 @@ impl<'mutex, 'context, 'module> WasmgrindInstrumentation<'mutex, 'context, 'modu
                     _ => {}
                 }
 
+                if i != start {
+                    let (instr, loc) = original;
+                    self.record(instr, loc, &seq.instrs()[start..=i]);
+                }
+
                 i += 1;
             }
         }
@@ -219,6 +347,10 @@ impl<'mutex, 'context, 'module> WasmgrindInstrumentation<'mutex, 'context, 'modu
         seq: &mut InstrSeqBuilder<'a>,
         idx: &mut usize,
     ) {
+        let Some(write_hook) = self.context.write_hook else {
+            return;
+        };
+
         let reusable_locals = self
             .local_provider
             .get([ValType::I32, ValType::I32, ValType::I32]);
@@ -233,7 +365,7 @@ impl<'mutex, 'context, 'module> WasmgrindInstrumentation<'mutex, 'context, 'modu
             .local_set_at(*idx, *data_offset_tmp)
             .local_set_at(*idx, *n_bytes_tmp)
             // These are instructions AFTER the original instruction
-            .call_at(*idx + 6, self.context.write_hook)
+            .call_at(*idx + 6, write_hook)
             .const_at(*idx + 6, Value::I32(instr_loc_id.data() as i32))
             .const_at(*idx + 6, Value::I32(self.function_loc.data() as i32))
             .const_at(*idx + 6, Value::I32(Self::NON_ATOMIC_ACCESS))
@@ -250,6 +382,10 @@ impl<'mutex, 'context, 'module> WasmgrindInstrumentation<'mutex, 'context, 'modu
         seq: &mut InstrSeqBuilder<'a>,
         idx: &mut usize,
     ) {
+        if self.context.read_hook.is_none() && self.context.write_hook.is_none() {
+            return;
+        }
+
         let reusable_locals = self
             .local_provider
             .get([ValType::I32, ValType::I32, ValType::I32]);
@@ -262,22 +398,28 @@ impl<'mutex, 'context, 'module> WasmgrindInstrumentation<'mutex, 'context, 'modu
             .local_get_at(*idx, *src_addr_tmp)
             .local_tee_at(*idx, *dst_addr_tmp)
             .local_set_at(*idx, *src_addr_tmp)
-            .local_set_at(*idx, *n_bytes_tmp)
-            // These are instructions AFTER the original instruction
-            .call_at(*idx + 6, self.context.write_hook)
-            .const_at(*idx + 6, Value::I32(instr_loc_id.data() as i32))
-            .const_at(*idx + 6, Value::I32(self.function_loc.data() as i32))
-            .const_at(*idx + 6, Value::I32(Self::NON_ATOMIC_ACCESS))
-            .local_get_at(*idx + 6, *n_bytes_tmp)
-            .local_get_at(*idx + 6, *dst_addr_tmp)
-            .call_at(*idx + 6, self.context.read_hook)
-            .const_at(*idx + 6, Value::I32(instr_loc_id.data() as i32))
-            .const_at(*idx + 6, Value::I32(self.function_loc.data() as i32))
-            .const_at(*idx + 6, Value::I32(Self::NON_ATOMIC_ACCESS))
-            .local_get_at(*idx + 6, *n_bytes_tmp)
-            .local_get_at(*idx + 6, *src_addr_tmp);
+            .local_set_at(*idx, *n_bytes_tmp);
+        *idx += 5;
 
-        *idx += 17; // We added 17 instructions in total
+        // These are instructions AFTER the original instruction
+        if let Some(read_hook) = self.context.read_hook {
+            seq.call_at(*idx + 1, read_hook)
+                .const_at(*idx + 1, Value::I32(instr_loc_id.data() as i32))
+                .const_at(*idx + 1, Value::I32(self.function_loc.data() as i32))
+                .const_at(*idx + 1, Value::I32(Self::NON_ATOMIC_ACCESS))
+                .local_get_at(*idx + 1, *n_bytes_tmp)
+                .local_get_at(*idx + 1, *src_addr_tmp);
+            *idx += 6;
+        }
+        if let Some(write_hook) = self.context.write_hook {
+            seq.call_at(*idx + 1, write_hook)
+                .const_at(*idx + 1, Value::I32(instr_loc_id.data() as i32))
+                .const_at(*idx + 1, Value::I32(self.function_loc.data() as i32))
+                .const_at(*idx + 1, Value::I32(Self::NON_ATOMIC_ACCESS))
+                .local_get_at(*idx + 1, *n_bytes_tmp)
+                .local_get_at(*idx + 1, *dst_addr_tmp);
+            *idx += 6;
+        }
     }
 
     fn instrument_memory_fill<'a>(
@@ -287,6 +429,10 @@ impl<'mutex, 'context, 'module> WasmgrindInstrumentation<'mutex, 'context, 'modu
         seq: &mut InstrSeqBuilder<'a>,
         idx: &mut usize,
     ) {
+        let Some(write_hook) = self.context.write_hook else {
+            return;
+        };
+
         let reusable_locals = self
             .local_provider
             .get([ValType::I32, ValType::I32, ValType::I32]);
@@ -301,7 +447,7 @@ impl<'mutex, 'context, 'module> WasmgrindInstrumentation<'mutex, 'context, 'modu
             .local_set_at(*idx, *byte_value_tmp)
             .local_set_at(*idx, *n_bytes_tmp)
             // These are instructions AFTER the original instruction
-            .call_at(*idx + 6, self.context.write_hook)
+            .call_at(*idx + 6, write_hook)
             .const_at(*idx + 6, Value::I32(instr_loc_id.data() as i32))
             .const_at(*idx + 6, Value::I32(self.function_loc.data() as i32))
             .const_at(*idx + 6, Value::I32(Self::NON_ATOMIC_ACCESS))
@@ -318,6 +464,10 @@ impl<'mutex, 'context, 'module> WasmgrindInstrumentation<'mutex, 'context, 'modu
         seq: &mut InstrSeqBuilder<'a>,
         idx: &mut usize,
     ) {
+        let Some(read_hook) = self.context.read_hook else {
+            return;
+        };
+
         if let walrus::ir::LoadKind::V128 = load.kind {
             // Unsupported: We do not instrument this ...
             return;
@@ -337,7 +487,7 @@ impl<'mutex, 'context, 'module> WasmgrindInstrumentation<'mutex, 'context, 'modu
             // These are instructions BEFORE the original instruction
             .local_tee_at(*idx, *addr_tmp)
             // These are instructions AFTER the original instruction
-            .call_at(*idx + 2, self.context.read_hook)
+            .call_at(*idx + 2, read_hook)
             .const_at(*idx + 2, Value::I32(instr_loc_id.data() as i32))
             .const_at(*idx + 2, Value::I32(self.function_loc.data() as i32))
             .const_at(*idx + 2, Value::I32(is_atomic))
@@ -356,6 +506,10 @@ impl<'mutex, 'context, 'module> WasmgrindInstrumentation<'mutex, 'context, 'modu
         seq: &mut InstrSeqBuilder<'a>,
         idx: &mut usize,
     ) {
+        let Some(write_hook) = self.context.write_hook else {
+            return;
+        };
+
         let val_type = match store.kind {
             walrus::ir::StoreKind::I32 { atomic: _ }
             | walrus::ir::StoreKind::I32_8 { atomic: _ }
@@ -389,7 +543,7 @@ impl<'mutex, 'context, 'module> WasmgrindInstrumentation<'mutex, 'context, 'modu
             .local_tee_at(*idx, *addr_tmp)
             .local_set_at(*idx, *value_tmp)
             // These are instructions AFTER the original instruction
-            .call_at(*idx + 4, self.context.write_hook)
+            .call_at(*idx + 4, write_hook)
             .const_at(*idx + 4, Value::I32(instr_loc_id.data() as i32))
             .const_at(*idx + 4, Value::I32(self.function_loc.data() as i32))
             .const_at(*idx + 4, Value::I32(is_atomic))
@@ -408,6 +562,10 @@ impl<'mutex, 'context, 'module> WasmgrindInstrumentation<'mutex, 'context, 'modu
         seq: &mut InstrSeqBuilder<'a>,
         idx: &mut usize,
     ) {
+        if self.context.read_hook.is_none() && self.context.write_hook.is_none() {
+            return;
+        }
+
         let val_type = match rmw.width {
             walrus::ir::AtomicWidth::I32
             | walrus::ir::AtomicWidth::I32_8
@@ -426,26 +584,24 @@ impl<'mutex, 'context, 'module> WasmgrindInstrumentation<'mutex, 'context, 'modu
             // These are instructions BEFORE the original instruction
             .local_get_at(*idx, *value_tmp)
             .local_tee_at(*idx, *addr_tmp)
-            .local_set_at(*idx, *value_tmp)
-            // These are instructions AFTER the original instruction
-            .call_at(*idx + 4, self.context.write_hook)
-            .const_at(*idx + 4, Value::I32(instr_loc_id.data() as i32))
-            .const_at(*idx + 4, Value::I32(self.function_loc.data() as i32))
-            .const_at(*idx + 4, Value::I32(Self::ATOMIC_ACCESS))
-            .const_at(*idx + 4, Value::I32(rmw.width.bytes() as i32))
-            .binop_at(*idx + 4, BinaryOp::I32Add)
-            .const_at(*idx + 4, Value::I32(rmw.arg.offset as i32))
-            .local_get_at(*idx + 4, *addr_tmp)
-            .call_at(*idx + 4, self.context.read_hook)
-            .const_at(*idx + 4, Value::I32(instr_loc_id.data() as i32))
-            .const_at(*idx + 4, Value::I32(self.function_loc.data() as i32))
-            .const_at(*idx + 4, Value::I32(Self::ATOMIC_ACCESS))
-            .const_at(*idx + 4, Value::I32(rmw.width.bytes() as i32))
-            .binop_at(*idx + 4, BinaryOp::I32Add)
-            .const_at(*idx + 4, Value::I32(rmw.arg.offset as i32))
-            .local_get_at(*idx + 4, *addr_tmp);
+            .local_set_at(*idx, *value_tmp);
+        *idx += 3;
 
-        *idx += 19; // We added 19 instructions in total
+        // These are instructions AFTER the original instruction
+        for hook in [self.context.read_hook, self.context.write_hook]
+            .into_iter()
+            .flatten()
+        {
+            seq.call_at(*idx + 1, hook)
+                .const_at(*idx + 1, Value::I32(instr_loc_id.data() as i32))
+                .const_at(*idx + 1, Value::I32(self.function_loc.data() as i32))
+                .const_at(*idx + 1, Value::I32(Self::ATOMIC_ACCESS))
+                .const_at(*idx + 1, Value::I32(rmw.width.bytes() as i32))
+                .binop_at(*idx + 1, BinaryOp::I32Add)
+                .const_at(*idx + 1, Value::I32(rmw.arg.offset as i32))
+                .local_get_at(*idx + 1, *addr_tmp);
+            *idx += 8;
+        }
     }
 
     fn instrument_cmpxchg<'a>(
@@ -455,6 +611,10 @@ impl<'mutex, 'context, 'module> WasmgrindInstrumentation<'mutex, 'context, 'modu
         seq: &mut InstrSeqBuilder<'a>,
         idx: &mut usize,
     ) {
+        if self.context.read_hook.is_none() && self.context.write_hook.is_none() {
+            return;
+        }
+
         let val_type = match cmpxchg.width {
             walrus::ir::AtomicWidth::I32
             | walrus::ir::AtomicWidth::I32_8
@@ -484,49 +644,58 @@ impl<'mutex, 'context, 'module> WasmgrindInstrumentation<'mutex, 'context, 'modu
             .local_tee_at(*idx, *addr_tmp)
             .local_set_at(*idx, *expected_tmp)
             .local_set_at(*idx, *replacement_tmp);
+        *idx += 5;
 
         // These are instructions AFTER the original instruction
-        seq.if_else_at(
-            *idx + 6,
-            None,
-            |then| {
-                // NOTE: The instructions in the sub-sequence are NOT added in reverse
-                // ==> Walrus internally creates a fresh sequence that is referenced by the if-else-blocks so no need for indexing
-                then.local_get(*addr_tmp)
-                    .i32_const(cmpxchg.arg.offset as i32)
-                    .binop(BinaryOp::I32Add)
-                    .i32_const(cmpxchg.width.bytes() as i32)
-                    .i32_const(Self::ATOMIC_ACCESS)
-                    .i32_const(self.function_loc.data() as i32)
-                    .i32_const(instr_loc_id.data() as i32)
-                    .call(self.context.write_hook);
-            },
-            |_| {},
-        );
-
-        match val_type {
-            ValType::I32 => {
-                seq.binop_at(*idx + 6, BinaryOp::I32Eq);
-            }
-            ValType::I64 => {
-                seq.binop_at(*idx + 6, BinaryOp::I64Eq);
-            }
-            _ => unreachable!("memory.atomic.cmpxchg instruction only takes i32 or i64 arguments"),
+        if let Some(read_hook) = self.context.read_hook {
+            seq.call_at(*idx + 1, read_hook)
+                .const_at(*idx + 1, Value::I32(instr_loc_id.data() as i32))
+                .const_at(*idx + 1, Value::I32(self.function_loc.data() as i32))
+                .const_at(*idx + 1, Value::I32(Self::ATOMIC_ACCESS))
+                .const_at(*idx + 1, Value::I32(cmpxchg.width.bytes() as i32))
+                .binop_at(*idx + 1, BinaryOp::I32Add)
+                .const_at(*idx + 1, Value::I32(cmpxchg.arg.offset as i32))
+                .local_get_at(*idx + 1, *addr_tmp);
+            *idx += 8;
         }
 
-        seq.local_get_at(*idx + 6, *expected_tmp)
-            .local_get_at(*idx + 6, *returned_tmp)
-            .local_tee_at(*idx + 6, *returned_tmp)
-            .call_at(*idx + 6, self.context.read_hook)
-            .const_at(*idx + 6, Value::I32(instr_loc_id.data() as i32))
-            .const_at(*idx + 6, Value::I32(self.function_loc.data() as i32))
-            .const_at(*idx + 6, Value::I32(Self::ATOMIC_ACCESS))
-            .const_at(*idx + 6, Value::I32(cmpxchg.width.bytes() as i32))
-            .binop_at(*idx + 6, BinaryOp::I32Add)
-            .const_at(*idx + 6, Value::I32(cmpxchg.arg.offset as i32))
-            .local_get_at(*idx + 6, *addr_tmp);
+        // The write is only recorded if the exchange took place
+        if let Some(write_hook) = self.context.write_hook {
+            seq.if_else_at(
+                *idx + 1,
+                None,
+                |then| {
+                    // NOTE: The instructions in the sub-sequence are NOT added in reverse
+                    // ==> Walrus internally creates a fresh sequence that is referenced by the if-else-blocks so no need for indexing
+                    then.local_get(*addr_tmp)
+                        .i32_const(cmpxchg.arg.offset as i32)
+                        .binop(BinaryOp::I32Add)
+                        .i32_const(cmpxchg.width.bytes() as i32)
+                        .i32_const(Self::ATOMIC_ACCESS)
+                        .i32_const(self.function_loc.data() as i32)
+                        .i32_const(instr_loc_id.data() as i32)
+                        .call(write_hook);
+                },
+                |_| {},
+            );
 
-        *idx += 18; // We added 18 instructions in total
+            match val_type {
+                ValType::I32 => {
+                    seq.binop_at(*idx + 1, BinaryOp::I32Eq);
+                }
+                ValType::I64 => {
+                    seq.binop_at(*idx + 1, BinaryOp::I64Eq);
+                }
+                _ => unreachable!(
+                    "memory.atomic.cmpxchg instruction only takes i32 or i64 arguments"
+                ),
+            }
+
+            seq.local_get_at(*idx + 1, *expected_tmp)
+                .local_get_at(*idx + 1, *returned_tmp)
+                .local_tee_at(*idx + 1, *returned_tmp);
+            *idx += 5;
+        }
     }
 
     fn instrument_atomic_wait<'a>(
@@ -536,6 +705,10 @@ impl<'mutex, 'context, 'module> WasmgrindInstrumentation<'mutex, 'context, 'modu
         seq: &mut InstrSeqBuilder<'a>,
         idx: &mut usize,
     ) {
+        let Some(read_hook) = self.context.read_hook else {
+            return;
+        };
+
         let (val_type, access_width) = if atomic_wait.sixty_four {
             (ValType::I64, Self::ACCESS_WIDTH_64BIT)
         } else {
@@ -553,7 +726,7 @@ impl<'mutex, 'context, 'module> WasmgrindInstrumentation<'mutex, 'context, 'modu
             .local_get_at(*idx, *timeout_tmp)
             .local_get_at(*idx, *expected_tmp)
             .local_get_at(*idx, *addr_tmp)
-            .call_at(*idx, self.context.read_hook)
+            .call_at(*idx, read_hook)
             .const_at(*idx, Value::I32(instr_loc_id.data() as i32))
             .const_at(*idx, Value::I32(self.function_loc.data() as i32))
             .const_at(*idx, Value::I32(Self::ATOMIC_ACCESS))
@@ -572,12 +745,16 @@ impl<'mutex, 'context, 'module> WasmgrindInstrumentation<'mutex, 'context, 'modu
 struct InstrumentationContext {
     external_hooks: HashSet<FunctionId>,
     initialize: FunctionId,
-    read_hook: FunctionId,
-    write_hook: FunctionId,
+    /// The `read_hook`, if memory reads are traced
+    read_hook: Option<FunctionId>,
+    /// The `write_hook`, if memory writes are traced
+    write_hook: Option<FunctionId>,
+    /// The `call_hook` and `return_hook`, if function calls are traced
+    call_hooks: Option<(FunctionId, FunctionId)>,
 }
 
 impl InstrumentationContext {
-    fn new(module: &mut Module) -> Self {
+    fn new(module: &mut Module, hooks: HookCategories) -> Self {
         let hook_params = [
             ValType::I32,
             ValType::I32,
@@ -587,19 +764,44 @@ impl InstrumentationContext {
         ];
         let hook_type = Self::get_or_create_type(&mut module.types, &hook_params, &[]);
 
-        let read_hook = Self::create_or_replace_function_import(
-            module,
-            "wasmgrind_tracing",
-            "read_hook",
-            hook_type,
-        );
-
-        let write_hook = Self::create_or_replace_function_import(
-            module,
-            "wasmgrind_tracing",
-            "write_hook",
-            hook_type,
-        );
+        let read_hook = hooks.reads.then(|| {
+            Self::create_or_replace_function_import(
+                module,
+                "wasmgrind_tracing",
+                "read_hook",
+                hook_type,
+            )
+        });
+
+        let write_hook = hooks.writes.then(|| {
+            Self::create_or_replace_function_import(
+                module,
+                "wasmgrind_tracing",
+                "write_hook",
+                hook_type,
+            )
+        });
+
+        let call_hooks = hooks.calls.then(|| {
+            let call_hook_type = Self::get_or_create_type(
+                &mut module.types,
+                &[ValType::I32, ValType::I32, ValType::I32],
+                &[],
+            );
+            let call_hook = Self::create_or_replace_function_import(
+                module,
+                "wasmgrind_tracing",
+                "call_hook",
+                call_hook_type,
+            );
+            let return_hook = Self::create_or_replace_function_import(
+                module,
+                "wasmgrind_tracing",
+                "return_hook",
+                call_hook_type,
+            );
+            (call_hook, return_hook)
+        });
 
         let init_fn_type = Self::get_or_create_type(&mut module.types, &[], &[]);
         let initialize = Self::create_or_replace_function_import(
@@ -614,6 +816,7 @@ impl InstrumentationContext {
             initialize,
             read_hook,
             write_hook,
+            call_hooks,
         }
     }
 
@@ -658,7 +861,7 @@ impl InstrumentationContext {
         match import.module.as_str() {
             "wasmgrind_tracing" => match import.name.as_str() {
                 "thread_create" | "thread_join" | "mutex_start_lock" | "mutex_finish_lock"
-                | "mutex_unlock" => {
+                | "mutex_unlock" | "condvar_wait" | "condvar_notify" => {
                     let fidx = Self::validate_function_import(import)?;
                     self.external_hooks.insert(fidx);
                     Ok(true)
@@ -709,27 +912,767 @@ fn patch_start_fn(module: &mut Module, context: &InstrumentationContext) {
     module.start = Some(id);
 }
 
+/// Metadata about the instrumentation that was applied to a module.
+#[derive(Clone, Debug, Default)]
+pub struct InstrumentationReport {
+    /// The number of calls to memory access hooks inserted into the module
+    pub hooks: usize,
+    /// Maps the `(fidx, iidx)` location pairs passed to the tracing hooks
+    /// back to the original instruction at that location
+    pub locations: HashMap<(u32, u32), Instr>,
+}
+
+impl InstrumentationReport {
+    fn merge(mut self, other: Self) -> Self {
+        self.hooks += other.hooks;
+        self.locations.extend(other.locations);
+        self
+    }
+}
+
 pub fn instrument(module: &mut Module) -> Result<&mut Module, Error> {
+    instrument_with_options(module, &InstrumentOptions::default())?;
+    Ok(module)
+}
+
+/// Instruments the module like [`instrument`] and reports which
+/// instructions were instrumented.
+///
+/// The report allows to correlate the locations recorded in a trace
+/// with the instrumented call sites.
+pub fn instrument_with_metadata(module: &mut Module) -> Result<InstrumentationReport, Error> {
+    instrument_with_options(module, &InstrumentOptions::default())
+}
+
+/// Selects functions of a module either by name or by index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FunctionSelector {
+    /// Functions whose name in the name section matches a glob pattern.
+    /// The wildcards `*` and `?` match any sequence of characters and any single character.
+    Name(String),
+    /// Functions whose index lies within the given range
+    Indices(Range<u32>),
+}
+
+impl FunctionSelector {
+    fn matches(&self, index: u32, name: Option<&str>) -> bool {
+        match self {
+            FunctionSelector::Name(pattern) => name.is_some_and(|name| glob_match(pattern, name)),
+            FunctionSelector::Indices(range) => range.contains(&index),
+        }
+    }
+}
+
+impl Display for FunctionSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FunctionSelector::Name(pattern) => write!(f, "{pattern}"),
+            FunctionSelector::Indices(range) => write!(f, "{}..{}", range.start, range.end),
+        }
+    }
+}
+
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+
+    // Position after the last `*` in the pattern and the name position it was matched against
+    let mut backtrack = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, n));
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bn)) => {
+                    p = bp;
+                    n = bn + 1;
+                    backtrack = Some((bp, bn + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Selects the hooks of the instrumentation and the functions into which memory
+/// access hooks are inserted.
+///
+/// A function is instrumented if it matches any of the `include` selectors (or if
+/// there are none) and none of the `exclude` selectors. Calls to thread and lock
+/// hooks are patched in all functions, so synchronization events are unaffected.
+#[derive(Clone, Debug)]
+pub struct InstrumentOptions {
+    pub include: Vec<FunctionSelector>,
+    pub exclude: Vec<FunctionSelector>,
+    /// The hooks to insert or keep. Function calls are not recorded by default.
+    pub hooks: HookCategories,
+}
+
+impl Default for InstrumentOptions {
+    fn default() -> Self {
+        Self {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            hooks: HookCategories {
+                calls: false,
+                ..HookCategories::ALL
+            },
+        }
+    }
+}
+
+impl InstrumentOptions {
+    fn instruments(&self, index: u32, name: Option<&str>) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|s| s.matches(index, name)))
+            && !self.exclude.iter().any(|s| s.matches(index, name))
+    }
+}
+
+/// Instruments the module like [`instrument_with_metadata`], but only inserts
+/// memory access hooks into the functions selected by `options`.
+pub fn instrument_with_options(
+    module: &mut Module,
+    options: &InstrumentOptions,
+) -> Result<InstrumentationReport, Error> {
     for memory in module.memories.iter() {
         if memory.memory64 {
             bail!("Wasmgrind instrumentation does not support 64bit WebAssembly memories")
         }
     }
 
-    let mut context = InstrumentationContext::new(module);
+    // Function indices have to be resolved before any hooks are imported
+    let selected = module
+        .funcs
+        .iter()
+        .filter(|func| matches!(func.kind, walrus::FunctionKind::Local(_)))
+        .filter(|func| options.instruments(func.id().index() as u32, func.name.as_deref()))
+        .map(|func| func.id())
+        .collect::<HashSet<_>>();
+
+    // The program's own calls to unselected thread and lock hooks are removed up front
+    strip_module_hooks(
+        module,
+        HookCategories {
+            threads: !options.hooks.threads,
+            locks: !options.hooks.locks,
+            ..HookCategories::default()
+        },
+    )?;
+
+    let mut context = InstrumentationContext::new(module, options.hooks);
     for import in module.imports.iter() {
         context.accept_import(import)?;
     }
 
     context.patch_hook_signatures(module)?;
 
+    // The original bodies are wrapped into blocks, whose types have to be part of the module
+    let body_types = match context.call_hooks {
+        Some(_) => selected
+            .iter()
+            .map(|fidx| {
+                let results = module
+                    .types
+                    .get(module.funcs.get(*fidx).ty())
+                    .results()
+                    .to_vec();
+                (*fidx, InstrSeqType::new(&mut module.types, &[], &results))
+            })
+            .collect::<HashMap<_, _>>(),
+        None => HashMap::new(),
+    };
+
     patch_start_fn(module, &context);
 
     let module_locals = Mutex::new(&mut module.locals);
+    let report = module
+        .funcs
+        .par_iter_local_mut()
+        .map(|(fidx, f_mut)| {
+            let mut instrumentation = WasmgrindInstrumentation::new(&context, &module_locals);
+            if let Some(hooks) = context.call_hooks
+                && let Some(ty) = body_types.get(&fidx)
+            {
+                instrumentation.instrument_function_calls(f_mut, fidx.index() as u32, *ty, hooks);
+            }
+            instrumentation.process_function(f_mut, selected.contains(&fidx));
+            instrumentation.report
+        })
+        .reduce(InstrumentationReport::default, InstrumentationReport::merge);
+
+    HookCategories::imported_by(module).write_section(module);
+
+    Ok(report)
+}
+
+/// Name of the custom section that lists the hook categories an instrumented module imports.
+///
+/// The section is written by the instrumentation and kept up to date by [`strip_hooks`],
+/// such that runtimes only have to provide the remaining hooks.
+pub const HOOKS_SECTION: &str = "wasmgrind_hooks";
+
+/// Categories of tracing hooks that can be selected for or stripped from an instrumented module.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HookCategories {
+    /// Calls to the `read_hook` that record memory reads
+    pub reads: bool,
+    /// Calls to the `write_hook` that record memory writes
+    pub writes: bool,
+    /// Calls to the `call_hook` and `return_hook` that record function entries and exits
+    pub calls: bool,
+    /// Calls to the thread lifecycle hooks, e.g., `thread_create` and `thread_join`.
+    ///
+    /// Without them, spawned threads are never registered and only the events
+    /// of the main thread are recorded.
+    pub threads: bool,
+    /// Calls to the mutex and condition variable hooks, e.g., `mutex_start_lock`
+    pub locks: bool,
+}
+
+impl HookCategories {
+    /// Selects all memory access hooks, i.e., reads and writes.
+    pub const MEMORY: Self = Self {
+        reads: true,
+        writes: true,
+        calls: false,
+        threads: false,
+        locks: false,
+    };
+
+    /// Selects all thread and lock hooks.
+    pub const SYNCHRONIZATION: Self = Self {
+        reads: false,
+        writes: false,
+        calls: false,
+        threads: true,
+        locks: true,
+    };
+
+    /// Selects all hooks that can be inserted or patched by the instrumentation.
+    pub const ALL: Self = Self {
+        reads: true,
+        writes: true,
+        calls: true,
+        threads: true,
+        locks: true,
+    };
+
+    const READS: u8 = 1 << 0;
+    const WRITES: u8 = 1 << 1;
+    const CALLS: u8 = 1 << 2;
+    const THREADS: u8 = 1 << 3;
+    const LOCKS: u8 = 1 << 4;
+
+    const THREAD_HOOKS: [&'static str; 5] = [
+        "thread_create",
+        "thread_register",
+        "thread_consume",
+        "thread_join",
+        "thread_detach",
+    ];
+    const LOCK_HOOKS: [&'static str; 9] = [
+        "mutex_register",
+        "mutex_unregister",
+        "mutex_start_lock",
+        "mutex_finish_lock",
+        "mutex_unlock",
+        "condvar_wait",
+        "condvar_notify",
+        "mutex_repair",
+        "mutex_invalid_access",
+    ];
+
+    /// Returns the hook categories listed in the [`HOOKS_SECTION`] of `module`.
+    ///
+    /// Returns `None` if the module has not been instrumented by Wasmgrind.
+    pub fn from_module(module: &Module) -> Option<Self> {
+        let section = module
+            .customs
+            .iter()
+            .filter(|(_, section)| section.name() == HOOKS_SECTION)
+            .find_map(|(_, section)| section.as_any().downcast_ref::<RawCustomSection>())?;
+        let bits = *section.data.first()?;
+
+        Some(Self {
+            reads: bits & Self::READS != 0,
+            writes: bits & Self::WRITES != 0,
+            calls: bits & Self::CALLS != 0,
+            threads: bits & Self::THREADS != 0,
+            locks: bits & Self::LOCKS != 0,
+        })
+    }
+
+    /// Returns the hook categories whose hooks are imported by `module`.
+    fn imported_by(module: &Module) -> Self {
+        let imports = |name| module.imports.find("wasmgrind_tracing", name).is_some();
+        Self {
+            reads: imports("read_hook"),
+            writes: imports("write_hook"),
+            calls: imports("call_hook") && imports("return_hook"),
+            threads: Self::THREAD_HOOKS.into_iter().any(imports),
+            locks: Self::LOCK_HOOKS.into_iter().any(imports),
+        }
+    }
+
+    /// Replaces the [`HOOKS_SECTION`] of `module` with one that lists these categories.
+    fn write_section(self, module: &mut Module) {
+        module.customs.remove_raw(HOOKS_SECTION);
+
+        let mut bits = 0;
+        for (selected, bit) in [
+            (self.reads, Self::READS),
+            (self.writes, Self::WRITES),
+            (self.calls, Self::CALLS),
+            (self.threads, Self::THREADS),
+            (self.locks, Self::LOCKS),
+        ] {
+            if selected {
+                bits |= bit;
+            }
+        }
+        module.customs.add(RawCustomSection {
+            name: HOOKS_SECTION.to_string(),
+            data: vec![bits],
+        });
+    }
+
+    fn hook_names(&self) -> impl Iterator<Item = &'static str> {
+        [
+            (self.reads, "read_hook"),
+            (self.writes, "write_hook"),
+            (self.calls, "call_hook"),
+            (self.calls, "return_hook"),
+        ]
+        .into_iter()
+        .chain(Self::THREAD_HOOKS.map(|name| (self.threads, name)))
+        .chain(Self::LOCK_HOOKS.map(|name| (self.locks, name)))
+        .filter_map(|(selected, name)| selected.then_some(name))
+    }
+}
+
+fn strip_calls(func: &mut LocalFunction, hooks: &HashMap<FunctionId, (usize, Vec<ValType>)>) {
+    let mut stack = vec![func.entry_block()];
+    while let Some(seq_id) = stack.pop() {
+        let mut seq = func.builder_mut().instr_seq(seq_id);
+        let instrs = seq.instrs_mut();
+
+        let mut i = 0;
+        while i < instrs.len() {
+            let stripped = match &instrs[i].0 {
+                Instr::Block(Block { seq }) | Instr::Loop(Loop { seq }) => {
+                    stack.push(*seq);
+                    None
+                }
+                Instr::IfElse(IfElse {
+                    consequent,
+                    alternative,
+                }) => {
+                    stack.push(*alternative);
+                    stack.push(*consequent);
+                    None
+                }
+                Instr::Call(Call { func }) => hooks.get(func),
+                _ => None,
+            };
+
+            if let Some((n_params, results)) = stripped {
+                // Dropping the arguments and pushing zeros for the results
+                // leaves the stack exactly as the call would have left it.
+                let loc = instrs[i].1;
+                let zeros = results.iter().map(|ty| {
+                    let value = match ty {
+                        ValType::I32 => Value::I32(0),
+                        ValType::I64 => Value::I64(0),
+                        ValType::F32 => Value::F32(0.0),
+                        ValType::F64 => Value::F64(0.0),
+                        ValType::V128 => Value::V128(0),
+                        ValType::Ref(_) => unreachable!("Hooks only return numeric values"),
+                    };
+                    (Instr::Const(Const { value }), loc)
+                });
+                let replacement =
+                    std::iter::repeat_n((Instr::Drop(walrus::ir::Drop {}), loc), *n_params)
+                        .chain(zeros)
+                        .collect::<Vec<_>>();
+                let n_instrs = replacement.len();
+                instrs.splice(i..=i, replacement);
+                i += n_instrs;
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+/// Removes the selected tracing hooks from an already instrumented module.
+///
+/// Every call to a selected hook is replaced by instructions that drop the
+/// hook arguments and push zeros for its results, e.g., the thread id returned
+/// by `thread_create`. The corresponding imports are removed afterwards.
+/// The [`HOOKS_SECTION`] is updated to list the remaining hooks.
+/// This allows to derive variants with a reduced hook set from a single
+/// instrumented binary without instrumenting it again.
+///
+/// # Errors
+///
+/// This function fails if `wasm` is not a valid WebAssembly module or if
+/// a selected hook is imported with an unexpected kind or signature.
+pub fn strip_hooks(wasm: &[u8], categories: HookCategories) -> Result<Vec<u8>, Error> {
+    let mut module = Module::from_buffer(wasm)?;
+    strip_module_hooks(&mut module, categories)?;
+    Ok(module.emit_wasm())
+}
+
+fn strip_module_hooks(module: &mut Module, categories: HookCategories) -> Result<(), Error> {
+    let mut hooks = HashMap::new();
+    for name in categories.hook_names() {
+        if let Some(import_id) = module.imports.find("wasmgrind_tracing", name) {
+            let fidx =
+                InstrumentationContext::validate_function_import(module.imports.get(import_id))?;
+            let ty = module.types.get(module.funcs.get(fidx).ty());
+            if ty.results().iter().any(|ty| matches!(ty, ValType::Ref(_))) {
+                bail!("Hook '{name}' must not return references!");
+            }
+
+            hooks.insert(fidx, (ty.params().len(), ty.results().to_vec()));
+            module.imports.delete(import_id);
+        }
+    }
+
     module.funcs.par_iter_local_mut().for_each(|(_, f_mut)| {
-        let mut instrumentation = WasmgrindInstrumentation::new(&context, &module_locals);
-        instrumentation.process_function(f_mut);
+        strip_calls(f_mut, &hooks);
     });
 
+    for fidx in hooks.keys() {
+        module.funcs.delete(*fidx);
+    }
+
+    HookCategories::imported_by(module).write_section(module);
+
+    Ok(())
+}
+
+/// Instruments the module like [`instrument`], but only inserts the selected hooks.
+///
+/// Calls to unselected thread and lock hooks of the module are removed, such that
+/// [`HookCategories::SYNCHRONIZATION`] yields a module that only records
+/// synchronization events.
+pub fn instrument_with_hooks(
+    module: &mut Module,
+    hooks: HookCategories,
+) -> Result<&mut Module, Error> {
+    let options = InstrumentOptions {
+        hooks,
+        ..Default::default()
+    };
+    instrument_with_options(module, &options)?;
+
     Ok(module)
 }
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use walrus::{
+        FunctionBuilder, Module, ModuleConfig, ValType,
+        ir::{Instr, MemArg},
+    };
+
+    use super::{
+        FunctionSelector, HookCategories, InstrumentOptions, glob_match, instrument,
+        instrument_with_hooks, instrument_with_metadata, instrument_with_options, strip_hooks,
+    };
+
+    fn example_module() -> Module {
+        let mut module = Module::with_config(ModuleConfig::new());
+        let memory = module.memories.add_local(false, false, 1, None, None);
+
+        let mut builder = FunctionBuilder::new(&mut module.types, &[], &[ValType::I32]);
+        builder
+            .func_body()
+            .i32_const(16)
+            .i32_const(42)
+            .store(
+                memory,
+                walrus::ir::StoreKind::I32 { atomic: false },
+                MemArg {
+                    align: 4,
+                    offset: 0,
+                },
+            )
+            .i32_const(16)
+            .load(
+                memory,
+                walrus::ir::LoadKind::I32 { atomic: false },
+                MemArg {
+                    align: 4,
+                    offset: 0,
+                },
+            );
+        let func = builder.finish(vec![], &mut module.funcs);
+        module.exports.add("run", func);
+
+        module
+    }
+
+    fn has_import(module: &Module, name: &str) -> bool {
+        module.imports.find("wasmgrind_tracing", name).is_some()
+    }
+
+    #[test]
+    fn strip_memory_hooks() -> Result<(), Error> {
+        // Instruction locations are only available for parsed modules
+        let mut module = Module::from_buffer(&example_module().emit_wasm())?;
+        let instrumented = instrument(&mut module)?.emit_wasm();
+
+        let stripped = strip_hooks(&instrumented, HookCategories::MEMORY)?;
+        let stripped = Module::from_buffer(&stripped)?;
+
+        assert!(!has_import(&stripped, "read_hook"));
+        assert!(!has_import(&stripped, "write_hook"));
+        assert!(has_import(&stripped, "initialize"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn strip_selected_hooks_only() -> Result<(), Error> {
+        // Instruction locations are only available for parsed modules
+        let mut module = Module::from_buffer(&example_module().emit_wasm())?;
+        let instrumented = instrument(&mut module)?.emit_wasm();
+
+        let categories = HookCategories {
+            reads: true,
+            ..HookCategories::default()
+        };
+        let stripped = Module::from_buffer(&strip_hooks(&instrumented, categories)?)?;
+
+        assert!(!has_import(&stripped, "read_hook"));
+        assert!(has_import(&stripped, "write_hook"));
+
+        // Runtimes learn about the remaining hooks from the custom section
+        assert_eq!(
+            HookCategories::from_module(&Module::from_buffer(&instrumented)?),
+            Some(HookCategories::MEMORY)
+        );
+        assert_eq!(
+            HookCategories::from_module(&stripped),
+            Some(HookCategories {
+                writes: true,
+                ..HookCategories::default()
+            })
+        );
+        assert_eq!(HookCategories::from_module(&example_module()), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn report_instrumented_locations() -> Result<(), Error> {
+        // Instruction locations are only available for parsed modules
+        let mut module = Module::from_buffer(&example_module().emit_wasm())?;
+        let report = instrument_with_metadata(&mut module)?;
+
+        // One store and one load
+        assert_eq!(report.hooks, 2);
+        assert_eq!(report.locations.len(), 2);
+        assert!(
+            report
+                .locations
+                .values()
+                .any(|instr| matches!(instr, Instr::Store(_)))
+        );
+        assert!(
+            report
+                .locations
+                .values()
+                .any(|instr| matches!(instr, Instr::Load(_)))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn instrument_selected_hooks_only() -> Result<(), Error> {
+        // `run` is preceded by a function that locks and unlocks a mutex
+        let mut module = example_module();
+        let ty = module.types.add(&[ValType::I32], &[]);
+        let lock_names = ["mutex_start_lock", "mutex_finish_lock", "mutex_unlock"];
+        let lock_hooks =
+            lock_names.map(|name| module.add_import_func("wasmgrind_tracing", name, ty).0);
+        let mut builder = FunctionBuilder::new(&mut module.types, &[], &[]);
+        let mut body = builder.func_body();
+        for hook in lock_hooks {
+            body.i32_const(0).call(hook);
+        }
+        let func = builder.finish(vec![], &mut module.funcs);
+        module.exports.add("lock", func);
+        // Instruction locations are only available for parsed modules
+        let wasm = module.emit_wasm();
+
+        let lock_only = HookCategories {
+            locks: true,
+            ..HookCategories::default()
+        };
+        let mut module = Module::from_buffer(&wasm)?;
+        let report = instrument_with_options(
+            &mut module,
+            &InstrumentOptions {
+                hooks: lock_only,
+                ..Default::default()
+            },
+        )?;
+        // Only the lock hooks are patched, no memory access is instrumented
+        assert_eq!(report.hooks, 0);
+        assert!(
+            report
+                .locations
+                .values()
+                .all(|i| matches!(i, Instr::Call(_)))
+        );
+        let module = Module::from_buffer(&module.emit_wasm())?;
+        assert!(lock_names.iter().all(|name| has_import(&module, name)));
+        assert!(!has_import(&module, "read_hook"));
+        assert!(!has_import(&module, "write_hook"));
+        assert_eq!(HookCategories::from_module(&module), Some(lock_only));
+
+        let read_only = HookCategories {
+            reads: true,
+            ..HookCategories::default()
+        };
+        let mut module = Module::from_buffer(&wasm)?;
+        let report = instrument_with_options(
+            &mut module,
+            &InstrumentOptions {
+                hooks: read_only,
+                ..Default::default()
+            },
+        )?;
+        // The store is left untouched and the calls to the lock hooks are removed
+        assert_eq!(report.hooks, 1);
+        assert_eq!(report.locations.len(), 1);
+        assert!(
+            report
+                .locations
+                .values()
+                .all(|i| matches!(i, Instr::Load(_)))
+        );
+        let module = Module::from_buffer(&module.emit_wasm())?;
+        assert!(has_import(&module, "read_hook"));
+        assert!(!has_import(&module, "write_hook"));
+        assert!(!lock_names.iter().any(|name| has_import(&module, name)));
+        assert!(has_import(&module, "initialize"));
+        assert_eq!(HookCategories::from_module(&module), Some(read_only));
+
+        Ok(())
+    }
+
+    #[test]
+    fn instrument_function_calls() -> Result<(), Error> {
+        let mut module = example_module();
+        let mut builder = FunctionBuilder::new(&mut module.types, &[ValType::I32], &[]);
+        let arg = module.locals.add(ValType::I32);
+        let mut body = builder.func_body();
+        let body_id = body.id();
+        body.local_get(arg)
+            .if_else(
+                None,
+                |then| {
+                    then.return_();
+                },
+                |_| {},
+            )
+            .local_get(arg)
+            .br_if(body_id);
+        let func = builder.finish(vec![arg], &mut module.funcs);
+        module.exports.add("leave_early", func);
+
+        let calls = HookCategories {
+            calls: true,
+            ..HookCategories::MEMORY
+        };
+        // Instruction locations are only available for parsed modules
+        let mut module = Module::from_buffer(&module.emit_wasm())?;
+        let wasm = instrument_with_hooks(&mut module, calls)?.emit_wasm();
+        let module = Module::from_buffer(&wasm)?;
+        assert!(has_import(&module, "call_hook"));
+        assert!(has_import(&module, "return_hook"));
+
+        // Call hooks are only inserted on request and can be stripped again
+        let mut module = Module::from_buffer(&example_module().emit_wasm())?;
+        assert!(!has_import(instrument(&mut module)?, "call_hook"));
+        let stripped = Module::from_buffer(&strip_hooks(&wasm, calls)?)?;
+        assert!(!has_import(&stripped, "call_hook"));
+        assert!(!has_import(&stripped, "return_hook"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn match_glob_patterns() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("dlmalloc::*", "dlmalloc::Dlmalloc::malloc"));
+        assert!(glob_match("*::lock?", "std::sync::lock2"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("a*b*c", "aXbYbZ"));
+        assert!(!glob_match("core::*", "alloc::core::fmt"));
+    }
+
+    #[test]
+    fn skip_excluded_functions() -> Result<(), Error> {
+        let mut module = example_module();
+        let memory = module.memories.iter().next().unwrap().id();
+        let ty = module.types.add(&[ValType::I32], &[]);
+        let (unlock, _) = module.add_import_func("wasmgrind_tracing", "mutex_unlock", ty);
+
+        let mut builder = FunctionBuilder::new(&mut module.types, &[], &[]);
+        builder
+            .name("skip_me".to_string())
+            .func_body()
+            .i32_const(16)
+            .i32_const(42)
+            .store(
+                memory,
+                walrus::ir::StoreKind::I32 { atomic: false },
+                MemArg {
+                    align: 4,
+                    offset: 0,
+                },
+            )
+            .i32_const(16)
+            .call(unlock);
+        let func = builder.finish(vec![], &mut module.funcs);
+        module.exports.add("skip_me", func);
+
+        // Instruction locations are only available for parsed modules
+        let mut module = Module::from_buffer(&module.emit_wasm())?;
+        let options = InstrumentOptions {
+            include: vec![],
+            exclude: vec![FunctionSelector::Name("skip_*".to_string())],
+            ..Default::default()
+        };
+        let report = instrument_with_options(&mut module, &options)?;
+
+        // Only the store and load of `run` are instrumented ...
+        assert_eq!(report.hooks, 2);
+        let count = |f: fn(&Instr) -> bool| report.locations.values().filter(|i| f(i)).count();
+        assert_eq!(count(|i| matches!(i, Instr::Store(_))), 1);
+        assert_eq!(count(|i| matches!(i, Instr::Load(_))), 1);
+        // ... but the lock hook is still patched within `skip_me`
+        assert_eq!(count(|i| matches!(i, Instr::Call(_))), 1);
+
+        // The patched module must still be valid
+        Module::from_buffer(&module.emit_wasm())?;
+
+        Ok(())
+    }
+}
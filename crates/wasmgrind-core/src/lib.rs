@@ -1,6 +1,16 @@
+/// Validation of modules against the Wasmgrind ABI
+pub mod abi;
+
 /// Utilities to instrument WebAssembly modules for execution tracing
 pub mod instrumentation;
 
+/// Utilities to map memory addresses back to symbol names
+pub mod symbols;
+
+/// Test fixtures for crates that build on the Wasmgrind ABI
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
 /// Utilities to patch WebAssembly modules for multithreading
 pub mod threadify;
 
@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+
+use anyhow::Error;
+use walrus::{ConstExpr, DataKind, Module, ir::Value};
+
+#[derive(Debug, PartialEq, Eq)]
+struct Symbol {
+    start: u32,
+    size: Option<u32>,
+    name: String,
+}
+
+impl Symbol {
+    fn contains(&self, addr: u32) -> bool {
+        match self.size {
+            Some(size) => self.start <= addr && addr - self.start < size,
+            None => self.start == addr,
+        }
+    }
+}
+
+/// Maps memory addresses of statics, such as locks, to symbol names.
+///
+/// Symbols are taken from the DWARF debug information of a binary if present.
+/// Otherwise, the names of active data segments from the `name` section are used,
+/// which are much coarser, e.g., `.bss` or `.data`.
+#[derive(Debug, Default)]
+pub struct LockSymbolizer {
+    symbols: Vec<Symbol>,
+}
+
+impl LockSymbolizer {
+    /// Collects the symbols of the given binary.
+    ///
+    /// Addresses recorded in a trace refer to the linear memory layout of the binary
+    /// that has been passed to the instrumentation. Hence, `wasm` has to be that very
+    /// binary, or a build of it that has been linked identically.
+    pub fn from_wasm(wasm: &[u8]) -> Result<Self, Error> {
+        let module = Module::from_buffer(wasm)?;
+
+        let mut symbols = dwarf_symbols(&module)?;
+        if symbols.is_empty() {
+            symbols = data_segment_symbols(&module);
+        }
+
+        Ok(Self { symbols })
+    }
+
+    /// Returns the name of the symbol at `addr`.
+    ///
+    /// Addresses that point into a symbol are described relative to its start,
+    /// e.g., `STATE+0x8`. If several symbols contain the address, the innermost is chosen.
+    pub fn lookup(&self, addr: u32) -> Option<String> {
+        self.symbols
+            .iter()
+            .filter(|symbol| symbol.contains(addr))
+            .max_by_key(|symbol| symbol.start)
+            .map(|symbol| match addr - symbol.start {
+                0 => symbol.name.clone(),
+                offset => format!("{}+{offset:#x}", symbol.name),
+            })
+    }
+}
+
+fn data_segment_symbols(module: &Module) -> Vec<Symbol> {
+    module
+        .data
+        .iter()
+        .filter_map(|data| match (&data.kind, &data.name) {
+            (
+                DataKind::Active {
+                    offset: ConstExpr::Value(Value::I32(offset)),
+                    ..
+                },
+                Some(name),
+            ) => Some(Symbol {
+                start: *offset as u32,
+                size: u32::try_from(data.value.len()).ok(),
+                name: name.clone(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+fn dwarf_symbols(module: &Module) -> Result<Vec<Symbol>, Error> {
+    let dwarf = module
+        .debug
+        .dwarf
+        .borrow(|section| gimli::EndianSlice::new(section, gimli::LittleEndian));
+
+    let mut symbols = Vec::new();
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let mut entries = unit.entries();
+        while let Some((_, entry)) = entries.next_dfs()? {
+            if entry.tag() != gimli::DW_TAG_variable {
+                continue;
+            }
+
+            // Statics are located at a constant address
+            let Some(gimli::AttributeValue::Exprloc(expr)) =
+                entry.attr_value(gimli::DW_AT_location)?
+            else {
+                continue;
+            };
+            let Ok(Some(gimli::Operation::Address { address })) =
+                expr.operations(unit.encoding()).next()
+            else {
+                continue;
+            };
+            let Some(name) = entry.attr_value(gimli::DW_AT_name)? else {
+                continue;
+            };
+
+            symbols.push(Symbol {
+                start: address as u32,
+                size: type_size(&unit, entry)?,
+                name: dwarf
+                    .attr_string(&unit, name)?
+                    .to_string_lossy()
+                    .into_owned(),
+            });
+        }
+    }
+
+    Ok(symbols)
+}
+
+/// Follows the type of a variable through typedefs and qualifiers until a size is found.
+fn type_size<R: gimli::Reader>(
+    unit: &gimli::Unit<R>,
+    entry: &gimli::DebuggingInformationEntry<R>,
+) -> Result<Option<u32>, Error> {
+    const MAX_DEPTH: usize = 8;
+
+    let mut offset = match entry.attr_value(gimli::DW_AT_type)? {
+        Some(gimli::AttributeValue::UnitRef(offset)) => offset,
+        _ => return Ok(None),
+    };
+    for _ in 0..MAX_DEPTH {
+        let ty = unit.entry(offset)?;
+        if let Some(size) = ty.attr_value(gimli::DW_AT_byte_size)?
+            && let Some(size) = size.udata_value()
+        {
+            return Ok(u32::try_from(size).ok());
+        }
+        match ty.attr_value(gimli::DW_AT_type)? {
+            Some(gimli::AttributeValue::UnitRef(next)) => offset = next,
+            _ => return Ok(None),
+        }
+    }
+
+    Ok(None)
+}
+
+/// Maps instruction locations of a binary to source code positions.
+///
+/// Source positions are taken from the DWARF line program of the binary.
+/// Binaries without debug information yield an empty map, such that all
+/// lookups fail and callers fall back to the numeric locations.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    /// Maps instruction locations, i.e., offsets into the binary, to code addresses
+    addresses: HashMap<u32, u64>,
+    /// Rows of the line program sorted by code address, where `None` ends a sequence
+    rows: Vec<(u64, Option<String>)>,
+}
+
+impl SourceMap {
+    /// Collects the source positions of the given binary.
+    ///
+    /// Locations are identified by offsets into the binary that has been passed to the
+    /// instrumentation. Hence, `wasm` has to be that very binary and not the instrumented one.
+    pub fn from_wasm(wasm: &[u8]) -> Result<Self, Error> {
+        let module = Module::from_buffer(wasm)?;
+
+        let addresses = module
+            .funcs
+            .iter_local()
+            .flat_map(|(_, func)| func.instruction_mapping.iter())
+            .map(|(address, loc)| (loc.data(), *address as u64))
+            .collect();
+
+        let mut rows = line_rows(&module)?;
+        rows.sort_by_key(|(address, _)| *address);
+
+        Ok(Self { addresses, rows })
+    }
+
+    /// Returns the source position, i.e., `file:line:column`, of the location `(fidx, iidx)`.
+    pub fn lookup(&self, loc: (u32, u32)) -> Option<&str> {
+        let address = *self.addresses.get(&loc.1)?;
+        let idx = self.rows.partition_point(|(row, _)| *row <= address);
+        idx.checked_sub(1)
+            .and_then(|idx| self.rows[idx].1.as_deref())
+    }
+}
+
+/// Returns whether `wasm` contains DWARF debug information, i.e., `.debug_*` sections.
+///
+/// Only the section headers are read, which is much cheaper than parsing the module.
+/// Malformed binaries are reported to have no debug information.
+pub fn has_debug_info(wasm: &[u8]) -> bool {
+    fn read_len(bytes: &[u8], pos: &mut usize) -> Option<usize> {
+        let mut len = 0;
+        for shift in (0..32).step_by(7) {
+            let byte = *bytes.get(*pos)?;
+            *pos += 1;
+            len |= usize::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Some(len);
+            }
+        }
+        None
+    }
+
+    if !wasm.starts_with(b"\0asm") {
+        return false;
+    }
+
+    // Skip the magic number and the version
+    let mut pos = 8;
+    while let Some(id) = wasm.get(pos) {
+        pos += 1;
+        let Some(size) = read_len(wasm, &mut pos) else {
+            return false;
+        };
+        let end = pos.saturating_add(size);
+
+        // Custom sections start with their name
+        let mut name_pos = pos;
+        if *id == 0
+            && let Some(len) = read_len(wasm, &mut name_pos)
+            && let Some(name) = wasm.get(name_pos..name_pos.saturating_add(len))
+            && name.starts_with(b".debug_")
+        {
+            return true;
+        }
+        pos = end;
+    }
+
+    false
+}
+
+fn line_rows(module: &Module) -> Result<Vec<(u64, Option<String>)>, Error> {
+    let dwarf = module
+        .debug
+        .dwarf
+        .borrow(|section| gimli::EndianSlice::new(section, gimli::LittleEndian));
+
+    let mut rows = Vec::new();
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let Some(program) = unit.line_program.clone() else {
+            continue;
+        };
+
+        let mut program_rows = program.rows();
+        while let Some((header, row)) = program_rows.next_row()? {
+            if row.end_sequence() {
+                rows.push((row.address(), None));
+                continue;
+            }
+
+            let Some(file) = row.file(header) else {
+                continue;
+            };
+            let mut path = dwarf
+                .attr_string(&unit, file.path_name())?
+                .to_string_lossy()
+                .into_owned();
+            if let Some(dir) = file.directory(header)
+                && !path.starts_with('/')
+            {
+                let dir = dwarf
+                    .attr_string(&unit, dir)?
+                    .to_string_lossy()
+                    .into_owned();
+                path = format!("{dir}/{path}");
+            }
+
+            let line = row.line().map(|line| line.get()).unwrap_or(0);
+            let position = match row.column() {
+                gimli::ColumnType::Column(column) => format!("{path}:{line}:{column}"),
+                gimli::ColumnType::LeftEdge => format!("{path}:{line}"),
+            };
+            rows.push((row.address(), Some(position)));
+        }
+    }
+
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use walrus::{ConstExpr, DataKind, Module, ModuleConfig, ir::Value};
+
+    use super::{LockSymbolizer, SourceMap, has_debug_info};
+    use crate::testing::{AbiModule, abi_module, attach_line_info};
+
+    fn example_module() -> Module {
+        let mut module = Module::with_config(ModuleConfig::new());
+        let memory = module.memories.add_local(false, false, 1, None, None);
+        for (offset, len, name) in [(1024, 64, ".data"), (2048, 16, ".bss")] {
+            let data = module.data.add(
+                DataKind::Active {
+                    memory,
+                    offset: ConstExpr::Value(Value::I32(offset)),
+                },
+                vec![0; len],
+            );
+            module.data.get_mut(data).name = Some(name.to_string());
+        }
+
+        module
+    }
+
+    #[test]
+    fn lookup_data_segment_symbols() -> Result<(), Error> {
+        let symbolizer = LockSymbolizer::from_wasm(&example_module().emit_wasm())?;
+
+        assert_eq!(symbolizer.lookup(1024).as_deref(), Some(".data"));
+        assert_eq!(symbolizer.lookup(1032).as_deref(), Some(".data+0x8"));
+        assert_eq!(symbolizer.lookup(2063).as_deref(), Some(".bss+0xf"));
+        assert_eq!(symbolizer.lookup(2064), None);
+        assert_eq!(symbolizer.lookup(16), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn lookup_source_positions() {
+        let source_map = SourceMap {
+            addresses: [(100, 4), (110, 12), (120, 20), (130, 30)].into(),
+            rows: vec![
+                (0, Some("src/main.rs:3:5".to_string())),
+                (10, Some("src/main.rs:4".to_string())),
+                (20, None),
+            ],
+        };
+
+        assert_eq!(source_map.lookup((100, 100)), Some("src/main.rs:3:5"));
+        assert_eq!(source_map.lookup((100, 110)), Some("src/main.rs:4"));
+        // The sequence ended before this address
+        assert_eq!(source_map.lookup((100, 130)), None);
+        // Unknown locations
+        assert_eq!(source_map.lookup((100, 140)), None);
+    }
+
+    #[test]
+    fn lookup_dwarf_line_program() -> Result<(), Error> {
+        let AbiModule { mut module, .. } = abi_module();
+        let mut wasm = module.emit_wasm();
+        attach_line_info(&mut wasm)?;
+        assert!(has_debug_info(&wasm));
+
+        // Locations are identified by the offsets of their instructions in the binary
+        let mut locations = Module::from_buffer(&wasm)?
+            .funcs
+            .iter_local()
+            .flat_map(|(_, func)| func.instruction_mapping.iter())
+            .map(|(address, loc)| (*address, loc.data()))
+            .collect::<Vec<_>>();
+        locations.sort();
+        assert!(locations.len() >= 3);
+
+        let source_map = SourceMap::from_wasm(&wasm)?;
+        for (line, (_, iidx)) in (1..).zip(&locations) {
+            let expected = format!("src/lib.rs:{line}");
+            assert_eq!(source_map.lookup((0, *iidx)), Some(expected.as_str()));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn degrade_without_debug_info() -> Result<(), Error> {
+        let source_map = SourceMap::from_wasm(&example_module().emit_wasm())?;
+        assert!(source_map.rows.is_empty());
+        assert_eq!(source_map.lookup((0, 0)), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn detect_debug_sections() {
+        let mut wasm = example_module().emit_wasm();
+        assert!(!has_debug_info(&wasm));
+
+        // Walrus only emits parsed debug information, so the section is appended by hand.
+        // Its size of 312 bytes is encoded as a LEB128 with two bytes.
+        wasm.extend([0, 0xb8, 0x02, 11]);
+        wasm.extend(b".debug_line");
+        wasm.extend([0; 300]);
+        assert!(has_debug_info(&wasm));
+        assert!(!has_debug_info(b"not a wasm binary"));
+    }
+}
@@ -0,0 +1,135 @@
+use anyhow::Error;
+use gimli::{
+    Encoding, Format, LittleEndian,
+    write::{Address, DwarfUnit, EndianVec, LineProgram, LineString, Sections},
+};
+use walrus::{ConstExpr, FunctionBuilder, FunctionId, MemoryId, Module, ValType, ir::Value};
+
+/// A minimal module that implements the Wasmgrind ABI, see [`abi_module`].
+pub struct AbiModule {
+    pub module: Module,
+    /// The local memory of the module, which is patched into a shared memory
+    pub memory: MemoryId,
+    /// The exported `__wasmgrind_thread_start`, whose body is empty
+    pub thread_start: FunctionId,
+}
+
+/// Creates a module that passes the validation against [`crate::abi::AbiFlavor::Standalone`].
+///
+/// It defines a single page of memory, the `__stack_pointer`, the TLS globals and
+/// the exported functions required by the standalone runtime. The functions do
+/// nothing, such that tests can add the behavior they need.
+pub fn abi_module() -> AbiModule {
+    let mut module = Module::default();
+    let memory = module.memories.add_local(false, false, 1, None, None);
+
+    let stack_pointer = module.globals.add_local(
+        ValType::I32,
+        true,
+        false,
+        ConstExpr::Value(Value::I32(1024)),
+    );
+    module.globals.get_mut(stack_pointer).name = Some("__stack_pointer".to_string());
+
+    for (name, value) in [("__tls_size", 16), ("__tls_align", 4)] {
+        let global = module.globals.add_local(
+            ValType::I32,
+            false,
+            false,
+            ConstExpr::Value(Value::I32(value)),
+        );
+        module.exports.add(name, global);
+    }
+
+    let mut exported = |name: &str, params: &[ValType]| {
+        let builder = FunctionBuilder::new(&mut module.types, params, &[]);
+        let args = params.iter().map(|ty| module.locals.add(*ty)).collect();
+        let func = builder.finish(args, &mut module.funcs);
+        module.exports.add(name, func);
+        func
+    };
+    exported("__wasm_init_tls", &[ValType::I32]);
+    exported("__wasmgrind_bootstrap", &[ValType::I32]);
+    let thread_start = exported("__wasmgrind_thread_start", &[ValType::I32, ValType::I32]);
+
+    AbiModule {
+        module,
+        memory,
+        thread_start,
+    }
+}
+
+/// Appends DWARF debug sections to `wasm` like a compiler emitting debug information.
+///
+/// The line program attributes the instructions of all local functions to consecutive
+/// lines of `src/lib.rs`, starting with line 1 at the lowest code address.
+pub fn attach_line_info(wasm: &mut Vec<u8>) -> Result<(), Error> {
+    let module = Module::from_buffer(wasm)?;
+    let mut addresses = module
+        .funcs
+        .iter_local()
+        .flat_map(|(_, func)| func.instruction_mapping.iter())
+        .map(|(address, _)| *address as u64)
+        .collect::<Vec<_>>();
+    addresses.sort();
+    let (Some(start), Some(end)) = (addresses.first(), addresses.last()) else {
+        return Ok(());
+    };
+
+    let encoding = Encoding {
+        format: Format::Dwarf32,
+        version: 4,
+        address_size: 4,
+    };
+    let mut program = LineProgram::new(
+        encoding,
+        Default::default(),
+        LineString::String(b"/build".to_vec()),
+        LineString::String(b"lib.rs".to_vec()),
+        None,
+    );
+    let dir = program.add_directory(LineString::String(b"src".to_vec()));
+    let file = program.add_file(LineString::String(b"lib.rs".to_vec()), dir, None);
+    program.begin_sequence(Some(Address::Constant(*start)));
+    for (line, address) in (1..).zip(&addresses) {
+        let row = program.row();
+        row.address_offset = address - start;
+        row.file = file;
+        row.line = line;
+        program.generate_row();
+    }
+    program.end_sequence(end - start + 1);
+
+    let mut dwarf = DwarfUnit::new(encoding);
+    dwarf.unit.line_program = program;
+    let mut sections = Sections::new(EndianVec::new(LittleEndian));
+    dwarf.write(&mut sections)?;
+
+    sections.for_each(|id, section| {
+        let data = section.slice();
+        if !data.is_empty() {
+            let name = id.name().as_bytes();
+            let mut payload = leb128(name.len());
+            payload.extend(name);
+            payload.extend(data);
+
+            wasm.push(0);
+            wasm.extend(leb128(payload.len()));
+            wasm.extend(payload);
+        }
+        Ok::<_, Error>(())
+    })
+}
+
+fn leb128(mut value: usize) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return bytes;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
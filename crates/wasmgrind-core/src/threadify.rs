@@ -32,8 +32,8 @@
 
 use anyhow::{Error, anyhow, bail};
 use walrus::{
-    ConstExpr, ExportItem, FunctionBuilder, FunctionId, GlobalId, GlobalKind, MemoryId, Module,
-    ValType, ir::Value,
+    ConstExpr, DataKind, ExportItem, FunctionBuilder, FunctionId, GlobalId, GlobalKind, ImportKind,
+    MemoryId, Module, ValType, ir::Value,
 };
 
 fn get_memory(module: &Module) -> Result<MemoryId, Error> {
@@ -49,7 +49,31 @@ fn get_memory(module: &Module) -> Result<MemoryId, Error> {
     })
 }
 
-fn get_stack_pointer(module: &Module) -> Option<GlobalId> {
+/// Selects the memory that is shared amongst threads.
+///
+/// Without a `memory_name`, the module must define exactly one memory. Otherwise,
+/// the memory exported or imported under the given name is selected, and all
+/// other memories of the module are left untouched.
+fn select_memory(module: &Module, memory_name: Option<&str>) -> Result<MemoryId, Error> {
+    let Some(name) = memory_name else {
+        return get_memory(module);
+    };
+
+    let exported = module.exports.iter().find_map(|export| match export.item {
+        ExportItem::Memory(memory) if export.name == name => Some(memory),
+        _ => None,
+    });
+    let imported = module.imports.iter().find_map(|import| match import.kind {
+        ImportKind::Memory(memory) if import.name == name => Some(memory),
+        _ => None,
+    });
+
+    exported
+        .or(imported)
+        .ok_or_else(|| anyhow!("module does not export or import a memory named `{name}`"))
+}
+
+pub(crate) fn get_stack_pointer(module: &Module) -> Option<GlobalId> {
     if let Some(g) = module
         .globals
         .iter()
@@ -61,13 +85,13 @@ fn get_stack_pointer(module: &Module) -> Option<GlobalId> {
     let candidates = module
         .globals
         .iter()
-        .filter(|g| g.ty == ValType::I32)
         .filter(|g| g.mutable)
         // The stack pointer is guaranteed to not be initialized to 0, and it's
-        // guaranteed to have an i32 initializer, so find globals which are
-        // locally defined, are an i32, and have a nonzero initializer
+        // guaranteed to have an i32 initializer (i64 for 64bit memories), so find
+        // globals which are locally defined, are an address, and have a nonzero initializer
         .filter(|g| match g.kind {
-            GlobalKind::Local(ConstExpr::Value(Value::I32(n))) => n != 0,
+            GlobalKind::Local(ConstExpr::Value(Value::I32(n))) => g.ty == ValType::I32 && n != 0,
+            GlobalKind::Local(ConstExpr::Value(Value::I64(n))) => g.ty == ValType::I64 && n != 0,
             _ => false,
         })
         .collect::<Vec<_>>();
@@ -101,7 +125,11 @@ fn delete_synthetic_global(module: &mut Module, name: &str) -> Result<u32, Error
     };
     match g {
         ConstExpr::Value(Value::I32(v)) => Ok(v as u32),
-        _ => bail!("`{}` was not an `i32` constant", name),
+        // Modules with 64bit memories use pointer-sized globals
+        ConstExpr::Value(Value::I64(v)) => {
+            u32::try_from(v).map_err(|_| anyhow!("`{}` does not fit into 32 bits", name))
+        }
+        _ => bail!("`{}` was not an `i32` or `i64` constant", name),
     }
 }
 
@@ -136,21 +164,45 @@ fn inject_instance_entry(module: &mut Module) -> Result<(), Error> {
     let stack_ptr_global =
         get_stack_pointer(module).ok_or_else(|| anyhow!("failed to find stack pointer"))?;
 
-    let mut builder = FunctionBuilder::new(
-        &mut module.types,
-        &[ValType::I32, ValType::I32, ValType::I32, ValType::I32],
-        &[],
-    );
+    // Addresses are i64 in modules with 64bit memories, so the parameters
+    // of the entry are derived from the functions it forwards them to.
+    let [start_fn_ptr_ty, start_fn_arg_ty] = *param_types(module, thread_start_func) else {
+        bail!("`__wasmgrind_thread_start` must take exactly two parameters");
+    };
+    let [tls_base_ptr_ty] = *param_types(module, tls_init_func) else {
+        bail!("`__wasm_init_tls` must take exactly one parameter");
+    };
+    let stack_ptr_ty = module.globals.get(stack_ptr_global).ty;
+
+    // Globals are private to each instance, i.e., to each thread. The main
+    // instance keeps the initial ID, which the runtime reserves for it.
+    let thread_id_global =
+        module
+            .globals
+            .add_local(ValType::I32, true, false, ConstExpr::Value(Value::I32(0)));
+    module.globals.get_mut(thread_id_global).name = Some("__wasmgrind_thread_id".to_string());
+
+    let params = [
+        start_fn_ptr_ty,
+        start_fn_arg_ty,
+        stack_ptr_ty,
+        tls_base_ptr_ty,
+        ValType::I32,
+    ];
+    let mut builder = FunctionBuilder::new(&mut module.types, &params, &[]);
 
     builder.name("__wasmgrind_instance_entry".into());
 
-    let start_fn_ptr = module.locals.add(ValType::I32);
-    let start_fn_arg = module.locals.add(ValType::I32);
-    let stack_ptr = module.locals.add(ValType::I32);
-    let tls_base_ptr = module.locals.add(ValType::I32);
+    let start_fn_ptr = module.locals.add(start_fn_ptr_ty);
+    let start_fn_arg = module.locals.add(start_fn_arg_ty);
+    let stack_ptr = module.locals.add(stack_ptr_ty);
+    let tls_base_ptr = module.locals.add(tls_base_ptr_ty);
+    let thread_id = module.locals.add(ValType::I32);
 
     builder
         .func_body()
+        .local_get(thread_id)
+        .global_set(thread_id_global)
         // First we store the exit code at the specified location
         .local_get(stack_ptr)
         .global_set(stack_ptr_global)
@@ -161,7 +213,13 @@ fn inject_instance_entry(module: &mut Module) -> Result<(), Error> {
         .call(thread_start_func);
 
     let instance_entry_id = builder.finish(
-        vec![start_fn_ptr, start_fn_arg, stack_ptr, tls_base_ptr],
+        vec![
+            start_fn_ptr,
+            start_fn_arg,
+            stack_ptr,
+            tls_base_ptr,
+            thread_id,
+        ],
         &mut module.funcs,
     );
 
@@ -169,13 +227,210 @@ fn inject_instance_entry(module: &mut Module) -> Result<(), Error> {
         .exports
         .add("__wasmgrind_instance_entry", instance_entry_id);
 
+    // Runners are instances created by the host to invoke exports concurrently. They
+    // set up their thread like the instance entry, but leave the call to the host.
+    let mut builder = FunctionBuilder::new(
+        &mut module.types,
+        &[stack_ptr_ty, tls_base_ptr_ty, ValType::I32],
+        &[],
+    );
+    builder.name("__wasmgrind_runner_entry".into());
+
+    let stack_ptr = module.locals.add(stack_ptr_ty);
+    let tls_base_ptr = module.locals.add(tls_base_ptr_ty);
+    let thread_id = module.locals.add(ValType::I32);
+
+    builder
+        .func_body()
+        .local_get(thread_id)
+        .global_set(thread_id_global)
+        .local_get(stack_ptr)
+        .global_set(stack_ptr_global)
+        .local_get(tls_base_ptr)
+        .call(tls_init_func);
+
+    let runner_entry_id =
+        builder.finish(vec![stack_ptr, tls_base_ptr, thread_id], &mut module.funcs);
+
+    module
+        .exports
+        .add("__wasmgrind_runner_entry", runner_entry_id);
+
+    let mut builder = FunctionBuilder::new(&mut module.types, &[], &[ValType::I32]);
+    builder.name("__wasmgrind_thread_id".into());
+    builder.func_body().global_get(thread_id_global);
+    let thread_id_accessor = builder.finish(vec![], &mut module.funcs);
+
+    module
+        .exports
+        .add("__wasmgrind_thread_id", thread_id_accessor);
+
+    Ok(())
+}
+
+fn param_types(module: &Module, func: FunctionId) -> Vec<ValType> {
+    module
+        .types
+        .get(module.funcs.get(func).ty())
+        .params()
+        .to_vec()
+}
+
+/// The maximum number of pages a 32bit WebAssembly memory can grow to.
+///
+/// Shared memories have to declare a maximum size. This is used as the default
+/// for memories without one, regardless of whether they are 64bit addressed.
+const MAX_MEMORY_PAGES: u64 = 65536;
+
+/// An active data segment that has been removed from a module by [`import_shared_memory`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct DataSegment {
+    /// The memory address where the segment has to be placed
+    pub offset: u64,
+    /// The contents of the segment
+    pub data: Vec<u8>,
+}
+
+/// Turns a memory defined by a binary WebAssembly module into an imported shared memory
+///
+/// Threads are spawned as separate instances of the same module, which only works if
+/// all of them import the same shared memory. Modules that have not been linked with
+/// `--import-memory --shared-memory` define their own memory instead. This function
+/// replaces such a memory with an import from `import_module`.`import_name`, marks it
+/// as shared and keeps its limits. If the memory had no maximum size, it may grow up to 4GiB.
+///
+/// 64bit addressed memories are only transformed if the `memory64` feature is enabled,
+/// and their data segments are placed at 64bit offsets. walrus currently emits imported
+/// memories as 32bit addressed, so the binary of such a transformed module would be invalid.
+///
+/// Active data segments would re-initialize the shared memory whenever a thread instantiates
+/// the module. They are therefore removed from the module and returned to the caller,
+/// who has to write them into the shared memory **once** before the first instantiation.
+///
+/// Modules that already import their memory are left untouched and no segments are returned.
+///
+/// Modules with multiple memories have to designate the memory to be shared via
+/// `memory_name`, which refers to the name under which that memory is exported or imported.
+/// Only this memory and its data segments are transformed.
+///
+/// # Errors
+///
+/// This function may fail in the following cases:
+/// - The given `module` did not define _exactly one_ memory and no `memory_name` was given.
+/// - The `module` did not export or import a memory named `memory_name`.
+/// - An active data segment was placed at an offset that is not a constant.
+/// - The memory is 64bit addressed and the `memory64` feature is disabled.
+pub fn import_shared_memory(
+    module: &mut Module,
+    memory_name: Option<&str>,
+    import_module: &str,
+    import_name: &str,
+) -> Result<Vec<DataSegment>, Error> {
+    let memory_id = select_memory(module, memory_name)?;
+    let memory = module.memories.get(memory_id);
+    if memory.import.is_some() {
+        return Ok(Vec::new());
+    }
+    if memory.memory64 && !cfg!(feature = "memory64") {
+        bail!("Sharing 64bit memories requires the `memory64` feature of wasmgrind-core");
+    }
+
+    let mut segments = Vec::new();
+    let mut segment_ids = Vec::new();
+    for data in module.data.iter() {
+        if let DataKind::Active { memory, offset } = &data.kind {
+            if *memory != memory_id {
+                continue;
+            }
+            let offset = match offset {
+                ConstExpr::Value(Value::I32(offset)) => u64::from(*offset as u32),
+                ConstExpr::Value(Value::I64(offset)) => *offset as u64,
+                _ => bail!("Active data segments with non-constant offsets are unsupported!"),
+            };
+            segments.push(DataSegment {
+                offset,
+                data: data.value.clone(),
+            });
+            segment_ids.push(data.id());
+        }
+    }
+
+    for id in &segment_ids {
+        module.data.delete(*id);
+    }
+
+    let import_id = module
+        .imports
+        .add(import_module, import_name, ImportKind::Memory(memory_id));
+
+    let memory = module.memories.get_mut(memory_id);
+    memory.import = Some(import_id);
+    memory.shared = true;
+    memory.maximum = memory.maximum.or(Some(MAX_MEMORY_PAGES));
+    memory
+        .data_segments
+        .retain(|segment| !segment_ids.contains(segment));
+
+    Ok(segments)
+}
+
+/// The maximum number of pages a 64bit WebAssembly memory can grow to.
+const MAX_MEMORY64_PAGES: u64 = 1 << 48;
+
+/// Raises the maximum size of the memory shared amongst threads by `headroom_pages`
+///
+/// Shared memories can not grow beyond the maximum size declared by the module, which
+/// is often equal to its initial size. Since an imported memory must not be larger than
+/// the declared maximum, the headroom has to be added to the module itself before it is
+/// compiled. The memory created for the patched module then uses the raised maximum.
+///
+/// # Errors
+///
+/// This function may fail in the following cases:
+/// - The given `module` did not define _exactly one_ memory and no `memory_name` was given.
+/// - The `module` did not export or import a memory named `memory_name`.
+/// - The `module` memory had no maximum size associated with it.
+/// - The initial size of the `module` memory exceeds its maximum size.
+/// - The raised maximum exceeds the size that the memory is able to address.
+pub fn add_memory_headroom(
+    module: &mut Module,
+    memory_name: Option<&str>,
+    headroom_pages: u32,
+) -> Result<(), Error> {
+    let memory_id = select_memory(module, memory_name)?;
+    let memory = module.memories.get_mut(memory_id);
+    let maximum = memory
+        .maximum
+        .ok_or_else(|| anyhow!("Module memory hand no maximum size specified!"))?;
+
+    if memory.initial > maximum {
+        bail!(
+            "The initial size of the module memory ({} pages) exceeds its maximum size ({maximum} pages)",
+            memory.initial
+        );
+    }
+
+    let limit = if memory.memory64 {
+        MAX_MEMORY64_PAGES
+    } else {
+        MAX_MEMORY_PAGES
+    };
+    let raised = maximum + u64::from(headroom_pages);
+    if raised > limit {
+        bail!(
+            "The module memory can not grow by {headroom_pages} pages beyond its maximum size ({maximum} pages), as it may not exceed {limit} pages"
+        );
+    }
+
+    memory.maximum = Some(raised);
+
     Ok(())
 }
 
 /// Retrieves the memory limits of a binary WebAssembly module
 ///
 /// The given `module` has to fulfill the following requirements:
-/// - It must define _exactly one_ memory.
+/// - It must define _exactly one_ memory, or designate one via `memory_name`.
 /// - The memory has to be marked as `shared`.
 /// - The memory has to be 32bit addressed.
 ///
@@ -184,31 +439,304 @@ fn inject_instance_entry(module: &mut Module) -> Result<(), Error> {
 /// # Errors
 ///
 /// This function may fail in the following cases:
-/// - The given `module` did not define _exactly one_ memory.
+/// - The given `module` did not define _exactly one_ memory and no `memory_name` was given.
+/// - The `module` did not export or import a memory named `memory_name`.
 /// - The `module` memory was not marked as `shared`.
 /// - The `module` memory was 64bit addressed.
 /// - The `module` memory had no maximum size associated with it.
 ///   (although this is disallowed when the memory is marked as `shared`).
-pub fn get_shared_memory_size(module: &Module) -> Result<(u32, u32), Error> {
-    let memory_id = get_memory(module)?;
+pub fn get_shared_memory_size(
+    module: &Module,
+    memory_name: Option<&str>,
+) -> Result<(u32, u32), Error> {
+    let memory_id = select_memory(module, memory_name)?;
+    if module.memories.get(memory_id).memory64 {
+        bail!("Module memory is 64bit. Use `get_shared_memory_size64` instead!");
+    }
+
+    let (min, max) = get_shared_memory_size64(module, memory_name)?;
+    Ok((u32::try_from(min)?, u32::try_from(max)?))
+}
+
+/// Retrieves the memory limits of a binary WebAssembly module with a 32bit or 64bit memory
+///
+/// This behaves like [`get_shared_memory_size`] but also accepts 64bit addressed memories.
+/// Whether the memory is 64bit addressed can be determined via [`is_memory64`].
+pub fn get_shared_memory_size64(
+    module: &Module,
+    memory_name: Option<&str>,
+) -> Result<(u64, u64), Error> {
+    let memory_id = select_memory(module, memory_name)?;
     let memory = module.memories.get(memory_id);
     if !memory.shared {
         bail!("Module memory is not shared!");
     }
 
-    if memory.memory64 {
-        bail!("Module memory is 64bit. This is unsupported!");
-    }
-
-    let min = u32::try_from(memory.initial)?;
     memory
         .maximum
-        .map(u32::try_from)
-        .transpose()?
-        .map(|max| (min, max))
+        .map(|max| (memory.initial, max))
         .ok_or_else(|| anyhow!("Module memory hand no maximum size specified!"))
 }
 
+/// Returns whether the memory shared amongst threads is 64bit addressed.
+pub fn is_memory64(module: &Module, memory_name: Option<&str>) -> Result<bool, Error> {
+    let memory_id = select_memory(module, memory_name)?;
+    Ok(module.memories.get(memory_id).memory64)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use walrus::{ConstExpr, DataKind, Module, ir::Value};
+
+    use super::{DataSegment, add_memory_headroom, get_shared_memory_size, import_shared_memory};
+
+    fn module_with_memory(maximum: Option<u64>) -> Module {
+        let mut module = Module::default();
+        let memory = module.memories.add_local(false, false, 2, maximum, None);
+        module.exports.add("memory", memory);
+        module.data.add(
+            DataKind::Active {
+                memory,
+                offset: ConstExpr::Value(Value::I32(1024)),
+            },
+            b"hello".to_vec(),
+        );
+        module.data.add(DataKind::Passive, b"world".to_vec());
+
+        module
+    }
+
+    #[test]
+    fn import_defined_memory() -> Result<(), Error> {
+        let mut module = module_with_memory(Some(16));
+
+        let segments = import_shared_memory(&mut module, None, "env", "memory")?;
+
+        assert_eq!(
+            segments,
+            vec![DataSegment {
+                offset: 1024,
+                data: b"hello".to_vec(),
+            }]
+        );
+        assert_eq!(get_shared_memory_size(&module, None)?, (2, 16));
+        assert!(module.imports.find("env", "memory").is_some());
+        assert_eq!(module.data.iter().count(), 1);
+
+        // The transformed module has to be valid
+        let module = Module::from_buffer(&module.emit_wasm())?;
+        assert_eq!(get_shared_memory_size(&module, None)?, (2, 16));
+
+        Ok(())
+    }
+
+    #[test]
+    fn import_defined_memory_without_maximum() -> Result<(), Error> {
+        let mut module = module_with_memory(None);
+
+        import_shared_memory(&mut module, None, "env", "memory")?;
+
+        assert_eq!(get_shared_memory_size(&module, None)?, (2, 65536));
+
+        Ok(())
+    }
+
+    #[test]
+    fn raise_memory_maximum() -> Result<(), Error> {
+        let mut module = module_with_memory(Some(2));
+        import_shared_memory(&mut module, None, "env", "memory")?;
+
+        add_memory_headroom(&mut module, None, 0)?;
+        assert_eq!(get_shared_memory_size(&module, None)?, (2, 2));
+
+        add_memory_headroom(&mut module, None, 14)?;
+        assert_eq!(get_shared_memory_size(&module, None)?, (2, 16));
+
+        let err = add_memory_headroom(&mut module, None, 65536).unwrap_err();
+        assert!(err.to_string().contains("may not exceed 65536 pages"));
+        assert_eq!(get_shared_memory_size(&module, None)?, (2, 16));
+
+        let memory = module.memories.iter_mut().next().unwrap();
+        memory.initial = 17;
+        let err = add_memory_headroom(&mut module, None, 1).unwrap_err();
+        assert!(err.to_string().contains("exceeds its maximum size"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn keep_imported_memory() -> Result<(), Error> {
+        let mut module = Module::default();
+        module.add_import_memory("env", "memory", true, false, 1, Some(4), None);
+        let wasm = module.emit_wasm();
+
+        let segments = import_shared_memory(&mut module, None, "env", "memory")?;
+
+        assert!(segments.is_empty());
+        assert_eq!(wasm, module.emit_wasm());
+
+        Ok(())
+    }
+
+    #[test]
+    fn import_designated_memory() -> Result<(), Error> {
+        let mut module = module_with_memory(Some(16));
+        let private = module.memories.add_local(false, false, 1, Some(1), None);
+        module.exports.add("scratch", private);
+        module.data.add(
+            DataKind::Active {
+                memory: private,
+                offset: ConstExpr::Value(Value::I32(0)),
+            },
+            b"private".to_vec(),
+        );
+
+        import_shared_memory(&mut module, None, "env", "memory").unwrap_err();
+        import_shared_memory(&mut module, Some("unknown"), "env", "memory").unwrap_err();
+
+        let segments = import_shared_memory(&mut module, Some("memory"), "env", "memory")?;
+
+        assert_eq!(
+            segments,
+            vec![DataSegment {
+                offset: 1024,
+                data: b"hello".to_vec(),
+            }]
+        );
+        assert_eq!(get_shared_memory_size(&module, Some("memory"))?, (2, 16));
+        get_shared_memory_size(&module, Some("scratch")).unwrap_err();
+
+        let private = module.memories.get(private);
+        assert!(private.import.is_none() && !private.shared);
+        assert_eq!(module.data.iter().count(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(not(feature = "memory64"))]
+    fn reject_memory64_module() {
+        let mut module = Module::default();
+        module.memories.add_local(false, true, 2, Some(8), None);
+        import_shared_memory(&mut module, None, "env", "memory").unwrap_err();
+        assert!(module.imports.iter().next().is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "memory64")]
+    fn patch_memory64_module() -> Result<(), Error> {
+        use walrus::{ExportItem, FunctionBuilder, ValType};
+
+        use super::{
+            extract_tls_align, extract_tls_size, get_shared_memory_size64, get_stack_pointer,
+            is_memory64, patch,
+        };
+
+        let mut module = Module::default();
+        let memory = module.memories.add_local(false, true, 2, Some(8), None);
+        module.data.add(
+            DataKind::Active {
+                memory,
+                offset: ConstExpr::Value(Value::I64(1 << 16)),
+            },
+            b"hello".to_vec(),
+        );
+
+        let stack_pointer = module.globals.add_local(
+            ValType::I64,
+            true,
+            false,
+            ConstExpr::Value(Value::I64(1024)),
+        );
+        for (name, value) in [("__tls_size", 16), ("__tls_align", 8)] {
+            let global = module.globals.add_local(
+                ValType::I64,
+                false,
+                false,
+                ConstExpr::Value(Value::I64(value)),
+            );
+            module.exports.add(name, global);
+        }
+        for (name, params) in [
+            (
+                "__wasmgrind_thread_start",
+                &[ValType::I32, ValType::I64][..],
+            ),
+            ("__wasm_init_tls", &[ValType::I64][..]),
+        ] {
+            let builder = FunctionBuilder::new(&mut module.types, params, &[]);
+            let args = params.iter().map(|ty| module.locals.add(*ty)).collect();
+            let func = builder.finish(args, &mut module.funcs);
+            module.exports.add(name, func);
+        }
+
+        assert_eq!(get_stack_pointer(&module), Some(stack_pointer));
+        patch(&mut module)?;
+        let segments = import_shared_memory(&mut module, None, "env", "memory")?;
+        assert_eq!(
+            segments,
+            vec![DataSegment {
+                offset: 1 << 16,
+                data: b"hello".to_vec(),
+            }]
+        );
+        assert_eq!(
+            (
+                extract_tls_size(&mut module)?,
+                extract_tls_align(&mut module)?
+            ),
+            (16, 8)
+        );
+
+        // walrus emits imported memories as 32bit addressed, so the
+        // transformed module is inspected without a roundtrip
+        assert!(is_memory64(&module, None)?);
+        assert_eq!(get_shared_memory_size64(&module, None)?, (2, 8));
+        get_shared_memory_size(&module, None).unwrap_err();
+
+        let ExportItem::Function(entry) = module
+            .exports
+            .iter()
+            .find(|e| e.name == "__wasmgrind_instance_entry")
+            .expect("Instance entry was not exported")
+            .item
+        else {
+            panic!("Instance entry is not a function");
+        };
+        assert_eq!(
+            module.types.get(module.funcs.get(entry).ty()).params(),
+            [
+                ValType::I32,
+                ValType::I64,
+                ValType::I64,
+                ValType::I64,
+                ValType::I32
+            ]
+        );
+        assert!(module.exports.get_func("__wasmgrind_thread_id").is_ok());
+
+        let runner_entry = module.exports.get_func("__wasmgrind_runner_entry")?;
+        assert_eq!(
+            module
+                .types
+                .get(module.funcs.get(runner_entry).ty())
+                .params(),
+            [ValType::I64, ValType::I64, ValType::I32]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn fail_on_unsupported_memories() {
+        let mut module = Module::default();
+        module.memories.add_local(false, false, 1, None, None);
+        module.memories.add_local(false, false, 1, None, None);
+        import_shared_memory(&mut module, None, "env", "memory").unwrap_err();
+    }
+}
+
 // ================================================================================================
 // We might need this code again in the future:
 
@@ -2,44 +2,81 @@ use std::{
     cell::RefCell,
     collections::HashMap,
     fs::File,
-    io::BufWriter,
-    path::Path,
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
     sync::{
-        Mutex,
+        Arc, Mutex, OnceLock, Weak,
         atomic::{AtomicBool, AtomicU32, Ordering},
     },
 };
 
-use anyhow::Error;
+use anyhow::{Error, bail};
 use representation::Event;
-use trace_tools::{generic::Encoder, rapidbin::encoder::RapidBinEncoder};
+use trace_tools::{
+    Codec, RapidBinParser,
+    analysis::{DeadlockDetector, DeadlockReport},
+    compression::compress,
+    generic::{Encoder, Parser},
+    open_trace,
+    rapidbin::{RapidBinLayout, encoder::RapidBinEncoder},
+};
 
 use crate::tracing::{
     converter::WasmgrindTraceConverter,
     metadata::WasmgrindTraceMetadata,
-    trace::{EventHandle, Trace},
+    stats::EventCounters,
+    timestamps::write_timestamps,
+    trace::{CachedTrace, EventHandle, FileTrace, Trace},
 };
 
+mod clock;
 mod converter;
+mod filter;
 
 /// Utilities to manage metadata of Wasmgrind execution traces.
 pub mod metadata;
+mod replay;
 mod representation;
+mod stats;
+mod timestamps;
 mod trace;
 
+pub use clock::{ClockSource, MonotonicClock};
+pub use filter::{OpKind, TraceFilter};
+pub use replay::ReplayScheduler;
 pub use representation::Op;
+pub use stats::RecordingStats;
+pub use timestamps::timestamps_path;
 
 thread_local! {
-    static THREAD_STATE: RefCell<ThreadState> = const { RefCell::new(ThreadState { id: None, ignore_memory_events: false }) };
+    /// The states of the current thread, one for each [`Tracing`] instance it has been used with
+    static THREAD_STATES: RefCell<Vec<ThreadState>> = const { RefCell::new(Vec::new()) };
 }
 
 pub type Tid = u32;
 
+/// A callback that is invoked with every deadlock detected while tracing.
+///
+/// See [`Tracing::on_deadlock`].
+type DeadlockHook = Box<dyn Fn(&DeadlockReport<(u32, u32)>, &[u32]) + Send + Sync>;
+
 struct ThreadState {
+    /// The [`Tracing`] instance this state belongs to
+    owner: Weak<()>,
     id: Option<Tid>,
     ignore_memory_events: bool,
 }
 
+impl ThreadState {
+    fn new(owner: Weak<()>) -> Self {
+        Self {
+            owner,
+            id: None,
+            ignore_memory_events: false,
+        }
+    }
+}
+
 struct ThreadRecord {
     id: Tid,
 }
@@ -47,16 +84,76 @@ struct ThreadRecord {
 struct MutexRecord {
     id: u32,
     owner: Tid,
-    last_event: Option<EventHandle>,
+    last_event: Option<RecordedEvent>,
+}
+
+/// An event that has been appended to the trace and may be invalidated later on.
+struct RecordedEvent {
+    handle: EventHandle,
+    kind: OpKind,
+}
+
+/// Where the events of a [`Tracing`] are kept until the trace is emitted.
+enum TraceStorage {
+    /// Events are cached and converted when the trace is generated
+    Cached(Trace),
+    /// Events are converted and written to a RapidBin file as they arrive
+    File(Box<FileTrace>),
+}
+
+impl TraceStorage {
+    fn append_event(&self, event: Event) -> EventHandle {
+        match self {
+            TraceStorage::Cached(trace) => trace.append_event(event),
+            TraceStorage::File(trace) => trace.append_event(event),
+        }
+    }
+
+    fn n_events(&self) -> u64 {
+        match self {
+            TraceStorage::Cached(trace) => trace.n_events(),
+            TraceStorage::File(trace) => trace.n_events(),
+        }
+    }
+
+    fn invalidate(&self, event_handle: EventHandle) {
+        match self {
+            TraceStorage::Cached(trace) => trace.invalidate(event_handle),
+            TraceStorage::File(trace) => trace.invalidate(event_handle),
+        }
+    }
+
+    fn close(self) -> Result<CachedTrace, Error> {
+        match self {
+            TraceStorage::Cached(trace) => trace.close(),
+            TraceStorage::File(_) => bail!(
+                "The trace has already been written to a file. Use Tracing::finalize to complete it instead."
+            ),
+        }
+    }
 }
 
 pub struct Tracing {
+    /// Identifies the thread states of this instance
+    instance: Arc<()>,
     tid_counter: AtomicU32,
+    /// The synthetic thread that forks all runner threads
+    runner_root: OnceLock<Tid>,
     mutex_counter: AtomicU32,
     initialized: AtomicBool,
-    events: Trace,
+    events: TraceStorage,
     threads: Mutex<HashMap<u32, ThreadRecord>>,
+    thread_names: Mutex<HashMap<Tid, String>>,
     mutexes: Mutex<HashMap<u32, MutexRecord>>,
+    /// Maps the IDs of all mutexes ever registered to their (userspace) addresses
+    lock_addresses: Mutex<HashMap<u32, u32>>,
+    deadlocks: Option<Mutex<DeadlockDetector<(u32, u32)>>>,
+    on_deadlock: Option<DeadlockHook>,
+    filter: Option<TraceFilter>,
+    replay: Option<ReplayScheduler>,
+    output_window: Option<u64>,
+    clock: Option<Box<dyn ClockSource>>,
+    counters: EventCounters,
 }
 
 impl Tracing {
@@ -64,19 +161,225 @@ impl Tracing {
     pub const THREAD_CREATE_DETACHED: u32 = 1;
     pub const MUTEX_INIT_NORMAL: u32 = 0;
     pub const MUTEX_INIT_RECURSIVE: u32 = 1;
+    /// The location of the synthetic events that start runner threads
+    const RUNNER_LOCATION: (u32, u32) = (0, 0);
 
     /// Creates an empty execution trace.
     pub fn new<P: AsRef<Path>>(cache_dir: P) -> Self {
+        Self::with_storage(TraceStorage::Cached(Trace::new(cache_dir)))
+    }
+
+    /// Creates an empty execution trace that is written to `outfile` as events arrive.
+    ///
+    /// Events are emitted in RapidBin format right away instead of being cached until
+    /// the trace is generated, so a full trace can be recorded without holding it in
+    /// memory or on disk twice. Call [`Tracing::finalize`] to complete the trace file;
+    /// [`Tracing::generate_trace`] and its variants fail for such traces.
+    ///
+    /// The trace is always emitted with [`RapidBinLayout::WIDE_LOCATIONS`]. Recording
+    /// never fails: if an event can not be written, the trace stops and the error is
+    /// returned by [`Tracing::finalize`]. [`Tracing::with_output_window`] has no effect
+    /// on such traces.
+    pub fn to_file<P: AsRef<Path>>(outfile: P) -> Result<Self, Error> {
+        let events = FileTrace::create(outfile)?;
+        Ok(Self::with_storage(TraceStorage::File(Box::new(events))))
+    }
+
+    fn with_storage(events: TraceStorage) -> Self {
         Self {
+            instance: Arc::new(()),
             tid_counter: AtomicU32::new(0),
+            runner_root: OnceLock::new(),
             mutex_counter: AtomicU32::new(0),
             initialized: AtomicBool::new(false),
-            events: Trace::new(cache_dir),
+            events,
             threads: Mutex::new(HashMap::new()),
+            thread_names: Mutex::new(HashMap::new()),
             mutexes: Mutex::new(HashMap::new()),
+            lock_addresses: Mutex::new(HashMap::new()),
+            deadlocks: None,
+            on_deadlock: None,
+            filter: None,
+            replay: None,
+            output_window: None,
+            clock: None,
+            counters: EventCounters::default(),
+        }
+    }
+
+    /// Restricts the recorded events to those matching `filter`.
+    ///
+    /// Events that do not match are dropped before they are appended to the trace.
+    pub fn with_filter(mut self, filter: TraceFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Enables the online detection of deadlocks.
+    ///
+    /// Lock requests, acquisitions, and releases are fed into a wait-for graph.
+    /// Detected deadlocks are logged and can be retrieved via [`Tracing::deadlock_report`].
+    pub fn with_deadlock_detection(mut self) -> Self {
+        self.deadlocks = Some(Mutex::new(DeadlockDetector::new()));
+        self
+    }
+
+    /// Calls `hook` whenever a deadlock is detected and enables the detection if necessary.
+    ///
+    /// Besides the report, the hook receives the userspace addresses of the deadlocked locks,
+    /// i.e., the IDs the guest passed to the lock hooks. It runs on the thread that closed
+    /// the cycle while the detector is locked, so it must not call back into the tracing.
+    pub fn on_deadlock(
+        mut self,
+        hook: impl Fn(&DeadlockReport<(u32, u32)>, &[u32]) + Send + Sync + 'static,
+    ) -> Self {
+        if self.deadlocks.is_none() {
+            self = self.with_deadlock_detection();
+        }
+        self.on_deadlock = Some(Box::new(hook));
+        self
+    }
+
+    /// Limits the generated trace to the most recent `capacity` events.
+    ///
+    /// This is a window on the output, not a bound on the recording: every event is
+    /// still cached on disk until the trace is generated, and only then are the older
+    /// events dropped. Memory usage is bounded by the per-thread event buffers either way.
+    /// The number of dropped events is recorded in the metadata (see
+    /// [`WasmgrindTraceMetadata::dropped_events`]). The truncated trace may start in the
+    /// middle of critical sections or after threads have been forked, which analyses of
+    /// the trace have to tolerate.
+    pub fn with_output_window(mut self, capacity: u64) -> Self {
+        self.output_window = Some(capacity);
+        self
+    }
+
+    /// Attaches a timestamp taken from `clock` to every recorded event.
+    ///
+    /// RapidBin traces have no room for timestamps, so they are emitted into a separate
+    /// file next to the trace instead (see [`WasmgrindTraceMetadata::timestamps`]).
+    /// Without this, neither the trace nor its metadata contain any timestamps.
+    pub fn with_timestamps(mut self, clock: impl ClockSource + 'static) -> Self {
+        self.clock = Some(Box::new(clock));
+        self
+    }
+
+    /// Replays the order of the synchronization events recorded by `scheduler`.
+    ///
+    /// Fork, join, and aquire events are delayed until it is their turn in the recorded
+    /// trace. The execution is aborted if it diverges from the recording.
+    pub fn with_replay(mut self, scheduler: ReplayScheduler) -> Self {
+        self.replay = Some(scheduler);
+        self
+    }
+
+    /// Returns the number of recorded synchronization events that have not been replayed yet.
+    ///
+    /// Returns `None` if no replay has been configured.
+    pub fn replay_remaining(&self) -> Option<usize> {
+        self.replay.as_ref().map(ReplayScheduler::remaining)
+    }
+
+    /// Returns all deadlocks that have been detected so far.
+    ///
+    /// The returned report is empty if deadlock detection has not been enabled.
+    pub fn deadlock_report(&self) -> Vec<DeadlockReport<(u32, u32)>> {
+        self.deadlocks
+            .as_ref()
+            .map(|detector| {
+                detector
+                    .lock()
+                    .expect("Could not lock deadlock detector!")
+                    .reports()
+                    .to_vec()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the number of events recorded so far.
+    ///
+    /// This is meant for progress reporting while the traced program is running.
+    /// Events of mutex operations that are invalidated later on are included.
+    pub fn event_count(&self) -> u64 {
+        self.events.n_events()
+    }
+
+    /// Returns whether no event has been recorded so far.
+    pub fn is_empty(&self) -> bool {
+        self.event_count() == 0
+    }
+
+    /// Returns the number of events of each kind recorded so far.
+    ///
+    /// In contrast to [`Tracing::event_count`], invalidated events are not included,
+    /// so the counts match the generated trace. If the trace is limited by
+    /// [`Tracing::with_output_window`], the counts also include the events that are dropped.
+    pub fn stats(&self) -> RecordingStats {
+        self.counters.snapshot()
+    }
+
+    /// Returns the approximate size of the generated trace in bytes.
+    ///
+    /// See [`RecordingStats::approx_size_bytes`].
+    pub fn approx_size_bytes(&self) -> u64 {
+        self.stats().approx_size_bytes()
+    }
+
+    /// Returns the number of threads that have been created so far, including the main thread.
+    pub fn thread_count(&self) -> u32 {
+        self.tid_counter.load(Ordering::Relaxed)
+    }
+
+    fn next_mutex_id(&self, userspace_mutex_id: u32) -> u32 {
+        let mutex_id = self.mutex_counter.fetch_add(1, Ordering::Relaxed);
+        self.lock_addresses
+            .lock()
+            .expect("Could not lock lock address registry!")
+            .insert(mutex_id, userspace_mutex_id);
+        mutex_id
+    }
+
+    fn with_deadlock_detector<F: FnOnce(&mut DeadlockDetector<(u32, u32)>)>(&self, f: F) {
+        if let Some(detector) = &self.deadlocks {
+            f(&mut detector.lock().expect("Could not lock deadlock detector!"));
+        }
+    }
+
+    #[inline]
+    fn replay_wait(&self, t: Tid, kind: OpKind) {
+        if let Some(scheduler) = &self.replay {
+            scheduler.wait_turn(t, kind);
+        }
+    }
+
+    #[inline]
+    fn replay_complete(&self, t: Tid, kind: OpKind, loc: (u32, u32)) {
+        if let Some(scheduler) = &self.replay {
+            scheduler.complete(t, kind, loc);
         }
     }
 
+    /// Provides access to the state of the current thread for this instance.
+    ///
+    /// Threads may be used by multiple instances, e.g., the main thread of a test harness,
+    /// so every instance has its own state. States of dropped instances are released when
+    /// the thread is used by a new instance or exits.
+    #[inline]
+    fn with_thread_state<R, F: FnOnce(&mut ThreadState) -> R>(&self, f: F) -> R {
+        THREAD_STATES.with_borrow_mut(|states| {
+            let owner = Arc::as_ptr(&self.instance);
+            let idx = match states.iter().position(|s| s.owner.as_ptr() == owner) {
+                Some(idx) => idx,
+                None => {
+                    states.retain(|s| s.owner.strong_count() > 0);
+                    states.push(ThreadState::new(Arc::downgrade(&self.instance)));
+                    states.len() - 1
+                }
+            };
+            f(&mut states[idx])
+        })
+    }
+
     #[inline]
     pub fn initialize(&self) {
         if !self.initialized.load(Ordering::Relaxed) {
@@ -86,7 +389,7 @@ impl Tracing {
                 "initialize should be called from the first thread, i.e., the main thread!"
             );
 
-            let prev_tid = THREAD_STATE.with_borrow_mut(|thread_state| {
+            let prev_tid = self.with_thread_state(|thread_state| {
                 if thread_state.ignore_memory_events {
                     log::warn!(
                         "Recording of memory access events was disabled at initialization time."
@@ -102,6 +405,8 @@ impl Tracing {
             self.initialized.store(true, Ordering::Relaxed);
 
             log::info!("Successfully initialized Tracing.");
+        } else if self.with_thread_state(|thread_state| thread_state.id.is_some()) {
+            log::debug!("Initialize called from a registered runner. Keeping its TID ...");
         } else {
             log::debug!(
                 "Initialize called from a thread other than the first. Starting to ignore memory accesses ..."
@@ -112,7 +417,7 @@ impl Tracing {
 
     #[inline]
     pub fn thread_ignore_begin(&self) {
-        THREAD_STATE.with_borrow_mut(|thread_state| {
+        self.with_thread_state(|thread_state| {
             if std::mem::replace(&mut thread_state.ignore_memory_events, true) {
                 log::warn!("Memory access event ignore flag was set although already being set! Did you forgot to call thread_ignore_end?")
             } else {
@@ -123,7 +428,7 @@ impl Tracing {
 
     #[inline]
     pub fn thread_ignore_end(&self) {
-        THREAD_STATE.with_borrow_mut(|thread_state| {
+        self.with_thread_state(|thread_state| {
             if !std::mem::replace(&mut thread_state.ignore_memory_events, false) {
                 log::warn!("Memory access event ignore flag was unset although already being unset! Did you forgot to call thread_ignore_start?")
             } else {
@@ -132,15 +437,32 @@ impl Tracing {
         });
     }
 
-    /// Append a new event to the execution trace.
+    /// Append a new event to the execution trace unless it is rejected by the filter.
     #[inline]
-    fn add_event(&self, tid: u32, op: Op, loc: (u32, u32)) -> EventHandle {
-        self.events.append_event(Event { t: tid, op, loc })
+    fn add_event(&self, tid: u32, op: Op, loc: (u32, u32)) -> Option<RecordedEvent> {
+        if let Some(filter) = &self.filter
+            && !filter.matches(tid, &op)
+        {
+            return None;
+        }
+
+        let kind = OpKind::from(&op);
+        self.counters.record(kind);
+        let timestamp = self.clock.as_ref().map(|clock| clock.now());
+        Some(RecordedEvent {
+            handle: self.events.append_event(Event {
+                t: tid,
+                op,
+                loc,
+                timestamp,
+            }),
+            kind,
+        })
     }
 
     #[inline]
     pub fn memory_access_read(&self, addr: u32, width: u32, atomic: u32, loc: (u32, u32)) {
-        THREAD_STATE.with_borrow(|thread_state| {
+        self.with_thread_state(|thread_state| {
             if !thread_state.ignore_memory_events {
                 if let Some(current_id) = thread_state.id {
                     self.add_event(current_id, Op::Read { addr, n: width, atomic: atomic != 0 }, loc);
@@ -158,7 +480,7 @@ impl Tracing {
 
     #[inline]
     pub fn memory_access_write(&self, addr: u32, width: u32, atomic: u32, loc: (u32, u32)) {
-        THREAD_STATE.with_borrow(|thread_state| {
+        self.with_thread_state(|thread_state| {
             if !thread_state.ignore_memory_events {
                 if let Some(current_id) = thread_state.id {
                     self.add_event(current_id, Op::Write { addr, n: width, atomic: atomic != 0 }, loc);
@@ -176,8 +498,9 @@ impl Tracing {
 
     #[inline]
     pub fn thread_create(&self, userspace_child_id: u32, flags: u32, loc: (u32, u32)) -> Tid {
-        THREAD_STATE.with_borrow(|thread_state| {
+        self.with_thread_state(|thread_state| {
             if let Some(current_tid) = thread_state.id {
+                self.replay_wait(current_tid, OpKind::Fork);
                 let tid = self.tid_counter.fetch_add(1, Ordering::Relaxed);
 
                 if flags & Self::THREAD_CREATE_DETACHED == 0 {
@@ -202,6 +525,7 @@ impl Tracing {
                 }
 
                 self.add_event(current_tid, Op::Fork { tid }, loc);
+                self.replay_complete(current_tid, OpKind::Fork, loc);
 
                 tid
             } else {
@@ -212,7 +536,7 @@ impl Tracing {
 
     #[inline]
     pub fn thread_register(&self, tid: Tid) {
-        let prev_tid = THREAD_STATE.with_borrow_mut(|thread_state| thread_state.id.replace(tid));
+        let prev_tid = self.with_thread_state(|thread_state| thread_state.id.replace(tid));
         assert_eq!(
             prev_tid, None,
             "Thread-local TID may only be initialized once per thread!"
@@ -221,6 +545,72 @@ impl Tracing {
         self.thread_ignore_end();
     }
 
+    /// Registers the current host thread as an additional runner of the traced module.
+    ///
+    /// Exports may be invoked from host threads other than the main thread, e.g., to
+    /// run several invocations concurrently. Each runner receives a fresh TID that is
+    /// forked by a synthetic root thread, such that the trace stays well-formed. The
+    /// root thread records no other events, so runners are not ordered after the main
+    /// thread or after each other by the happens-before relation.
+    /// Returns `None` if the current thread already has a TID or if the tracing has not
+    /// been initialized by the main thread yet.
+    pub fn runner_begin(&self) -> Option<Tid> {
+        if !self.initialized.load(Ordering::Relaxed) {
+            log::warn!("Tracing was not yet initialized. Can not register a runner thread ...");
+            return None;
+        }
+
+        self.with_thread_state(|thread_state| {
+            if thread_state.id.is_some() {
+                return None;
+            }
+
+            let root = *self
+                .runner_root
+                .get_or_init(|| self.tid_counter.fetch_add(1, Ordering::Relaxed));
+            let tid = self.tid_counter.fetch_add(1, Ordering::Relaxed);
+            self.replay_wait(root, OpKind::Fork);
+            self.add_event(root, Op::Fork { tid }, Self::RUNNER_LOCATION);
+            self.replay_complete(root, OpKind::Fork, Self::RUNNER_LOCATION);
+
+            thread_state.id = Some(tid);
+            // Instances created on this thread skip the main initialization
+            thread_state.ignore_memory_events = false;
+            Some(tid)
+        })
+    }
+
+    /// Unregisters a runner thread registered by [`Tracing::runner_begin`].
+    ///
+    /// Runners are not joined by the root thread. Otherwise, every runner that ends
+    /// would happen before all runners that are forked afterwards.
+    pub fn runner_end(&self, tid: Tid) {
+        self.with_thread_state(|thread_state| {
+            if thread_state.id == Some(tid) {
+                thread_state.id = None;
+            } else {
+                log::warn!("Runner '{tid}' was ended by a thread other than itself");
+            }
+        });
+    }
+
+    /// Assigns a human-readable name to the current thread.
+    ///
+    /// The name is emitted into the metadata of the generated trace.
+    /// Naming a thread more than once replaces its previous name.
+    pub fn thread_set_name(&self, name: String) {
+        self.with_thread_state(|thread_state| {
+            if let Some(current_tid) = thread_state.id {
+                self.thread_names
+                    .lock()
+                    .expect("Could not lock thread name registry!")
+                    .insert(current_tid, name);
+            } else {
+                log::warn!("Local TID was not yet initialized. Ignoring thread name '{name}' ...");
+            }
+        });
+    }
+
     #[inline]
     pub fn thread_consume(&self, userspace_child_id: u32) -> Tid {
         self.threads
@@ -233,9 +623,11 @@ impl Tracing {
 
     #[inline]
     pub fn thread_join(&self, tid: Tid, loc: (u32, u32)) {
-        THREAD_STATE.with_borrow(|thread_state| {
+        self.with_thread_state(|thread_state| {
             if let Some(current_tid) = thread_state.id {
+                self.replay_wait(current_tid, OpKind::Join);
                 self.add_event(current_tid, Op::Join { tid }, loc);
+                self.replay_complete(current_tid, OpKind::Join, loc);
             } else {
                 log::warn!("Local TID was not yet initialized. Ignoring thread join event ...")
             }
@@ -244,7 +636,7 @@ impl Tracing {
 
     #[inline]
     pub fn thread_detach(&self, tid: Tid) {
-        THREAD_STATE.with_borrow(|thread_state| {
+        self.with_thread_state(|thread_state| {
             if let Some(current_tid) = thread_state.id {
                 // We enable this check only in debug builds to save us the locking overhead in release builds
                 debug_assert!(
@@ -263,9 +655,9 @@ impl Tracing {
 
     #[inline]
     pub fn mutex_register(&self, userspace_mutex_id: u32, flags: u32) {
-        THREAD_STATE.with_borrow(|thread_state| {
+        self.with_thread_state(|thread_state| {
             if let Some(current_tid) = thread_state.id {
-                let mutex_id = self.mutex_counter.fetch_add(1, Ordering::Relaxed);
+                let mutex_id = self.next_mutex_id(userspace_mutex_id);
 
                 if flags & Self::MUTEX_INIT_RECURSIVE != 0 {
                     panic!("Recursive Mutexes are not yet supported!");
@@ -305,9 +697,12 @@ impl Tracing {
 
     #[inline]
     pub fn mutex_start_lock(&self, userspace_mutex_id: u32, loc: (u32, u32)) {
-        THREAD_STATE.with_borrow(|thread_state| {
+        self.with_thread_state(|thread_state| {
             if let Some(current_tid) = thread_state.id {
-                self.mutexes
+                // The turn is held until the lock has been aquired, see `mutex_finish_lock`
+                self.replay_wait(current_tid, OpKind::Aquire);
+                let mutex_id = self
+                    .mutexes
                     .lock()
                     .expect("Could not lock mutex registry!")
                     .entry(userspace_mutex_id)
@@ -319,18 +714,54 @@ impl Tracing {
                             },
                             loc,
                         );
-                        mutex_record.last_event = Some(event_record);
+                        mutex_record.last_event = event_record;
                     })
                     .or_insert_with(|| {
-                        let mutex_id = self.mutex_counter.fetch_add(1, Ordering::Relaxed);
+                        let mutex_id = self.next_mutex_id(userspace_mutex_id);
                         let event_record =
                             self.add_event(current_tid, Op::Request { lock: mutex_id }, loc);
                         MutexRecord {
                             id: mutex_id,
                             owner: current_tid,
-                            last_event: Some(event_record),
+                            last_event: event_record,
                         }
-                    });
+                    })
+                    .id;
+
+                self.with_deadlock_detector(|detector| {
+                    if let Some(report) =
+                        detector.request(current_tid.into(), mutex_id.into(), loc)
+                    {
+                        let lock_addresses = self
+                            .lock_addresses
+                            .lock()
+                            .expect("Could not lock lock address registry!");
+                        let address = |lock: &u64| {
+                            let lock = u32::try_from(*lock).ok()?;
+                            lock_addresses.get(&lock).copied()
+                        };
+                        // Lock names are only known after the trace has been symbolized
+                        let locks = report
+                            .locks
+                            .iter()
+                            .map(|lock| match address(lock) {
+                                Some(address) => format!("{address:#x}"),
+                                None => format!("L{lock}"),
+                            })
+                            .collect::<Vec<_>>();
+                        log::error!(
+                            "Detected a deadlock between the threads {:?} waiting for the locks {:?} at the locations {:?}",
+                            report.threads,
+                            locks,
+                            report.locations
+                        );
+
+                        if let Some(hook) = &self.on_deadlock {
+                            let addresses = report.locks.iter().filter_map(address).collect::<Vec<_>>();
+                            hook(report, &addresses);
+                        }
+                    }
+                });
             } else {
                 log::warn!(
                     "Local TID was not yet initialized. Ignoring mutex start lock event ..."
@@ -341,17 +772,21 @@ impl Tracing {
 
     #[inline]
     pub fn mutex_finish_lock(&self, userspace_mutex_id: u32, loc: (u32, u32)) {
-        THREAD_STATE.with_borrow(|thread_state| {
+        self.with_thread_state(|thread_state| {
             if let Some(current_tid) = thread_state.id {
-                self.mutexes
+                let mutex_id = self.mutexes
                     .lock()
                     .expect("Could not lock mutex registry!")
                     .get_mut(&userspace_mutex_id)
                     .map(|mutex_record| {
                         let event_record = self.add_event(current_tid, Op::Aquire { lock: mutex_record.id }, loc);
-                        mutex_record.last_event = Some(event_record);
+                        mutex_record.last_event = event_record;
+                        mutex_record.id
                     })
                     .unwrap_or_else(|| panic!("Tried to register an aquire event for a mutex that could not be found in the mutex registry!"));
+
+                self.with_deadlock_detector(|detector| detector.acquire(current_tid.into(), mutex_id.into()));
+                self.replay_complete(current_tid, OpKind::Aquire, loc);
             } else {
                 log::warn!("Local TID was not yet initialized. Ignoring mutex finish lock event ...");
             }
@@ -360,17 +795,20 @@ impl Tracing {
 
     #[inline]
     pub fn mutex_unlock(&self, userspace_mutex_id: u32, loc: (u32, u32)) {
-        THREAD_STATE.with_borrow(|thread_state| {
+        self.with_thread_state(|thread_state| {
             if let Some(current_tid) = thread_state.id {
-                self.mutexes
+                let mutex_id = self.mutexes
                     .lock()
                     .expect("Could not lock mutex registry!")
                     .get_mut(&userspace_mutex_id)
                     .map(|mutex_record| {
                         let event_record = self.add_event(current_tid, Op::Release { lock: mutex_record.id }, loc);
-                        mutex_record.last_event = Some(event_record);
+                        mutex_record.last_event = event_record;
+                        mutex_record.id
                     })
                     .unwrap_or_else(|| panic!("Tried to register an unlock event for a mutex that could not be found in the mutex registry!"));
+
+                self.with_deadlock_detector(|detector| detector.release(current_tid.into(), mutex_id.into()));
             } else {
                 log::warn!("Local TID was not yet initialized. Ignoring mutex finish unlock event ...");
             }
@@ -379,7 +817,7 @@ impl Tracing {
 
     #[inline]
     pub fn mutex_repair(&self, userspace_mutex_id: u32) {
-        THREAD_STATE.with_borrow(|thread_state| {
+        self.with_thread_state(|thread_state| {
             if let Some(current_tid) = thread_state.id {
                 self.mutexes
                     .lock()
@@ -401,47 +839,348 @@ impl Tracing {
 
     #[inline]
     pub fn mutex_invalid_access(&self, userspace_mutex_id: u32) {
-        let event_handle = self.mutexes
+        let event_handle = self
+            .mutexes
             .lock()
             .expect("Could not lock mutex registry!")
             .get_mut(&userspace_mutex_id)
-            .map(|mutex_record| {
-                mutex_record
-                    .last_event
-                    .take()
-                    .unwrap_or_else(|| panic!("Invalid access has been issued before any event for mutex '{userspace_mutex_id:x}' has been recorded!"))
-            })
-            .unwrap_or_else(|| panic!("Tried to repair a mutex that could not be found in the mutex registry!"));
+            .map(|mutex_record| mutex_record.last_event.take())
+            .unwrap_or_else(|| {
+                panic!("Tried to repair a mutex that could not be found in the mutex registry!")
+            });
+
+        match event_handle {
+            Some(event) => {
+                self.events.invalidate(event.handle);
+                self.counters.retract(event.kind);
+            }
+            // The last event may have been dropped by the filter
+            None if self.filter.is_some() => {}
+            None => panic!(
+                "Invalid access has been issued before any event for mutex '{userspace_mutex_id:x}' has been recorded!"
+            ),
+        }
+
+        self.with_thread_state(|thread_state| {
+            if let Some(current_tid) = thread_state.id {
+                self.with_deadlock_detector(|detector| detector.cancel(current_tid.into()));
+            }
+        });
+    }
+
+    /// Records that the current thread returned from waiting on the condition variable `cond`.
+    ///
+    /// Condition variables are identified by their address in linear memory.
+    #[inline]
+    pub fn cond_wait(&self, cond: u32, loc: (u32, u32)) {
+        self.with_thread_state(|thread_state| {
+            if let Some(current_tid) = thread_state.id {
+                self.add_event(current_tid, Op::Wait { cond }, loc);
+            } else {
+                log::warn!("Local TID was not yet initialized. Ignoring condvar wait event ...")
+            }
+        });
+    }
+
+    /// Records that the current thread notified the condition variable `cond`.
+    #[inline]
+    pub fn cond_notify(&self, cond: u32, loc: (u32, u32)) {
+        self.with_thread_state(|thread_state| {
+            if let Some(current_tid) = thread_state.id {
+                self.add_event(current_tid, Op::Notify { cond }, loc);
+            } else {
+                log::warn!("Local TID was not yet initialized. Ignoring condvar notify event ...")
+            }
+        });
+    }
+
+    /// Records that the current thread entered the function with index `fidx`.
+    #[inline]
+    pub fn function_call(&self, fidx: u32, loc: (u32, u32)) {
+        self.with_thread_state(|thread_state| {
+            if let Some(current_tid) = thread_state.id {
+                self.add_event(current_tid, Op::Call { fidx }, loc);
+            } else {
+                log::warn!("Local TID was not yet initialized. Ignoring function call event ...")
+            }
+        });
+    }
+
+    /// Records that the current thread left the function with index `fidx`.
+    #[inline]
+    pub fn function_return(&self, fidx: u32, loc: (u32, u32)) {
+        self.with_thread_state(|thread_state| {
+            if let Some(current_tid) = thread_state.id {
+                self.add_event(current_tid, Op::Return { fidx }, loc);
+            } else {
+                log::warn!("Local TID was not yet initialized. Ignoring function return event ...")
+            }
+        });
+    }
 
-        self.events.invalidate(event_handle);
+    /// Emits the current state of the execution trace in the format of the given `encoder`.
+    ///
+    /// The returned metadata does not depend on the chosen format, i.e., it can be
+    /// used to map the IDs of the emitted trace back to their WebAssembly counterparts
+    /// regardless of the encoder.
+    pub fn generate_trace<E: Encoder, P: AsRef<Path>>(
+        self,
+        encoder: &mut E,
+        outfile: P,
+    ) -> Result<WasmgrindTraceMetadata, Error> {
+        log::info!("Starting to generate {} trace ...", encoder.format());
+        let events = self.events.close()?;
+        let dropped = Self::dropped_events(self.output_window, &events);
+
+        let mut converter = WasmgrindTraceConverter::new();
+        Self::encode_events(&events, dropped, encoder, outfile.as_ref(), &mut converter)?;
+
+        let thread_names = self
+            .thread_names
+            .into_inner()
+            .expect("Thread name registry mutex was poisoned");
+        let lock_addresses = self
+            .lock_addresses
+            .into_inner()
+            .expect("Lock address registry mutex was poisoned");
+
+        let mut metadata = converter.generate_metadata(&thread_names);
+        metadata.fill_lock_addresses(&lock_addresses);
+        metadata.set_dropped_events(dropped);
+        Ok(metadata)
     }
 
     /// Emits the current state of the execution trace in RapidBin format.
+    ///
+    /// If the trace contains more distinct locations than the default RapidBin
+    /// layout can represent, it is emitted with [`RapidBinLayout::WIDE_LOCATIONS`]
+    /// instead. Such traces can still be parsed by [`RapidBinParser`] but not by RAPID.
     pub fn generate_binary_trace<P: AsRef<Path>>(
         self,
         outfile: P,
     ) -> Result<WasmgrindTraceMetadata, Error> {
-        log::info!("Starting to generate binary trace ...");
+        log::info!("Starting to generate RapidBin trace ...");
+        let events = self.events.close()?;
+        let dropped = Self::dropped_events(self.output_window, &events);
+        let outfile = outfile.as_ref();
+
         let mut converter = WasmgrindTraceConverter::new();
+        if let Err(e) = Self::encode_events(
+            &events,
+            dropped,
+            &mut RapidBinEncoder::new(),
+            outfile,
+            &mut converter,
+        ) {
+            let max_locations = RapidBinLayout::DEFAULT.max_locations();
+            if u64::try_from(converter.n_locations())? <= max_locations {
+                return Err(e);
+            }
+
+            log::warn!(
+                "Trace contains more than {max_locations} distinct locations. Falling back to a wider RapidBin layout ..."
+            );
+            converter = WasmgrindTraceConverter::new();
+            Self::encode_events(
+                &events,
+                dropped,
+                &mut RapidBinEncoder::with_layout(RapidBinLayout::WIDE_LOCATIONS),
+                outfile,
+                &mut converter,
+            )?;
+        }
+
+        let thread_names = self
+            .thread_names
+            .into_inner()
+            .expect("Thread name registry mutex was poisoned");
+        let lock_addresses = self
+            .lock_addresses
+            .into_inner()
+            .expect("Lock address registry mutex was poisoned");
+
+        let mut metadata = converter.generate_metadata(&thread_names);
+        metadata.fill_lock_addresses(&lock_addresses);
+        metadata.set_dropped_events(dropped);
+        Ok(metadata)
+    }
+
+    /// Emits the current state of the execution trace in RapidBin format compressed with `codec`.
+    ///
+    /// The trace is emitted uncompressed next to `outfile` first and removed after compression.
+    /// With [`Codec::None`], the trace is emitted exactly like by [`Tracing::generate_binary_trace`].
+    /// Compressed traces are opened transparently by the analyses of [`WasmgrindTraceMetadata`]
+    /// and by [`trace_tools::open_trace`].
+    pub fn generate_binary_trace_compressed<P: AsRef<Path>>(
+        self,
+        outfile: P,
+        codec: Codec,
+    ) -> Result<WasmgrindTraceMetadata, Error> {
+        let outfile = outfile.as_ref();
+        if codec == Codec::None {
+            return self.generate_binary_trace(outfile);
+        }
+
+        let mut uncompressed = outfile.as_os_str().to_owned();
+        uncompressed.push(".uncompressed");
+        let uncompressed = PathBuf::from(uncompressed);
+
+        let metadata = self.generate_binary_trace(&uncompressed)?;
+        log::info!("Compressing RapidBin trace with {codec:?} ...");
+        compress(
+            codec,
+            BufReader::new(File::open(&uncompressed)?),
+            std::fs::metadata(&uncompressed)?.len(),
+            BufWriter::new(File::create(outfile)?),
+        )?;
+        std::fs::remove_file(&uncompressed)?;
+        // The timestamps are not compressed, but belong to the compressed trace
+        if timestamps_path(&uncompressed).exists() {
+            std::fs::rename(timestamps_path(&uncompressed), timestamps_path(outfile))?;
+        }
+
+        Ok(metadata)
+    }
+
+    /// Completes the trace file of a trace created by [`Tracing::to_file`].
+    ///
+    /// Invalidated events are removed from the file and its header is written.
+    /// Fails if the trace has not been created by [`Tracing::to_file`].
+    pub fn finalize(self) -> Result<WasmgrindTraceMetadata, Error> {
+        let TraceStorage::File(events) = self.events else {
+            bail!(
+                "Only traces created by Tracing::to_file can be finalized. Use Tracing::generate_trace instead."
+            );
+        };
+        if self.output_window.is_some() {
+            log::warn!(
+                "Output windows are not supported for traces written to a file. Emitting all events ..."
+            );
+        }
+        let converter = events.close()?;
+
+        let thread_names = self
+            .thread_names
+            .into_inner()
+            .expect("Thread name registry mutex was poisoned");
+        let lock_addresses = self
+            .lock_addresses
+            .into_inner()
+            .expect("Lock address registry mutex was poisoned");
 
-        let mut encoder = RapidBinEncoder::new();
-        let outfile = BufWriter::new(File::create(outfile)?);
+        let mut metadata = converter.generate_metadata(&thread_names);
+        metadata.fill_lock_addresses(&lock_addresses);
+        Ok(metadata)
+    }
+
+    /// Returns the number of events that fall out of the output `window` of the most recent events.
+    fn dropped_events(window: Option<u64>, events: &CachedTrace) -> u64 {
+        let dropped = window.map_or(0, |capacity| {
+            events.n_valid_events().saturating_sub(capacity)
+        });
+        if dropped > 0 {
+            log::warn!("Dropping the {dropped} oldest events that exceed the output window ...");
+        }
 
+        dropped
+    }
+
+    fn encode_events<E: Encoder>(
+        events: &CachedTrace,
+        skip: u64,
+        encoder: &mut E,
+        outfile: &Path,
+        converter: &mut WasmgrindTraceConverter,
+    ) -> Result<(), Error> {
+        let mut output = BufWriter::new(File::create(outfile)?);
+
+        let mut timestamps = Vec::new();
         encoder.encode(
-            self.events
-                .close()?
-                .iter()?
-                .map(|e| Ok(converter.convert_event(&e))),
-            outfile,
+            events.iter()?.skip(usize::try_from(skip)?).map(|e| {
+                timestamps.extend(e.timestamp);
+                Ok(converter.convert_event(&e))
+            }),
+            &mut output,
         )?;
 
-        Ok(converter.generate_metadata())
+        output.flush()?;
+        write_timestamps(outfile, &timestamps)?;
+
+        Ok(())
+    }
+}
+
+/// Combines multiple partial execution traces in RapidBin format into a single trace.
+///
+/// Each input consists of a trace file and the metadata that has been generated
+/// alongside it. The events of the inputs are concatenated in the given order.
+/// Since the IDs of a trace are assigned per trace, threads, locks, variables and
+/// locations are unified by their WebAssembly IDs as recorded in the metadata. The
+/// merged trace is emitted in the format of the given `encoder`.
+///
+/// Function names added by [`metadata::symbolize`] and lock names are not carried over.
+/// Symbolize the returned metadata again if needed.
+pub fn merge_traces<P, I, E, Q>(
+    inputs: I,
+    encoder: &mut E,
+    outfile: Q,
+) -> Result<WasmgrindTraceMetadata, Error>
+where
+    P: AsRef<Path>,
+    I: IntoIterator<Item = (P, WasmgrindTraceMetadata)>,
+    E: Encoder,
+    Q: AsRef<Path>,
+{
+    let mut thread_names = HashMap::new();
+    let mut lock_addresses = HashMap::new();
+    let mut sources = Vec::new();
+    let mut dropped = 0;
+    for (trace_file, metadata) in inputs {
+        dropped += metadata.dropped_events();
+        thread_names.extend(metadata.thread_names_by_wasm_id());
+        lock_addresses.extend(metadata.lock_addresses_by_wasm_id());
+        let events =
+            RapidBinParser::new().parse(open_trace(BufReader::new(File::open(trace_file)?))?)?;
+        sources.push((metadata.into_converter(), events));
     }
+
+    log::info!(
+        "Merging {} traces into a single {} trace ...",
+        sources.len(),
+        encoder.format()
+    );
+    let mut converter = WasmgrindTraceConverter::new();
+
+    let mut outfile = BufWriter::new(File::create(outfile)?);
+
+    encoder.encode(
+        sources
+            .into_iter()
+            .flat_map(|(generic, events)| events.map(move |event| generic.convert_event(&event?)))
+            .map(|event| Ok(converter.convert_event(&event?))),
+        &mut outfile,
+    )?;
+
+    outfile.flush()?;
+
+    let mut metadata = converter.generate_metadata(&thread_names);
+    metadata.fill_lock_addresses(&lock_addresses);
+    metadata.set_dropped_events(dropped);
+    Ok(metadata)
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{fs::File, io::BufReader, path::PathBuf};
+    use std::{
+        collections::{HashMap, HashSet},
+        fs::File,
+        io::BufReader,
+        path::{Path, PathBuf},
+        sync::{
+            Arc, Mutex,
+            atomic::{AtomicU64, Ordering},
+        },
+    };
 
     use anyhow::Error;
     use rand_xoshiro::{
@@ -449,19 +1188,37 @@ mod tests {
         rand_core::{RngCore, SeedableRng},
     };
     use tempfile::tempdir;
-    use trace_tools::{RapidBinParser, generic::Parser};
+    use trace_tools::{
+        Codec, RapidBinEncoder, RapidBinParser, StdFormatEncoder,
+        generic::{self, Operation, Parser},
+        open_trace,
+    };
 
-    use crate::tracing::{Op, metadata::WasmgrindTraceMetadata, trace::Trace};
+    use crate::tracing::{
+        Op,
+        metadata::{TraceProvenance, WasmgrindTraceMetadata, symbolize},
+        trace::Trace,
+    };
 
-    use super::Tracing;
+    use super::{
+        ClockSource, OpKind, RecordingStats, ReplayScheduler, TraceFilter, Tracing, merge_traces,
+    };
+    use crate::{
+        abi::AbiFlavor,
+        instrumentation::{FunctionSelector, HookCategories, InstrumentOptions},
+        symbols::LockSymbolizer,
+    };
 
     fn example_trace(trace_cache: PathBuf) -> Tracing {
-        let tracing = Tracing::new(trace_cache);
+        record_example_events(Tracing::new(trace_cache))
+    }
+
+    fn record_example_events(tracing: Tracing) -> Tracing {
         let mut rng = Xoshiro256PlusPlus::seed_from_u64(42);
         const MAX_N_BYTES_ACCESSED: u32 = 8;
 
         for _ in 0..100 {
-            let op = match rng.next_u32() % 7 {
+            let op = match rng.next_u32() % 11 {
                 0 => Op::Aquire {
                     lock: rng.next_u32(),
                 },
@@ -487,6 +1244,18 @@ mod tests {
                     n: rng.next_u32() % (MAX_N_BYTES_ACCESSED + 1),
                     atomic: rng.next_u32() % 2 != 0,
                 },
+                7 => Op::Wait {
+                    cond: rng.next_u32(),
+                },
+                8 => Op::Notify {
+                    cond: rng.next_u32(),
+                },
+                9 => Op::Call {
+                    fidx: rng.next_u32(),
+                },
+                10 => Op::Return {
+                    fidx: rng.next_u32(),
+                },
                 _ => unreachable!(),
             };
 
@@ -496,6 +1265,88 @@ mod tests {
         tracing
     }
 
+    #[test]
+    fn write_trace_to_file() -> Result<(), Error> {
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let streamed_file = tmp.path().join("streamed.data");
+        let streamed_metadata =
+            record_example_events(Tracing::to_file(&streamed_file)?).finalize()?;
+
+        let cached_file = tmp.path().join("cached.data");
+        let cached_metadata =
+            example_trace(tmp.path().join("trace-cache")).generate_binary_trace(&cached_file)?;
+
+        assert_eq!(streamed_metadata, cached_metadata);
+        // The streamed trace uses a wider layout, so only the events are identical
+        let parse = |path: &Path| -> Result<Vec<generic::Event>, Error> {
+            RapidBinParser::new()
+                .parse(BufReader::new(File::open(path)?))?
+                .collect()
+        };
+        assert_eq!(parse(&streamed_file)?, parse(&cached_file)?);
+
+        let tracing = Tracing::new(tmp.path().join("trace-cache"));
+        assert!(tracing.finalize().is_err());
+        let tracing = Tracing::to_file(tmp.path().join("other.data"))?;
+        assert!(tracing.generate_binary_trace(&cached_file).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn report_file_write_errors_on_finalize() -> Result<(), Error> {
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let tracing = Tracing::to_file(tmp.path().join("trace.data"))?;
+        tracing.initialize();
+
+        // The layout of the trace file can not represent this many threads
+        let tids = (0..1100)
+            .map(|child| tracing.thread_create(child, 0, (0, 1)))
+            .collect::<Vec<_>>();
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                tracing.thread_register(tids[1099]);
+                tracing.memory_access_write(40, 4, 0, (0, 2));
+                tracing.memory_access_read(40, 4, 0, (0, 3));
+            });
+        });
+
+        let error = tracing.finalize().unwrap_err();
+        assert!(format!("{error:#}").contains("Failed to append event to trace file"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn remove_invalidated_events_from_file() -> Result<(), Error> {
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let trace_file = tmp.path().join("trace.data");
+        let tracing = Tracing::to_file(&trace_file)?;
+        tracing.initialize();
+
+        tracing.memory_access_write(40, 4, 0, (0, 1));
+        tracing.mutex_register(8, Tracing::MUTEX_INIT_NORMAL);
+        tracing.mutex_start_lock(8, (0, 2));
+        tracing.mutex_invalid_access(8);
+        tracing.memory_access_read(40, 4, 0, (0, 3));
+        assert_eq!(tracing.event_count(), 3);
+
+        let converter = tracing.finalize()?.into_converter();
+        let events = RapidBinParser::new()
+            .parse(BufReader::new(File::open(&trace_file)?))?
+            .map(|event| converter.convert_event(&event?))
+            .collect::<Result<Vec<_>, Error>>()?;
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| (OpKind::from(&event.op), event.loc))
+                .collect::<Vec<_>>(),
+            vec![(OpKind::Write, (0, 1)), (OpKind::Read, (0, 3))]
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn wasmgrind_trace_roundtrip() -> Result<(), Error> {
         let tmp = tempdir().expect("Could not create out dir for trace!");
@@ -539,15 +1390,952 @@ mod tests {
     }
 
     #[test]
-    fn wasmgrind_metadata_roundtrip() -> Result<(), Error> {
+    fn wasmgrind_metadata_format_independent() -> Result<(), Error> {
         let tmp = tempdir().expect("Could not create out dir for trace!");
-        let trace_metadata = example_trace(tmp.path().join("trace-cache"))
+        let binary_metadata = example_trace(tmp.path().join("binary-cache"))
             .generate_binary_trace(tmp.path().join("trace.data"))?;
-        let json_metadata = trace_metadata.to_json()?;
-        assert_eq!(
-            trace_metadata,
-            WasmgrindTraceMetadata::from_json(json_metadata.as_bytes())?
+        let std_metadata = example_trace(tmp.path().join("std-cache"))
+            .generate_trace(&mut StdFormatEncoder::new(), tmp.path().join("trace.std"))?;
+
+        assert_eq!(binary_metadata, std_metadata);
+
+        let std_trace = std::fs::read_to_string(tmp.path().join("trace.std"))?;
+        assert_eq!(std_trace.lines().count(), 100);
+
+        Ok(())
+    }
+
+    #[test]
+    fn wasmgrind_detect_deadlock() {
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let deadlocked = Arc::new(Mutex::new(Vec::new()));
+        let hook_deadlocked = deadlocked.clone();
+        let tracing =
+            Tracing::new(tmp.path().join("trace-cache")).on_deadlock(move |_, addresses| {
+                hook_deadlocked.lock().unwrap().extend_from_slice(addresses)
+            });
+        tracing.initialize();
+
+        let child = tracing.thread_create(1, Tracing::THREAD_CREATE_JOINABLE, (0, 1));
+        tracing.mutex_start_lock(0xA, (0, 2));
+        tracing.mutex_finish_lock(0xA, (0, 2));
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                tracing.thread_register(child);
+                tracing.mutex_start_lock(0xB, (1, 1));
+                tracing.mutex_finish_lock(0xB, (1, 1));
+                tracing.mutex_start_lock(0xA, (1, 2));
+            });
+        });
+        assert!(tracing.deadlock_report().is_empty());
+
+        tracing.mutex_start_lock(0xB, (0, 3));
+
+        let report = tracing.deadlock_report();
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].threads, vec![0, 1]);
+        assert_eq!(report[0].locks, vec![1, 0]);
+        assert_eq!(report[0].locations, vec![(0, 3), (1, 2)]);
+        assert_eq!(*deadlocked.lock().unwrap(), vec![0xB, 0xA]);
+    }
+
+    #[test]
+    fn wasmgrind_merge_traces() -> Result<(), Error> {
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+
+        let first = Tracing::new(tmp.path().join("cache-1"));
+        first.add_event(0, Op::Fork { tid: 1 }, (1, 1));
+        first.add_event(
+            1,
+            Op::Write {
+                addr: 100,
+                n: 4,
+                atomic: false,
+            },
+            (1, 2),
+        );
+        let first_file = tmp.path().join("first.data");
+        let first_metadata = first.generate_binary_trace(&first_file)?;
+
+        // Trace-IDs are assigned per trace: Thread 1 is the first thread seen here
+        let second = Tracing::new(tmp.path().join("cache-2"));
+        second.add_event(
+            1,
+            Op::Write {
+                addr: 200,
+                n: 4,
+                atomic: false,
+            },
+            (1, 3),
+        );
+        second.add_event(
+            1,
+            Op::Read {
+                addr: 100,
+                n: 4,
+                atomic: false,
+            },
+            (1, 2),
+        );
+        second.add_event(0, Op::Join { tid: 1 }, (1, 4));
+        let second_file = tmp.path().join("second.data");
+        let second_metadata = second.generate_binary_trace(&second_file)?;
+
+        let merged_file = tmp.path().join("merged.data");
+        merge_traces(
+            [(first_file, first_metadata), (second_file, second_metadata)],
+            &mut RapidBinEncoder::new(),
+            &merged_file,
+        )?;
+
+        let merged = RapidBinParser::new()
+            .parse(BufReader::new(File::open(&merged_file)?))?
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        assert_eq!(
+            merged,
+            vec![
+                generic::Event::new(0, Operation::Fork { tid: 1 }, 0),
+                generic::Event::new(1, Operation::Write { memory: 0 }, 1),
+                generic::Event::new(1, Operation::Write { memory: 1 }, 2),
+                generic::Event::new(1, Operation::Read { memory: 0 }, 1),
+                generic::Event::new(0, Operation::Join { tid: 1 }, 3),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn wasmgrind_trace_statistics() -> Result<(), Error> {
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let tracing = Tracing::new(tmp.path().join("trace-cache"));
+        tracing.add_event(0, Op::Request { lock: 3 }, (1, 1));
+        tracing.add_event(0, Op::Aquire { lock: 3 }, (1, 1));
+        tracing.add_event(
+            0,
+            Op::Write {
+                addr: 100,
+                n: 8,
+                atomic: false,
+            },
+            (1, 2),
+        );
+        tracing.add_event(
+            0,
+            Op::Read {
+                addr: 100,
+                n: 8,
+                atomic: false,
+            },
+            (1, 3),
+        );
+        tracing.add_event(
+            0,
+            Op::Read {
+                addr: 104,
+                n: 2,
+                atomic: false,
+            },
+            (1, 4),
+        );
+        tracing.add_event(0, Op::Release { lock: 3 }, (1, 5));
+
+        let trace_file = tmp.path().join("trace.data");
+        let stats = tracing
+            .generate_binary_trace(&trace_file)?
+            .statistics(&trace_file)?;
+
+        assert_eq!(stats.n_events, 6);
+        assert_eq!((stats.n_reads, stats.n_writes), (2, 1));
+        assert_eq!(stats.n_variables, 2);
+        assert_eq!(stats.max_lock_depth, 1);
+        assert_eq!(stats.bytes_accessed, Some(18));
+
+        Ok(())
+    }
+
+    #[test]
+    fn wasmgrind_compressed_trace() -> Result<(), Error> {
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let record = |cache| {
+            let tracing = Tracing::new(tmp.path().join(cache));
+            tracing.add_event(0, Op::Fork { tid: 1 }, (1, 1));
+            for addr in (0..1024).step_by(4) {
+                tracing.add_event(
+                    1,
+                    Op::Write {
+                        addr,
+                        n: 4,
+                        atomic: false,
+                    },
+                    (1, 2),
+                );
+            }
+            tracing.add_event(0, Op::Join { tid: 1 }, (1, 3));
+            tracing
+        };
+
+        let plain_file = tmp.path().join("plain.data");
+        let plain = record("plain-cache").generate_binary_trace(&plain_file)?;
+        let compressed_file = tmp.path().join("compressed.data");
+        let compressed = record("compressed-cache")
+            .generate_binary_trace_compressed(&compressed_file, Codec::Zstd)?;
+
+        assert!(std::fs::metadata(&compressed_file)?.len() < std::fs::metadata(&plain_file)?.len());
+        assert!(!tmp.path().join("compressed.data.uncompressed").exists());
+        assert_eq!(
+            compressed.statistics(&compressed_file)?,
+            plain.statistics(&plain_file)?
+        );
+
+        let parse = |file: &PathBuf| -> Result<Vec<generic::Event>, Error> {
+            RapidBinParser::new()
+                .parse(open_trace(BufReader::new(File::open(file)?))?)?
+                .collect()
+        };
+        assert_eq!(parse(&compressed_file)?, parse(&plain_file)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn wasmgrind_detect_races() -> Result<(), Error> {
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let tracing = Tracing::new(tmp.path().join("trace-cache"));
+        let write = |addr| Op::Write {
+            addr,
+            n: 4,
+            atomic: false,
+        };
+
+        tracing.add_event(0, Op::Fork { tid: 1 }, (1, 1));
+        for tid in [0, 1] {
+            tracing.add_event(tid, write(100), (1, 2));
+            tracing.add_event(tid, Op::Request { lock: 3 }, (1, 3));
+            tracing.add_event(tid, Op::Aquire { lock: 3 }, (1, 3));
+            tracing.add_event(tid, write(200), (1, 4));
+            tracing.add_event(tid, Op::Release { lock: 3 }, (1, 5));
+        }
+
+        let trace_file = tmp.path().join("trace.data");
+        let metadata = tracing.generate_binary_trace(&trace_file)?;
+        let races = metadata.detect_races(&trace_file)?;
+
+        // Only the accesses outside of the critical section race
+        assert_eq!(races.len(), 1);
+        assert_eq!((races[0].first_thread, races[0].second_thread), (0, 1));
+        assert_eq!(
+            metadata.describe_race(&races[0]),
+            format!(
+                "Data race on 0x64 (4 bytes) between thread 0 at location {0} and thread 1 at location {0}",
+                races[0].first_location
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn wasmgrind_lock_contention() -> Result<(), Error> {
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let tracing = Tracing::new(tmp.path().join("trace-cache"));
+
+        tracing.add_event(0, Op::Fork { tid: 1 }, (1, 1));
+        tracing.add_event(0, Op::Request { lock: 3 }, (1, 2));
+        tracing.add_event(0, Op::Aquire { lock: 3 }, (1, 2));
+        tracing.add_event(1, Op::Request { lock: 3 }, (2, 2));
+        tracing.add_event(0, Op::Release { lock: 3 }, (1, 3));
+        tracing.add_event(1, Op::Aquire { lock: 3 }, (2, 2));
+        tracing.add_event(1, Op::Release { lock: 3 }, (2, 3));
+
+        let trace_file = tmp.path().join("trace.data");
+        let metadata = tracing.generate_binary_trace(&trace_file)?;
+        let report = metadata.lock_contention_report(&trace_file)?;
+
+        assert_eq!(report.locks.len(), 1);
+        let lock = &report.locks[0];
+        assert_eq!((lock.n_acquisitions, lock.n_contended), (2, 1));
+        // The contended request of thread 1 is the third distinct location
+        assert_eq!(lock.top_locations, [(2, 1)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn retain_output_window_of_recent_events() -> Result<(), Error> {
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let write = |addr| Op::Write {
+            addr,
+            n: 4,
+            atomic: false,
+        };
+        let record = |tracing: Tracing| {
+            for addr in 0..10 {
+                tracing.add_event(0, write(addr * 4), (1, addr));
+            }
+            tracing
+        };
+
+        let trace_file = tmp.path().join("window.data");
+        let metadata = record(Tracing::new(tmp.path().join("window-cache")).with_output_window(4))
+            .generate_binary_trace(&trace_file)?;
+        assert_eq!(metadata.dropped_events(), 6);
+        assert!(metadata.is_truncated());
+
+        let converter =
+            WasmgrindTraceMetadata::from_json(metadata.to_json()?.as_bytes())?.into_converter();
+        let events = RapidBinParser::new()
+            .parse(BufReader::new(File::open(&trace_file)?))?
+            .map(|event| converter.convert_event(&event?))
+            .collect::<Result<Vec<_>, Error>>()?;
+        assert_eq!(
+            events.iter().map(|event| event.loc).collect::<Vec<_>>(),
+            vec![(1, 6), (1, 7), (1, 8), (1, 9)]
+        );
+
+        // Windows exceeding the trace drop nothing
+        let metadata = record(Tracing::new(tmp.path().join("large-cache")).with_output_window(10))
+            .generate_binary_trace(tmp.path().join("large.data"))?;
+        assert_eq!(metadata.dropped_events(), 0);
+        assert!(!metadata.is_truncated());
+
+        Ok(())
+    }
+
+    #[test]
+    fn wasmgrind_replay_trace() -> Result<(), Error> {
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let lock = |tracing: &Tracing, loc| {
+            tracing.mutex_start_lock(0xA, loc);
+            tracing.mutex_finish_lock(0xA, loc);
+            tracing.mutex_unlock(0xA, loc);
+        };
+
+        // Record an execution in which the child aquires the lock first
+        let recording = Tracing::new(tmp.path().join("record-cache"));
+        recording.initialize();
+        let child = recording.thread_create(1, Tracing::THREAD_CREATE_JOINABLE, (0, 1));
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                recording.thread_register(child);
+                lock(&recording, (1, 1));
+            });
+        });
+        lock(&recording, (0, 2));
+        recording.thread_join(child, (0, 3));
+        let trace_file = tmp.path().join("recorded.data");
+        let metadata = recording.generate_binary_trace(&trace_file)?;
+
+        // The main thread attempts to aquire the lock first, but has to wait for the child
+        let replay = Tracing::new(tmp.path().join("replay-cache"))
+            .with_replay(ReplayScheduler::from_trace(&trace_file, metadata)?);
+        replay.initialize();
+        let child = replay.thread_create(1, Tracing::THREAD_CREATE_JOINABLE, (0, 1));
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                std::thread::sleep(std::time::Duration::from_millis(20));
+                replay.thread_register(child);
+                lock(&replay, (1, 1));
+            });
+            lock(&replay, (0, 2));
+        });
+        replay.thread_join(child, (0, 3));
+        assert_eq!(replay.replay_remaining(), Some(0));
+
+        let replay_file = tmp.path().join("replayed.data");
+        replay.generate_binary_trace(&replay_file)?;
+        let aquiring_threads = RapidBinParser::new()
+            .parse(BufReader::new(File::open(&replay_file)?))?
+            .filter_map(|event| match event {
+                Ok(event) => matches!(event.get_fields().1, Operation::Aquire { .. })
+                    .then(|| Ok(*event.get_fields().0)),
+                Err(err) => Some(Err(err)),
+            })
+            .collect::<Result<Vec<u64>, Error>>()?;
+        assert_eq!(aquiring_threads, vec![1, 0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_binary_trace_with_many_locations() -> Result<(), Error> {
+        const N_LOCATIONS: u32 = 100_000;
+
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let tracing = Tracing::new(tmp.path().join("trace-cache"));
+        for iidx in 0..N_LOCATIONS {
+            tracing.add_event(
+                0,
+                Op::Read {
+                    addr: 8,
+                    n: 4,
+                    atomic: false,
+                },
+                (0, iidx),
+            );
+        }
+
+        let trace_file = tmp.path().join("trace.data");
+        let trace_metadata = tracing.generate_binary_trace(&trace_file)?;
+
+        let mut parser = RapidBinParser::new();
+        let events = parser
+            .parse(BufReader::new(File::open(&trace_file)?))?
+            .collect::<Result<Vec<generic::Event>, Error>>()?;
+        assert_eq!(events.len(), N_LOCATIONS as usize);
+        assert_eq!(
+            *events.last().unwrap().get_fields().2,
+            u64::from(N_LOCATIONS - 1)
+        );
+
+        let converter = trace_metadata.into_converter();
+        assert_eq!(
+            converter.convert_event(events.last().unwrap())?.loc,
+            (0, N_LOCATIONS - 1)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn report_progress_counters() {
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let tracing = Tracing::new(tmp.path().join("trace-cache"));
+        tracing.initialize();
+        assert_eq!((tracing.event_count(), tracing.thread_count()), (0, 1));
+        assert!(tracing.is_empty());
+
+        tracing.thread_create(1, Tracing::THREAD_CREATE_JOINABLE, (0, 1));
+        tracing.memory_access_write(40, 4, 0, (0, 2));
+        assert_eq!((tracing.event_count(), tracing.thread_count()), (2, 2));
+        assert!(!tracing.is_empty());
+    }
+
+    #[test]
+    fn trace_concurrent_runners() -> Result<(), Error> {
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let tracing = Arc::new(Tracing::new(tmp.path().join("trace-cache")));
+        tracing.initialize();
+        assert_eq!(tracing.runner_begin(), None);
+
+        let runners = (0..2)
+            .map(|_| {
+                let tracing = tracing.clone();
+                std::thread::spawn(move || {
+                    let tid = tracing.runner_begin().expect("Runner was not registered");
+                    // The instance of the runner is initialized like the one of the main thread
+                    tracing.initialize();
+                    tracing.memory_access_write(64, 4, 0, (1, 2));
+                    tracing.runner_end(tid);
+                    tid
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut tids = runners
+            .into_iter()
+            .map(|runner| runner.join().expect("Runner panicked"))
+            .collect::<Vec<_>>();
+        tids.sort();
+        // TID 1 belongs to the root thread that forks the runners
+        assert_eq!(tids, [2, 3]);
+
+        let tracing = Arc::into_inner(tracing).expect("Trace is still shared");
+        let trace_file = tmp.path().join("trace.data");
+        let stats = tracing
+            .generate_binary_trace(&trace_file)?
+            .statistics(&trace_file)?;
+        assert_eq!(
+            (
+                stats.n_threads,
+                stats.n_forks,
+                stats.n_joins,
+                stats.n_writes
+            ),
+            (3, 2, 0, 2)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn detect_races_between_main_and_runner() -> Result<(), Error> {
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let tracing = Arc::new(Tracing::new(tmp.path().join("trace-cache")));
+        tracing.initialize();
+
+        // Neither the end of the runner nor the start of a later runner are ordered
+        // with the accesses of the main thread
+        tracing.memory_access_write(64, 4, 0, (0, 1));
+        for _ in 0..2 {
+            let tracing = tracing.clone();
+            std::thread::spawn(move || {
+                let tid = tracing.runner_begin().expect("Runner was not registered");
+                tracing.memory_access_write(64, 4, 0, (1, 2));
+                tracing.runner_end(tid);
+            })
+            .join()
+            .expect("Runner panicked");
+        }
+        tracing.memory_access_read(64, 4, 0, (0, 3));
+
+        let tracing = Arc::into_inner(tracing).expect("Trace is still shared");
+        let trace_file = tmp.path().join("trace.data");
+        let races = tracing
+            .generate_binary_trace(&trace_file)?
+            .detect_races(&trace_file)?;
+        let mut racing_threads = races
+            .iter()
+            .map(|race| (race.first_thread, race.second_thread))
+            .collect::<Vec<_>>();
+        racing_threads.sort();
+        assert_eq!(racing_threads, [(0, 2), (0, 3), (2, 0), (2, 3), (3, 0)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn count_events_like_generated_trace() -> Result<(), Error> {
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let tracing = example_trace(tmp.path().join("trace-cache"));
+        tracing.initialize();
+        tracing.mutex_register(8, Tracing::MUTEX_INIT_NORMAL);
+        tracing.mutex_start_lock(8, (0, 1));
+        tracing.mutex_invalid_access(8);
+        tracing.mutex_start_lock(8, (0, 2));
+        tracing.mutex_finish_lock(8, (0, 2));
+
+        let stats = tracing.stats();
+        assert_eq!(stats.n_events, tracing.event_count() - 1);
+        assert_eq!(tracing.approx_size_bytes(), stats.n_events * 8);
+
+        let trace_file = tmp.path().join("trace.data");
+        let expected = tracing
+            .generate_binary_trace(&trace_file)?
+            .statistics(&trace_file)?;
+        assert_eq!(
+            stats,
+            RecordingStats {
+                n_events: expected.n_events,
+                n_reads: expected.n_reads,
+                n_writes: expected.n_writes,
+                n_aquires: expected.n_aquires,
+                n_requests: expected.n_requests,
+                n_releases: expected.n_releases,
+                n_forks: expected.n_forks,
+                n_joins: expected.n_joins,
+                n_waits: expected.n_waits,
+                n_notifies: expected.n_notifies,
+                n_calls: expected.n_calls,
+                n_returns: expected.n_returns,
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn drop_filtered_events() {
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let filter = TraceFilter::new()
+            .address_range(64, 128)
+            .ops(&[OpKind::Write]);
+        let tracing = Tracing::new(tmp.path().join("trace-cache")).with_filter(filter);
+        tracing.initialize();
+
+        tracing.thread_create(1, Tracing::THREAD_CREATE_JOINABLE, (0, 1));
+        tracing.memory_access_write(40, 4, 0, (0, 2));
+        tracing.memory_access_write(64, 4, 0, (0, 3));
+        tracing.memory_access_read(64, 4, 0, (0, 4));
+        tracing.mutex_register(8, Tracing::MUTEX_INIT_NORMAL);
+        tracing.mutex_start_lock(8, (0, 5));
+        tracing.mutex_invalid_access(8);
+
+        // Only the fork and the write to 64 are recorded
+        assert_eq!(tracing.event_count(), 2);
+    }
+
+    #[test]
+    fn distinguish_recycled_userspace_tids() -> Result<(), Error> {
+        const USERSPACE_TID: u32 = 1;
+
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let tracing = Tracing::new(tmp.path().join("trace-cache"));
+        tracing.initialize();
+
+        let mut children = Vec::new();
+        for loc in [1, 2] {
+            let child =
+                tracing.thread_create(USERSPACE_TID, Tracing::THREAD_CREATE_JOINABLE, (0, loc));
+            std::thread::scope(|scope| {
+                scope
+                    .spawn(|| {
+                        tracing.thread_register(child);
+                        tracing.memory_access_write(40, 4, 0, (0, 3));
+                    })
+                    .join()
+                    .expect("Child thread panicked!");
+            });
+            tracing.thread_join(tracing.thread_consume(USERSPACE_TID), (0, 4));
+            children.push(child);
+        }
+        assert_ne!(children[0], children[1]);
+
+        let trace_file = tmp.path().join("trace.data");
+        tracing.generate_binary_trace(&trace_file)?;
+        let writers = RapidBinParser::new()
+            .parse(BufReader::new(File::open(&trace_file)?))?
+            .filter_map(|event| match event {
+                Ok(event) => matches!(event.get_fields().1, Operation::Write { .. })
+                    .then(|| Ok(*event.get_fields().0)),
+                Err(e) => Some(Err(e)),
+            })
+            .collect::<Result<HashSet<u64>, Error>>()?;
+        assert_eq!(writers.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reuse_thread_for_consecutive_traces() -> Result<(), Error> {
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+
+        // The first trace is abandoned while this thread still holds its state
+        let first = Tracing::new(tmp.path().join("first-cache"));
+        first.initialize();
+        first.memory_access_write(40, 4, 0, (0, 1));
+        drop(first);
+
+        let second = Tracing::new(tmp.path().join("second-cache"));
+        second.initialize();
+        second.memory_access_write(40, 4, 0, (0, 2));
+        second.memory_access_read(40, 4, 0, (0, 3));
+        assert_eq!((second.event_count(), second.thread_count()), (2, 1));
+
+        let trace_file = tmp.path().join("trace.data");
+        second.generate_binary_trace(&trace_file)?;
+        let events = RapidBinParser::new()
+            .parse(BufReader::new(File::open(&trace_file)?))?
+            .collect::<Result<Vec<generic::Event>, Error>>()?;
+        assert_eq!(events.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn interleave_traces_on_one_thread() -> Result<(), Error> {
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+
+        let first = Tracing::new(tmp.path().join("first-cache"));
+        let second = Tracing::new(tmp.path().join("second-cache"));
+        first.initialize();
+        second.initialize();
+        first.memory_access_write(40, 4, 0, (0, 1));
+        second.memory_access_write(40, 4, 0, (0, 2));
+        first.memory_access_read(40, 4, 0, (0, 3));
+        second.thread_ignore_begin();
+        second.memory_access_read(40, 4, 0, (0, 4));
+        first.memory_access_read(44, 4, 0, (0, 5));
+
+        // Each instance keeps its own TID, ignore flag and event buffer
+        let count_events = |tracing: Tracing, name: &str| -> Result<usize, Error> {
+            let trace_file = tmp.path().join(name);
+            tracing.generate_binary_trace(&trace_file)?;
+            Ok(RapidBinParser::new()
+                .parse(BufReader::new(File::open(&trace_file)?))?
+                .collect::<Result<Vec<generic::Event>, Error>>()?
+                .len())
+        };
+        assert_eq!(count_events(second, "second.data")?, 1);
+        assert_eq!(count_events(first, "first.data")?, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn wasmgrind_lock_names() -> Result<(), Error> {
+        let mut module = walrus::Module::with_config(walrus::ModuleConfig::new());
+        let memory = module.memories.add_local(false, false, 1, None, None);
+        let data = module.data.add(
+            walrus::DataKind::Active {
+                memory,
+                offset: walrus::ConstExpr::Value(walrus::ir::Value::I32(1024)),
+            },
+            vec![0; 64],
+        );
+        module.data.get_mut(data).name = Some(".data".to_string());
+        let symbolizer = LockSymbolizer::from_wasm(&module.emit_wasm())?;
+
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let tracing = Tracing::new(tmp.path().join("trace-cache"));
+        tracing.initialize();
+        tracing.mutex_register(1032, Tracing::MUTEX_INIT_NORMAL);
+        tracing.mutex_start_lock(1032, (0, 1));
+        tracing.mutex_finish_lock(1032, (0, 1));
+        tracing.mutex_unlock(1032, (0, 2));
+        tracing.mutex_unregister(1032);
+
+        let trace_file = tmp.path().join("trace.data");
+        let mut metadata = tracing.generate_binary_trace(&trace_file)?;
+        assert_eq!(metadata.lock_address(0), Some(1032));
+        assert_eq!(metadata.lock_name(0), None);
+        // Unnamed locks are reported by their address
+        assert_eq!(
+            metadata.lock_names(),
+            HashMap::from([(0, "0x408".to_string())])
+        );
+
+        metadata.attach_lock_names(&symbolizer);
+        assert_eq!(metadata.lock_name(0), Some(".data+0x8"));
+        assert_eq!(
+            metadata.lock_names(),
+            HashMap::from([(0, ".data+0x8".to_string())])
+        );
+        let report = metadata.lock_contention_report(&trace_file)?;
+        assert_eq!(report.locks[0].name.as_deref(), Some(".data+0x8"));
+
+        let metadata = WasmgrindTraceMetadata::from_json(metadata.to_json()?.as_bytes())?;
+        assert_eq!(metadata.lock_name(0), Some(".data+0x8"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn wasmgrind_overlap_report() -> Result<(), Error> {
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let tracing = Tracing::new(tmp.path().join("trace-cache"));
+        tracing.initialize();
+
+        let child = tracing.thread_create(1, Tracing::THREAD_CREATE_JOINABLE, (0, 1));
+        let access_both = |tracing: &Tracing| {
+            tracing.memory_access_write(40, 4, 0, (0, 2));
+            tracing.memory_access_read(42, 2, 0, (0, 3));
+        };
+        access_both(&tracing);
+        // The events of the child are flushed by a thread-local destructor.
+        // Joining explicitly waits for it, whereas leaving the scope does not.
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    tracing.thread_register(child);
+                    access_both(&tracing);
+                })
+                .join()
+                .expect("Child thread panicked!");
+        });
+
+        let trace_file = tmp.path().join("trace.data");
+        let trace_metadata = tracing.generate_binary_trace(&trace_file)?;
+        let overlaps = trace_metadata.find_overlaps(&trace_file)?;
+
+        let overlap = &overlaps.get_overlaps()[0];
+        let (access_x, access_y) = overlap.accesses();
+        let mut accesses = [
+            (access_x.address(), access_x.access_width()),
+            (access_y.address(), access_y.access_width()),
+        ];
+        accesses.sort();
+        assert_eq!(accesses, [(40, 4), (42, 2)]);
+
+        let report = overlaps.to_report();
+        assert_eq!(report.overlaps.len(), 1);
+        assert_eq!(report.overlaps[0].access_x.threads, vec![0, 1]);
+        assert_eq!(report.overlaps[0].description, overlap.description());
+        assert_eq!((report.n_overlap_events, report.n_memory_events), (4, 4));
+        assert!(report.to_json()?.contains("\"access_width\": 2"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn wasmgrind_symbolize_locations() -> Result<(), Error> {
+        let mut module = walrus::Module::default();
+        for name in [Some("worker_main"), None] {
+            let mut builder = walrus::FunctionBuilder::new(&mut module.types, &[], &[]);
+            builder.func_body().i32_const(0).drop();
+            if let Some(name) = name {
+                builder.name(name.to_string());
+            }
+            let func = builder.finish(vec![], &mut module.funcs);
+            module.exports.add(name.unwrap_or("anonymous"), func);
+        }
+        let wasm = module.emit_wasm();
+
+        // Locations are identified by the offset of the first instruction of a function
+        let module = walrus::Module::from_buffer(&wasm)?;
+        let mut entries = module
+            .funcs
+            .iter_local()
+            .map(|(_, func)| func.block(func.entry_block()).first().unwrap().1.data())
+            .collect::<Vec<_>>();
+        entries.sort();
+
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let tracing = Tracing::new(tmp.path().join("trace-cache"));
+        tracing.initialize();
+        tracing.memory_access_write(42, 4, 0, (entries[0], entries[0] + 2));
+        tracing.memory_access_read(42, 4, 0, (entries[1], entries[1] + 2));
+
+        let mut trace_metadata = tracing.generate_binary_trace(tmp.path().join("trace.data"))?;
+        symbolize(&mut trace_metadata, &wasm)?;
+        let trace_metadata =
+            WasmgrindTraceMetadata::from_json(trace_metadata.to_json()?.as_bytes())?;
+
+        assert_eq!(trace_metadata.function_name(0), Some("worker_main"));
+        assert_eq!(trace_metadata.function_name(1), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn wasmgrind_thread_names_roundtrip() -> Result<(), Error> {
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let tracing = Tracing::new(tmp.path().join("trace-cache"));
+        tracing.initialize();
+        tracing.thread_set_name("Hauptfäden-🧵".to_string());
+        tracing.memory_access_write(42, 4, 0, (1, 2));
+
+        let trace_metadata = tracing.generate_binary_trace(tmp.path().join("trace.data"))?;
+        let trace_metadata =
+            WasmgrindTraceMetadata::from_json(trace_metadata.to_json()?.as_bytes())?;
+
+        assert_eq!(trace_metadata.thread_name(0), Some("Hauptfäden-🧵"));
+        assert_eq!(trace_metadata.thread_name(1), None);
+
+        Ok(())
+    }
+
+    /// A clock that advances by 10ns whenever it is read
+    #[derive(Default)]
+    struct SteppingClock(AtomicU64);
+
+    impl ClockSource for SteppingClock {
+        fn now(&self) -> u64 {
+            self.0.fetch_add(10, Ordering::Relaxed) + 10
+        }
+    }
+
+    #[test]
+    fn wasmgrind_timestamps_roundtrip() -> Result<(), Error> {
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let tracing =
+            Tracing::new(tmp.path().join("trace-cache")).with_timestamps(SteppingClock::default());
+        tracing.initialize();
+        tracing.memory_access_write(42, 4, 0, (1, 2));
+        tracing.memory_access_read(42, 4, 0, (1, 3));
+
+        let std_file = tmp.path().join("trace.std");
+        let trace_metadata = tracing.generate_trace(&mut StdFormatEncoder::new(), &std_file)?;
+        assert!(!trace_metadata.to_json()?.contains("timestamp"));
+        let trace_metadata =
+            WasmgrindTraceMetadata::from_json(trace_metadata.to_json()?.as_bytes())?;
+        assert_eq!(trace_metadata.timestamps(&std_file)?, [10, 20]);
+
+        // Without a clock, neither the trace nor the metadata change
+        let tracing = Tracing::new(tmp.path().join("trace-cache-plain"));
+        tracing.initialize();
+        tracing.memory_access_write(42, 4, 0, (1, 2));
+        tracing.memory_access_read(42, 4, 0, (1, 3));
+
+        let plain_file = tmp.path().join("plain.std");
+        let plain_metadata = tracing.generate_trace(&mut StdFormatEncoder::new(), &plain_file)?;
+        assert!(plain_metadata.timestamps(&plain_file)?.is_empty());
+        assert!(!tmp.path().join("plain.std.timestamps").exists());
+        assert_eq!(std::fs::read(&std_file)?, std::fs::read(&plain_file)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn drop_timestamps_of_invalidated_events() -> Result<(), Error> {
+        fn record(tracing: &Tracing) {
+            tracing.initialize();
+            tracing.memory_access_write(40, 4, 0, (0, 1));
+            tracing.mutex_register(8, Tracing::MUTEX_INIT_NORMAL);
+            tracing.mutex_start_lock(8, (0, 2));
+            tracing.mutex_invalid_access(8);
+            tracing.memory_access_read(40, 4, 0, (0, 3));
+        }
+
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let trace_file = tmp.path().join("trace.data");
+        let tracing = Tracing::to_file(&trace_file)?.with_timestamps(SteppingClock::default());
+        record(&tracing);
+        let metadata = tracing.finalize()?;
+        // The timestamp of the invalidated request (20) is dropped
+        assert_eq!(metadata.timestamps(&trace_file)?, [10, 30]);
+        assert_eq!(std::fs::read_dir(tmp.path())?.count(), 2);
+
+        let cached_file = tmp.path().join("cached.data");
+        let tracing =
+            Tracing::new(tmp.path().join("trace-cache")).with_timestamps(SteppingClock::default());
+        record(&tracing);
+        let metadata = tracing.generate_binary_trace(&cached_file)?;
+        assert_eq!(metadata.timestamps(&cached_file)?, [10, 30]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn wasmgrind_metadata_roundtrip() -> Result<(), Error> {
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let trace_metadata = example_trace(tmp.path().join("trace-cache"))
+            .generate_binary_trace(tmp.path().join("trace.data"))?;
+        let json_metadata = trace_metadata.to_json()?;
+        assert_eq!(
+            trace_metadata,
+            WasmgrindTraceMetadata::from_json(json_metadata.as_bytes())?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn wasmgrind_provenance_roundtrip() -> Result<(), Error> {
+        let tmp = tempdir().expect("Could not create out dir for trace!");
+        let mut trace_metadata = example_trace(tmp.path().join("trace-cache"))
+            .generate_binary_trace(tmp.path().join("trace.data"))?;
+
+        // Metadata generated without provenance omits the section and parses as before
+        let json_metadata = trace_metadata.to_json()?;
+        assert!(!json_metadata.contains("provenance"));
+        assert_eq!(
+            WasmgrindTraceMetadata::from_json(json_metadata.as_bytes())?.provenance(),
+            None
+        );
+
+        let options = InstrumentOptions {
+            include: vec![FunctionSelector::Name("worker_*".to_string())],
+            exclude: vec![FunctionSelector::Indices(0..4)],
+            hooks: HookCategories::ALL,
+        };
+        trace_metadata.attach_provenance(TraceProvenance::new(
+            b"",
+            b"abc",
+            Some(AbiFlavor::Tracing),
+            &options,
+        ));
+        let trace_metadata =
+            WasmgrindTraceMetadata::from_json(trace_metadata.to_json()?.as_bytes())?;
+
+        let provenance = trace_metadata
+            .provenance()
+            .expect("Provenance was not retained");
+        assert_eq!(
+            provenance.original_sha256(),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            provenance.instrumented_sha256(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
         );
+        assert_eq!(provenance.wasmgrind_version(), env!("CARGO_PKG_VERSION"));
+        assert_eq!(provenance.abi(), Some(AbiFlavor::Tracing));
+        assert_eq!(provenance.instrument_include(), ["worker_*"]);
+        assert_eq!(provenance.instrument_exclude(), ["0..4"]);
+        assert!(provenance.trace_calls());
+        assert!(provenance.recorded_at() > 0);
 
         Ok(())
     }
@@ -0,0 +1,34 @@
+use std::time::Instant;
+
+/// A source of the timestamps that are attached to recorded events.
+///
+/// See [`super::Tracing::with_timestamps`].
+pub trait ClockSource: Send + Sync {
+    /// Returns the current time in nanoseconds.
+    fn now(&self) -> u64;
+}
+
+/// A monotonic clock that counts the nanoseconds since its creation.
+pub struct MonotonicClock {
+    start: Instant,
+}
+
+impl MonotonicClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClockSource for MonotonicClock {
+    fn now(&self) -> u64 {
+        u64::try_from(self.start.elapsed().as_nanos()).unwrap_or(u64::MAX)
+    }
+}
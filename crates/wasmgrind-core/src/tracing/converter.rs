@@ -43,6 +43,7 @@ pub struct WasmgrindTraceConverter {
     threads: WasmgrindToGeneric<u32>,
     variables: WasmgrindToGeneric<(u32, u32)>,
     locks: WasmgrindToGeneric<u32>,
+    conds: WasmgrindToGeneric<u32>,
     locations: WasmgrindToGeneric<(u32, u32)>,
     shared_variables: HashMap<u64, HashSet<u64>>,
 }
@@ -53,13 +54,20 @@ impl WasmgrindTraceConverter {
             threads: WasmgrindToGeneric::new(),
             variables: WasmgrindToGeneric::new(),
             locks: WasmgrindToGeneric::new(),
+            conds: WasmgrindToGeneric::new(),
             locations: WasmgrindToGeneric::new(),
             shared_variables: HashMap::new(),
         }
     }
 
     pub fn convert_event(&mut self, event: &Event) -> generic::Event {
-        let Event { t, op, loc } = event;
+        // Timestamps are not part of the generic trace (see `super::timestamps`)
+        let Event {
+            t,
+            op,
+            loc,
+            timestamp: _,
+        } = event;
 
         let thread_id = self.threads.get_identifier(t);
         let operation = match op {
@@ -98,18 +106,38 @@ impl WasmgrindTraceConverter {
             Op::Join { tid } => generic::Operation::Join {
                 tid: self.threads.get_identifier(tid),
             },
+            Op::Wait { cond } => generic::Operation::Wait {
+                cond: self.conds.get_identifier(cond),
+            },
+            Op::Notify { cond } => generic::Operation::Notify {
+                cond: self.conds.get_identifier(cond),
+            },
+            // Function indices are stable across traces and do not need to be mapped
+            Op::Call { fidx } => generic::Operation::Call {
+                fidx: u64::from(*fidx),
+            },
+            Op::Return { fidx } => generic::Operation::Return {
+                fidx: u64::from(*fidx),
+            },
         };
         let location = self.locations.get_identifier(loc);
 
         generic::Event::new(thread_id, operation, location)
     }
 
-    pub fn generate_metadata(&self) -> WasmgrindTraceMetadata {
+    /// Returns the number of distinct locations converted so far.
+    pub fn n_locations(&self) -> usize {
+        self.locations.get_map().len()
+    }
+
+    pub fn generate_metadata(&self, thread_names: &HashMap<u32, String>) -> WasmgrindTraceMetadata {
         let mut metadata = WasmgrindTraceMetadata::new();
 
         metadata.fill_thread_records(self.threads.get_map());
+        metadata.fill_thread_names(self.threads.get_map(), thread_names);
         metadata.fill_memory_records(self.variables.get_map());
         metadata.fill_lock_records(self.locks.get_map());
+        metadata.fill_cond_records(self.conds.get_map());
         metadata.fill_location_records(self.locations.get_map());
         metadata.fill_shared_variables(&self.shared_variables);
 
@@ -0,0 +1,173 @@
+use std::ops::Range;
+
+use crate::tracing::{Op, Tid};
+
+/// The kind of an [`Op`] without its operands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OpKind {
+    Read,
+    Write,
+    Aquire,
+    Request,
+    Release,
+    Fork,
+    Join,
+    Wait,
+    Notify,
+    Call,
+    Return,
+}
+
+impl OpKind {
+    fn bit(self) -> u16 {
+        1 << self as u16
+    }
+}
+
+impl From<&Op> for OpKind {
+    fn from(op: &Op) -> Self {
+        match op {
+            Op::Read { .. } => OpKind::Read,
+            Op::Write { .. } => OpKind::Write,
+            Op::Aquire { .. } => OpKind::Aquire,
+            Op::Request { .. } => OpKind::Request,
+            Op::Release { .. } => OpKind::Release,
+            Op::Fork { .. } => OpKind::Fork,
+            Op::Join { .. } => OpKind::Join,
+            Op::Wait { .. } => OpKind::Wait,
+            Op::Notify { .. } => OpKind::Notify,
+            Op::Call { .. } => OpKind::Call,
+            Op::Return { .. } => OpKind::Return,
+        }
+    }
+}
+
+/// Restricts the events that are recorded by [`crate::tracing::Tracing`].
+///
+/// An event is recorded if it satisfies all configured criteria. Fork and join
+/// events are exempt from filtering by default, such that the thread structure
+/// of the trace is preserved (see [`TraceFilter::exempt_thread_ops`]).
+#[derive(Clone, Debug)]
+pub struct TraceFilter {
+    address_ranges: Vec<Range<u32>>,
+    ops: Option<u16>,
+    threads: Option<Vec<Tid>>,
+    exempt_thread_ops: bool,
+}
+
+impl Default for TraceFilter {
+    fn default() -> Self {
+        Self {
+            address_ranges: Vec::new(),
+            ops: None,
+            threads: None,
+            exempt_thread_ops: true,
+        }
+    }
+}
+
+impl TraceFilter {
+    /// Creates a filter that records all events.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only records memory accesses that overlap with `start..end`.
+    ///
+    /// This can be called multiple times to record accesses to several ranges.
+    /// Events other than memory accesses are not affected.
+    pub fn address_range(mut self, start: u32, end: u32) -> Self {
+        self.address_ranges.push(start..end);
+        self
+    }
+
+    /// Only records events with one of the given kinds.
+    pub fn ops(mut self, ops: &[OpKind]) -> Self {
+        self.ops = Some(ops.iter().fold(0, |mask, op| mask | op.bit()));
+        self
+    }
+
+    /// Only records events executed by one of the given threads.
+    pub fn threads(mut self, threads: &[Tid]) -> Self {
+        self.threads = Some(threads.to_vec());
+        self
+    }
+
+    /// Determines whether fork and join events are recorded regardless of the other criteria.
+    pub fn exempt_thread_ops(mut self, exempt: bool) -> Self {
+        self.exempt_thread_ops = exempt;
+        self
+    }
+
+    /// Returns whether an event of thread `tid` executing `op` should be recorded.
+    #[inline]
+    pub fn matches(&self, tid: Tid, op: &Op) -> bool {
+        if self.exempt_thread_ops && matches!(op, Op::Fork { .. } | Op::Join { .. }) {
+            return true;
+        }
+
+        if let Some(threads) = &self.threads
+            && !threads.contains(&tid)
+        {
+            return false;
+        }
+
+        if let Some(ops) = self.ops
+            && ops & OpKind::from(op).bit() == 0
+        {
+            return false;
+        }
+
+        match op {
+            Op::Read { addr, n, .. } | Op::Write { addr, n, .. }
+                if !self.address_ranges.is_empty() =>
+            {
+                let end = addr.saturating_add(*n);
+                self.address_ranges
+                    .iter()
+                    .any(|range| *addr < range.end && range.start < end)
+            }
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OpKind, TraceFilter};
+    use crate::tracing::Op;
+
+    #[test]
+    fn filter_events() {
+        let filter = TraceFilter::new()
+            .address_range(0x100, 0x200)
+            .ops(&[OpKind::Read, OpKind::Aquire])
+            .threads(&[0, 1]);
+
+        let read = |addr| Op::Read {
+            addr,
+            n: 4,
+            atomic: false,
+        };
+
+        assert!(filter.matches(0, &read(0x100)));
+        assert!(filter.matches(1, &read(0xfe)));
+        assert!(!filter.matches(1, &read(0x200)));
+        assert!(!filter.matches(2, &read(0x100)));
+        assert!(!filter.matches(
+            0,
+            &Op::Write {
+                addr: 0x100,
+                n: 4,
+                atomic: false
+            }
+        ));
+        assert!(filter.matches(0, &Op::Aquire { lock: 1 }));
+        assert!(!filter.matches(0, &Op::Release { lock: 1 }));
+
+        // Thread operations are exempt by default
+        assert!(filter.matches(2, &Op::Fork { tid: 3 }));
+        let filter = filter.exempt_thread_ops(false);
+        assert!(!filter.matches(2, &Op::Fork { tid: 3 }));
+    }
+}
@@ -9,12 +9,26 @@ use anyhow::{Error, anyhow};
 use serde::{Deserialize, Serialize};
 use trace_tools::{
     RapidBinParser,
+    analysis::{
+        ContentionAnalyzer, ContentionReport, HappensBefore, RaceReport, TraceStats,
+        TraceStatsCollector,
+    },
     generic::{self, Operation, Parser},
+    open_trace,
 };
 
-use crate::tracing::{Op, metadata::analysis::line_sweep_algorithm, representation::Event};
+use crate::{
+    symbols::{LockSymbolizer, SourceMap},
+    tracing::{
+        Op, metadata::analysis::line_sweep_algorithm, representation::Event,
+        timestamps::read_timestamps,
+    },
+};
 
 mod analysis;
+mod provenance;
+
+pub use provenance::TraceProvenance;
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Hash)]
 struct MemoryIdentifier {
@@ -34,13 +48,35 @@ impl ThreadRecord {
     }
 }
 
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+struct ThreadNameRecord {
+    trace_id: u64,
+    name: String,
+}
+
+/// A memory access identified by its target address and number of accessed bytes.
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Hash)]
-struct MemoryRecord {
+pub struct MemoryRecord {
     wasm_id: MemoryIdentifier,
     trace_id: u64,
 }
 
 impl MemoryRecord {
+    /// Returns the memory address targeted by the access.
+    pub fn address(&self) -> u32 {
+        self.wasm_id.address
+    }
+
+    /// Returns the number of bytes accessed.
+    pub fn access_width(&self) -> u32 {
+        self.wasm_id.access_width
+    }
+
+    /// Returns the ID under which the access appears as variable in the trace.
+    pub fn trace_id(&self) -> u64 {
+        self.trace_id
+    }
+
     fn into_fields(self) -> ((u32, u32), u64) {
         (
             (self.wasm_id.address, self.wasm_id.access_width),
@@ -53,6 +89,10 @@ impl MemoryRecord {
 struct LockRecord {
     wasm_id: u32,
     trace_id: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    address: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
 }
 
 impl LockRecord {
@@ -61,6 +101,18 @@ impl LockRecord {
     }
 }
 
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+struct CondRecord {
+    wasm_id: u32,
+    trace_id: u64,
+}
+
+impl CondRecord {
+    fn into_fields(self) -> (u32, u64) {
+        (self.wasm_id, self.trace_id)
+    }
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
 struct LocationIdentifier {
     fidx: u32,
@@ -71,6 +123,10 @@ struct LocationIdentifier {
 struct LocationRecord {
     wasm_id: LocationIdentifier,
     trace_id: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    function_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
 }
 
 impl LocationRecord {
@@ -82,20 +138,32 @@ impl LocationRecord {
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
 pub struct WasmgrindTraceMetadata {
     thread_records: Vec<ThreadRecord>,
+    #[serde(default)]
+    thread_names: Vec<ThreadNameRecord>,
     memory_records: Vec<MemoryRecord>,
     lock_records: Vec<LockRecord>,
+    #[serde(default)]
+    cond_records: Vec<CondRecord>,
     location_records: Vec<LocationRecord>,
     shared_variables: HashMap<u64, HashSet<u64>>,
+    #[serde(default)]
+    dropped_events: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    provenance: Option<TraceProvenance>,
 }
 
 impl WasmgrindTraceMetadata {
     pub(super) fn new() -> Self {
         Self {
             thread_records: Vec::new(),
+            thread_names: Vec::new(),
             memory_records: Vec::new(),
             lock_records: Vec::new(),
+            cond_records: Vec::new(),
             location_records: Vec::new(),
             shared_variables: HashMap::new(),
+            dropped_events: 0,
+            provenance: None,
         }
     }
 
@@ -119,6 +187,12 @@ impl WasmgrindTraceMetadata {
                     .map(|record| record.into_fields())
                     .map(|(fst, snd)| (snd, fst)),
             ),
+            conds: HashMap::from_iter(
+                self.cond_records
+                    .into_iter()
+                    .map(|record| record.into_fields())
+                    .map(|(fst, snd)| (snd, fst)),
+            ),
             locations: HashMap::from_iter(
                 self.location_records
                     .into_iter()
@@ -138,8 +212,37 @@ impl WasmgrindTraceMetadata {
             });
         }
 
-        self.thread_records
-            .sort_by(|r1, r2| r1.trace_id.cmp(&r2.trace_id));
+        self.thread_records.sort_by_key(|record| record.trace_id);
+    }
+
+    pub(super) fn fill_thread_names(
+        &mut self,
+        threads: &HashMap<u32, u64>,
+        names: &HashMap<u32, String>,
+    ) {
+        self.thread_names = names
+            .iter()
+            .filter_map(|(wasm_id, name)| {
+                threads.get(wasm_id).map(|trace_id| ThreadNameRecord {
+                    trace_id: *trace_id,
+                    name: name.clone(),
+                })
+            })
+            .collect();
+
+        self.thread_names.sort_by_key(|record| record.trace_id);
+    }
+
+    pub(super) fn thread_names_by_wasm_id(&self) -> HashMap<u32, String> {
+        self.thread_names
+            .iter()
+            .filter_map(|name| {
+                self.thread_records
+                    .iter()
+                    .find(|record| record.trace_id == name.trace_id)
+                    .map(|record| (record.wasm_id, name.name.clone()))
+            })
+            .collect()
     }
 
     pub(super) fn fill_memory_records(&mut self, map: &HashMap<(u32, u32), u64>) {
@@ -154,8 +257,7 @@ impl WasmgrindTraceMetadata {
                 trace_id: *v,
             });
         }
-        self.memory_records
-            .sort_by(|r1, r2| r1.trace_id.cmp(&r2.trace_id));
+        self.memory_records.sort_by_key(|record| record.trace_id);
     }
 
     pub(super) fn fill_lock_records(&mut self, map: &HashMap<u32, u64>) {
@@ -165,11 +267,38 @@ impl WasmgrindTraceMetadata {
             self.lock_records.push(LockRecord {
                 wasm_id: *k,
                 trace_id: *v,
+                address: None,
+                name: None,
             });
         }
 
+        self.lock_records.sort_by_key(|record| record.trace_id);
+    }
+
+    pub(super) fn fill_lock_addresses(&mut self, addresses: &HashMap<u32, u32>) {
+        for record in self.lock_records.iter_mut() {
+            record.address = addresses.get(&record.wasm_id).copied();
+        }
+    }
+
+    pub(super) fn lock_addresses_by_wasm_id(&self) -> HashMap<u32, u32> {
         self.lock_records
-            .sort_by(|r1, r2| r1.trace_id.cmp(&r2.trace_id));
+            .iter()
+            .filter_map(|record| record.address.map(|address| (record.wasm_id, address)))
+            .collect()
+    }
+
+    pub(super) fn fill_cond_records(&mut self, map: &HashMap<u32, u64>) {
+        self.cond_records.clear();
+
+        for (k, v) in map.iter() {
+            self.cond_records.push(CondRecord {
+                wasm_id: *k,
+                trace_id: *v,
+            });
+        }
+
+        self.cond_records.sort_by_key(|record| record.trace_id);
     }
 
     pub(super) fn fill_location_records(&mut self, map: &HashMap<(u32, u32), u64>) {
@@ -182,11 +311,12 @@ impl WasmgrindTraceMetadata {
                     iidx: *k2,
                 },
                 trace_id: *v,
+                function_name: None,
+                source: None,
             });
         }
 
-        self.location_records
-            .sort_by(|r1, r2| r1.trace_id.cmp(&r2.trace_id));
+        self.location_records.sort_by_key(|record| record.trace_id);
     }
 
     pub(super) fn fill_shared_variables(&mut self, map: &HashMap<u64, HashSet<u64>>) {
@@ -197,6 +327,143 @@ impl WasmgrindTraceMetadata {
             .collect();
     }
 
+    /// Returns the name of the thread with the given trace ID if it has been named.
+    pub fn thread_name(&self, trace_id: u64) -> Option<&str> {
+        self.thread_names
+            .iter()
+            .find(|record| record.trace_id == trace_id)
+            .map(|record| record.name.as_str())
+    }
+
+    /// Returns the name of the function containing the location with the given trace ID.
+    ///
+    /// Function names are only available after the metadata has been passed to [`symbolize`].
+    pub fn function_name(&self, trace_id: u64) -> Option<&str> {
+        self.location_records
+            .iter()
+            .find(|record| record.trace_id == trace_id)
+            .and_then(|record| record.function_name.as_deref())
+    }
+
+    /// Returns the source position of the location with the given trace ID.
+    ///
+    /// Source positions are only available after [`WasmgrindTraceMetadata::resolve_locations`].
+    pub fn source_location(&self, trace_id: u64) -> Option<&str> {
+        self.location_records
+            .iter()
+            .find(|record| record.trace_id == trace_id)
+            .and_then(|record| record.source.as_deref())
+    }
+
+    /// Returns the source positions of all resolved locations by their trace IDs.
+    ///
+    /// This can be passed to [`trace_tools::StdFormatEncoder::with_location_names`].
+    pub fn location_sources(&self) -> HashMap<u64, String> {
+        self.location_records
+            .iter()
+            .filter_map(|record| Some((record.trace_id, record.source.clone()?)))
+            .collect()
+    }
+
+    /// Annotates the location records with their source positions.
+    ///
+    /// Locations that can not be resolved, e.g., due to missing debug information,
+    /// keep only their numeric form.
+    pub fn resolve_locations(&mut self, source_map: &SourceMap) {
+        for record in self.location_records.iter_mut() {
+            record.source = source_map
+                .lookup((record.wasm_id.fidx, record.wasm_id.iidx))
+                .map(str::to_string);
+        }
+    }
+
+    /// Returns the number of events that have been dropped from the beginning of the trace.
+    ///
+    /// Events are only dropped if the generated trace has been limited to a window of
+    /// the most recent events, see [`crate::tracing::Tracing::with_output_window`].
+    pub fn dropped_events(&self) -> u64 {
+        self.dropped_events
+    }
+
+    /// Returns whether events have been dropped from the beginning of the trace.
+    pub fn is_truncated(&self) -> bool {
+        self.dropped_events > 0
+    }
+
+    pub(super) fn set_dropped_events(&mut self, dropped_events: u64) {
+        self.dropped_events = dropped_events;
+    }
+
+    /// Reads the timestamp of every event of the trace at `trace_file`, indexed by the position of the event.
+    ///
+    /// The timestamps are only recorded by [`crate::tracing::Tracing::with_timestamps`],
+    /// otherwise the returned list is empty. They are stored in a file next to the trace,
+    /// as RapidBin traces have no room for them. They are taken when the event is recorded,
+    /// so events of different threads may appear slightly out of order.
+    pub fn timestamps<P: AsRef<Path>>(&self, trace_file: P) -> Result<Vec<u64>, Error> {
+        read_timestamps(trace_file.as_ref())
+    }
+
+    /// Returns the binary and settings that produced the trace.
+    ///
+    /// Only available after [`WasmgrindTraceMetadata::attach_provenance`].
+    pub fn provenance(&self) -> Option<&TraceProvenance> {
+        self.provenance.as_ref()
+    }
+
+    /// Records the binary and settings that produced the trace.
+    pub fn attach_provenance(&mut self, provenance: TraceProvenance) {
+        self.provenance = Some(provenance);
+    }
+
+    /// Returns the memory address of the lock with the given trace ID.
+    pub fn lock_address(&self, trace_id: u64) -> Option<u32> {
+        self.lock_records
+            .iter()
+            .find(|record| record.trace_id == trace_id)
+            .and_then(|record| record.address)
+    }
+
+    /// Returns the name of the lock with the given trace ID.
+    ///
+    /// Lock names are only available after [`WasmgrindTraceMetadata::attach_lock_names`].
+    pub fn lock_name(&self, trace_id: u64) -> Option<&str> {
+        self.lock_records
+            .iter()
+            .find(|record| record.trace_id == trace_id)
+            .and_then(|record| record.name.as_deref())
+    }
+
+    /// Returns how each lock should be named in reports, by the trace IDs of the locks.
+    ///
+    /// Locks are named by their symbol if available and by their address otherwise.
+    /// Locks without either are left out. This can be passed to
+    /// [`trace_tools::StdFormatEncoder::with_lock_names`].
+    pub fn lock_names(&self) -> HashMap<u64, String> {
+        self.lock_records
+            .iter()
+            .filter_map(|record| {
+                let name = match (&record.name, record.address) {
+                    (Some(name), _) => name.clone(),
+                    (None, Some(address)) => format!("{address:#x}"),
+                    (None, None) => return None,
+                };
+                Some((record.trace_id, name))
+            })
+            .collect()
+    }
+
+    /// Annotates the lock records with the names of the statics at their addresses.
+    ///
+    /// Locks without a known address or symbol stay unnamed.
+    pub fn attach_lock_names(&mut self, symbolizer: &LockSymbolizer) {
+        for record in self.lock_records.iter_mut() {
+            record.name = record
+                .address
+                .and_then(|address| symbolizer.lookup(address));
+        }
+    }
+
     /// Attempts to serialize the metadata to JSON format.
     pub fn to_json(&self) -> Result<String, Error> {
         serde_json::to_string_pretty(&self).map_err(Error::from)
@@ -264,7 +531,7 @@ impl WasmgrindTraceMetadata {
         let mut parser = RapidBinParser::new();
         let mut n_memory_events = 0;
         let mut n_overlap_events = 0;
-        let trace_reader = BufReader::new(File::open(rapid_bin_file)?);
+        let trace_reader = open_trace(BufReader::new(File::open(rapid_bin_file)?))?;
         for event in parser.parse(trace_reader)? {
             let (_, op, _) = event?.into_fields();
             match op {
@@ -284,6 +551,97 @@ impl WasmgrindTraceMetadata {
             n_overlap_events,
         })
     }
+
+    /// Computes summary statistics of the execution trace in RapidBin format.
+    ///
+    /// In addition to the format-independent statistics, the metadata allows
+    /// to determine the total number of bytes read and written by the trace.
+    pub fn statistics<P: AsRef<Path>>(&self, rapid_bin_file: P) -> Result<TraceStats, Error> {
+        let widths: HashMap<u64, u32> = self
+            .memory_records
+            .iter()
+            .map(|record| (record.trace_id, record.wasm_id.access_width))
+            .collect();
+
+        let mut collector = TraceStatsCollector::new();
+        let mut bytes_accessed = 0;
+        let trace_reader = open_trace(BufReader::new(File::open(rapid_bin_file)?))?;
+        for event in RapidBinParser::new().parse(trace_reader)? {
+            let event = event?;
+            if let (_, Operation::Read { memory } | Operation::Write { memory }, _) =
+                event.get_fields()
+            {
+                bytes_accessed += u64::from(
+                    *widths
+                        .get(memory)
+                        .ok_or(anyhow!("Variable-ID not present in metadata"))?,
+                );
+            }
+            collector.process(&event);
+        }
+
+        Ok(TraceStats {
+            bytes_accessed: Some(bytes_accessed),
+            ..collector.finish()
+        })
+    }
+
+    /// Detects data races in the execution trace in RapidBin format.
+    ///
+    /// In contrast to [`WasmgrindTraceMetadata::find_overlaps`], only conflicting
+    /// accesses to the same variable that are not ordered by the happens-before
+    /// relation are reported (see [`HappensBefore`]).
+    pub fn detect_races<P: AsRef<Path>>(
+        &self,
+        rapid_bin_file: P,
+    ) -> Result<Vec<RaceReport>, Error> {
+        let trace_reader = open_trace(BufReader::new(File::open(rapid_bin_file)?))?;
+        HappensBefore::analyze(RapidBinParser::new().parse(trace_reader)?)
+    }
+
+    /// Determines how often threads had to wait for each lock in the execution trace in RapidBin format.
+    ///
+    /// See [`ContentionAnalyzer`]. Locks are named as by [`WasmgrindTraceMetadata::lock_names`].
+    pub fn lock_contention_report<P: AsRef<Path>>(
+        &self,
+        rapid_bin_file: P,
+    ) -> Result<ContentionReport, Error> {
+        let trace_reader = open_trace(BufReader::new(File::open(rapid_bin_file)?))?;
+        let report = ContentionAnalyzer::analyze(RapidBinParser::new().parse(trace_reader)?)?;
+        Ok(report.with_lock_names(&self.lock_names()))
+    }
+
+    /// Creates a short message describing a data race found by [`WasmgrindTraceMetadata::detect_races`].
+    ///
+    /// Locations are described by their source position or function name if available.
+    pub fn describe_race(&self, race: &RaceReport) -> String {
+        let variable = match self
+            .memory_records
+            .iter()
+            .find(|record| record.trace_id == race.variable)
+        {
+            Some(record) => format!(
+                "{:#x} ({} bytes)",
+                record.wasm_id.address, record.wasm_id.access_width
+            ),
+            None => format!("variable {}", race.variable),
+        };
+
+        format!(
+            "Data race on {variable} between thread {} at {} and thread {} at {}",
+            race.first_thread,
+            self.describe_location(race.first_location),
+            race.second_thread,
+            self.describe_location(race.second_location),
+        )
+    }
+
+    fn describe_location(&self, trace_id: u64) -> String {
+        self.source_location(trace_id)
+            .or_else(|| self.function_name(trace_id))
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("location {trace_id}"))
+    }
 }
 
 pub struct Overlaps<'a> {
@@ -321,6 +679,72 @@ impl<'a> Overlaps<'a> {
     pub fn get_overlap_ratio(&self) -> (usize, usize) {
         (self.n_overlap_events, self.n_memory_events)
     }
+
+    /// Collects the overlaps into a report that can be serialized, e.g., to JSON.
+    pub fn to_report(&self) -> OverlapReport {
+        OverlapReport {
+            overlaps: self
+                .overlaps
+                .iter()
+                .map(|overlap| OverlapEntry {
+                    access_x: OverlappingAccess::new(overlap.access_x, overlap.threads_x),
+                    access_y: OverlappingAccess::new(overlap.access_y, overlap.threads_y),
+                    description: overlap.description(),
+                })
+                .collect(),
+            n_overlap_events: self.n_overlap_events,
+            n_memory_events: self.n_memory_events,
+        }
+    }
+}
+
+/// A serializable summary of all overlaps found in an execution trace.
+///
+/// See [`Overlaps::get_overlaps`] and [`Overlaps::get_overlap_ratio`] for the meaning of the fields.
+#[derive(Serialize, PartialEq, Eq, Debug)]
+pub struct OverlapReport {
+    pub overlaps: Vec<OverlapEntry>,
+    pub n_overlap_events: usize,
+    pub n_memory_events: usize,
+}
+
+impl OverlapReport {
+    /// Attempts to serialize the report to JSON format.
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(&self).map_err(Error::from)
+    }
+}
+
+/// A single pair of overlapping memory accesses in an [`OverlapReport`].
+#[derive(Serialize, PartialEq, Eq, Debug)]
+pub struct OverlapEntry {
+    pub access_x: OverlappingAccess,
+    pub access_y: OverlappingAccess,
+    pub description: String,
+}
+
+/// One of the two memory accesses of an [`OverlapEntry`].
+#[derive(Serialize, PartialEq, Eq, Debug)]
+pub struct OverlappingAccess {
+    pub trace_id: u64,
+    pub address: u32,
+    pub access_width: u32,
+    /// The threads that performed the access, in ascending order
+    pub threads: Vec<u64>,
+}
+
+impl OverlappingAccess {
+    fn new(access: &MemoryRecord, threads: &HashSet<u64>) -> Self {
+        let mut threads = Vec::from_iter(threads.iter().copied());
+        threads.sort();
+
+        Self {
+            trace_id: access.trace_id,
+            address: access.wasm_id.address,
+            access_width: access.wasm_id.access_width,
+            threads,
+        }
+    }
 }
 
 /// A pair of two distinct memory accesses that share at least one byte of targeted memory.
@@ -332,7 +756,17 @@ pub struct Overlap<'a> {
     access_y: &'a MemoryRecord,
 }
 
-impl Overlap<'_> {
+impl<'a> Overlap<'a> {
+    /// Returns the two overlapping memory accesses.
+    pub fn accesses(&self) -> (&'a MemoryRecord, &'a MemoryRecord) {
+        (self.access_x, self.access_y)
+    }
+
+    /// Returns the threads among which each of the two memory accesses is shared.
+    pub fn threads(&self) -> (&'a HashSet<u64>, &'a HashSet<u64>) {
+        (self.threads_x, self.threads_y)
+    }
+
     fn is_intersection(&self) -> bool {
         let start_x = self.access_x.wasm_id.address;
         let start_y = self.access_y.wasm_id.address;
@@ -424,10 +858,38 @@ impl Default for WasmgrindTraceMetadata {
     }
 }
 
+/// Annotates the location records of `metadata` with the names of their functions.
+///
+/// Locations are identified by code offsets into the binary that has been passed to
+/// the instrumentation. Hence, `wasm` has to be that very binary and not the instrumented one.
+/// Function names are taken from the `name` custom section. If the binary does not contain
+/// such a section, or if it lacks the name of a function, the affected records stay unnamed.
+pub fn symbolize(metadata: &mut WasmgrindTraceMetadata, wasm: &[u8]) -> Result<(), Error> {
+    let module = walrus::Module::from_buffer(wasm)?;
+
+    let names: HashMap<u32, &str> = module
+        .funcs
+        .iter_local()
+        .filter_map(|(id, func)| {
+            let name = module.funcs.get(id).name.as_deref()?;
+            let entry = func.block(func.entry_block());
+            let loc = entry.first().map(|(_, loc)| loc).unwrap_or(&entry.end);
+            Some((loc.data(), name))
+        })
+        .collect();
+
+    for record in metadata.location_records.iter_mut() {
+        record.function_name = names.get(&record.wasm_id.fidx).map(|name| name.to_string());
+    }
+
+    Ok(())
+}
+
 pub(super) struct GenericTraceConverter {
     threads: HashMap<u64, u32>,
     variables: HashMap<u64, (u32, u32)>,
     locks: HashMap<u64, u32>,
+    conds: HashMap<u64, u32>,
     locations: HashMap<u64, (u32, u32)>,
 }
 
@@ -496,12 +958,31 @@ impl GenericTraceConverter {
                     .get(lock)
                     .ok_or(anyhow!("Lock-ID not present in metadata"))?,
             },
+            generic::Operation::Wait { cond } => Op::Wait {
+                cond: *self
+                    .conds
+                    .get(cond)
+                    .ok_or(anyhow!("Condvar-ID not present in metadata"))?,
+            },
+            generic::Operation::Notify { cond } => Op::Notify {
+                cond: *self
+                    .conds
+                    .get(cond)
+                    .ok_or(anyhow!("Condvar-ID not present in metadata"))?,
+            },
+            generic::Operation::Call { fidx } => Op::Call {
+                fidx: u32::try_from(*fidx)?,
+            },
+            generic::Operation::Return { fidx } => Op::Return {
+                fidx: u32::try_from(*fidx)?,
+            },
         };
 
         Ok(Event {
             t: *thread,
             op,
             loc: *location,
+            timestamp: None,
         })
     }
 }
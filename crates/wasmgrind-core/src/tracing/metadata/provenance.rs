@@ -0,0 +1,86 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{abi::AbiFlavor, instrumentation::InstrumentOptions};
+
+/// Describes which binary and which settings produced a trace.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct TraceProvenance {
+    /// SHA-256 of the binary before instrumentation (hex encoded)
+    original_sha256: String,
+    /// SHA-256 of the instrumented binary (hex encoded)
+    instrumented_sha256: String,
+    /// Version of Wasmgrind that recorded the trace
+    wasmgrind_version: String,
+    /// ABI the binary has been executed with, `None` for runtimes without a Wasmgrind ABI (e.g., WALI)
+    abi: Option<AbiFlavor>,
+    /// Selectors of the functions that received memory access hooks
+    #[serde(default)]
+    instrument_include: Vec<String>,
+    /// Selectors of the functions that were excluded from memory access hooks
+    #[serde(default)]
+    instrument_exclude: Vec<String>,
+    /// Whether function entries and exits were recorded
+    #[serde(default)]
+    trace_calls: bool,
+    /// Seconds since the UNIX epoch at which the provenance was recorded
+    recorded_at: u64,
+}
+
+impl TraceProvenance {
+    /// Records the provenance of a trace of the `instrumented` version of `original`.
+    pub fn new(
+        original: &[u8],
+        instrumented: &[u8],
+        abi: Option<AbiFlavor>,
+        options: &InstrumentOptions,
+    ) -> Self {
+        Self {
+            original_sha256: format!("{:x}", Sha256::digest(original)),
+            instrumented_sha256: format!("{:x}", Sha256::digest(instrumented)),
+            wasmgrind_version: env!("CARGO_PKG_VERSION").to_string(),
+            abi,
+            instrument_include: options.include.iter().map(ToString::to_string).collect(),
+            instrument_exclude: options.exclude.iter().map(ToString::to_string).collect(),
+            trace_calls: options.hooks.calls,
+            recorded_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+        }
+    }
+
+    pub fn original_sha256(&self) -> &str {
+        &self.original_sha256
+    }
+
+    pub fn instrumented_sha256(&self) -> &str {
+        &self.instrumented_sha256
+    }
+
+    pub fn wasmgrind_version(&self) -> &str {
+        &self.wasmgrind_version
+    }
+
+    pub fn abi(&self) -> Option<AbiFlavor> {
+        self.abi
+    }
+
+    pub fn instrument_include(&self) -> &[String] {
+        &self.instrument_include
+    }
+
+    pub fn instrument_exclude(&self) -> &[String] {
+        &self.instrument_exclude
+    }
+
+    pub fn trace_calls(&self) -> bool {
+        self.trace_calls
+    }
+
+    /// Returns the seconds since the UNIX epoch at which the provenance was recorded.
+    pub fn recorded_at(&self) -> u64 {
+        self.recorded_at
+    }
+}
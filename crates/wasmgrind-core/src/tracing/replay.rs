@@ -0,0 +1,300 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::BufReader,
+    path::Path,
+    sync::{Condvar, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use anyhow::Error;
+use trace_tools::{RapidBinParser, generic::Parser, open_trace};
+
+use crate::tracing::{OpKind, Tid, metadata::WasmgrindTraceMetadata, representation::Event};
+
+/// Enforces the order of the synchronization events of a previously recorded trace.
+///
+/// Fork, join, and aquire events are replayed in the order in which they occur in the
+/// recorded trace: a thread that is about to perform one of these operations is blocked
+/// until all preceding operations of the trace have been performed. Any other events
+/// are not constrained.
+///
+/// If a thread performs an operation that does not match its next recorded operation,
+/// the replay has diverged from the trace and is aborted with a diagnostic. The same
+/// happens if the next recorded operation is not performed within the configured
+/// timeout (see [`ReplayScheduler::with_timeout`]).
+pub struct ReplayScheduler {
+    schedule: Vec<Event>,
+    state: Mutex<ReplayState>,
+    turn: Condvar,
+    timeout: Duration,
+}
+
+struct ReplayState {
+    /// Index of the next operation of the schedule that may be performed
+    next: usize,
+    /// Indices of the operations that remain to be performed by each thread
+    pending: HashMap<Tid, VecDeque<usize>>,
+    /// Set as soon as a thread diverged from the schedule
+    diverged: bool,
+}
+
+impl ReplayScheduler {
+    /// The time a thread waits for its turn before the replay is considered to be stuck.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+    pub(super) fn new<I: IntoIterator<Item = Event>>(events: I) -> Self {
+        let schedule: Vec<Event> = events
+            .into_iter()
+            .filter(|event| Self::is_scheduled(OpKind::from(&event.op)))
+            .collect();
+
+        let mut pending: HashMap<Tid, VecDeque<usize>> = HashMap::new();
+        for (idx, event) in schedule.iter().enumerate() {
+            pending.entry(event.t).or_default().push_back(idx);
+        }
+
+        Self {
+            schedule,
+            state: Mutex::new(ReplayState {
+                next: 0,
+                pending,
+                diverged: false,
+            }),
+            turn: Condvar::new(),
+            timeout: Self::DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Loads the schedule from a RapidBin trace and the metadata generated alongside it.
+    pub fn from_trace<P: AsRef<Path>>(
+        rapid_bin_file: P,
+        metadata: WasmgrindTraceMetadata,
+    ) -> Result<Self, Error> {
+        let converter = metadata.into_converter();
+        let events = RapidBinParser::new()
+            .parse(open_trace(BufReader::new(File::open(rapid_bin_file)?))?)?
+            .map(|event| converter.convert_event(&event?))
+            .collect::<Result<Vec<Event>, Error>>()?;
+
+        Ok(Self::new(events))
+    }
+
+    /// Sets the time a thread waits for its turn before the replay is aborted.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the number of recorded operations that have not been replayed yet.
+    pub fn remaining(&self) -> usize {
+        let state = self.state.lock().expect("Could not lock replay state!");
+        self.schedule.len() - state.next
+    }
+
+    fn is_scheduled(kind: OpKind) -> bool {
+        matches!(kind, OpKind::Fork | OpKind::Join | OpKind::Aquire)
+    }
+
+    /// Blocks the thread `t` until it may perform an operation of the given `kind`.
+    ///
+    /// Operations that are not part of the schedule return immediately.
+    /// Each call must be followed by a call to [`ReplayScheduler::complete`] once the
+    /// operation has been performed.
+    pub(super) fn wait_turn(&self, t: Tid, kind: OpKind) {
+        if !Self::is_scheduled(kind) {
+            return;
+        }
+
+        let mut state = self.state.lock().expect("Could not lock replay state!");
+        let idx = match state.pending.get(&t).and_then(|pending| pending.front()) {
+            Some(&idx) if OpKind::from(&self.schedule[idx].op) == kind => idx,
+            Some(&idx) => {
+                let expected = &self.schedule[idx];
+                self.diverge(
+                    state,
+                    format!(
+                        "thread {t} performed {kind:?}, but the trace recorded {:?} at {:?} \
+                        as its next synchronization event (#{idx})",
+                        OpKind::from(&expected.op),
+                        expected.loc
+                    ),
+                );
+            }
+            None => self.diverge(
+                state,
+                format!(
+                    "thread {t} performed {kind:?}, but the trace recorded no further \
+                    synchronization events for this thread"
+                ),
+            ),
+        };
+
+        while state.next != idx {
+            if state.diverged {
+                panic!("Replay aborted: another thread diverged from the recorded trace");
+            }
+
+            let (next_state, result) = self
+                .turn
+                .wait_timeout(state, self.timeout)
+                .expect("Could not lock replay state!");
+            state = next_state;
+
+            if result.timed_out() && state.next != idx && !state.diverged {
+                let blocking = &self.schedule[state.next];
+                let message = format!(
+                    "thread {t} waited {:?} for its turn (#{idx}), but thread {} did not \
+                    perform {:?} at {:?} (#{})",
+                    self.timeout,
+                    blocking.t,
+                    OpKind::from(&blocking.op),
+                    blocking.loc,
+                    state.next
+                );
+                self.diverge(state, message);
+            }
+        }
+    }
+
+    /// Marks the operation `kind` of thread `t` at `loc` as performed, passing the turn on.
+    pub(super) fn complete(&self, t: Tid, kind: OpKind, loc: (u32, u32)) {
+        if !Self::is_scheduled(kind) {
+            return;
+        }
+
+        let mut state = self.state.lock().expect("Could not lock replay state!");
+        let idx = state
+            .pending
+            .get_mut(&t)
+            .and_then(|pending| pending.pop_front())
+            .expect("Completed an operation without waiting for its turn");
+        debug_assert_eq!(idx, state.next, "Completed an operation out of turn");
+
+        let expected = &self.schedule[idx];
+        if expected.loc != loc {
+            self.diverge(
+                state,
+                format!(
+                    "thread {t} performed {kind:?} at {loc:?}, but the trace recorded it at {:?} (#{idx})",
+                    expected.loc
+                ),
+            );
+        }
+
+        state.next += 1;
+        self.turn.notify_all();
+    }
+
+    /// Aborts the replay, waking up all threads that wait for their turn.
+    fn diverge(&self, mut state: MutexGuard<'_, ReplayState>, message: String) -> ! {
+        state.diverged = true;
+        drop(state);
+        self.turn.notify_all();
+        panic!("Replay diverged from the recorded trace: {message}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    use super::ReplayScheduler;
+    use crate::tracing::{Op, OpKind, representation::Event};
+
+    fn event(t: u32, op: Op, loc: (u32, u32)) -> Event {
+        Event {
+            t,
+            op,
+            loc,
+            timestamp: None,
+        }
+    }
+
+    #[test]
+    fn enforce_recorded_order() {
+        let scheduler = Arc::new(ReplayScheduler::new(vec![
+            event(0, Op::Fork { tid: 1 }, (1, 1)),
+            event(0, Op::Fork { tid: 2 }, (1, 2)),
+            event(2, Op::Aquire { lock: 0 }, (2, 1)),
+            event(
+                2,
+                Op::Read {
+                    addr: 4,
+                    n: 4,
+                    atomic: false,
+                },
+                (2, 2),
+            ),
+            event(1, Op::Aquire { lock: 0 }, (2, 1)),
+            event(0, Op::Join { tid: 1 }, (1, 3)),
+            event(0, Op::Join { tid: 2 }, (1, 4)),
+        ]));
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let perform = |t: u32, kind: OpKind, loc: (u32, u32)| {
+            let scheduler = scheduler.clone();
+            let order = order.clone();
+            move || {
+                scheduler.wait_turn(t, kind);
+                order.lock().unwrap().push((t, kind));
+                scheduler.complete(t, kind, loc);
+            }
+        };
+
+        perform(0, OpKind::Fork, (1, 1))();
+        perform(0, OpKind::Fork, (1, 2))();
+        let first = thread::spawn(perform(1, OpKind::Aquire, (2, 1)));
+        thread::sleep(Duration::from_millis(20));
+        let second = thread::spawn(perform(2, OpKind::Aquire, (2, 1)));
+        first.join().unwrap();
+        second.join().unwrap();
+        perform(0, OpKind::Join, (1, 3))();
+        perform(0, OpKind::Join, (1, 4))();
+
+        assert_eq!(
+            *order.lock().unwrap(),
+            vec![
+                (0, OpKind::Fork),
+                (0, OpKind::Fork),
+                (2, OpKind::Aquire),
+                (1, OpKind::Aquire),
+                (0, OpKind::Join),
+                (0, OpKind::Join),
+            ]
+        );
+        assert_eq!(scheduler.remaining(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Replay diverged from the recorded trace: thread 0 performed Join")]
+    fn report_divergence() {
+        let scheduler = ReplayScheduler::new(vec![event(0, Op::Fork { tid: 1 }, (1, 1))]);
+        scheduler.wait_turn(0, OpKind::Join);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "thread 0 performed Fork at (1, 2), but the trace recorded it at (1, 1)"
+    )]
+    fn report_diverging_location() {
+        let scheduler = ReplayScheduler::new(vec![event(0, Op::Fork { tid: 1 }, (1, 1))]);
+        scheduler.wait_turn(0, OpKind::Fork);
+        scheduler.complete(0, OpKind::Fork, (1, 2));
+    }
+
+    #[test]
+    #[should_panic(expected = "but thread 1 did not perform Aquire")]
+    fn report_stalled_replay() {
+        let scheduler = ReplayScheduler::new(vec![
+            event(1, Op::Aquire { lock: 0 }, (2, 1)),
+            event(0, Op::Join { tid: 1 }, (1, 1)),
+        ])
+        .with_timeout(Duration::from_millis(20));
+        scheduler.wait_turn(0, OpKind::Join);
+    }
+}
@@ -23,12 +23,25 @@ pub enum Op {
 
     /// A thread with id `tid` was joined
     Join { tid: u32 },
+
+    /// The condition variable with id `cond` was waited on
+    Wait { cond: u32 },
+
+    /// The condition variable with id `cond` was notified
+    Notify { cond: u32 },
+
+    /// The function with index `fidx` was entered
+    Call { fidx: u32 },
+
+    /// The function with index `fidx` was left
+    Return { fidx: u32 },
 }
 
 /// A single event of the execution trace.
 #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Event {
-    pub t: u32,          // ID of the executing thread
-    pub op: Op,          // executed operation
-    pub loc: (u32, u32), // location in the program: (function_idx, instr_idx)
+    pub t: u32,                 // ID of the executing thread
+    pub op: Op,                 // executed operation
+    pub loc: (u32, u32),        // location in the program: (function_idx, instr_idx)
+    pub timestamp: Option<u64>, // nanoseconds reported by the clock of the tracing
 }
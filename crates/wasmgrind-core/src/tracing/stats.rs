@@ -0,0 +1,102 @@
+use std::{
+    fmt::Display,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use trace_tools::{RapidBinEncoder, generic::Encoder};
+
+use crate::tracing::OpKind;
+
+/// The number of events of each kind that a [`super::Tracing`] has recorded so far.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct RecordingStats {
+    pub n_events: u64,
+    pub n_reads: u64,
+    pub n_writes: u64,
+    pub n_aquires: u64,
+    pub n_requests: u64,
+    pub n_releases: u64,
+    pub n_forks: u64,
+    pub n_joins: u64,
+    pub n_waits: u64,
+    pub n_notifies: u64,
+    pub n_calls: u64,
+    pub n_returns: u64,
+}
+
+impl RecordingStats {
+    /// Returns the approximate size of the recorded events in RapidBin format.
+    ///
+    /// The size of the header is not included.
+    pub fn approx_size_bytes(&self) -> u64 {
+        self.n_events * RapidBinEncoder::EVENT_SIZE_HINT as u64
+    }
+}
+
+impl Display for RecordingStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Events:          {}", self.n_events)?;
+        writeln!(f, "  Reads:         {}", self.n_reads)?;
+        writeln!(f, "  Writes:        {}", self.n_writes)?;
+        writeln!(f, "  Requests:      {}", self.n_requests)?;
+        writeln!(f, "  Aquires:       {}", self.n_aquires)?;
+        writeln!(f, "  Releases:      {}", self.n_releases)?;
+        writeln!(f, "  Forks:         {}", self.n_forks)?;
+        writeln!(f, "  Joins:         {}", self.n_joins)?;
+        writeln!(f, "  Waits:         {}", self.n_waits)?;
+        writeln!(f, "  Notifies:      {}", self.n_notifies)?;
+        writeln!(f, "  Calls:         {}", self.n_calls)?;
+        writeln!(f, "  Returns:       {}", self.n_returns)?;
+        write!(f, "Approx. size:    {} bytes", self.approx_size_bytes())
+    }
+}
+
+/// Counts the recorded events per kind without taking any lock.
+#[derive(Default)]
+pub(super) struct EventCounters {
+    counts: [AtomicU64; 11],
+}
+
+impl EventCounters {
+    pub(super) fn record(&self, kind: OpKind) {
+        self.counts[kind as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Reverts [`EventCounters::record`] for an event that has been invalidated.
+    pub(super) fn retract(&self, kind: OpKind) {
+        self.counts[kind as usize].fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn snapshot(&self) -> RecordingStats {
+        let count = |kind: OpKind| self.counts[kind as usize].load(Ordering::Relaxed);
+        let stats = RecordingStats {
+            n_events: 0,
+            n_reads: count(OpKind::Read),
+            n_writes: count(OpKind::Write),
+            n_aquires: count(OpKind::Aquire),
+            n_requests: count(OpKind::Request),
+            n_releases: count(OpKind::Release),
+            n_forks: count(OpKind::Fork),
+            n_joins: count(OpKind::Join),
+            n_waits: count(OpKind::Wait),
+            n_notifies: count(OpKind::Notify),
+            n_calls: count(OpKind::Call),
+            n_returns: count(OpKind::Return),
+        };
+
+        RecordingStats {
+            n_events: stats.n_reads
+                + stats.n_writes
+                + stats.n_aquires
+                + stats.n_requests
+                + stats.n_releases
+                + stats.n_forks
+                + stats.n_joins
+                + stats.n_waits
+                + stats.n_notifies
+                + stats.n_calls
+                + stats.n_returns,
+            ..stats
+        }
+    }
+}
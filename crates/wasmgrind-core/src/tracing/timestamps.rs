@@ -0,0 +1,219 @@
+use std::{
+    collections::BTreeSet,
+    ffi::OsString,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Error, bail};
+
+/// Returns the path of the file that holds the timestamps of the events of `trace_file`.
+///
+/// The timestamps are stored as the difference to the timestamp of the preceding
+/// event (zigzag and LEB128 encoded), indexed by the position of the event in the trace.
+pub fn timestamps_path(trace_file: &Path) -> PathBuf {
+    let mut path = trace_file.as_os_str().to_owned();
+    path.push(".timestamps");
+    PathBuf::from(path)
+}
+
+/// Writes `timestamps` to the timestamp file of `trace_file`.
+///
+/// Without any timestamps, a timestamp file of an earlier trace is removed instead,
+/// such that it is not mistaken for the timestamps of this trace.
+pub fn write_timestamps(trace_file: &Path, timestamps: &[u64]) -> Result<(), Error> {
+    if timestamps.is_empty() {
+        return remove_timestamps(trace_file);
+    }
+
+    let mut output = BufWriter::new(File::create(timestamps_path(trace_file))?);
+    let mut encoder = DeltaEncoder::default();
+    for timestamp in timestamps {
+        encoder.write(&mut output, *timestamp)?;
+    }
+    output.flush()?;
+
+    Ok(())
+}
+
+/// Reads the timestamps of the events of `trace_file`.
+///
+/// Returns an empty list if the trace has been recorded without timestamps.
+pub fn read_timestamps(trace_file: &Path) -> Result<Vec<u64>, Error> {
+    let mut input = match File::open(timestamps_path(trace_file)) {
+        Ok(file) => BufReader::new(file),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut timestamps = Vec::new();
+    let mut previous = 0u64;
+    while let Some(delta) = read_varint(&mut input)? {
+        let delta = ((delta >> 1) as i64) ^ -((delta & 1) as i64);
+        previous = previous.wrapping_add_signed(delta);
+        timestamps.push(previous);
+    }
+
+    Ok(timestamps)
+}
+
+/// Removes the timestamp file of `trace_file` if there is one.
+pub fn remove_timestamps(trace_file: &Path) -> Result<(), Error> {
+    match std::fs::remove_file(timestamps_path(trace_file)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Reads a LEB128 encoded integer. Returns `None` at the end of `input`.
+fn read_varint<R: Read>(input: &mut R) -> Result<Option<u64>, Error> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0u8];
+        if input.read(&mut byte)? == 0 {
+            if shift == 0 {
+                return Ok(None);
+            }
+            bail!("The timestamp file ends within a timestamp");
+        }
+
+        value |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+
+    bail!("The timestamp file contains a timestamp that exceeds 64 bits")
+}
+
+/// Encodes timestamps as the difference to their predecessor.
+#[derive(Default)]
+struct DeltaEncoder {
+    previous: u64,
+}
+
+impl DeltaEncoder {
+    fn write<W: Write>(&mut self, output: &mut W, timestamp: u64) -> Result<(), Error> {
+        let delta = timestamp.wrapping_sub(self.previous) as i64;
+        self.previous = timestamp;
+
+        let mut value = ((delta << 1) ^ (delta >> 63)) as u64;
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                output.write_all(&[byte])?;
+                return Ok(());
+            }
+            output.write_all(&[byte | 0x80])?;
+        }
+    }
+}
+
+/// Records the timestamps of a trace that is written to a file as events arrive.
+///
+/// The timestamps are buffered in a temporary file next to the timestamp file, as
+/// events may still be invalidated. [`TimestampFile::finish`] removes the timestamps
+/// of invalidated events, such that the remaining ones line up with the events of
+/// the finished trace.
+pub struct TimestampFile {
+    path: PathBuf,
+    raw_path: PathBuf,
+    raw: BufWriter<File>,
+    invalid: BTreeSet<u64>,
+}
+
+impl TimestampFile {
+    pub fn create(trace_file: &Path) -> Result<Self, Error> {
+        let path = timestamps_path(trace_file);
+        let mut raw_path = OsString::from(path.as_os_str());
+        raw_path.push(".raw");
+        let raw_path = PathBuf::from(raw_path);
+
+        Ok(Self {
+            raw: BufWriter::new(File::create(&raw_path)?),
+            path,
+            raw_path,
+            invalid: BTreeSet::new(),
+        })
+    }
+
+    /// Appends the timestamp of the next event of the trace.
+    pub fn append(&mut self, timestamp: u64) -> Result<(), Error> {
+        self.raw.write_all(&timestamp.to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Drops the timestamp of the event with the given `index` once the file is finished.
+    pub fn invalidate(&mut self, index: u64) {
+        self.invalid.insert(index);
+    }
+
+    /// Writes the timestamps of all valid events to the timestamp file.
+    pub fn finish(self) -> Result<(), Error> {
+        let Self {
+            path,
+            raw_path,
+            raw,
+            invalid,
+        } = self;
+        drop(raw.into_inner().map_err(|e| e.into_error())?);
+
+        let mut input = BufReader::new(File::open(&raw_path)?);
+        let mut output = BufWriter::new(File::create(path)?);
+        let mut encoder = DeltaEncoder::default();
+        let mut bytes = [0u8; 8];
+        for index in 0.. {
+            match input.read_exact(&mut bytes) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            }
+            if !invalid.contains(&index) {
+                encoder.write(&mut output, u64::from_le_bytes(bytes))?;
+            }
+        }
+        output.flush()?;
+        std::fs::remove_file(raw_path)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use tempfile::tempdir;
+
+    use super::{TimestampFile, read_timestamps, timestamps_path, write_timestamps};
+
+    #[test]
+    fn timestamps_roundtrip() -> Result<(), Error> {
+        let tmp = tempdir()?;
+        let trace_file = tmp.path().join("trace.data");
+        assert!(read_timestamps(&trace_file)?.is_empty());
+
+        let timestamps = [0, 5, 3, 1 << 40, u64::MAX, 7];
+        write_timestamps(&trace_file, &timestamps)?;
+        assert_eq!(read_timestamps(&trace_file)?, timestamps);
+        // Small deltas take a single byte
+        write_timestamps(&trace_file, &[10, 20, 15])?;
+        assert_eq!(std::fs::read(timestamps_path(&trace_file))?, [20, 20, 9]);
+
+        write_timestamps(&trace_file, &[])?;
+        assert!(!timestamps_path(&trace_file).exists());
+
+        let mut file = TimestampFile::create(&trace_file)?;
+        for timestamp in timestamps {
+            file.append(timestamp)?;
+        }
+        file.invalidate(1);
+        file.invalidate(4);
+        file.finish()?;
+        assert_eq!(read_timestamps(&trace_file)?, [0, 3, 1 << 40, 7]);
+        assert_eq!(std::fs::read_dir(tmp.path())?.count(), 1);
+
+        Ok(())
+    }
+}
@@ -3,7 +3,7 @@ use std::{
     collections::HashSet,
     path::Path,
     sync::{
-        Mutex,
+        Arc, Mutex, Weak,
         atomic::{self, AtomicU64},
     },
 };
@@ -20,11 +20,15 @@ use crate::tracing::{
 };
 
 mod cursor;
+mod file;
 mod registry;
 mod tls;
 
+pub use file::FileTrace;
+
 thread_local! {
-    static EVENT_BUFFER: RefCell<Option<TlsTrace>> = const { RefCell::new(None) };
+    /// The buffered events of the current thread, one buffer for each [`Trace`] it appends to
+    static EVENT_BUFFERS: RefCell<Vec<(Weak<()>, TlsTrace)>> = const { RefCell::new(Vec::new()) };
 }
 
 #[derive(Encode, Decode, Debug)]
@@ -39,6 +43,8 @@ pub struct EventHandle {
 }
 
 pub struct Trace {
+    /// Identifies the thread-local buffers of this trace
+    id: Arc<()>,
     next_event_id: AtomicU64,
     registry: TraceRegistry,
     invalid: Mutex<HashSet<u64>>,
@@ -47,6 +53,7 @@ pub struct Trace {
 impl Trace {
     pub fn new<P: AsRef<Path>>(cache_dir: P) -> Self {
         Self {
+            id: Arc::new(()),
             next_event_id: AtomicU64::new(0),
             registry: TraceRegistry::new(cache_dir).expect("Could not create trace registry"),
             invalid: Mutex::new(HashSet::new()),
@@ -60,41 +67,52 @@ impl Trace {
             event,
         };
 
-        EVENT_BUFFER.with_borrow_mut(|tls| {
-            if let Some(tls_trace) = tls {
-                tls_trace
-                    .append(record, &self.registry)
-                    .expect("Failed to append event to TLS trace");
-            } else {
-                let mut tls_trace = TlsTrace::new(record.event.t, &self.registry)
-                    .expect("Could not initialize TLS trace");
-                tls_trace
-                    .append(record, &self.registry)
-                    .expect("Failed to append event to TLS trace");
-                *tls = Some(tls_trace);
+        EVENT_BUFFERS.with_borrow_mut(|buffers| {
+            let owner = Arc::as_ptr(&self.id);
+            let idx = match buffers.iter().position(|(id, _)| id.as_ptr() == owner) {
+                Some(idx) => idx,
+                None => {
+                    // Buffers left behind by dropped traces are finalized when they are dropped
+                    buffers.retain(|(id, _)| id.strong_count() > 0);
+                    let tls_trace =
+                        TlsTrace::new(&self.registry).expect("Could not initialize TLS trace");
+                    buffers.push((Arc::downgrade(&self.id), tls_trace));
+                    buffers.len() - 1
+                }
             };
+            buffers[idx]
+                .1
+                .append(record, &self.registry)
+                .expect("Failed to append event to TLS trace");
         });
 
         EventHandle { id: event_id }
     }
 
+    /// Returns the number of events appended so far, including invalidated ones.
+    pub fn n_events(&self) -> u64 {
+        self.next_event_id.load(atomic::Ordering::Relaxed)
+    }
+
     /// Invalidate an event with the given global ID
     ///
     /// # Panics
     /// If the id was already invalidated before
     pub fn invalidate(&self, event_handle: EventHandle) {
-        let was_invalid = self
+        let newly_invalid = self
             .invalid
             .lock()
             .expect("Invalidation mutex was poisoned!")
             .insert(event_handle.id);
 
-        assert!(!was_invalid, "Event was already invalidated once!");
+        assert!(newly_invalid, "Event was already invalidated once!");
     }
 
     pub fn close(self) -> Result<CachedTrace, Error> {
-        EVENT_BUFFER.with_borrow_mut(|tls| {
-            if let Some(mut tls_trace) = tls.take() {
+        EVENT_BUFFERS.with_borrow_mut(|buffers| {
+            let owner = Arc::as_ptr(&self.id);
+            if let Some(idx) = buffers.iter().position(|(id, _)| id.as_ptr() == owner) {
+                let (_, mut tls_trace) = buffers.swap_remove(idx);
                 tls_trace.flush().and_then(|_| tls_trace.seal())?;
             }
 
@@ -119,6 +137,11 @@ pub struct CachedTrace {
 }
 
 impl CachedTrace {
+    /// Returns the number of events that have not been invalidated.
+    pub fn n_valid_events(&self) -> u64 {
+        self.n_events - self.invalid.len() as u64
+    }
+
     pub fn iter(&self) -> Result<TraceIter<'_>, Error> {
         Ok(TraceIter {
             n_events: self.n_events,
@@ -221,6 +244,7 @@ mod tests {
                 atomic: false,
             },
             loc: (5, 42),
+            timestamp: None,
         };
         trace.append_event(event.clone());
 
@@ -250,6 +274,7 @@ mod tests {
                 t: rng.next_u32(),
                 op: generate_op(&mut rng),
                 loc: (rng.next_u32(), rng.next_u32()),
+                timestamp: None,
             };
             trace.append_event(event.clone());
             trace_cmp.push(event);
@@ -297,6 +322,7 @@ mod tests {
                         t: tid,
                         op: generate_op(&mut rng),
                         loc: (rng.next_u32(), rng.next_u32()),
+                        timestamp: None,
                     };
                     trace.append_event(event.clone());
                     thread_events.push(event);
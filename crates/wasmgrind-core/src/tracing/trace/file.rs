@@ -0,0 +1,150 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{self, AtomicU64},
+    },
+};
+
+use anyhow::Error;
+use trace_tools::{RapidBinFileWriter, rapidbin::RapidBinLayout};
+
+use crate::tracing::{
+    converter::WasmgrindTraceConverter,
+    representation::Event,
+    timestamps::{TimestampFile, remove_timestamps},
+    trace::EventHandle,
+};
+
+/// An execution trace that is written to a file in RapidBin format as events arrive.
+///
+/// In contrast to [`super::Trace`], events are converted to their trace IDs right
+/// away, such that neither the events nor their encoding accumulate in memory or
+/// in a cache. Events are written in the order in which they are appended.
+///
+/// All threads append through a single mutex, which serializes the recording: each
+/// event is converted, packed and written to a buffered file while the mutex is held.
+/// This trades throughput of programs with many threads for a constant memory footprint.
+///
+/// The trace is written with [`RapidBinLayout::WIDE_LOCATIONS`], as the number of
+/// locations is not known upfront. Since recording must not fail, the first error
+/// while writing stops the trace and is reported by [`FileTrace::close`].
+///
+/// Timestamps of the events are written to a separate file, which is created
+/// when the first event with a timestamp is appended (see [`TimestampFile`]).
+pub struct FileTrace {
+    n_events: AtomicU64,
+    state: Mutex<FileTraceState>,
+}
+
+struct FileTraceState {
+    outfile: PathBuf,
+    writer: RapidBinFileWriter,
+    converter: WasmgrindTraceConverter,
+    timestamps: Option<TimestampFile>,
+    /// The first error that occurred while writing the trace
+    error: Option<Error>,
+}
+
+impl FileTrace {
+    pub fn create<P: AsRef<Path>>(outfile: P) -> Result<Self, Error> {
+        let outfile = outfile.as_ref();
+        remove_timestamps(outfile)?;
+
+        Ok(Self {
+            n_events: AtomicU64::new(0),
+            state: Mutex::new(FileTraceState {
+                outfile: outfile.to_path_buf(),
+                writer: RapidBinFileWriter::create_with_layout(
+                    outfile,
+                    RapidBinLayout::WIDE_LOCATIONS,
+                )?,
+                converter: WasmgrindTraceConverter::new(),
+                timestamps: None,
+                error: None,
+            }),
+        })
+    }
+
+    pub fn append_event(&self, event: Event) -> EventHandle {
+        let mut state = self.state.lock().expect("Trace file mutex was poisoned");
+        let timestamp = event.timestamp;
+        let event = state.converter.convert_event(&event);
+        // Events are not written anymore once writing has failed
+        let id = match state.error {
+            Some(_) => 0,
+            None => state.writer.append(event).unwrap_or_else(|e| {
+                state.error = Some(e.context("Failed to append event to trace file"));
+                0
+            }),
+        };
+        if let (Some(timestamp), None) = (timestamp, &state.error)
+            && let Err(e) = state.append_timestamp(timestamp)
+        {
+            state.error = Some(e.context("Failed to append timestamp to timestamp file"));
+        }
+        self.n_events.fetch_add(1, atomic::Ordering::Relaxed);
+
+        EventHandle { id }
+    }
+
+    /// Returns the number of events appended so far, including invalidated ones.
+    pub fn n_events(&self) -> u64 {
+        self.n_events.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Removes the event with the given global ID from the trace once it is closed.
+    ///
+    /// # Panics
+    /// If the id was already invalidated before
+    pub fn invalidate(&self, event_handle: EventHandle) {
+        let mut state = self.state.lock().expect("Trace file mutex was poisoned");
+        if state.error.is_none() {
+            state
+                .writer
+                .invalidate(event_handle.id)
+                .expect("Event was already invalidated once!");
+            if let Some(timestamps) = &mut state.timestamps {
+                timestamps.invalidate(event_handle.id);
+            }
+        }
+    }
+
+    /// Writes the header of the trace file and returns the converter of its events.
+    ///
+    /// Fails with the first error that occurred while writing the trace.
+    pub fn close(self) -> Result<WasmgrindTraceConverter, Error> {
+        let FileTraceState {
+            writer,
+            converter,
+            timestamps,
+            error,
+            ..
+        } = self
+            .state
+            .into_inner()
+            .expect("Trace file mutex was poisoned");
+        if let Some(e) = error {
+            return Err(e);
+        }
+        let n_events = writer.finish()?;
+        log::info!("Wrote {n_events} events to the trace file");
+        if let Some(timestamps) = timestamps {
+            timestamps.finish()?;
+        }
+
+        Ok(converter)
+    }
+}
+
+impl FileTraceState {
+    fn append_timestamp(&mut self, timestamp: u64) -> Result<(), Error> {
+        let timestamps = match &mut self.timestamps {
+            Some(timestamps) => timestamps,
+            None => self
+                .timestamps
+                .insert(TimestampFile::create(&self.outfile)?),
+        };
+        timestamps.append(timestamp)
+    }
+}
@@ -173,7 +173,8 @@ pub struct Registry {
     next_file_id: u64,
     file_size: u64,
     registry_dir: PathBuf,
-    cache: HashMap<u32, Vec<Arc<CacheFile>>>,
+    /// The cache files of every thread-local buffer, keyed by the ID of its first file
+    cache: HashMap<u64, Vec<Arc<CacheFile>>>,
 }
 
 impl Registry {
@@ -193,7 +194,12 @@ impl Registry {
         })
     }
 
-    fn request_cache_file(&mut self, thread_id: u32) -> Arc<CacheFile> {
+    fn open_stream(&mut self) -> (u64, Arc<CacheFile>) {
+        let stream = self.next_file_id;
+        (stream, self.request_cache_file(stream))
+    }
+
+    fn request_cache_file(&mut self, stream: u64) -> Arc<CacheFile> {
         let path = self
             .registry_dir
             .join(format!("cache-file-{}.data", self.next_file_id));
@@ -203,7 +209,7 @@ impl Registry {
             file_id: self.next_file_id,
         });
 
-        self.cache.entry(thread_id).or_default().push(file.clone());
+        self.cache.entry(stream).or_default().push(file.clone());
 
         self.next_file_id += 1;
 
@@ -345,11 +351,21 @@ impl TraceRegistry {
         Ok(Self(Mutex::new(Registry::new(cache_dir)?)))
     }
 
-    pub fn request_cache_file(&self, thread_id: u32) -> Arc<CacheFile> {
+    /// Requests the first cache file of a new stream of events.
+    ///
+    /// Returns the ID of the stream, which is used to request its subsequent cache files.
+    pub fn open_stream(&self) -> (u64, Arc<CacheFile>) {
+        self.0
+            .lock()
+            .expect("TraceRegistry lock was poisoned!")
+            .open_stream()
+    }
+
+    pub fn request_cache_file(&self, stream: u64) -> Arc<CacheFile> {
         self.0
             .lock()
             .expect("TraceRegistry lock was poisoned!")
-            .request_cache_file(thread_id)
+            .request_cache_file(stream)
     }
 
     pub fn close(self) -> Registry {
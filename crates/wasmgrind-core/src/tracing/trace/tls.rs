@@ -13,6 +13,7 @@ use crate::tracing::trace::{
 };
 
 pub struct TlsTrace {
+    stream: u64,
     capacity: usize,
     buffer: Vec<EventRecord>,
     metadata: Arc<CacheFile>,
@@ -35,9 +36,9 @@ impl TlsTrace {
     const RECORD_SIZE: usize = std::mem::size_of::<EventRecord>();
     const TLS_CAPACITY: usize = 64 * 1024 * 1024 / Self::RECORD_SIZE; // 64 MiB
 
-    pub fn new(thread_id: u32, registry: &TraceRegistry) -> Result<Self, Error> {
-        // Request a new cache file from the registry
-        let metadata = registry.request_cache_file(thread_id);
+    pub fn new(registry: &TraceRegistry) -> Result<Self, Error> {
+        // Request a new stream of cache files from the registry
+        let (stream, metadata) = registry.open_stream();
         let mut file = File::create(metadata.path())?;
 
         // Set the header to zero
@@ -45,6 +46,7 @@ impl TlsTrace {
         file.write_all(&n_buffers.to_le_bytes())?;
 
         Ok(Self {
+            stream,
             capacity: Self::TLS_CAPACITY,
             buffer: Vec::with_capacity(Self::TLS_CAPACITY),
             metadata,
@@ -67,17 +69,13 @@ impl TlsTrace {
         Ok(())
     }
 
-    fn maybe_swap_cache_file(
-        &mut self,
-        thread_id: u32,
-        registry: &TraceRegistry,
-    ) -> Result<(), Error> {
+    fn maybe_swap_cache_file(&mut self, registry: &TraceRegistry) -> Result<(), Error> {
         if self.n_written >= self.metadata.target_size() || self.is_sealed {
             // Seal the file, i.e., write self.n_buffers to the header
             self.seal()?;
 
             // Retrieve a new cache file from the registry and open it
-            let new_file = registry.request_cache_file(thread_id);
+            let new_file = registry.request_cache_file(self.stream);
             self.file = File::create(new_file.path())?;
 
             // Set the header to zero
@@ -113,7 +111,7 @@ impl TlsTrace {
     }
 
     pub fn append(&mut self, record: EventRecord, registry: &TraceRegistry) -> Result<(), Error> {
-        self.maybe_swap_cache_file(record.event.t, registry)?;
+        self.maybe_swap_cache_file(registry)?;
 
         self.buffer.push(record);
 
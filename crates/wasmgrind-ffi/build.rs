@@ -0,0 +1,7 @@
+fn main() {
+    // The C smoke test in tests/ is compiled for the same target at runtime
+    println!(
+        "cargo:rustc-env=WASMGRIND_FFI_TARGET={}",
+        std::env::var("TARGET").expect("Cargo did not set TARGET")
+    );
+}
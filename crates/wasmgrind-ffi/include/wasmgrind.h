@@ -0,0 +1,88 @@
+/*
+ * C API of the standalone runtime of Wasmgrind.
+ *
+ * Keep the declarations in sync with `src/lib.rs`.
+ */
+
+#ifndef WASMGRIND_H
+#define WASMGRIND_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Instruments the binary and records an execution trace while it runs */
+#define WASMGRIND_TRACE 1
+
+/* A binary that is executed by the standalone runtime */
+typedef struct WasmgrindRuntime WasmgrindRuntime;
+
+/* A pending invocation of an export */
+typedef struct WasmgrindHandle WasmgrindHandle;
+
+/*
+ * Returns the message of the last error that occurred on the calling thread.
+ *
+ * The string remains valid until the next failing call on the same thread.
+ * Returns null if no call has failed yet.
+ */
+const char *wasmgrind_last_error(void);
+
+/*
+ * Loads and instantiates the binary at `path`.
+ *
+ * Returns null on failure. The runtime has to be released with
+ * `wasmgrind_free_runtime`.
+ */
+WasmgrindRuntime *wasmgrind_runtime_new(const char *path, uint32_t flags);
+
+/*
+ * Invokes the export `name` of the runtime without waiting for it to return.
+ *
+ * The export must take no parameters and return no results. Invocations run one
+ * after another. Returns null on failure. The handle has to be passed to
+ * `wasmgrind_join` or `wasmgrind_free_handle`.
+ */
+WasmgrindHandle *wasmgrind_invoke(const WasmgrindRuntime *rt, const char *name);
+
+/*
+ * Waits until the invocation of `handle` has returned and releases the handle.
+ *
+ * Returns 0 if the invoked export returned and -1 if it trapped or could not be invoked.
+ */
+int wasmgrind_join(WasmgrindHandle *handle);
+
+/*
+ * Stops the runtime and returns its execution trace in RapidBin format.
+ *
+ * The trace is stored in `*out_buf` and `*out_len`, and its metadata as a JSON
+ * string in `*out_meta_json`. They have to be released with `wasmgrind_free_buffer`
+ * and `wasmgrind_free_string`. Returns 0 on success and -1 on failure, e.g., if
+ * the runtime has been created without `WASMGRIND_TRACE`. The runtime does not
+ * accept any invocations afterwards.
+ */
+int wasmgrind_generate_trace(WasmgrindRuntime *rt,
+                             uint8_t **out_buf,
+                             size_t *out_len,
+                             char **out_meta_json);
+
+/* Waits for all pending invocations and releases the runtime. */
+void wasmgrind_free_runtime(WasmgrindRuntime *rt);
+
+/* Releases a handle without waiting for its invocation to return. */
+void wasmgrind_free_handle(WasmgrindHandle *handle);
+
+/* Releases a trace returned by `wasmgrind_generate_trace`. */
+void wasmgrind_free_buffer(uint8_t *buf, size_t len);
+
+/* Releases a string returned by `wasmgrind_generate_trace`. */
+void wasmgrind_free_string(char *s);
+
+#ifdef __cplusplus
+}  /* extern "C" */
+#endif
+
+#endif  /* WASMGRIND_H */
@@ -0,0 +1,225 @@
+//! A C API for embedding the standalone runtime of Wasmgrind.
+//!
+//! The functions of this crate are declared in `include/wasmgrind.h`. None of them
+//! unwinds into the caller: errors and panics are turned into a `NULL` or negative
+//! return value and the reason can be retrieved via [`wasmgrind_last_error`].
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString, c_char, c_int},
+    panic::{AssertUnwindSafe, catch_unwind},
+    ptr,
+};
+
+use anyhow::{Error, anyhow};
+
+mod runtime;
+pub use runtime::{FLAG_TRACE, Handle, Runtime};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(error: &Error) {
+    let message = CString::new(format!("{error:#}").replace('\0', "\\0"))
+        .expect("Error message contains no NUL bytes");
+    LAST_ERROR.with_borrow_mut(|last_error| *last_error = Some(message));
+}
+
+/// Runs `f` and records its error or panic as the last error of this thread.
+///
+/// Returns `on_error` if `f` fails.
+fn ffi_call<R>(on_error: R, f: impl FnOnce() -> Result<R, Error>) -> R {
+    let error = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(result)) => return result,
+        Ok(Err(e)) => e,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "Unknown panic payload".to_string());
+            anyhow!("Wasmgrind panicked: {message}")
+        }
+    };
+
+    set_last_error(&error);
+    on_error
+}
+
+/// Borrows the C string `s` as UTF-8.
+///
+/// # Safety
+///
+/// `s` must be null or point to a NUL-terminated string that outlives `'a`.
+unsafe fn borrow_str<'a>(s: *const c_char, what: &str) -> Result<&'a str, Error> {
+    if s.is_null() {
+        return Err(anyhow!("The {what} is null"));
+    }
+    unsafe { CStr::from_ptr(s) }
+        .to_str()
+        .map_err(|e| anyhow!("The {what} is not valid UTF-8: {e}"))
+}
+
+/// Returns the message of the last error that occurred on the calling thread.
+///
+/// The string remains valid until the next failing call on the same thread.
+/// Returns null if no call has failed yet.
+#[unsafe(no_mangle)]
+pub extern "C" fn wasmgrind_last_error() -> *const c_char {
+    LAST_ERROR.with_borrow(|last_error| last_error.as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// Loads and instantiates the binary at `path`.
+///
+/// Returns null on failure. The runtime has to be released with
+/// [`wasmgrind_free_runtime`].
+///
+/// # Safety
+///
+/// `path` must be null or point to a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wasmgrind_runtime_new(path: *const c_char, flags: u32) -> *mut Runtime {
+    ffi_call(ptr::null_mut(), || {
+        let path = unsafe { borrow_str(path, "path") }?;
+        Ok(Box::into_raw(Box::new(Runtime::new(path, flags)?)))
+    })
+}
+
+/// Invokes the export `name` of the runtime without waiting for it to return.
+///
+/// Returns null on failure. The handle has to be passed to [`wasmgrind_join`]
+/// or [`wasmgrind_free_handle`].
+///
+/// # Safety
+///
+/// `rt` must be null or a runtime created by [`wasmgrind_runtime_new`] and `name`
+/// must be null or point to a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wasmgrind_invoke(rt: *const Runtime, name: *const c_char) -> *mut Handle {
+    ffi_call(ptr::null_mut(), || {
+        let rt = unsafe { rt.as_ref() }.ok_or(anyhow!("The runtime is null"))?;
+        let name = unsafe { borrow_str(name, "function name") }?;
+        Ok(Box::into_raw(Box::new(rt.invoke(name)?)))
+    })
+}
+
+/// Waits until the invocation of `handle` has returned and releases the handle.
+///
+/// Returns 0 if the invoked export returned and -1 if it trapped or could not be invoked.
+///
+/// # Safety
+///
+/// `handle` must be null or a handle returned by [`wasmgrind_invoke`] that has
+/// not been released yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wasmgrind_join(handle: *mut Handle) -> c_int {
+    ffi_call(-1, || {
+        if handle.is_null() {
+            return Err(anyhow!("The handle is null"));
+        }
+        unsafe { Box::from_raw(handle) }.join()?;
+        Ok(0)
+    })
+}
+
+/// Stops the runtime and returns its execution trace in RapidBin format.
+///
+/// The trace is stored in `*out_buf` and `*out_len`, and its metadata as a JSON
+/// string in `*out_meta_json`. They have to be released with [`wasmgrind_free_buffer`]
+/// and [`wasmgrind_free_string`]. Returns 0 on success and -1 on failure, e.g., if
+/// the runtime has been created without `WASMGRIND_TRACE`. The runtime does not
+/// accept any invocations afterwards.
+///
+/// # Safety
+///
+/// `rt` must be null or a runtime created by [`wasmgrind_runtime_new`] that is not
+/// used concurrently. The output pointers must be null or valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wasmgrind_generate_trace(
+    rt: *mut Runtime,
+    out_buf: *mut *mut u8,
+    out_len: *mut usize,
+    out_meta_json: *mut *mut c_char,
+) -> c_int {
+    ffi_call(-1, || {
+        let rt = unsafe { rt.as_mut() }.ok_or(anyhow!("The runtime is null"))?;
+        if out_buf.is_null() || out_len.is_null() || out_meta_json.is_null() {
+            return Err(anyhow!("The output pointers must not be null"));
+        }
+
+        let (trace, metadata) = rt.generate_trace()?;
+        let metadata = CString::new(metadata)?;
+        let trace = Box::into_raw(trace.into_boxed_slice());
+        unsafe {
+            out_len.write(trace.len());
+            out_buf.write(trace.cast());
+            out_meta_json.write(metadata.into_raw());
+        }
+        Ok(0)
+    })
+}
+
+/// Waits for all pending invocations and releases the runtime.
+///
+/// # Safety
+///
+/// `rt` must be null or a runtime created by [`wasmgrind_runtime_new`] that has
+/// not been released yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wasmgrind_free_runtime(rt: *mut Runtime) {
+    ffi_call((), || {
+        if !rt.is_null() {
+            drop(unsafe { Box::from_raw(rt) });
+        }
+        Ok(())
+    })
+}
+
+/// Releases a handle without waiting for its invocation to return.
+///
+/// # Safety
+///
+/// `handle` must be null or a handle returned by [`wasmgrind_invoke`] that has
+/// not been released yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wasmgrind_free_handle(handle: *mut Handle) {
+    ffi_call((), || {
+        if !handle.is_null() {
+            drop(unsafe { Box::from_raw(handle) });
+        }
+        Ok(())
+    })
+}
+
+/// Releases a trace returned by [`wasmgrind_generate_trace`].
+///
+/// # Safety
+///
+/// `buf` must be null or a buffer returned by [`wasmgrind_generate_trace`] with
+/// its length `len`, which has not been released yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wasmgrind_free_buffer(buf: *mut u8, len: usize) {
+    ffi_call((), || {
+        if !buf.is_null() {
+            drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(buf, len)) });
+        }
+        Ok(())
+    })
+}
+
+/// Releases a string returned by [`wasmgrind_generate_trace`].
+///
+/// # Safety
+///
+/// `s` must be null or a string returned by [`wasmgrind_generate_trace`] that has
+/// not been released yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wasmgrind_free_string(s: *mut c_char) {
+    ffi_call((), || {
+        if !s.is_null() {
+            drop(unsafe { CString::from_raw(s) });
+        }
+        Ok(())
+    })
+}
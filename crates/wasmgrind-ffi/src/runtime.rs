@@ -0,0 +1,326 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU32, Ordering},
+        mpsc::{self, Receiver, Sender},
+    },
+    thread::JoinHandle,
+};
+
+use anyhow::{Context, Error, anyhow, bail};
+use wasmgrind::{
+    standalone::{
+        StandaloneCtxView, StandaloneView,
+        ctx::{StandaloneCtxProvider, WasmgrindStandaloneCtx},
+    },
+    tracing::{TracingCtxView, TracingView, ctx::WasmgrindTracingCtx},
+};
+use wasmgrind_core::{
+    abi::{self, AbiFlavor},
+    instrumentation::{self, InstrumentOptions},
+};
+use wasmtime::{Engine, Instance, Linker, Store};
+
+/// Instruments the binary and records an execution trace while it runs
+pub const FLAG_TRACE: u32 = 1;
+
+static NEXT_RUNTIME_ID: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Clone)]
+struct FfiCtx {
+    standalone_ctx: WasmgrindStandaloneCtx,
+    tracing_ctx: WasmgrindTracingCtx,
+}
+
+impl StandaloneView for FfiCtx {
+    fn ctx(&self) -> StandaloneCtxView<'_> {
+        StandaloneCtxView::from(&self.standalone_ctx)
+    }
+}
+
+impl TracingView for FfiCtx {
+    fn ctx(&self) -> TracingCtxView<'_> {
+        TracingCtxView::from(&self.tracing_ctx)
+    }
+}
+
+/// A request to invoke an export on the executor of a [`Runtime`].
+struct Invocation {
+    function: String,
+    result: Sender<Result<(), Error>>,
+}
+
+/// A binary that is executed by the standalone runtime.
+///
+/// The main instance lives on a dedicated executor thread, which runs the invoked
+/// exports one after another. Hence, the main thread of the trace is the executor
+/// and not the thread that created the runtime. Exports invoked concurrently run
+/// on runner threads instead, see [`Runtime::invoke_concurrently`].
+pub struct Runtime {
+    invocations: Option<Sender<Invocation>>,
+    executor: Option<JoinHandle<Result<(), Error>>>,
+    provider: Option<Arc<StandaloneCtxProvider<FfiCtx>>>,
+    ctx: Option<FfiCtx>,
+    runners: Mutex<Vec<JoinHandle<()>>>,
+    tracing_ctx: Option<WasmgrindTracingCtx>,
+    cache_dir: PathBuf,
+}
+
+impl Runtime {
+    /// Loads the binary at `path` and instantiates it on a new executor thread.
+    ///
+    /// See [`FLAG_TRACE`] for the supported `flags`.
+    pub fn new<P: AsRef<Path>>(path: P, flags: u32) -> Result<Self, Error> {
+        if flags & !FLAG_TRACE != 0 {
+            bail!("Unknown runtime flags {flags:#x}");
+        }
+        let trace = flags & FLAG_TRACE != 0;
+
+        let mut module = walrus::Module::from_file(&path)
+            .with_context(|| format!("Could not load '{}'", path.as_ref().display()))?;
+        let flavor = if trace {
+            instrumentation::instrument_with_options(&mut module, &InstrumentOptions::default())?;
+            AbiFlavor::Tracing
+        } else {
+            AbiFlavor::Standalone
+        };
+        abi::validate_module(&module, flavor).ensure_valid()?;
+
+        let engine = Engine::default();
+        let provider = StandaloneCtxProvider::from_walrus(&engine, &mut module)?
+            .on_wasm_exit(|code| log::error!("Module exited with raw error code {code}"));
+
+        let cache_dir = std::env::temp_dir().join(format!(
+            "wasmgrind-ffi-{}-{}",
+            std::process::id(),
+            NEXT_RUNTIME_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        let ctx = FfiCtx {
+            standalone_ctx: provider.create_ctx(),
+            tracing_ctx: WasmgrindTracingCtx::new(&cache_dir),
+        };
+        let tracing_ctx = trace.then(|| ctx.tracing_ctx.clone());
+
+        let provider = Arc::new(provider);
+        let (invocations, pending) = mpsc::channel();
+        let (ready, started) = mpsc::channel();
+        let executor = {
+            let (provider, ctx) = (provider.clone(), ctx.clone());
+            std::thread::spawn(move || execute(provider, ctx, pending, ready))
+        };
+        match started.recv() {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return Err(e),
+            Err(_) => bail!("The executor of the runtime panicked during instantiation"),
+        }
+
+        Ok(Self {
+            invocations: Some(invocations),
+            executor: Some(executor),
+            provider: Some(provider),
+            ctx: Some(ctx),
+            runners: Mutex::new(Vec::new()),
+            tracing_ctx,
+            cache_dir,
+        })
+    }
+
+    /// Invokes the export `function`, which takes no parameters and returns no results.
+    ///
+    /// The invocation runs after all previous invocations have completed.
+    pub fn invoke(&self, function: &str) -> Result<Handle, Error> {
+        let (result, completed) = mpsc::channel();
+        self.invocations
+            .as_ref()
+            .ok_or(anyhow!("The runtime has already been stopped"))?
+            .send(Invocation {
+                function: function.to_string(),
+                result,
+            })
+            .map_err(|_| anyhow!("The executor of the runtime is no longer running"))?;
+
+        Ok(Handle {
+            function: function.to_string(),
+            completed,
+        })
+    }
+
+    /// Invokes the export `function` on a new runner thread, which takes no parameters
+    /// and returns no results.
+    ///
+    /// In contrast to [`Runtime::invoke`], the invocation does not wait for previous
+    /// invocations. It runs on its own instance, which shares the memory of the main
+    /// instance but has its own TID, stack and TLS block. When tracing, every such
+    /// invocation is recorded as a separate thread that is forked by a synthetic root
+    /// thread, see [`WasmgrindTracingCtx::runner_begin`].
+    pub fn invoke_concurrently(&self, function: &str) -> Result<Handle, Error> {
+        let (Some(provider), Some(ctx)) = (self.provider.clone(), self.ctx.clone()) else {
+            bail!("The runtime has already been stopped");
+        };
+        let (result, completed) = mpsc::channel();
+        let tracing_ctx = self.tracing_ctx.clone();
+        let name = function.to_string();
+        let runner = std::thread::spawn(move || {
+            let runner_tid = tracing_ctx
+                .as_ref()
+                .and_then(|tracing_ctx| tracing_ctx.runner_begin());
+            let mut store = provider.create_store(ctx);
+            let _ = result.send(provider.invoke_runner::<(), ()>(&mut store, &name, ()));
+            if let (Some(tracing_ctx), Some(tid)) = (tracing_ctx, runner_tid) {
+                tracing_ctx.runner_end(tid);
+            }
+        });
+        self.runners
+            .lock()
+            .expect("Could not lock runner threads!")
+            .push(runner);
+
+        Ok(Handle {
+            function: function.to_string(),
+            completed,
+        })
+    }
+
+    /// Stops the runtime and returns the execution trace in RapidBin format along
+    /// with its metadata as JSON.
+    ///
+    /// Pending invocations are completed and all spawned threads are joined before.
+    /// The runtime does not accept any invocations afterwards. Spawned threads that
+    /// trapped are logged, but do not prevent generating the trace of the execution.
+    pub fn generate_trace(&mut self) -> Result<(Vec<u8>, String), Error> {
+        if self.tracing_ctx.is_none() {
+            bail!(
+                "The runtime has been created without tracing or its trace has already been generated"
+            );
+        }
+        if let Err(e) = self.stop() {
+            log::error!("The runtime did not stop cleanly: {e:?}");
+        }
+        let tracing_ctx = self
+            .tracing_ctx
+            .take()
+            .expect("Tracing context has been checked before");
+
+        let trace_file = self.cache_dir.join("trace.data");
+        let metadata = tracing_ctx
+            .generate_binary_trace(&trace_file)
+            .map_err(|_| anyhow!("Some thread still holds a reference to the trace"))??;
+
+        Ok((std::fs::read(&trace_file)?, metadata.to_json()?))
+    }
+
+    /// Waits for pending invocations and joins all spawned threads.
+    fn stop(&mut self) -> Result<(), Error> {
+        let runners =
+            std::mem::take(&mut *self.runners.lock().expect("Could not lock runner threads!"));
+        // All runners are joined, even if one of them panicked
+        let runners_panicked = runners
+            .into_iter()
+            .map(JoinHandle::join)
+            .filter(Result::is_err)
+            .count();
+        // The executor needs the only reference to the provider to shut it down and
+        // the trace can only be generated once no context refers to it anymore
+        drop(self.provider.take());
+        drop(self.ctx.take());
+        drop(self.invocations.take());
+        if let Some(executor) = self.executor.take() {
+            executor
+                .join()
+                .map_err(|_| anyhow!("The executor of the runtime panicked"))??;
+        }
+        if runners_panicked > 0 {
+            bail!("{runners_panicked} runner threads of the runtime panicked");
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        if let Err(e) = self.stop() {
+            log::error!("Could not stop the runtime: {e:?}");
+        }
+        if self.cache_dir.exists()
+            && let Err(e) = std::fs::remove_dir_all(&self.cache_dir)
+        {
+            log::warn!(
+                "Could not remove cache directory '{}': {e}",
+                self.cache_dir.display()
+            );
+        }
+    }
+}
+
+/// A pending invocation of an export, see [`Runtime::invoke`].
+pub struct Handle {
+    function: String,
+    completed: Receiver<Result<(), Error>>,
+}
+
+impl Handle {
+    /// Waits until the invoked export has returned.
+    pub fn join(self) -> Result<(), Error> {
+        self.completed.recv().map_err(|_| {
+            anyhow!(
+                "The runtime stopped before '{}' has completed",
+                self.function
+            )
+        })?
+    }
+}
+
+/// Runs the main instance of a [`Runtime`] until all invocations have been received.
+fn execute(
+    provider: Arc<StandaloneCtxProvider<FfiCtx>>,
+    ctx: FfiCtx,
+    pending: Receiver<Invocation>,
+    ready: Sender<Result<(), Error>>,
+) -> Result<(), Error> {
+    let (mut store, instance) = match instantiate(&provider, ctx) {
+        Ok(main) => {
+            let _ = ready.send(Ok(()));
+            main
+        }
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return Ok(());
+        }
+    };
+
+    for invocation in pending {
+        let result = instance
+            .get_typed_func::<(), ()>(&mut store, &invocation.function)
+            .with_context(|| format!("No function export named '{}'", invocation.function))
+            .and_then(|func| func.call(&mut store, ()));
+        let _ = invocation.result.send(result);
+    }
+
+    drop(store);
+    Arc::into_inner(provider)
+        .ok_or(anyhow!("The provider is still used by a runner thread"))?
+        .shutdown()
+}
+
+/// Creates and bootstraps the main instance.
+fn instantiate(
+    provider: &StandaloneCtxProvider<FfiCtx>,
+    ctx: FfiCtx,
+) -> Result<(Store<FfiCtx>, Instance), Error> {
+    let mut linker = Linker::new(provider.engine());
+    WasmgrindTracingCtx::add_to_linker(&mut linker)?;
+
+    let mut store = provider.create_store(ctx);
+    provider.add_to_linker(&mut linker, &store)?;
+    let instance = linker.instantiate(&mut store, provider.module())?;
+    provider.finalize(linker)?;
+
+    instance
+        .get_typed_func::<u32, ()>(&mut store, "__wasmgrind_bootstrap")
+        .context("Wasmgrind standalone needs an exported function named '__wasmgrind_bootstrap'")?
+        .call(&mut store, WasmgrindStandaloneCtx::MAIN_TID)?;
+
+    Ok((store, instance))
+}
@@ -0,0 +1,147 @@
+use std::{collections::BTreeMap, path::Path, process::Command};
+
+use anyhow::{Context, Error, bail, ensure};
+use tempfile::tempdir;
+use walrus::{
+    FunctionBuilder, Module,
+    ir::{AtomicOp, AtomicWidth, MemArg},
+};
+use wasmgrind_core::testing::{AbiModule, abi_module};
+use wasmgrind_ffi::FLAG_TRACE;
+
+/// Creates a module that exports `increment`, which atomically increments a
+/// counter, and `fail`, which traps.
+fn example_module() -> Module {
+    let AbiModule {
+        mut module, memory, ..
+    } = abi_module();
+
+    let mut increment = FunctionBuilder::new(&mut module.types, &[], &[]);
+    increment
+        .func_body()
+        .i32_const(0)
+        .i32_const(1)
+        .atomic_rmw(
+            memory,
+            AtomicOp::Add,
+            AtomicWidth::I32,
+            MemArg {
+                align: 4,
+                offset: 0,
+            },
+        )
+        .drop();
+    let increment = increment.finish(vec![], &mut module.funcs);
+    module.exports.add("increment", increment);
+
+    let mut fail = FunctionBuilder::new(&mut module.types, &[], &[]);
+    fail.func_body().unreachable();
+    let fail = fail.finish(vec![], &mut module.funcs);
+    module.exports.add("fail", fail);
+
+    module
+}
+
+/// Parses the name and number of parameters of a function declaration that
+/// starts with the name of the function.
+fn parse_declaration(declaration: &str) -> Option<(String, usize)> {
+    let (name, rest) = declaration.split_once('(')?;
+    let name = name.trim();
+    if !name.starts_with("wasmgrind_") || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return None;
+    }
+
+    let n_params = match rest.split_once(')')?.0.trim() {
+        "" | "void" => 0,
+        params => params.split(',').filter(|p| !p.trim().is_empty()).count(),
+    };
+    Some((name.to_string(), n_params))
+}
+
+/// Removes the C comments from `source`.
+fn strip_comments(source: &str) -> String {
+    let mut stripped = String::new();
+    let mut rest = source;
+    while let Some((code, comment)) = rest.split_once("/*") {
+        stripped.push_str(code);
+        rest = comment.split_once("*/").map_or("", |(_, code)| code);
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+#[test]
+fn header_matches_exports() -> Result<(), Error> {
+    let crate_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let header = strip_comments(&std::fs::read_to_string(
+        crate_dir.join("include/wasmgrind.h"),
+    )?);
+    let source = std::fs::read_to_string(crate_dir.join("src/lib.rs"))?;
+
+    let declared = header
+        .split(';')
+        .filter_map(|statement| parse_declaration(&statement[statement.find("wasmgrind_")?..]))
+        .collect::<BTreeMap<_, _>>();
+    let exported = source
+        .split("extern \"C\" fn ")
+        .skip(1)
+        .filter_map(parse_declaration)
+        .collect::<BTreeMap<_, _>>();
+    assert!(!exported.is_empty());
+    assert_eq!(declared, exported);
+
+    assert!(header.contains(&format!("#define WASMGRIND_TRACE {FLAG_TRACE}\n")));
+
+    Ok(())
+}
+
+#[test]
+fn call_from_c() -> Result<(), Error> {
+    let tmp = tempdir()?;
+    let binary = tmp.path().join("example.wasm");
+    example_module().emit_wasm_file(&binary)?;
+
+    // The cdylib is placed next to the test executable
+    let lib_dir = std::env::current_exe()?
+        .parent()
+        .context("Test executable has no parent directory")?
+        .to_path_buf();
+    let lib = lib_dir.join(format!(
+        "{}wasmgrind_ffi{}",
+        std::env::consts::DLL_PREFIX,
+        std::env::consts::DLL_SUFFIX
+    ));
+    ensure!(lib.exists(), "Could not find '{}'", lib.display());
+
+    let crate_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let smoke_test = tmp.path().join("smoke");
+    let compiler = cc::Build::new()
+        .target(env!("WASMGRIND_FFI_TARGET"))
+        .host(env!("WASMGRIND_FFI_TARGET"))
+        .opt_level(0)
+        .cargo_metadata(false)
+        .warnings_into_errors(true)
+        .try_get_compiler()?;
+    let status = compiler
+        .to_command()
+        .arg("-I")
+        .arg(crate_dir.join("include"))
+        .arg(crate_dir.join("tests/smoke.c"))
+        .arg(&lib)
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .arg("-o")
+        .arg(&smoke_test)
+        .status()?;
+    ensure!(status.success(), "Could not compile the smoke test");
+
+    let output = Command::new(&smoke_test).arg(&binary).output()?;
+    if !output.status.success() {
+        bail!(
+            "The smoke test failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    Ok(())
+}
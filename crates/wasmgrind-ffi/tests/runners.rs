@@ -0,0 +1,112 @@
+use std::{collections::HashSet, io::Cursor};
+
+use anyhow::Error;
+use tempfile::tempdir;
+use trace_tools::{
+    RapidBinParser,
+    generic::{Operation, Parser},
+    open_trace,
+};
+use walrus::{
+    FunctionBuilder,
+    ir::{MemArg, StoreKind},
+};
+use wasmgrind_core::testing::{AbiModule, abi_module};
+use wasmgrind_ffi::{FLAG_TRACE, Runtime};
+
+/// Creates a module whose `write` export writes a word of memory.
+fn write_module() -> walrus::Module {
+    let AbiModule {
+        mut module, memory, ..
+    } = abi_module();
+
+    let mut write = FunctionBuilder::new(&mut module.types, &[], &[]);
+    write.func_body().i32_const(2048).i32_const(42).store(
+        memory,
+        StoreKind::I32 { atomic: false },
+        MemArg {
+            align: 4,
+            offset: 0,
+        },
+    );
+    let write = write.finish(vec![], &mut module.funcs);
+    module.exports.add("write", write);
+
+    module
+}
+
+/// Returns the threads that forked the writing threads and the writing threads of `trace`.
+fn writers(trace: &[u8]) -> Result<(HashSet<u64>, Vec<u64>), Error> {
+    let mut forked = Vec::new();
+    let mut writers = Vec::new();
+    for event in RapidBinParser::new().parse(open_trace(Cursor::new(trace))?)? {
+        match event?.into_fields() {
+            (parent, Operation::Fork { tid }, _) => forked.push((parent, tid)),
+            (tid, Operation::Write { .. }, _) => writers.push(tid),
+            _ => {}
+        }
+    }
+
+    let parents = forked
+        .iter()
+        .filter(|(_, tid)| writers.contains(tid))
+        .map(|(parent, _)| *parent)
+        .collect();
+    Ok((parents, writers))
+}
+
+/// Invokes `write` twice on runner threads and returns the threads of the trace as by [`writers`].
+fn trace_runners(concurrently: bool) -> Result<(HashSet<u64>, Vec<u64>), Error> {
+    let tmp = tempdir()?;
+    let binary = tmp.path().join("write.wasm");
+    write_module().emit_wasm_file(&binary)?;
+
+    let mut runtime = Runtime::new(&binary, FLAG_TRACE)?;
+    if concurrently {
+        let first = runtime.invoke_concurrently("write")?;
+        let second = runtime.invoke_concurrently("write")?;
+        first.join()?;
+        second.join()?;
+    } else {
+        runtime.invoke_concurrently("write")?.join()?;
+        runtime.invoke_concurrently("write")?.join()?;
+    }
+    let (trace, _) = runtime.generate_trace()?;
+
+    writers(&trace)
+}
+
+/// Checks that both invocations have been recorded as distinct threads forked by the same root.
+fn assert_distinct_runners((parents, writers): (HashSet<u64>, Vec<u64>)) {
+    assert_eq!(writers.len(), 2);
+    assert_ne!(writers[0], writers[1]);
+    assert_eq!(parents.len(), 1);
+    assert!(parents.iter().all(|parent| !writers.contains(parent)));
+}
+
+#[test]
+fn trace_sequential_runners() -> Result<(), Error> {
+    assert_distinct_runners(trace_runners(false)?);
+    Ok(())
+}
+
+#[test]
+fn trace_concurrent_runners() -> Result<(), Error> {
+    assert_distinct_runners(trace_runners(true)?);
+    Ok(())
+}
+
+#[test]
+fn invoke_missing_export_concurrently() -> Result<(), Error> {
+    let tmp = tempdir()?;
+    let binary = tmp.path().join("write.wasm");
+    write_module().emit_wasm_file(&binary)?;
+
+    let runtime = Runtime::new(&binary, 0)?;
+    let error = runtime.invoke_concurrently("missing")?.join().unwrap_err();
+    assert!(format!("{error:#}").contains("missing"));
+    // Failed runners do not affect later ones
+    runtime.invoke_concurrently("write")?.join()?;
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use std::io::Cursor;
+
+use anyhow::Error;
+use tempfile::tempdir;
+use trace_tools::{
+    RapidBinParser,
+    generic::{Operation, Parser},
+    open_trace,
+};
+use walrus::{
+    FunctionBuilder, ValType,
+    ir::{MemArg, StoreKind},
+};
+use wasmgrind_core::testing::{AbiModule, abi_module};
+use wasmgrind_ffi::{FLAG_TRACE, Runtime};
+
+/// Creates a module whose `spawn` export writes a word of memory and spawns a thread
+/// that traps immediately.
+fn trapping_thread_module() -> walrus::Module {
+    let AbiModule {
+        mut module,
+        memory,
+        thread_start,
+    } = abi_module();
+
+    module
+        .funcs
+        .get_mut(thread_start)
+        .kind
+        .unwrap_local_mut()
+        .builder_mut()
+        .func_body()
+        .unreachable();
+
+    let clone_instance_ty = module.types.add(&[ValType::I32; 5], &[ValType::I32]);
+    let (clone_instance, _) =
+        module.add_import_func("wasmgrind_standalone", "clone_instance", clone_instance_ty);
+    let mut spawn = FunctionBuilder::new(&mut module.types, &[], &[]);
+    spawn
+        .func_body()
+        .i32_const(1024)
+        .i32_const(42)
+        .store(
+            memory,
+            StoreKind::I32 { atomic: false },
+            MemArg {
+                align: 4,
+                offset: 0,
+            },
+        )
+        .i32_const(0)
+        .i32_const(0)
+        .i32_const(2048)
+        .i32_const(0)
+        .i32_const(0)
+        .call(clone_instance)
+        .drop();
+    let spawn = spawn.finish(vec![], &mut module.funcs);
+    module.exports.add("spawn", spawn);
+
+    module
+}
+
+#[test]
+fn trace_trapped_threads() -> Result<(), Error> {
+    let tmp = tempdir()?;
+    let binary = tmp.path().join("trap.wasm");
+    trapping_thread_module().emit_wasm_file(&binary)?;
+
+    let mut runtime = Runtime::new(&binary, FLAG_TRACE)?;
+    runtime.invoke("spawn")?.join()?;
+    let (trace, _) = runtime.generate_trace()?;
+
+    let mut writes = 0;
+    for event in RapidBinParser::new().parse(open_trace(Cursor::new(trace))?)? {
+        if let (_, Operation::Write { .. }, _) = event?.into_fields() {
+            writes += 1;
+        }
+    }
+    assert_eq!(writes, 1);
+
+    Ok(())
+}
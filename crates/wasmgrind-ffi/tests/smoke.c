@@ -0,0 +1,66 @@
+/*
+ * Exercises the C API like an embedder would. It is compiled and run against
+ * the cdylib of this crate by `tests/c_api.rs`.
+ */
+
+#include <stdio.h>
+#include <string.h>
+
+#include "wasmgrind.h"
+
+#define CHECK(cond, step)                                                      \
+    do {                                                                       \
+        if (!(cond)) {                                                         \
+            const char *error = wasmgrind_last_error();                        \
+            fprintf(stderr, "Smoke test failed at step %d: %s\n", step,        \
+                    error != NULL ? error : "no error");                       \
+            return step;                                                       \
+        }                                                                      \
+    } while (0)
+
+/* Returns 0 on success and the number of the failed step otherwise. */
+static int smoke_test(const char *path) {
+    CHECK(wasmgrind_runtime_new(NULL, 0) == NULL, 1);
+    CHECK(strstr(wasmgrind_last_error(), "null") != NULL, 1);
+
+    WasmgrindRuntime *rt = wasmgrind_runtime_new(path, WASMGRIND_TRACE);
+    CHECK(rt != NULL, 2);
+
+    WasmgrindHandle *first = wasmgrind_invoke(rt, "increment");
+    WasmgrindHandle *second = wasmgrind_invoke(rt, "increment");
+    CHECK(first != NULL && second != NULL, 3);
+    CHECK(wasmgrind_join(first) == 0, 4);
+    CHECK(wasmgrind_join(second) == 0, 4);
+
+    /* Traps and missing exports are reported without affecting the runtime */
+    CHECK(wasmgrind_join(wasmgrind_invoke(rt, "fail")) == -1, 5);
+    CHECK(strstr(wasmgrind_last_error(), "unreachable") != NULL, 5);
+    CHECK(wasmgrind_join(wasmgrind_invoke(rt, "missing")) == -1, 6);
+    CHECK(strstr(wasmgrind_last_error(), "missing") != NULL, 6);
+    wasmgrind_free_handle(wasmgrind_invoke(rt, "increment"));
+
+    uint8_t *trace = NULL;
+    size_t len = 0;
+    char *metadata = NULL;
+    CHECK(wasmgrind_generate_trace(rt, &trace, &len, &metadata) == 0, 7);
+    CHECK(trace != NULL && len > 0, 7);
+    CHECK(metadata != NULL && metadata[0] == '{', 7);
+    wasmgrind_free_buffer(trace, len);
+    wasmgrind_free_string(metadata);
+
+    /* The trace can only be generated once */
+    CHECK(wasmgrind_generate_trace(rt, &trace, &len, &metadata) == -1, 8);
+    CHECK(wasmgrind_invoke(rt, "increment") == NULL, 9);
+    wasmgrind_free_runtime(rt);
+
+    return 0;
+}
+
+int main(int argc, char **argv) {
+    if (argc != 2) {
+        fprintf(stderr, "Usage: %s <binary>\n", argv[0]);
+        return 100;
+    }
+
+    return smoke_test(argv[1]);
+}
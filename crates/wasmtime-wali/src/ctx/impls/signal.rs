@@ -159,7 +159,7 @@ pub fn wali_rt_sigaction<T: WaliView>(
                 // Anyway, as long as the Wasm program does not use it or rely on it in any way. This should be
                 // fine - although it seems a bit dangerous and could probably be refined.
                 wasm_restorer: sa_restorer
-                    .map(|restorer_fn| restorer_fn as u32)
+                    .map(|restorer_fn| restorer_fn as usize as u32)
                     .unwrap_or(0),
                 mask,
             };
@@ -1,8 +1,9 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
 use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use trace_tools::Codec;
 
-use crate::cmd::{RtInterface, RtPhaseMarkers};
+use crate::cmd::{EmitOptions, RtInterface, RtPhaseMarkers, trace::RtTraceFormat};
 
 #[derive(Parser)]
 pub struct Cli {
@@ -18,6 +19,14 @@ pub struct Cli {
     #[arg(short, long)]
     pub logdir: Option<PathBuf>,
 
+    /// Directory where emitted *.wasm/*.wat files are placed
+    #[arg(long, global = true, default_value = "tmp")]
+    emit_dir: PathBuf,
+
+    /// Only emit *.wasm files, without their *.wat counterparts
+    #[arg(long, global = true)]
+    no_wat: bool,
+
     /// The Wasmgrind command to be executed
     #[command(subcommand)]
     pub cmd: Cmd,
@@ -40,6 +49,13 @@ impl Cli {
             _ => Some(log::Level::Trace),
         }
     }
+
+    pub fn emit_options(&self) -> EmitOptions {
+        EmitOptions {
+            dir: self.emit_dir.clone(),
+            wat: !self.no_wat,
+        }
+    }
 }
 
 #[derive(Subcommand)]
@@ -71,9 +87,68 @@ pub enum ExecCmd {
         outdir: PathBuf,
 
         /// Name of the generated *.data/*.json files
-        #[arg(long, default_value = "trace")]
+        #[arg(long, visible_alias = "output", default_value = "trace")]
         outfile: PathBuf,
 
+        /// Report overlapping memory accesses found in the generated trace.
+        /// Exits with an error if any are found
+        #[arg(long)]
+        analyze: bool,
+
+        /// Report data races found in the generated trace by a happens-before analysis
+        #[arg(long)]
+        detect_races: bool,
+
+        /// Report how often threads had to wait for each lock in the generated trace
+        #[arg(long)]
+        contention: bool,
+
+        /// Stop the execution as soon as threads wait for each other's locks and
+        /// report the deadlocked threads (standalone interface only)
+        #[arg(long)]
+        detect_deadlocks: bool,
+
+        /// Print the number of recorded events per operation after the execution
+        #[arg(long)]
+        stats: bool,
+
+        /// Compress the generated *.data file
+        #[arg(long, value_enum, default_value_t = TraceCompression::None)]
+        compress: TraceCompression,
+
+        /// The format of the generated trace. STD traces are written to a *.std file and
+        /// JSON traces to a *.events.json file. Both can neither be analyzed nor compressed
+        #[arg(long, value_enum, default_value_t = TraceFormat::Rapidbin)]
+        format: TraceFormat,
+
+        /// Record when each event happened. STD traces append `@<nanoseconds>` to each line,
+        /// other formats store the timestamps in a *.timestamps file next to the trace
+        #[arg(long)]
+        timestamps: bool,
+
+        /// Take function and lock names from this binary instead of the traced one,
+        /// e.g., from an identically linked build that contains debug information
+        #[arg(long, value_name = "ORIGINAL_WASM")]
+        symbolicate: Option<PathBuf>,
+
+        /// Resolve trace locations to source positions using the DWARF information
+        /// of this binary instead of the one taken for symbol names
+        #[arg(long, value_name = "FILE_WASM")]
+        source_map: Option<PathBuf>,
+
+        /// Replay the order of thread and lock operations recorded in this *.data file.
+        /// The *.json metadata is expected next to it
+        #[arg(long, value_name = "TRACE")]
+        replay: Option<PathBuf>,
+
+        /// Only record memory accesses of functions whose name matches the glob (repeatable)
+        #[arg(long = "instrument-only", value_name = "GLOB")]
+        instrument_only: Vec<String>,
+
+        /// Additionally record when the instrumented functions are entered and left
+        #[arg(long)]
+        trace_calls: bool,
+
         /// The interface used to enable threading
         #[command(subcommand)]
         interface: Interface,
@@ -87,6 +162,26 @@ pub enum Cmd {
         /// The binary to be instrumented
         binary: PathBuf,
     },
+    /// Measure the overhead of tracing a function of a binary (standalone interface)
+    Bench {
+        /// The binary to be measured
+        binary: PathBuf,
+
+        /// The function to execute (needs to be of type () -> ())
+        function: String,
+
+        /// How often the function is executed with and without tracing
+        #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+        iterations: u32,
+
+        /// Directory where the on-disk cache of the traces should be located
+        #[arg(long, default_value = ".wasmgrind-cache")]
+        cachedir: PathBuf,
+
+        /// Print the report as JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
     /// Run Wasmgrind with profiling options
     Profile {
         /// Specifies, how phase markers are emitted
@@ -106,6 +201,19 @@ pub enum PhaseMarkers {
     Stdout,
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+pub enum TraceCompression {
+    None,
+    Zstd,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum TraceFormat {
+    Rapidbin,
+    Std,
+    Json,
+}
+
 #[derive(Subcommand)]
 pub enum Interface {
     /// Use Wasmgrind's standalone interface
@@ -116,6 +224,10 @@ pub enum Interface {
 
         /// The function to execute (needs to be of type () -> ())
         function: String,
+
+        /// Interrupt the function if it has not returned after the given number of seconds
+        #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+        timeout: Option<u64>,
     },
     /// Use the WebAssembly Linux Interface
     Wali {
@@ -132,9 +244,11 @@ impl From<Interface> for RtInterface {
             Interface::Standalone {
                 emit_patched,
                 function,
+                timeout,
             } => Self::Standalone {
                 emit_patched,
                 function,
+                timeout: timeout.map(Duration::from_secs),
             },
             Interface::Wali { args } => Self::Wali { args },
             Interface::Wasi => Self::Wasi,
@@ -150,3 +264,22 @@ impl From<PhaseMarkers> for RtPhaseMarkers {
         }
     }
 }
+
+impl From<TraceFormat> for RtTraceFormat {
+    fn from(value: TraceFormat) -> Self {
+        match value {
+            TraceFormat::Rapidbin => RtTraceFormat::RapidBin,
+            TraceFormat::Std => RtTraceFormat::Std,
+            TraceFormat::Json => RtTraceFormat::Json,
+        }
+    }
+}
+
+impl From<TraceCompression> for Codec {
+    fn from(value: TraceCompression) -> Self {
+        match value {
+            TraceCompression::None => Codec::None,
+            TraceCompression::Zstd => Codec::Zstd,
+        }
+    }
+}
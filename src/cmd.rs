@@ -1,14 +1,19 @@
 use std::{
     io::{Write, stdout},
-    path::Path,
+    path::{Path, PathBuf},
     sync::{OnceLock, atomic::Ordering},
-    time::Instant,
+    time::{Duration, Instant},
 };
 
-use anyhow::{Error, anyhow};
-use wasmgrind::standalone::{StandaloneView, ctx::StandaloneCtxProvider};
-use wasmtime::{Linker, Store, WasmParams, WasmResults};
+use anyhow::{Error, anyhow, bail};
+use wasmgrind::standalone::{
+    StandaloneView,
+    ctx::{StandaloneCtxProvider, WasmgrindStandaloneCtx},
+};
+use wasmgrind_core::instrumentation::InstrumentOptions;
+use wasmtime::{Linker, Trap, WasmParams, WasmResults};
 
+pub mod bench;
 pub mod dump;
 pub mod run;
 pub mod trace;
@@ -17,6 +22,7 @@ pub enum RtInterface {
     Standalone {
         emit_patched: bool,
         function: String,
+        timeout: Option<Duration>,
     },
     Wali {
         args: Vec<String>,
@@ -82,21 +88,33 @@ impl ProfilingOptions {
     }
 }
 
-fn load_and_instrument<P: AsRef<Path>>(binary: P) -> Result<walrus::Module, Error> {
+fn load_and_instrument<P: AsRef<Path>>(
+    binary: P,
+    options: &InstrumentOptions,
+) -> Result<walrus::Module, Error> {
     let mut module = walrus::Module::from_file(binary)?;
-    wasmgrind_core::instrumentation::instrument(&mut module)?;
+    wasmgrind_core::instrumentation::instrument_with_options(&mut module, options)?;
     Ok(module)
 }
 
-fn emit_to_file<P: AsRef<Path>>(parent_dir: P, wasm: &[u8], name: &str) -> Result<(), Error> {
-    std::fs::create_dir_all(&parent_dir)?;
+/// Specifies where and how intermediate binaries are emitted.
+#[derive(Clone)]
+pub struct EmitOptions {
+    /// Directory where the emitted files are placed
+    pub dir: PathBuf,
+    /// Whether to emit a *.wat file next to each *.wasm file
+    pub wat: bool,
+}
+
+fn emit_to_file(options: &EmitOptions, wasm: &[u8], name: &str) -> Result<(), Error> {
+    std::fs::create_dir_all(&options.dir)?;
 
-    let file = parent_dir.as_ref().join(name);
-    let wasm_file = file.with_extension("wasm");
-    let wat_file = file.with_extension("wat");
+    let file = options.dir.join(name);
+    std::fs::write(file.with_extension("wasm"), wasm)?;
 
-    std::fs::write(&wasm_file, wasm)?;
-    std::fs::write(&wat_file, wasmprinter::print_bytes(wasm)?)?;
+    if options.wat {
+        std::fs::write(file.with_extension("wat"), wasmprinter::print_bytes(wasm)?)?;
+    }
 
     Ok(())
 }
@@ -118,8 +136,7 @@ where
         "The Wasmgrind Standalone interface is outdated and untested. Prepare for runtime errors!"
     );
 
-    let main_tid = ctx.ctx().next_available_tid();
-    let mut store = Store::new(provider.engine(), ctx);
+    let mut store = provider.create_store(ctx);
     provider.add_to_linker(&mut linker, &store)?;
 
     if let Some(markers) = &options.markers {
@@ -129,17 +146,41 @@ where
     let instance = linker.instantiate(&mut store, provider.module())?;
     provider.finalize(linker)?;
 
+    // The deadline also covers spawned threads that outlive the function
+    let timeout = provider.deadline();
+    let deadline = timeout.map(|timeout| provider.interrupt_handle().interrupt_after(timeout));
+
     instance
         .get_func(&mut store, "__wasmgrind_bootstrap")
         .expect("Wasmgrind standalone needs an exported function named '__wasmgrind_bootstrap'")
         .typed::<u32, ()>(&store)?
-        .call(&mut store, main_tid)?;
+        .call(&mut store, WasmgrindStandaloneCtx::MAIN_TID)?;
 
     let results = instance
         .get_func(&mut store, &function)
         .ok_or(anyhow!("No function export named '{function}'"))?
         .typed::<Params, Results>(&store)?
-        .call(&mut store, params)?;
+        .call(&mut store, params)
+        .map_err(|e| match (e.downcast_ref::<Trap>(), timeout) {
+            (Some(Trap::Interrupt), Some(timeout)) => {
+                e.context(format!("'{function}' did not return within {timeout:?}"))
+            }
+            _ => e,
+        })?;
+
+    let running = provider.running_threads();
+    if !running.is_empty() {
+        log::info!(
+            "Waiting for spawned threads {running:?} that are still running after '{function}' returned"
+        );
+    }
+    provider.shutdown()?;
+    drop(deadline);
+    if let Some(timeout) = timeout
+        && store.data().ctx().is_interrupted()
+    {
+        bail!("Spawned threads did not terminate within {timeout:?}");
+    }
 
     if let Some(markers) = &options.markers {
         markers.end_wasm()?;
@@ -0,0 +1,218 @@
+use std::{
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::Error;
+use serde::Serialize;
+use wasmgrind::{standalone::ctx::StandaloneCtxProvider, tracing::ctx::WasmgrindTracingCtx};
+use wasmgrind_core::{
+    abi::{self, AbiFlavor},
+    instrumentation::InstrumentOptions,
+};
+use wasmtime::{Config, Engine, Linker};
+
+use crate::cmd::{
+    ProfilingOptions, load_and_instrument, run_standalone_binary_func, trace::StandaloneTracingCtx,
+};
+
+pub struct BenchCmd {
+    pub binary: PathBuf,
+    pub function: String,
+    pub iterations: u32,
+    pub cachedir: PathBuf,
+    pub json: bool,
+}
+
+impl BenchCmd {
+    pub fn exec(self) -> Result<(), Error> {
+        let report = measure_overhead(
+            &self.binary,
+            &self.function,
+            self.iterations,
+            &self.cachedir,
+        )?;
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            println!("{report}");
+        }
+
+        Ok(())
+    }
+}
+
+/// Wall-clock times of the repeated execution of a function in one mode.
+#[derive(Clone, Debug, Serialize)]
+pub struct Timings {
+    pub mean: Duration,
+    pub min: Duration,
+    pub max: Duration,
+}
+
+impl Timings {
+    fn from_samples(samples: &[Duration]) -> Self {
+        Self {
+            mean: samples.iter().sum::<Duration>() / samples.len().max(1) as u32,
+            min: samples.iter().min().copied().unwrap_or_default(),
+            max: samples.iter().max().copied().unwrap_or_default(),
+        }
+    }
+}
+
+/// Compares the execution of a function with and without tracing.
+#[derive(Clone, Debug, Serialize)]
+pub struct OverheadReport {
+    pub iterations: u32,
+    /// The patched binary without any tracing hooks
+    pub untraced: Timings,
+    /// The patched and instrumented binary
+    pub traced: Timings,
+    /// The mean number of events recorded by a traced execution
+    pub events_per_run: u64,
+    /// The ratio of the mean traced and the mean untraced execution time
+    pub slowdown: f64,
+}
+
+impl Display for OverheadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Iterations:      {}", self.iterations)?;
+        writeln!(
+            f,
+            "{:<10} {:>14} {:>14} {:>14}",
+            "Mode", "Mean", "Min", "Max"
+        )?;
+        for (mode, timings) in [("untraced", &self.untraced), ("traced", &self.traced)] {
+            writeln!(
+                f,
+                "{:<10} {:>14} {:>14} {:>14}",
+                mode,
+                format!("{:.3?}", timings.mean),
+                format!("{:.3?}", timings.min),
+                format!("{:.3?}", timings.max)
+            )?;
+        }
+        writeln!(f, "Events per run:  {}", self.events_per_run)?;
+        write!(f, "Slowdown:        {:.2}x", self.slowdown)
+    }
+}
+
+/// Runs the export `function` of `binary` with and without tracing `iterations` times each.
+///
+/// Every execution runs on a fresh runtime. Only the instantiation and the invocation of
+/// `function` are measured, i.e., patching, instrumenting and compiling the binary are not.
+pub fn measure_overhead(
+    binary: &Path,
+    function: &str,
+    iterations: u32,
+    cachedir: &Path,
+) -> Result<OverheadReport, Error> {
+    let original = std::fs::read(binary)?;
+    let mut instrumented = load_and_instrument(binary, &InstrumentOptions::default())?;
+    abi::validate_module(&instrumented, AbiFlavor::Tracing).ensure_valid()?;
+    let instrumented = instrumented.emit_wasm();
+
+    let engine = Engine::new(&Config::new())?;
+    let options = ProfilingOptions::new();
+
+    let mut untraced = Vec::new();
+    for _ in 0..iterations {
+        let (provider, _) = StandaloneCtxProvider::from_binary(&engine, &original)?;
+        let linker = Linker::new(provider.engine());
+        let ctx = provider.create_ctx();
+
+        let start = Instant::now();
+        run_standalone_binary_func::<_, (), ()>(
+            linker,
+            provider,
+            ctx,
+            function.to_string(),
+            (),
+            &options,
+        )?;
+        untraced.push(start.elapsed());
+    }
+
+    let mut traced = Vec::new();
+    let mut events = 0;
+    for _ in 0..iterations {
+        let (provider, _) = StandaloneCtxProvider::from_binary(&engine, &instrumented)?;
+        let mut linker = Linker::new(provider.engine());
+        WasmgrindTracingCtx::add_to_linker(&mut linker)?;
+        let ctx = StandaloneTracingCtx {
+            standalone_ctx: provider.create_ctx(),
+            tracing_ctx: WasmgrindTracingCtx::new(cachedir),
+        };
+
+        let start = Instant::now();
+        run_standalone_binary_func::<_, (), ()>(
+            linker,
+            provider,
+            ctx.clone(),
+            function.to_string(),
+            (),
+            &options,
+        )?;
+        traced.push(start.elapsed());
+        events += ctx.tracing_ctx.event_count();
+    }
+
+    let untraced = Timings::from_samples(&untraced);
+    let traced = Timings::from_samples(&traced);
+    let slowdown = traced.mean.as_secs_f64() / untraced.mean.as_secs_f64();
+
+    Ok(OverheadReport {
+        iterations,
+        untraced,
+        traced,
+        events_per_run: events / u64::from(iterations.max(1)),
+        slowdown,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{OverheadReport, Timings};
+
+    #[test]
+    fn summarize_samples() {
+        let timings = Timings::from_samples(&[
+            Duration::from_millis(3),
+            Duration::from_millis(1),
+            Duration::from_millis(2),
+        ]);
+
+        assert_eq!(timings.mean, Duration::from_millis(2));
+        assert_eq!(timings.min, Duration::from_millis(1));
+        assert_eq!(timings.max, Duration::from_millis(3));
+    }
+
+    #[test]
+    fn print_report_as_table() {
+        let timings = |ms| Timings {
+            mean: Duration::from_millis(ms),
+            min: Duration::from_millis(ms),
+            max: Duration::from_millis(ms),
+        };
+        let report = OverheadReport {
+            iterations: 3,
+            untraced: timings(2),
+            traced: timings(10),
+            events_per_run: 42,
+            slowdown: 5.0,
+        };
+
+        let table = report.to_string();
+        assert!(table.contains("untraced"));
+        assert!(table.contains("Events per run:  42"));
+        assert!(table.ends_with("Slowdown:        5.00x"));
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["events_per_run"], 42);
+        assert_eq!(json["slowdown"], 5.0);
+    }
+}
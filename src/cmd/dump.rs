@@ -1,17 +1,19 @@
 use std::path::PathBuf;
 
 use anyhow::Error;
+use wasmgrind_core::instrumentation::InstrumentOptions;
 
-use crate::cmd::{emit_to_file, load_and_instrument};
+use crate::cmd::{EmitOptions, emit_to_file, load_and_instrument};
 
 pub struct DumpCmd {
     pub binary: PathBuf,
+    pub emit: EmitOptions,
 }
 
 impl DumpCmd {
     pub fn exec(self) -> Result<(), Error> {
-        let mut module = load_and_instrument(&self.binary)?;
-        emit_to_file("tmp", &module.emit_wasm(), "instrumented")?;
+        let mut module = load_and_instrument(&self.binary, &InstrumentOptions::default())?;
+        emit_to_file(&self.emit, &module.emit_wasm(), "instrumented")?;
         Ok(())
     }
 }
@@ -1,17 +1,19 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
-use anyhow::{Error, anyhow};
+use anyhow::{Context, Error, anyhow};
 use wasmgrind::standalone::ctx::StandaloneCtxProvider;
 use wasmtime::{Config, Engine, Linker, ProfilingStrategy, Store};
 use wasmtime_wali::ctx::WaliCtxProvider;
 
 use crate::cmd::{
-    ProfilingOptions, RtInterface, RtPhaseMarkers, emit_to_file, run_standalone_binary_func,
+    EmitOptions, ProfilingOptions, RtInterface, RtPhaseMarkers, emit_to_file,
+    run_standalone_binary_func,
 };
 
 pub struct RunCmd {
     pub binary: PathBuf,
     pub interface: RtInterface,
+    pub emit: EmitOptions,
 }
 
 impl RunCmd {
@@ -29,7 +31,15 @@ impl RunCmd {
             RtInterface::Standalone {
                 emit_patched,
                 function,
-            } => run_standalone(self.binary, config, emit_patched, function, options),
+                timeout,
+            } => run_standalone(
+                self.binary,
+                config,
+                emit_patched.then_some(&self.emit),
+                function,
+                timeout,
+                options,
+            ),
             RtInterface::Wali { args } => run_wali(self.binary, config, args, options),
             RtInterface::Wasi => {
                 todo!("Support for WASI (wasi-threads-p1) is not yet implemented.")
@@ -40,17 +50,27 @@ impl RunCmd {
 
 fn run_standalone(
     binary: PathBuf,
-    config: Config,
-    emit_patched: bool,
+    mut config: Config,
+    emit_patched: Option<&EmitOptions>,
     function: String,
+    timeout: Option<Duration>,
     options: &ProfilingOptions,
 ) -> Result<(), Error> {
+    if timeout.is_some() {
+        config.epoch_interruption(true);
+    }
     let engine = Engine::new(&config)?;
 
-    let (provider, mut module) = StandaloneCtxProvider::from_file(&engine, &binary)?;
+    let wasm = std::fs::read(&binary)
+        .with_context(|| format!("Could not read binary '{}'", binary.display()))?;
+    let (mut provider, artifacts) =
+        StandaloneCtxProvider::from_binary_with_artifacts(&engine, &wasm, None)?;
+    if let Some(timeout) = timeout {
+        provider = provider.with_deadline(timeout);
+    }
 
-    if emit_patched {
-        emit_to_file("tmp", &module.emit_wasm(), "patched")?;
+    if let Some(emit) = emit_patched {
+        emit_to_file(emit, &artifacts.patched, "patched")?;
     }
 
     let linker = Linker::new(provider.engine());
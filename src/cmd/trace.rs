@@ -1,14 +1,34 @@
-use std::path::PathBuf;
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
 
-use anyhow::{Error, anyhow, bail};
+use anyhow::{Error, anyhow, bail, ensure};
+use trace_tools::{Codec, JsonFormatEncoder, RapidBinParser, StdFormatEncoder};
 use walrus::Module;
 use wasmgrind::{
     standalone::{
         StandaloneCtxView, StandaloneView,
-        ctx::{StandaloneCtxProvider, WasmgrindStandaloneCtx},
+        ctx::{InterruptHandle, StandaloneCtxProvider, WasmgrindStandaloneCtx},
     },
     tracing::{TracingCtxView, TracingView, ctx::WasmgrindTracingCtx},
 };
+use wasmgrind_core::{
+    abi::{self, AbiFlavor},
+    instrumentation::{HookCategories, InstrumentOptions},
+    symbols::{LockSymbolizer, SourceMap, has_debug_info},
+    tracing::{
+        MonotonicClock, ReplayScheduler, Tracing,
+        metadata::{TraceProvenance, WasmgrindTraceMetadata, symbolize},
+        timestamps_path,
+    },
+};
 use wasmtime::{Config, Engine, Linker, ProfilingStrategy, Store};
 use wasmtime_wali::{
     WaliCtxView, WaliView,
@@ -16,17 +36,40 @@ use wasmtime_wali::{
 };
 
 use crate::cmd::{
-    ProfilingOptions, RtInterface, RtPhaseMarkers, emit_to_file, load_and_instrument,
+    EmitOptions, ProfilingOptions, RtInterface, RtPhaseMarkers, emit_to_file, load_and_instrument,
     run_standalone_binary_func,
 };
 
+/// The format of the emitted execution trace
+pub enum RtTraceFormat {
+    /// Binary RapidBin format (*.data)
+    RapidBin,
+    /// Human-readable STD format (*.std)
+    Std,
+    /// One JSON object per event and line (*.events.json)
+    Json,
+}
+
 pub struct TraceCmd {
     pub binary: PathBuf,
     pub cachedir: PathBuf,
     pub emit_instrumented: bool,
     pub outdir: PathBuf,
     pub outfile: PathBuf,
+    pub analyze: bool,
+    pub detect_races: bool,
+    pub contention: bool,
+    pub detect_deadlocks: bool,
+    pub stats: bool,
+    pub compress: Codec,
+    pub format: RtTraceFormat,
+    pub timestamps: bool,
+    pub symbolicate: Option<PathBuf>,
+    pub source_map: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub instrument: InstrumentOptions,
     pub interface: RtInterface,
+    pub emit: EmitOptions,
 }
 
 impl TraceCmd {
@@ -35,10 +78,33 @@ impl TraceCmd {
     }
 
     pub fn exec_with_options(self, options: &ProfilingOptions) -> Result<(), Error> {
+        if !matches!(self.format, RtTraceFormat::RapidBin)
+            && (self.analyze
+                || self.detect_races
+                || self.contention
+                || self.compress != Codec::None)
+        {
+            bail!("Analyzing and compressing the trace requires the RapidBin format");
+        }
+        if self.detect_deadlocks && !matches!(self.interface, RtInterface::Standalone { .. }) {
+            bail!("Stopping deadlocked executions requires the standalone interface");
+        }
+
         let mut config = Config::new();
         if let Some(RtPhaseMarkers::Perf) = options.markers {
             config.profiler(ProfilingStrategy::PerfMap);
         }
+        if self.detect_deadlocks
+            || matches!(
+                self.interface,
+                RtInterface::Standalone {
+                    timeout: Some(_),
+                    ..
+                }
+            )
+        {
+            config.epoch_interruption(true);
+        }
 
         let program_name = self
             .binary
@@ -50,39 +116,171 @@ impl TraceCmd {
                 self.binary.display()
             ))?;
 
-        let mut module = load_and_instrument(self.binary)?;
+        let mut module = load_and_instrument(&self.binary, &self.instrument)?;
+        let instrumented = module.emit_wasm();
 
         if self.emit_instrumented {
-            emit_to_file("tmp", &module.emit_wasm(), "instrumented")?;
+            emit_to_file(&self.emit, &instrumented, "instrumented")?;
         }
 
-        let tracing_ctx = match self.interface {
+        let abi = match self.interface {
+            RtInterface::Standalone { .. } => Some(AbiFlavor::Tracing),
+            RtInterface::Wali { .. } | RtInterface::Wasi => None,
+        };
+        let provenance = TraceProvenance::new(
+            &std::fs::read(&self.binary)?,
+            &instrumented,
+            abi,
+            &self.instrument,
+        );
+
+        let tracing = match &self.replay {
+            Some(trace_file) => {
+                let metadata = WasmgrindTraceMetadata::from_json(BufReader::new(File::open(
+                    trace_file.with_extension("json"),
+                )?))?;
+                let scheduler = ReplayScheduler::from_trace(trace_file, metadata)?;
+                log::info!(
+                    "Replaying the synchronization order of '{}' ...",
+                    trace_file.display()
+                );
+                Tracing::new(&self.cachedir).with_replay(scheduler)
+            }
+            None => Tracing::new(&self.cachedir),
+        };
+        let tracing = if self.timestamps {
+            tracing.with_timestamps(MonotonicClock::new())
+        } else {
+            tracing
+        };
+
+        // The interrupt handle is only available once the module has been prepared
+        let interrupt = Arc::new(OnceLock::<InterruptHandle>::new());
+        let n_deadlocks = Arc::new(AtomicUsize::new(0));
+        let tracing = if self.detect_deadlocks {
+            let interrupt = interrupt.clone();
+            let n_deadlocks = n_deadlocks.clone();
+            // The deadlocks themselves are logged by the tracing
+            tracing.on_deadlock(move |_, addresses| {
+                n_deadlocks.fetch_add(1, Ordering::Relaxed);
+                if let Some(handle) = interrupt.get() {
+                    handle.interrupt_waiters(addresses);
+                }
+            })
+        } else {
+            tracing
+        };
+        let tracing_ctx = WasmgrindTracingCtx::from(tracing);
+
+        let traced = match self.interface {
             RtInterface::Standalone {
                 emit_patched,
                 function,
+                timeout,
             } => trace_standalone(
                 module,
                 config,
-                emit_patched,
-                self.cachedir,
+                emit_patched.then_some(&self.emit),
+                tracing_ctx,
+                &interrupt,
                 function,
+                timeout,
                 options,
-            )?,
+            ),
             RtInterface::Wali { mut args } => {
                 args.insert(0, program_name);
-                trace_wali(module, config, self.cachedir, args, options)?
+                trace_wali(module, config, tracing_ctx, args, options)
             }
             RtInterface::Wasi => todo!(),
         };
+        let tracing_ctx = match traced {
+            Ok(tracing_ctx) => tracing_ctx,
+            Err(e) => match n_deadlocks.load(Ordering::Relaxed) {
+                0 => return Err(e),
+                n => {
+                    return Err(e.context(format!(
+                        "Stopped the execution after detecting {n} deadlocks"
+                    )));
+                }
+            },
+        };
+
+        if let Some(remaining) = tracing_ctx.replay_remaining().filter(|n| *n > 0) {
+            log::warn!(
+                "Replay finished before {remaining} recorded synchronization events were performed"
+            );
+        }
+
+        if self.stats {
+            println!("{}", tracing_ctx.stats());
+        }
 
         if options.emit_trace {
             std::fs::create_dir_all(&self.outdir)?;
             let outfile = self.outdir.join(self.outfile);
-            let trace_file = outfile.with_extension("data");
-            match tracing_ctx.generate_binary_trace(&trace_file) {
+            let (trace_file, generated) = match self.format {
+                RtTraceFormat::RapidBin => {
+                    let trace_file = outfile.with_extension("data");
+                    let generated =
+                        tracing_ctx.generate_binary_trace_compressed(&trace_file, self.compress);
+                    (trace_file, generated)
+                }
+                RtTraceFormat::Std => {
+                    // The STD trace is converted from a RapidBin trace once the metadata is
+                    // complete, as its timestamps are emitted inline
+                    let trace_file = outfile.with_extension("std.data");
+                    let generated =
+                        tracing_ctx.generate_binary_trace_compressed(&trace_file, Codec::None);
+                    (trace_file, generated)
+                }
+                RtTraceFormat::Json => {
+                    // The metadata already takes the *.json file
+                    let trace_file = outfile.with_extension("events.json");
+                    let generated =
+                        tracing_ctx.generate_trace(&mut JsonFormatEncoder::new(), &trace_file);
+                    (trace_file, generated)
+                }
+            };
+            match generated {
                 Ok(metadata) => {
-                    std::fs::write(outfile.with_extension("json"), metadata?.to_json()?)
-                        .map_err(Error::from)?;
+                    let mut metadata = metadata?;
+                    metadata.attach_provenance(provenance);
+
+                    // The plain metadata is written first, such that the trace remains usable
+                    // if the binary cannot be symbolized
+                    let metadata_file = outfile.with_extension("json");
+                    std::fs::write(&metadata_file, metadata.to_json()?)?;
+                    if enrich_metadata(
+                        &mut metadata,
+                        self.symbolicate.as_ref().unwrap_or(&self.binary),
+                        self.source_map.as_deref(),
+                    ) {
+                        std::fs::write(&metadata_file, metadata.to_json()?)?;
+                    }
+
+                    if let RtTraceFormat::Std = self.format {
+                        convert_to_std(&metadata, &trace_file, &outfile.with_extension("std"))?;
+                    }
+
+                    let n_overlaps = if self.analyze {
+                        analyze(&metadata, &trace_file, &outfile)?
+                    } else {
+                        0
+                    };
+
+                    if self.detect_races {
+                        detect_races(&metadata, &trace_file)?;
+                    }
+
+                    if self.contention {
+                        report_contention(&metadata, &trace_file, &outfile)?;
+                    }
+
+                    // Fail after all reports have been emitted, such that CI runs can be gated
+                    ensure!(
+                        n_overlaps == 0,
+                        "Found {n_overlaps} overlapping memory accesses"
+                    );
                 }
                 Err(_) => bail!(
                     "Could not generate binary trace. Some thread still holds a reference to the trace!"
@@ -94,10 +292,143 @@ impl TraceCmd {
     }
 }
 
+/// Attaches function names, lock names and source positions to `metadata`.
+///
+/// Each step is best-effort: failures are logged and the remaining steps are applied
+/// regardless. Source positions are only resolved from an explicit `source_map` or if
+/// the binary contains DWARF debug information. Returns whether anything was attached.
+fn enrich_metadata(
+    metadata: &mut WasmgrindTraceMetadata,
+    symbols: &Path,
+    source_map: Option<&Path>,
+) -> bool {
+    let symbols = match std::fs::read(symbols) {
+        Ok(symbols) => symbols,
+        Err(e) => {
+            log::warn!(
+                "Could not read '{}' for symbolization: {e}",
+                symbols.display()
+            );
+            return false;
+        }
+    };
+
+    let mut enriched = false;
+    match symbolize(metadata, &symbols) {
+        Ok(()) => enriched = true,
+        Err(e) => log::warn!("Could not resolve function names: {e:#}"),
+    }
+    match LockSymbolizer::from_wasm(&symbols) {
+        Ok(locks) => {
+            metadata.attach_lock_names(&locks);
+            enriched = true;
+        }
+        Err(e) => log::warn!("Could not resolve lock names: {e:#}"),
+    }
+
+    let source_map = match source_map {
+        Some(path) => std::fs::read(path)
+            .map_err(Error::from)
+            .and_then(|wasm| SourceMap::from_wasm(&wasm))
+            .map(Some),
+        None if has_debug_info(&symbols) => SourceMap::from_wasm(&symbols).map(Some),
+        None => Ok(None),
+    };
+    match source_map {
+        Ok(Some(source_map)) => {
+            metadata.resolve_locations(&source_map);
+            enriched = true;
+        }
+        Ok(None) => {}
+        Err(e) => log::warn!("Could not resolve source positions: {e:#}"),
+    }
+
+    enriched
+}
+
+/// Converts the RapidBin trace at `binary_trace` into STD format and removes it.
+///
+/// Locations and locks are named as far as the metadata has been enriched.
+/// Timestamps recorded for the trace are appended to the lines of their events.
+fn convert_to_std(
+    metadata: &WasmgrindTraceMetadata,
+    binary_trace: &Path,
+    std_file: &Path,
+) -> Result<(), Error> {
+    let mut encoder = StdFormatEncoder::with_location_names(metadata.location_sources())
+        .with_lock_names(metadata.lock_names())
+        .with_timestamps(metadata.timestamps(binary_trace)?);
+    trace_tools::convert(
+        &mut RapidBinParser::new(),
+        &mut encoder,
+        BufReader::new(File::open(binary_trace)?),
+        BufWriter::new(File::create(std_file)?),
+    )?;
+
+    std::fs::remove_file(binary_trace)?;
+    let timestamps = timestamps_path(binary_trace);
+    if timestamps.exists() {
+        std::fs::remove_file(timestamps)?;
+    }
+
+    Ok(())
+}
+
+/// Reports overlapping memory accesses and returns their number.
+fn analyze(
+    metadata: &WasmgrindTraceMetadata,
+    trace_file: &Path,
+    outfile: &Path,
+) -> Result<usize, Error> {
+    let overlaps = metadata.find_overlaps(trace_file)?;
+
+    for overlap in overlaps.get_overlaps() {
+        println!("{}", overlap.description());
+    }
+
+    let (n_overlap_events, n_memory_events) = overlaps.get_overlap_ratio();
+    println!("{n_overlap_events} of {n_memory_events} memory accesses overlap");
+
+    std::fs::write(
+        outfile.with_extension("overlaps.json"),
+        overlaps.to_report().to_json()?,
+    )?;
+
+    Ok(overlaps.get_overlaps().len())
+}
+
+fn detect_races(metadata: &WasmgrindTraceMetadata, trace_file: &Path) -> Result<(), Error> {
+    let races = metadata.detect_races(trace_file)?;
+
+    for race in &races {
+        println!("{}", metadata.describe_race(race));
+    }
+    println!("Found {} data races", races.len());
+
+    Ok(())
+}
+
+/// Reports the contention of every lock of the trace.
+fn report_contention(
+    metadata: &WasmgrindTraceMetadata,
+    trace_file: &Path,
+    outfile: &Path,
+) -> Result<(), Error> {
+    let report = metadata.lock_contention_report(trace_file)?;
+    println!("{report}");
+
+    std::fs::write(
+        outfile.with_extension("contention.json"),
+        serde_json::to_string_pretty(&report)?,
+    )?;
+
+    Ok(())
+}
+
 #[derive(Clone)]
-struct StandaloneTracingCtx {
-    standalone_ctx: WasmgrindStandaloneCtx,
-    tracing_ctx: WasmgrindTracingCtx,
+pub(super) struct StandaloneTracingCtx {
+    pub(super) standalone_ctx: WasmgrindStandaloneCtx,
+    pub(super) tracing_ctx: WasmgrindTracingCtx,
 }
 
 impl StandaloneView for StandaloneTracingCtx {
@@ -130,28 +461,38 @@ impl TracingView for WALITracingCtx {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn trace_standalone(
     mut binary: Module,
     config: Config,
-    emit_patched: bool,
-    cachedir: PathBuf,
+    emit_patched: Option<&EmitOptions>,
+    tracing_ctx: WasmgrindTracingCtx,
+    interrupt: &OnceLock<InterruptHandle>,
     function: String,
+    timeout: Option<Duration>,
     options: &ProfilingOptions,
 ) -> Result<WasmgrindTracingCtx, Error> {
+    abi::validate_module(&binary, AbiFlavor::Tracing).ensure_valid()?;
+
     let engine = Engine::new(&config)?;
 
-    let provider = StandaloneCtxProvider::from_walrus(&engine, &mut binary)?;
+    let mut provider = StandaloneCtxProvider::from_walrus(&engine, &mut binary)?;
+    if let Some(timeout) = timeout {
+        provider = provider.with_deadline(timeout);
+    }
+    let _ = interrupt.set(provider.interrupt_handle());
 
-    if emit_patched {
-        emit_to_file("tmp", &binary.emit_wasm(), "patched")?;
+    if let Some(emit) = emit_patched {
+        emit_to_file(emit, &binary.emit_wasm(), "patched")?;
     }
 
     let mut linker = Linker::new(provider.engine());
-    WasmgrindTracingCtx::add_to_linker(&mut linker)?;
+    let hooks = HookCategories::from_module(&binary).unwrap_or(HookCategories::ALL);
+    WasmgrindTracingCtx::add_to_linker_with_hooks(&mut linker, hooks)?;
 
     let ctx = StandaloneTracingCtx {
         standalone_ctx: provider.create_ctx(),
-        tracing_ctx: WasmgrindTracingCtx::new(cachedir),
+        tracing_ctx,
     };
 
     run_standalone_binary_func::<_, (), ()>(linker, provider, ctx.clone(), function, (), options)?;
@@ -162,21 +503,22 @@ fn trace_standalone(
 fn trace_wali(
     mut binary: Module,
     mut config: Config,
-    cachedir: PathBuf,
+    tracing_ctx: WasmgrindTracingCtx,
     args: Vec<String>,
     options: &ProfilingOptions,
 ) -> Result<WasmgrindTracingCtx, Error> {
     let provider = WaliCtxProvider::from_config(&mut config)?.with_walrus(&mut binary)?;
 
     let mut linker = Linker::new(provider.engine());
-    WasmgrindTracingCtx::add_to_linker(&mut linker)?;
+    let hooks = HookCategories::from_module(&binary).unwrap_or(HookCategories::ALL);
+    WasmgrindTracingCtx::add_to_linker_with_hooks(&mut linker, hooks)?;
     unsafe {
         provider.add_to_linker(&mut linker)?;
     }
 
     let ctx = WALITracingCtx {
         wali_ctx: provider.create_ctx(args)?,
-        tracing_ctx: WasmgrindTracingCtx::new(cachedir),
+        tracing_ctx,
     };
 
     let mut store = Store::new(provider.engine(), ctx.clone());
@@ -12,10 +12,14 @@ use log4rs::{
     encode::pattern::PatternEncoder,
     filter::threshold::ThresholdFilter,
 };
+use wasmgrind_core::instrumentation::{FunctionSelector, InstrumentOptions};
 
 use crate::{
     cli::{Cli, Cmd, ExecCmd},
-    cmd::{ProfilingOptions, RtPhaseMarkers, dump::DumpCmd, run::RunCmd, trace::TraceCmd},
+    cmd::{
+        ProfilingOptions, RtPhaseMarkers, bench::BenchCmd, dump::DumpCmd, run::RunCmd,
+        trace::TraceCmd,
+    },
 };
 
 mod cli;
@@ -68,15 +72,42 @@ fn init_logging(level: Level, dir: Option<PathBuf>) -> Result<(), Error> {
     Ok(())
 }
 
+fn instrument_options(instrument_only: Vec<String>, trace_calls: bool) -> InstrumentOptions {
+    let mut options = InstrumentOptions {
+        include: instrument_only
+            .into_iter()
+            .map(FunctionSelector::Name)
+            .collect(),
+        ..Default::default()
+    };
+    options.hooks.calls = trace_calls;
+    options
+}
+
 fn main() -> Result<(), anyhow::Error> {
     let args = Cli::args();
+    let emit = args.emit_options();
 
     if let Some(level) = args.loglevel() {
         init_logging(level, args.logdir)?;
     }
 
     match args.cmd {
-        Cmd::Dump { binary } => DumpCmd { binary }.exec()?,
+        Cmd::Dump { binary } => DumpCmd { binary, emit }.exec()?,
+        Cmd::Bench {
+            binary,
+            function,
+            iterations,
+            cachedir,
+            json,
+        } => BenchCmd {
+            binary,
+            function,
+            iterations,
+            cachedir,
+            json,
+        }
+        .exec()?,
         Cmd::Profile { markers, exec_cmd } => {
             let markers = markers.map(|marker_option| {
                 // Start phase marker timer
@@ -92,6 +123,7 @@ fn main() -> Result<(), anyhow::Error> {
                     RunCmd {
                         binary,
                         interface: interface.into(),
+                        emit,
                     }
                     .exec_with_options(&options)?;
                 }
@@ -101,6 +133,19 @@ fn main() -> Result<(), anyhow::Error> {
                     emit_instrumented,
                     outdir,
                     outfile,
+                    analyze,
+                    detect_races,
+                    contention,
+                    detect_deadlocks,
+                    stats,
+                    compress,
+                    format,
+                    timestamps,
+                    symbolicate,
+                    source_map,
+                    replay,
+                    instrument_only,
+                    trace_calls,
                     interface,
                 } => {
                     TraceCmd {
@@ -109,7 +154,20 @@ fn main() -> Result<(), anyhow::Error> {
                         emit_instrumented,
                         outdir,
                         outfile,
+                        analyze,
+                        detect_races,
+                        contention,
+                        detect_deadlocks,
+                        stats,
+                        compress: compress.into(),
+                        format: format.into(),
+                        timestamps,
+                        symbolicate,
+                        source_map,
+                        replay,
+                        instrument: instrument_options(instrument_only, trace_calls),
                         interface: interface.into(),
+                        emit,
                     }
                     .exec_with_options(&options)?;
                 }
@@ -120,6 +178,7 @@ fn main() -> Result<(), anyhow::Error> {
                 RunCmd {
                     binary,
                     interface: interface.into(),
+                    emit,
                 }
                 .exec()?;
             }
@@ -129,6 +188,19 @@ fn main() -> Result<(), anyhow::Error> {
                 emit_instrumented,
                 outdir,
                 outfile,
+                analyze,
+                detect_races,
+                contention,
+                detect_deadlocks,
+                stats,
+                compress,
+                format,
+                timestamps,
+                symbolicate,
+                source_map,
+                replay,
+                instrument_only,
+                trace_calls,
                 interface,
             } => {
                 TraceCmd {
@@ -137,7 +209,20 @@ fn main() -> Result<(), anyhow::Error> {
                     emit_instrumented,
                     outdir,
                     outfile,
+                    analyze,
+                    detect_races,
+                    contention,
+                    detect_deadlocks,
+                    stats,
+                    compress: compress.into(),
+                    format: format.into(),
+                    timestamps,
+                    symbolicate,
+                    source_map,
+                    replay,
+                    instrument: instrument_options(instrument_only, trace_calls),
                     interface: interface.into(),
+                    emit,
                 }
                 .exec()?;
             }
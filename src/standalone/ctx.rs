@@ -1,18 +1,35 @@
 use std::sync::{
-    Arc,
-    atomic::{AtomicU32, Ordering},
+    Arc, Mutex,
+    atomic::{AtomicBool, AtomicU32, Ordering},
 };
 
+use anyhow::Error;
 use wasmtime::Module;
 
+mod options;
 mod provider;
-pub use provider::StandaloneCtxProvider;
+mod threads;
+pub use options::RuntimeOptions;
+pub use provider::{InterruptHandle, PatchArtifacts, ShutdownTimeout, StandaloneCtxProvider};
+use threads::ThreadRegistry;
+pub use threads::ThreadState;
+
+/// A callback that is invoked with the TID of a spawned thread that failed.
+pub(crate) type ThreadErrorSink = Arc<dyn Fn(u32, &Error) + Send + Sync>;
+
+/// A hook that is invoked with the error code passed to the `exit` import.
+pub(crate) type WasmExitHook = Arc<dyn Fn(i32) + Send + Sync>;
 
 pub struct WasmgrindStandaloneCtx {
     module: Module,
     tls_size: u32,
     tls_align: u32,
     next_tid: Arc<AtomicU32>,
+    free_tids: Option<Arc<Mutex<Vec<u32>>>>,
+    interrupted: Arc<AtomicBool>,
+    shutting_down: Arc<AtomicBool>,
+    threads: ThreadRegistry,
+    on_thread_error: Option<ThreadErrorSink>,
 }
 
 impl Clone for WasmgrindStandaloneCtx {
@@ -22,6 +39,11 @@ impl Clone for WasmgrindStandaloneCtx {
             tls_size: self.tls_size,
             tls_align: self.tls_align,
             next_tid: self.next_tid.clone(),
+            free_tids: self.free_tids.clone(),
+            interrupted: self.interrupted.clone(),
+            shutting_down: self.shutting_down.clone(),
+            threads: self.threads.clone(),
+            on_thread_error: self.on_thread_error.clone(),
         }
     }
 }
@@ -31,7 +53,91 @@ impl WasmgrindStandaloneCtx {
     const MEMORY_IMPORT_NAME: &str = "memory";
     const MEMORY_IMPORT_MODULE: &str = "env";
 
+    /// The TID of the main instance, which is reserved by [`StandaloneCtxProvider::create_ctx`].
+    ///
+    /// Patched modules start with this TID, such that it is never handed out to other threads.
+    pub const MAIN_TID: u32 = 0;
+
     pub fn next_available_tid(&self) -> u32 {
-        self.next_tid.fetch_add(1, Ordering::Relaxed)
+        self.free_tids
+            .as_ref()
+            .and_then(|free_tids| {
+                free_tids
+                    .lock()
+                    .expect("Could not lock free TID list!")
+                    .pop()
+            })
+            .unwrap_or_else(|| self.next_tid.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Returns the TID of a terminated thread such that it can be reused.
+    ///
+    /// This has no effect unless TID recycling has been enabled via
+    /// [`StandaloneCtxProvider::with_tid_recycling`].
+    fn release_tid(&self, tid: u32) {
+        if let Some(free_tids) = &self.free_tids {
+            free_tids
+                .lock()
+                .expect("Could not lock free TID list!")
+                .push(tid);
+        }
+    }
+
+    /// Returns whether the spawned thread with the given TID has terminated.
+    ///
+    /// Threads that have already been joined by [`StandaloneCtxProvider::shutdown`]
+    /// count as terminated. TIDs that have been handed out without belonging to a
+    /// spawned thread yet, such as the TID of the main instance or of a thread that
+    /// is still being started, count as running. Returns `None` if the TID has never
+    /// been handed out.
+    pub fn is_thread_finished(&self, tid: u32) -> Option<bool> {
+        match self.threads.thread_state(tid) {
+            Some(ThreadState::Running) => Some(false),
+            Some(ThreadState::Finished | ThreadState::Joined | ThreadState::Failed) => Some(true),
+            None => (tid < self.next_tid.load(Ordering::Relaxed)).then_some(false),
+        }
+    }
+
+    /// Returns whether the spawned thread with the given TID has terminated on behalf
+    /// of the guest, which is joining the thread.
+    ///
+    /// Once the guest has seen the thread terminate, the TID of the thread is released,
+    /// such that it can be reused if TID recycling is enabled.
+    fn join_thread(&self, tid: u32) -> Option<bool> {
+        let finished = self.is_thread_finished(tid);
+        if finished == Some(true) {
+            self.release_thread_tid(tid);
+        }
+        finished
+    }
+
+    /// Releases the TID of a spawned thread unless it has been released already.
+    fn release_thread_tid(&self, tid: u32) {
+        if self.threads.release(tid) {
+            self.release_tid(tid);
+        }
+    }
+
+    /// Records that the spawned thread with the given TID trapped or could not be started.
+    ///
+    /// The failure is passed to the callback registered via
+    /// [`StandaloneCtxProvider::on_thread_error`], if any.
+    fn report_thread_error(&self, tid: u32, error: &Error) {
+        log::error!("Spawned thread {tid} failed: {error:?}");
+        self.threads.record_failure(tid);
+        if let Some(on_thread_error) = &self.on_thread_error {
+            on_thread_error(tid, error);
+        }
+    }
+
+    /// Returns whether the execution has been interrupted via an [`InterruptHandle`].
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::SeqCst)
+    }
+
+    /// Returns whether the runtime is shutting down and refuses to spawn threads,
+    /// see [`StandaloneCtxProvider::shutdown_timeout`].
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
     }
 }
@@ -0,0 +1,52 @@
+use wasmtime::{Config, InstanceAllocationStrategy, OptLevel, PoolingAllocationConfig};
+
+/// Tuning of the engine that runs patched binaries.
+///
+/// The options are turned into a [`Config`] by [`RuntimeOptions::to_config`], which
+/// can be passed to [`super::StandaloneCtxProvider::with_engine_config`].
+#[derive(Clone, Debug)]
+pub struct RuntimeOptions {
+    /// Allocates instances from a pool that fits this many instances at once.
+    ///
+    /// Every spawned thread runs its own instance, so the pool has to fit the
+    /// maximum number of concurrently running threads plus the main instance.
+    pub pooling_allocator: Option<u32>,
+    /// Compiles the functions of a module on multiple threads
+    pub parallel_compilation: bool,
+    /// The optimization level of the generated machine code
+    pub opt_level: OptLevel,
+}
+
+impl Default for RuntimeOptions {
+    fn default() -> Self {
+        Self {
+            pooling_allocator: None,
+            parallel_compilation: true,
+            opt_level: OptLevel::Speed,
+        }
+    }
+}
+
+impl RuntimeOptions {
+    /// Creates an engine configuration with these options.
+    ///
+    /// The WebAssembly threads proposal, which patched binaries depend on, is
+    /// always enabled.
+    pub fn to_config(&self) -> Config {
+        let mut config = Config::new();
+        config
+            .wasm_threads(true)
+            .parallel_compilation(self.parallel_compilation)
+            .cranelift_opt_level(self.opt_level);
+
+        if let Some(max_instances) = self.pooling_allocator {
+            let mut pool = PoolingAllocationConfig::new();
+            pool.total_core_instances(max_instances)
+                .total_memories(max_instances)
+                .total_tables(max_instances);
+            config.allocation_strategy(InstanceAllocationStrategy::Pooling(pool));
+        }
+
+        config
+    }
+}
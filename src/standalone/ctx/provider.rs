@@ -1,22 +1,158 @@
 use std::{
+    fmt::Display,
     path::Path,
-    sync::{Arc, OnceLock, atomic::AtomicU32},
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicBool, AtomicU8, AtomicU32, Ordering},
+        mpsc::{self, RecvTimeoutError},
+    },
+    time::{Duration, Instant},
 };
 
-use anyhow::{Error, anyhow};
-use wasmtime::{AsContext, Caller, Engine, Extern, Linker, MemoryType, Module, SharedMemory};
+use anyhow::{Context, Error, anyhow, bail};
+use wasmtime::{
+    AsContext, Caller, Config, Engine, Extern, InstancePre, Linker, MemoryType, Module,
+    SharedMemory, Store, Trap, WasmParams, WasmResults,
+};
+
+use wasmgrind_core::{
+    abi::{self, AbiFlavor},
+    instrumentation::{self, InstrumentOptions},
+    threadify::DataSegment,
+};
 
-use crate::standalone::{StandaloneView, ctx::WasmgrindStandaloneCtx};
+use crate::standalone::{
+    StandaloneView,
+    ctx::{ThreadErrorSink, ThreadRegistry, ThreadState, WasmExitHook, WasmgrindStandaloneCtx},
+};
 
 pub struct StandaloneCtxProvider<T> {
     module: Module,
     memory_min: u32,
     memory_max: u32,
+    memory: Arc<OnceLock<SharedMemory>>,
+    data_segments: Vec<DataSegment>,
     tls_size: u32,
     tls_align: u32,
     linker: Arc<OnceLock<Linker<T>>>,
+    instance_pre: Arc<OnceLock<InstancePre<T>>>,
+    preinstantiate: bool,
+    interrupted: Arc<AtomicBool>,
+    shutting_down: Arc<AtomicBool>,
+    threads: ThreadRegistry,
+    recycle_tids: bool,
+    runner_stacks: Mutex<Vec<RunnerStack>>,
+    deadline: Option<Duration>,
+    on_thread_error: Option<ThreadErrorSink>,
+    on_wasm_exit: Option<WasmExitHook>,
+}
+
+/// The stack and TLS block of an instance created by [`StandaloneCtxProvider::invoke_runner`].
+#[derive(Clone, Copy)]
+struct RunnerStack {
+    stack_ptr: u32,
+    tls_base: u32,
+}
+
+/// The intermediate binaries of a module prepared by
+/// [`StandaloneCtxProvider::from_binary_with_artifacts`].
+#[derive(Debug, Clone)]
+pub struct PatchArtifacts {
+    /// The binary after it has been patched for the standalone runtime
+    pub patched: Vec<u8>,
+    /// The binary after it has been instrumented, before it has been patched
+    pub instrumented: Option<Vec<u8>>,
+}
+
+/// A handle to stop all instances created by a [`StandaloneCtxProvider`].
+///
+/// Interrupting only takes effect if the engine has been configured with
+/// [`Config::epoch_interruption`] and all stores have been created through
+/// [`StandaloneCtxProvider::create_store`]. Interrupted instances trap with
+/// [`Trap::Interrupt`] and no further threads can be spawned afterwards.
+#[derive(Clone)]
+pub struct InterruptHandle {
+    engine: Engine,
+    interrupted: Arc<AtomicBool>,
+    memory: Arc<OnceLock<SharedMemory>>,
+}
+
+impl InterruptHandle {
+    /// Causes the main instance and all spawned threads to trap.
+    pub fn interrupt(&self) {
+        // The flag must be visible before the epoch changes, such that a
+        // thread spawned concurrently either sees the flag or the new epoch.
+        self.interrupted.store(true, Ordering::SeqCst);
+        self.engine.increment_epoch();
+    }
+
+    /// Interrupts the execution and wakes the threads waiting on any of `addresses`.
+    ///
+    /// Threads blocked in `memory.atomic.wait` only trap once they are woken up, e.g.,
+    /// the threads waiting for the locks of a deadlock. Addresses that are misaligned or
+    /// out of bounds of the shared memory are ignored.
+    pub fn interrupt_waiters(&self, addresses: &[u32]) {
+        self.interrupt();
+
+        if let Some(memory) = self.memory.get() {
+            for address in addresses {
+                if let Err(e) = memory.atomic_notify(u64::from(*address), u32::MAX) {
+                    log::debug!("Could not wake the threads waiting on {address:#x}: {e}");
+                }
+            }
+        }
+    }
+
+    /// Interrupts the execution once `timeout` has elapsed.
+    ///
+    /// The interruption is cancelled if the returned [`Deadline`] is dropped
+    /// before, e.g., because the invoked function returned in time.
+    pub fn interrupt_after(&self, timeout: Duration) -> Deadline {
+        let (cancel, cancelled) = mpsc::channel::<()>();
+        let handle = self.clone();
+        std::thread::spawn(move || {
+            if let Err(RecvTimeoutError::Timeout) = cancelled.recv_timeout(timeout) {
+                handle.interrupt();
+            }
+        });
+
+        Deadline { _cancel: cancel }
+    }
+}
+
+/// A pending interruption created by [`InterruptHandle::interrupt_after`].
+///
+/// Dropping the deadline cancels the interruption if it has not happened yet.
+pub struct Deadline {
+    _cancel: mpsc::Sender<()>,
+}
+
+/// The error returned by [`StandaloneCtxProvider::shutdown_timeout`] if spawned
+/// threads are still running when the timeout expires.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShutdownTimeout {
+    running: Vec<u32>,
+}
+
+impl ShutdownTimeout {
+    /// Returns the IDs of the threads that were still running.
+    pub fn running_threads(&self) -> &[u32] {
+        &self.running
+    }
 }
 
+impl Display for ShutdownTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Spawned threads {:?} were still running when the shutdown timed out",
+            self.running
+        )
+    }
+}
+
+impl std::error::Error for ShutdownTimeout {}
+
 impl<T> StandaloneCtxProvider<T> {
     pub fn from_file<P: AsRef<Path>>(
         engine: &Engine,
@@ -33,10 +169,93 @@ impl<T> StandaloneCtxProvider<T> {
         Ok((provider, module))
     }
 
+    /// Prepares the given binary like [`StandaloneCtxProvider::from_binary`], but hands
+    /// back the intermediate binaries instead of the transformed module.
+    ///
+    /// If `instrument` is given, the binary is instrumented for tracing before it is
+    /// patched. This allows embedders to inspect or store the binaries without writing
+    /// them to files.
+    pub fn from_binary_with_artifacts(
+        engine: &Engine,
+        wasm: &[u8],
+        instrument: Option<&InstrumentOptions>,
+    ) -> Result<(Self, PatchArtifacts), Error> {
+        let mut module = walrus::Module::from_buffer(wasm)?;
+        let instrumented = match instrument {
+            Some(options) => {
+                instrumentation::instrument_with_options(&mut module, options)?;
+                Some(module.emit_wasm())
+            }
+            None => None,
+        };
+        let provider = Self::from_walrus(engine, &mut module)?;
+        let artifacts = PatchArtifacts {
+            patched: module.emit_wasm(),
+            instrumented,
+        };
+
+        Ok((provider, artifacts))
+    }
+
+    /// Creates an engine from `config` and prepares the given binary for it.
+    ///
+    /// Spawned threads are instantiated on the same engine, so they share its
+    /// compilation caches. Epoch interruption is enabled on top of `config`
+    /// to support [`StandaloneCtxProvider::interrupt_handle`].
+    pub fn with_engine_config(
+        wasm: &[u8],
+        config: &Config,
+    ) -> Result<(Self, walrus::Module), Error> {
+        let mut config = config.clone();
+        config.epoch_interruption(true);
+        let engine = Engine::new(&config)?;
+        Self::from_binary(&engine, wasm)
+    }
+
     pub fn from_walrus(engine: &Engine, module: &mut walrus::Module) -> Result<Self, Error> {
+        Self::from_walrus_with_memory(engine, module, None)
+    }
+
+    /// Prepares a module with multiple memories, where `memory_name` designates
+    /// the exported memory that is shared amongst threads.
+    ///
+    /// All other memories remain private to the instance of each thread.
+    pub fn from_walrus_with_memory(
+        engine: &Engine,
+        module: &mut walrus::Module,
+        memory_name: Option<&str>,
+    ) -> Result<Self, Error> {
+        Self::from_walrus_with_headroom(engine, module, memory_name, 0)
+    }
+
+    /// Prepares a module whose shared memory may grow `headroom_pages` beyond the
+    /// maximum size declared by the module.
+    ///
+    /// The raised maximum is written into the patched module, because an imported
+    /// memory must not be larger than the maximum declared by its import. Thus, only
+    /// the patched module is able to use the enlarged memory, whereas the original
+    /// binary keeps its limits. Note that the memory can still not grow beyond 4GiB
+    /// (65536 pages) and that guest allocators may assume that the declared maximum
+    /// is never exceeded.
+    pub fn from_walrus_with_headroom(
+        engine: &Engine,
+        module: &mut walrus::Module,
+        memory_name: Option<&str>,
+        headroom_pages: u32,
+    ) -> Result<Self, Error> {
+        Self::validate_engine(engine)?;
+        abi::validate_module(module, AbiFlavor::Standalone).ensure_valid()?;
         wasmgrind_core::threadify::patch(module)?;
 
-        let (memory_min, memory_max) = wasmgrind_core::threadify::get_shared_memory_size(module)?;
+        let data_segments = wasmgrind_core::threadify::import_shared_memory(
+            module,
+            memory_name,
+            WasmgrindStandaloneCtx::MEMORY_IMPORT_MODULE,
+            WasmgrindStandaloneCtx::MEMORY_IMPORT_NAME,
+        )?;
+        wasmgrind_core::threadify::add_memory_headroom(module, memory_name, headroom_pages)?;
+        let (memory_min, memory_max) =
+            wasmgrind_core::threadify::get_shared_memory_size(module, memory_name)?;
 
         let tls_size = wasmgrind_core::threadify::extract_tls_size(module)?;
         let tls_align = wasmgrind_core::threadify::extract_tls_align(module)?;
@@ -46,9 +265,128 @@ impl<T> StandaloneCtxProvider<T> {
             module,
             memory_min,
             memory_max,
+            memory: Arc::new(OnceLock::new()),
+            data_segments,
             tls_size,
             tls_align,
             linker: Arc::new(OnceLock::new()),
+            instance_pre: Arc::new(OnceLock::new()),
+            preinstantiate: false,
+            interrupted: Arc::new(AtomicBool::new(false)),
+            shutting_down: Arc::new(AtomicBool::new(false)),
+            threads: ThreadRegistry::new(),
+            recycle_tids: false,
+            runner_stacks: Mutex::new(Vec::new()),
+            deadline: None,
+            on_thread_error: None,
+            on_wasm_exit: None,
+        })
+    }
+
+    /// Enables or disables the reuse of TIDs of terminated threads.
+    ///
+    /// By default, every spawned thread receives a fresh TID. Long running
+    /// programs that spawn many short-lived threads may want to reuse TIDs
+    /// instead. A TID is only reused once the guest has joined the thread that
+    /// previously used it, i.e., once `thread_is_finished` has reported that the
+    /// thread terminated.
+    pub fn with_tid_recycling(mut self, enable: bool) -> Self {
+        self.recycle_tids = enable;
+        self
+    }
+
+    /// Enables or disables the reuse of a pre-instantiated module for spawned threads.
+    ///
+    /// By default, the imports of the module are resolved by the linker whenever a
+    /// thread is spawned. With pre-instantiation, they are resolved once by
+    /// [`StandaloneCtxProvider::finalize`], such that spawning a thread only has to
+    /// create the instance. Hence, imports missing from the linker are already
+    /// reported by [`StandaloneCtxProvider::finalize`].
+    pub fn with_preinstantiation(mut self, enable: bool) -> Self {
+        self.preinstantiate = enable;
+        self
+    }
+
+    /// Limits the number of spawned threads that run at the same time.
+    ///
+    /// By default, every spawned thread is backed by its own OS thread, so programs
+    /// that spawn many threads may exhaust the limits of the OS. With a limit, spawning
+    /// a thread blocks until a running thread has terminated. The main instance does
+    /// not count towards the limit.
+    ///
+    /// Detached threads keep their slot until they terminate. A program deadlocks if
+    /// all running threads wait for threads that have not been able to start yet,
+    /// e.g., if every thread spawns and joins children of its own. Hence, the limit
+    /// has to exceed the depth of the thread hierarchy of such programs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_threads` is zero.
+    pub fn with_max_threads(mut self, max_threads: usize) -> Self {
+        self.threads = ThreadRegistry::with_limit(max_threads);
+        self
+    }
+
+    /// Interrupts the execution if the invoked function has not returned within `timeout`.
+    ///
+    /// The deadline is not enforced by the provider itself, but by embedders that invoke
+    /// the function, e.g., through [`InterruptHandle::interrupt_after`]. Like any other
+    /// interruption, it requires the engine to be configured with [`Config::epoch_interruption`].
+    pub fn with_deadline(mut self, timeout: Duration) -> Self {
+        self.deadline = Some(timeout);
+        self
+    }
+
+    /// Returns the deadline set by [`StandaloneCtxProvider::with_deadline`].
+    pub fn deadline(&self) -> Option<Duration> {
+        self.deadline
+    }
+
+    /// Registers a callback that is invoked whenever a spawned thread fails.
+    ///
+    /// A thread fails if its instance can not be created or if it traps, except
+    /// for traps caused by an [`InterruptHandle`]. The callback receives the TID of
+    /// the failed thread, whose state is [`ThreadState::Failed`] afterwards. It runs
+    /// on the failed thread or, if the thread could not be started, on its parent.
+    pub fn on_thread_error(
+        mut self,
+        callback: impl Fn(u32, &Error) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_thread_error = Some(Arc::new(callback));
+        self
+    }
+
+    /// Registers a hook that is invoked when the module calls the `exit` import.
+    ///
+    /// By default, `exit` panics with the raw error code, which takes down the
+    /// host along with the module. With a hook, the hook receives the error code
+    /// and the calling instance traps once the hook returns. Other instances keep
+    /// running, so embedders may want to interrupt them from within the hook.
+    pub fn on_wasm_exit(mut self, hook: impl Fn(i32) + Send + Sync + 'static) -> Self {
+        self.on_wasm_exit = Some(Arc::new(hook));
+        self
+    }
+
+    /// Checks that `engine` is able to compile patched binaries.
+    ///
+    /// Patching replaces the memory of a binary with an imported shared memory,
+    /// which requires the WebAssembly threads proposal to be enabled.
+    fn validate_engine(engine: &Engine) -> Result<(), Error> {
+        let mut probe = walrus::Module::default();
+        probe.add_import_memory(
+            WasmgrindStandaloneCtx::MEMORY_IMPORT_MODULE,
+            WasmgrindStandaloneCtx::MEMORY_IMPORT_NAME,
+            true,
+            false,
+            1,
+            Some(1),
+            None,
+        );
+
+        Module::validate(engine, &probe.emit_wasm()).map_err(|e| {
+            anyhow!(
+                "The engine configuration does not support shared memories, which are required by patched binaries (enable the WebAssembly threads proposal): {e}"
+            )
         })
     }
 
@@ -60,16 +398,148 @@ impl<T> StandaloneCtxProvider<T> {
         self.module.engine()
     }
 
+    /// Returns the minimum and maximum number of pages of the shared memory.
+    pub fn memory_limits(&self) -> (u32, u32) {
+        (self.memory_min, self.memory_max)
+    }
+
+    /// Returns the current number of pages of the shared memory.
+    ///
+    /// Returns `None` if the memory has not been created by
+    /// [`StandaloneCtxProvider::add_to_linker`] yet.
+    pub fn current_memory_pages(&self) -> Option<u64> {
+        self.memory.get().map(SharedMemory::size)
+    }
+
     pub fn create_ctx(&self) -> WasmgrindStandaloneCtx {
         WasmgrindStandaloneCtx {
             module: self.module.clone(),
             tls_size: self.tls_size,
             tls_align: self.tls_align,
-            next_tid: Arc::new(AtomicU32::new(0)),
+            next_tid: Arc::new(AtomicU32::new(WasmgrindStandaloneCtx::MAIN_TID + 1)),
+            free_tids: self.recycle_tids.then(|| Arc::new(Mutex::new(Vec::new()))),
+            interrupted: self.interrupted.clone(),
+            shutting_down: self.shutting_down.clone(),
+            threads: self.threads.clone(),
+            on_thread_error: self.on_thread_error.clone(),
+        }
+    }
+
+    /// Returns the IDs of all spawned threads that are still running.
+    pub fn running_threads(&self) -> Vec<u32> {
+        self.threads.running_threads()
+    }
+
+    /// Returns the IDs of all spawned threads that have not been joined by
+    /// [`StandaloneCtxProvider::shutdown`] yet, including terminated ones.
+    pub fn thread_ids(&self) -> Vec<u32> {
+        self.threads.thread_ids()
+    }
+
+    /// Returns the state of the spawned thread with the given TID.
+    ///
+    /// Returns `None` if no thread has been spawned with this TID. If TIDs are
+    /// recycled, the state refers to the most recent thread with this TID.
+    pub fn thread_state(&self, tid: u32) -> Option<ThreadState> {
+        self.threads.thread_state(tid)
+    }
+
+    /// Returns the number of spawned threads that are still running.
+    pub fn thread_count(&self) -> usize {
+        self.running_threads().len()
+    }
+
+    /// Returns the highest number of spawned threads that have been running at the same time.
+    ///
+    /// Returns `None` unless the number of threads is limited by
+    /// [`StandaloneCtxProvider::with_max_threads`].
+    pub fn peak_thread_count(&self) -> Option<usize> {
+        self.threads.peak_running()
+    }
+
+    /// Waits until all spawned threads have terminated.
+    ///
+    /// Spawned threads may outlive the invoked function and keep mutating the
+    /// shared memory. After this method returns, no background mutation can
+    /// occur anymore. Combine it with an [`InterruptHandle`] to abort threads
+    /// that would otherwise never terminate.
+    ///
+    /// Fails with the error of the first spawned thread that trapped, if any.
+    /// Threads that could not be started are not considered, as their failure
+    /// has already been reported to the guest.
+    pub fn shutdown(self) -> Result<(), Error> {
+        let panicked = self.threads.join_all();
+        if !panicked.is_empty() {
+            bail!("Spawned threads {panicked:?} panicked before shutdown");
         }
+
+        self.take_thread_errors()
+    }
+
+    /// Stops spawning threads and waits up to `timeout` for all spawned threads to terminate.
+    ///
+    /// Once called, the guest fails to spawn further threads with the error code `-2`.
+    /// If threads are still running when the timeout expires, a [`ShutdownTimeout`]
+    /// error reports their IDs. These threads can be stopped with an [`InterruptHandle`]
+    /// before calling [`StandaloneCtxProvider::shutdown`].
+    pub fn shutdown_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        self.shutting_down.store(true, Ordering::SeqCst);
+
+        match self.threads.join_all_until(Instant::now() + timeout) {
+            Ok(panicked) if panicked.is_empty() => self.take_thread_errors(),
+            Ok(panicked) => bail!("Spawned threads {panicked:?} panicked before shutdown"),
+            Err(running) => Err(ShutdownTimeout { running }.into()),
+        }
+    }
+
+    /// Returns the error of the spawned thread with the lowest TID that trapped.
+    fn take_thread_errors(&self) -> Result<(), Error> {
+        let errors = self.threads.take_errors();
+        let tids: Vec<u32> = errors.iter().map(|(tid, _)| *tid).collect();
+
+        match errors.into_iter().next() {
+            None => Ok(()),
+            Some((tid, error)) if tids.len() == 1 => {
+                Err(error.context(format!("Spawned thread {tid} trapped")))
+            }
+            Some((tid, error)) => Err(error.context(format!(
+                "Spawned threads {tids:?} trapped, the first error is the one of thread {tid}"
+            ))),
+        }
+    }
+
+    /// Creates a store for running the module of this provider.
+    ///
+    /// The store traps as soon as the execution is interrupted.
+    pub fn create_store(&self, data: T) -> Store<T> {
+        let mut store = Store::new(self.engine(), data);
+        store.set_epoch_deadline(1);
+        store
     }
 
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        InterruptHandle {
+            engine: self.engine().clone(),
+            interrupted: self.interrupted.clone(),
+            memory: self.memory.clone(),
+        }
+    }
+}
+
+impl<T: 'static> StandaloneCtxProvider<T> {
+    /// Hands the linker of the main instance to the provider, which uses it to
+    /// instantiate spawned threads and runners.
+    ///
+    /// With [`StandaloneCtxProvider::with_preinstantiation`], the imports of the module
+    /// are resolved once here instead of whenever a thread is spawned.
     pub fn finalize(&self, linker: Linker<T>) -> Result<(), Error> {
+        if self.preinstantiate {
+            let instance_pre = linker.instantiate_pre(&self.module)?;
+            if self.instance_pre.set(instance_pre).is_err() {
+                bail!("Linker has already been set for this provider!");
+            }
+        }
+
         self.linker
             .set(linker)
             .map_err(|_| anyhow!("Linker has already been set for this provider!"))
@@ -77,17 +547,117 @@ impl<T> StandaloneCtxProvider<T> {
 }
 
 impl<T: StandaloneView + Clone + 'static> StandaloneCtxProvider<T> {
+    /// The number of pages of the shared memory reserved for the stack of each runner
+    pub const RUNNER_STACK_PAGES: u64 = 16;
+
+    /// Invokes the export `function` on a new instance that runs on the current host thread.
+    ///
+    /// Unlike the main instance, whose store can only be used by one host thread at a time,
+    /// runners allow invoking exports concurrently from several host threads. Like a thread
+    /// spawned by the guest, each runner shares the memory of the main instance but receives
+    /// its own TID, stack and TLS block. The memory is grown by
+    /// [`StandaloneCtxProvider::RUNNER_STACK_PAGES`] pages plus the TLS block for every
+    /// runner that runs concurrently to the others, and reused by later runners.
+    ///
+    /// Requires the main instance to be created and the linker to be finalized before.
+    pub fn invoke_runner<Params: WasmParams, Results: WasmResults>(
+        &self,
+        store: &mut Store<T>,
+        function: &str,
+        params: Params,
+    ) -> Result<Results, Error> {
+        let linker = self
+            .linker
+            .get()
+            .ok_or(anyhow!("Runners require the linker to be finalized"))?;
+        let stack = match self
+            .runner_stacks
+            .lock()
+            .expect("Could not lock runner stacks!")
+            .pop()
+        {
+            Some(stack) => stack,
+            None => self.grow_runner_stack()?,
+        };
+        let ctx: WasmgrindStandaloneCtx = store.data().ctx().clone();
+        let tid = ctx.next_available_tid();
+
+        let invoke = || {
+            let instance = match self.instance_pre.get() {
+                Some(instance_pre) => instance_pre.instantiate(&mut *store)?,
+                None => linker.instantiate(&mut *store, &self.module)?,
+            };
+            instance
+                .get_typed_func::<(u32, u32, u32), ()>(&mut *store, "__wasmgrind_runner_entry")?
+                .call(&mut *store, (stack.stack_ptr, stack.tls_base, tid))?;
+            instance
+                .get_typed_func::<Params, Results>(&mut *store, function)
+                .with_context(|| format!("No function export named '{function}'"))?
+                .call(&mut *store, params)
+        };
+        let results = invoke();
+
+        ctx.release_tid(tid);
+        self.runner_stacks
+            .lock()
+            .expect("Could not lock runner stacks!")
+            .push(stack);
+        results
+    }
+
+    /// Grows the shared memory by the stack and TLS block of a runner.
+    fn grow_runner_stack(&self) -> Result<RunnerStack, Error> {
+        const PAGE_SIZE: u64 = 1 << 16;
+
+        let memory = self
+            .memory
+            .get()
+            .ok_or(anyhow!("Runners require the shared memory to be created"))?;
+        let tls_align = u64::from(self.tls_align.max(1));
+        let tls_pages = (u64::from(self.tls_size) + tls_align).div_ceil(PAGE_SIZE);
+        let base = memory
+            .grow(Self::RUNNER_STACK_PAGES + tls_pages)
+            .context("Could not grow the shared memory by the stack of a runner")?
+            * PAGE_SIZE;
+
+        // The stack grows downwards from the TLS block
+        let stack_ptr = base + Self::RUNNER_STACK_PAGES * PAGE_SIZE;
+        Ok(RunnerStack {
+            stack_ptr: u32::try_from(stack_ptr)?,
+            tls_base: u32::try_from(stack_ptr.next_multiple_of(tls_align))?,
+        })
+    }
+
     pub fn add_to_linker(
         &self,
         linker: &mut Linker<T>,
         store: impl AsContext<Data = T>,
     ) -> Result<(), Error> {
         let closure_linker = self.linker.clone();
+        let closure_instance_pre = self.instance_pre.clone();
+        let on_wasm_exit = self.on_wasm_exit.clone();
         let memory = SharedMemory::new(
             self.module.engine(),
             MemoryType::shared(self.memory_min, self.memory_max),
         )?;
 
+        // Data segments that have been removed from the module must only be
+        // written once, i.e., before any instance is created.
+        for segment in &self.data_segments {
+            write_to_memory(&memory, usize::try_from(segment.offset)?, &segment.data)
+                .with_context(|| {
+                    format!(
+                        "Data segment at {:#x} of length {} exceeds the initial memory",
+                        segment.offset,
+                        segment.data.len()
+                    )
+                })?;
+        }
+
+        if self.memory.set(memory.clone()).is_err() {
+            log::warn!("The shared memory has already been created for this provider!");
+        }
+
         linker
             .define(
                 store,
@@ -105,6 +675,7 @@ impl<T: StandaloneView + Clone + 'static> StandaloneCtxProvider<T> {
                       start_fn_ptr: u32,
                       start_fn_arg: u32| {
                     const GENERIC_ERROR_CODE: i32 = -1;
+                    const SHUTDOWN_ERROR_CODE: i32 = -2;
                     let data = caller.data().clone();
                     let ctx = data.ctx();
                     let linker = closure_linker.get().expect("Linker was not initialized!");
@@ -121,49 +692,82 @@ impl<T: StandaloneView + Clone + 'static> StandaloneCtxProvider<T> {
 
                     let engine = caller.engine();
                     let mut store = wasmtime::Store::new(engine, data.clone());
-                    let instance = match linker.instantiate(&mut store, &ctx.module) {
-                        Ok(instance) => instance,
-                        Err(_) => return GENERIC_ERROR_CODE,
-                    };
+                    store.set_epoch_deadline(1);
+                    if ctx.is_interrupted() {
+                        return GENERIC_ERROR_CODE;
+                    }
+                    if ctx.is_shutting_down() {
+                        return SHUTDOWN_ERROR_CODE;
+                    }
 
-                    let instance_entry = match instance.get_typed_func::<(u32, u32, u32, u32), ()>(
-                        &mut store,
-                        "__wasmgrind_instance_entry",
-                    ) {
-                        Ok(instance_entry) => instance_entry,
-                        Err(_) => return GENERIC_ERROR_CODE,
+                    // No lock of the runtime is held while waiting, such that
+                    // running threads are able to terminate and release their slots.
+                    let Some(slot) = ctx
+                        .threads
+                        .acquire_slot(|| ctx.is_interrupted() || ctx.is_shutting_down())
+                    else {
+                        return if ctx.is_shutting_down() {
+                            SHUTDOWN_ERROR_CODE
+                        } else {
+                            GENERIC_ERROR_CODE
+                        };
                     };
 
                     let tid = ctx.next_available_tid();
-                    let tid_ptr = match usize::try_from(tid_ptr) {
-                        Ok(tid_ptr) => tid_ptr,
-                        Err(_) => {
+                    let instance = match closure_instance_pre.get() {
+                        Some(instance_pre) => instance_pre.instantiate(&mut store),
+                        None => linker.instantiate(&mut store, &ctx.module),
+                    };
+                    let instance_entry = instance
+                        .and_then(|instance| {
+                            instance.get_typed_func::<(u32, u32, u32, u32, u32), ()>(
+                                &mut store,
+                                "__wasmgrind_instance_entry",
+                            )
+                        })
+                        .and_then(|instance_entry| {
+                            write_to_memory(&memory, tid_ptr as usize, &tid.to_le_bytes())
+                                .map(|()| instance_entry)
+                        });
+                    let instance_entry = match instance_entry {
+                        Ok(instance_entry) => instance_entry,
+                        Err(e) => {
+                            ctx.report_thread_error(tid, &e);
+                            ctx.release_thread_tid(tid);
                             return GENERIC_ERROR_CODE;
                         }
                     };
 
-                    if memory.data()[tid_ptr..].len() < std::mem::size_of::<u32>() {
-                        return GENERIC_ERROR_CODE;
-                    } else {
-                        unsafe {
-                            let native_tid_ptr = memory.data().as_ptr().add(tid_ptr);
-                            std::ptr::write(native_tid_ptr.cast::<u32>().cast_mut(), tid);
-                        };
-                    }
-
-                    std::thread::spawn(move || {
-                        let panic_msg = format!("Child {tid} trapped!");
-                        instance_entry
-                            .call(
-                                &mut store,
-                                (start_fn_ptr, start_fn_arg, stack_ptr, tls_base_ptr),
-                            )
-                            .expect(&panic_msg);
+                    let handle = std::thread::spawn(move || {
+                        match instance_entry.call(
+                            &mut store,
+                            (start_fn_ptr, start_fn_arg, stack_ptr, tls_base_ptr, tid),
+                        ) {
+                            Ok(()) => {}
+                            Err(e) if e.downcast_ref::<Trap>() == Some(&Trap::Interrupt) => {
+                                log::debug!("Child {tid} was interrupted.");
+                            }
+                            Err(e) => {
+                                let ctx = store.data().ctx();
+                                ctx.report_thread_error(tid, &e);
+                                ctx.threads.record_error(tid, e);
+                            }
+                        }
+                        drop(slot);
                     });
+                    ctx.threads.register(tid, handle);
 
                     0
                 },
             )?
+            .func_wrap(
+                WasmgrindStandaloneCtx::MODULE_NAME,
+                "thread_is_finished",
+                |caller: Caller<'_, T>, tid: u32| match caller.data().ctx().join_thread(tid) {
+                    Some(finished) => finished as i32,
+                    None => -1,
+                },
+            )?
             .func_wrap(
                 WasmgrindStandaloneCtx::MODULE_NAME,
                 "get_tls_size",
@@ -177,18 +781,898 @@ impl<T: StandaloneView + Clone + 'static> StandaloneCtxProvider<T> {
             .func_wrap(
                 WasmgrindStandaloneCtx::MODULE_NAME,
                 "exit",
-                |_: Caller<'_, T>, exit_code: i32| {
-                    panic!("Raw Error Code: {}", exit_code);
-
-                    // We need this here to make the type checker happy.
-                    // A non returning function does not implement any wasm-type
-                    // as expected by wasmtime, so we have to return something
-                    // although we know this function will not resume execution.
-                    #[allow(unreachable_code)]
-                    ()
+                move |_: Caller<'_, T>, exit_code: i32| -> Result<(), Error> {
+                    let Some(on_wasm_exit) = &on_wasm_exit else {
+                        panic!("Raw Error Code: {}", exit_code);
+                    };
+
+                    on_wasm_exit(exit_code);
+                    bail!("Module exited with raw error code {exit_code}")
                 },
             )?;
 
         Ok(())
     }
 }
+
+/// Copies `data` into the shared `memory` at `address`.
+///
+/// Fails without writing anything if the range exceeds the current size of the memory.
+fn write_to_memory(memory: &SharedMemory, address: usize, data: &[u8]) -> Result<(), Error> {
+    let Some(cells) = address
+        .checked_add(data.len())
+        .and_then(|end| memory.data().get(address..end))
+    else {
+        bail!(
+            "Writing {} bytes at {address:#x} exceeds the memory of {} bytes",
+            data.len(),
+            memory.data().len()
+        );
+    };
+
+    // Other threads may access the memory concurrently, so every store is atomic.
+    // Aligned words, such as TIDs, are written at once.
+    if let Ok(word) = <[u8; 4]>::try_from(data)
+        && address.is_multiple_of(std::mem::align_of::<u32>())
+    {
+        // SAFETY: `cells` covers `address..address + 4`, which lies within the memory
+        // as checked above. The base of a linear memory is page aligned, hence the
+        // pointer is aligned for a `u32` as `address` is.
+        let cell = unsafe { AtomicU32::from_ptr(cells[0].get().cast::<u32>()) };
+        cell.store(u32::from_ne_bytes(word), Ordering::SeqCst);
+        return Ok(());
+    }
+
+    for (cell, byte) in cells.iter().zip(data) {
+        // SAFETY: Each cell lies within the memory as checked above, and a `u8` has no
+        // alignment requirements.
+        let cell = unsafe { AtomicU8::from_ptr(cell.get()) };
+        cell.store(*byte, Ordering::SeqCst);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            Arc, Mutex, OnceLock,
+            atomic::{AtomicBool, AtomicU32, Ordering},
+        },
+        time::{Duration, Instant},
+    };
+
+    use anyhow::Error;
+    use walrus::{
+        FunctionBuilder, ValType,
+        ir::{AtomicOp, AtomicWidth, BinaryOp, LoadKind, MemArg},
+    };
+    use wasmtime::{
+        Config, Engine, Instance, Linker, MemoryType, Module, OptLevel, SharedMemory, Store, Trap,
+    };
+
+    use wasmgrind_core::{
+        instrumentation::InstrumentOptions,
+        testing::{AbiModule, abi_module},
+    };
+
+    use super::{InterruptHandle, StandaloneCtxProvider, write_to_memory};
+    use crate::standalone::ctx::{
+        RuntimeOptions, ThreadRegistry, ThreadState, WasmgrindStandaloneCtx,
+    };
+
+    fn spinning_module(engine: &Engine) -> Result<Module, Error> {
+        let mut module = walrus::Module::default();
+        let mut builder = FunctionBuilder::new(&mut module.types, &[], &[]);
+        builder.func_body().loop_(None, |body| {
+            let id = body.id();
+            body.br(id);
+        });
+        let spin = builder.finish(vec![], &mut module.funcs);
+        module.exports.add("spin", spin);
+
+        Module::from_binary(engine, &module.emit_wasm())
+    }
+
+    #[test]
+    fn interrupt_after_deadline() -> Result<(), Error> {
+        let mut config = Config::new();
+        config.epoch_interruption(true);
+        let engine = Engine::new(&config)?;
+        let module = spinning_module(&engine)?;
+
+        let handle = InterruptHandle {
+            engine: engine.clone(),
+            interrupted: Arc::new(AtomicBool::new(false)),
+            memory: Arc::new(OnceLock::new()),
+        };
+
+        let mut store = Store::new(&engine, ());
+        store.set_epoch_deadline(1);
+        let instance = Instance::new(&mut store, &module, &[])?;
+        let spin = instance.get_typed_func::<(), ()>(&mut store, "spin")?;
+
+        let start = Instant::now();
+        let _deadline = handle.interrupt_after(Duration::from_millis(50));
+        let err = spin.call(&mut store, ()).unwrap_err();
+        assert_eq!(err.downcast_ref::<Trap>(), Some(&Trap::Interrupt));
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        // Dropping a deadline cancels the interruption
+        let handle = InterruptHandle {
+            engine: engine.clone(),
+            interrupted: Arc::new(AtomicBool::new(false)),
+            memory: Arc::new(OnceLock::new()),
+        };
+        drop(handle.interrupt_after(Duration::from_millis(10)));
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!handle.interrupted.load(Ordering::SeqCst));
+
+        Ok(())
+    }
+
+    #[test]
+    fn interrupt_waiting_instance() -> Result<(), Error> {
+        let mut config = Config::new();
+        config.epoch_interruption(true).wasm_threads(true);
+        let engine = Engine::new(&config)?;
+
+        let mut module = walrus::Module::default();
+        let (memory, _) = module.add_import_memory("env", "memory", true, false, 1, Some(1), None);
+        let mut builder = FunctionBuilder::new(&mut module.types, &[], &[]);
+        builder.func_body().loop_(None, |body| {
+            let id = body.id();
+            body.i32_const(0)
+                .i32_const(0)
+                .i64_const(-1)
+                .atomic_wait(
+                    memory,
+                    MemArg {
+                        align: 4,
+                        offset: 0,
+                    },
+                    false,
+                )
+                .drop()
+                .br(id);
+        });
+        let wait = builder.finish(vec![], &mut module.funcs);
+        module.exports.add("wait", wait);
+        let module = Module::from_binary(&engine, &module.emit_wasm())?;
+
+        let shared_memory = SharedMemory::new(&engine, MemoryType::shared(1, 1))?;
+        let handle = InterruptHandle {
+            engine: engine.clone(),
+            interrupted: Arc::new(AtomicBool::new(false)),
+            memory: Arc::new(OnceLock::from(shared_memory.clone())),
+        };
+
+        let mut store = Store::new(&engine, ());
+        store.set_epoch_deadline(1);
+        let instance = Instance::new(&mut store, &module, &[shared_memory.into()])?;
+        let wait = instance.get_typed_func::<(), ()>(&mut store, "wait")?;
+
+        // The waiter may not be blocked yet when it is woken up the first time
+        let trapped = Arc::new(AtomicBool::new(false));
+        let interrupter = (handle.clone(), trapped.clone());
+        std::thread::spawn(move || {
+            let (handle, trapped) = interrupter;
+            while !trapped.load(Ordering::SeqCst) {
+                std::thread::sleep(Duration::from_millis(50));
+                handle.interrupt_waiters(&[0]);
+            }
+        });
+
+        let err = wait.call(&mut store, ()).unwrap_err();
+        trapped.store(true, Ordering::SeqCst);
+        assert_eq!(err.downcast_ref::<Trap>(), Some(&Trap::Interrupt));
+
+        Ok(())
+    }
+
+    fn example_ctx(recycle_tids: bool) -> Result<WasmgrindStandaloneCtx, Error> {
+        let engine = Engine::default();
+        let module = Module::from_binary(&engine, &walrus::Module::default().emit_wasm())?;
+
+        Ok(WasmgrindStandaloneCtx {
+            module,
+            tls_size: 0,
+            tls_align: 0,
+            next_tid: Arc::new(AtomicU32::new(WasmgrindStandaloneCtx::MAIN_TID + 1)),
+            free_tids: recycle_tids.then(|| Arc::new(Mutex::new(Vec::new()))),
+            interrupted: Arc::new(AtomicBool::new(false)),
+            shutting_down: Arc::new(AtomicBool::new(false)),
+            threads: ThreadRegistry::new(),
+            on_thread_error: None,
+        })
+    }
+
+    #[test]
+    fn recycle_released_tids() -> Result<(), Error> {
+        let ctx = example_ctx(true)?;
+        assert_eq!(ctx.next_available_tid(), 1);
+        assert_eq!(ctx.next_available_tid(), 2);
+
+        ctx.release_tid(1);
+        assert_eq!(ctx.next_available_tid(), 1);
+        assert_eq!(ctx.next_available_tid(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn recycle_tids_of_joined_threads() -> Result<(), Error> {
+        let ctx = example_ctx(true)?;
+        let tid = ctx.next_available_tid();
+        ctx.threads.register(tid, std::thread::spawn(|| {}));
+        while ctx.is_thread_finished(tid) != Some(true) {
+            std::thread::yield_now();
+        }
+
+        // The TID is only reused once the guest has joined the thread
+        let other = ctx.next_available_tid();
+        assert_ne!(other, tid);
+        ctx.release_tid(other);
+        assert_eq!(ctx.join_thread(tid), Some(true));
+        assert_eq!(ctx.join_thread(tid), Some(true));
+        let mut reused = [ctx.next_available_tid(), ctx.next_available_tid()];
+        reused.sort_unstable();
+        assert_eq!(reused, [tid, other]);
+        assert_eq!(ctx.next_available_tid(), other + 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn keep_tids_unique_by_default() -> Result<(), Error> {
+        let ctx = example_ctx(false)?;
+        assert_eq!(ctx.next_available_tid(), 1);
+
+        ctx.release_tid(1);
+        assert_eq!(ctx.next_available_tid(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn query_finished_threads() -> Result<(), Error> {
+        let ctx = example_ctx(false)?;
+        let main_tid = WasmgrindStandaloneCtx::MAIN_TID;
+        let tid = ctx.next_available_tid();
+        assert_eq!(ctx.is_thread_finished(main_tid), Some(false));
+        // The TID of a thread that is being started is not finished either
+        assert_eq!(ctx.is_thread_finished(tid), Some(false));
+
+        let (sender, receiver) = std::sync::mpsc::channel::<()>();
+        ctx.threads.register(
+            tid,
+            std::thread::spawn(move || {
+                let _ = receiver.recv();
+            }),
+        );
+        assert_eq!(ctx.is_thread_finished(tid), Some(false));
+
+        drop(sender);
+        while ctx.is_thread_finished(tid) != Some(true) {
+            std::thread::yield_now();
+        }
+
+        assert!(ctx.threads.join_all().is_empty());
+        assert_eq!(ctx.is_thread_finished(tid), Some(true));
+        assert_eq!(ctx.is_thread_finished(main_tid), Some(false));
+        assert_eq!(ctx.is_thread_finished(tid + 1), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn fail_on_disabled_threads() {
+        let mut module = walrus::Module::default();
+        let wasm = module.emit_wasm();
+
+        let mut config = Config::new();
+        config.wasm_threads(false);
+
+        let err = StandaloneCtxProvider::<()>::with_engine_config(&wasm, &config)
+            .err()
+            .expect("Engine without threads support was accepted");
+        assert!(err.to_string().contains("threads proposal"));
+    }
+
+    /// Creates a module whose `run` export spawns `threads` threads, which
+    /// increment a counter that is returned by its `count` export.
+    ///
+    /// `run` takes the address where the TIDs are stored and returns the
+    /// result of the last spawn.
+    fn spawning_module(threads: i32) -> walrus::Module {
+        let AbiModule {
+            mut module,
+            memory,
+            thread_start,
+        } = abi_module();
+        let counter = MemArg {
+            align: 4,
+            offset: 0,
+        };
+
+        let clone_instance_ty = module.types.add(&[ValType::I32; 5], &[ValType::I32]);
+        let (clone_instance, _) = module.add_import_func(
+            WasmgrindStandaloneCtx::MODULE_NAME,
+            "clone_instance",
+            clone_instance_ty,
+        );
+
+        module
+            .funcs
+            .get_mut(thread_start)
+            .kind
+            .unwrap_local_mut()
+            .builder_mut()
+            .func_body()
+            .i32_const(0)
+            .i32_const(1)
+            .atomic_rmw(memory, AtomicOp::Add, AtomicWidth::I32, counter)
+            .drop();
+
+        let mut run = FunctionBuilder::new(&mut module.types, &[ValType::I32], &[ValType::I32]);
+        let tid_ptr = module.locals.add(ValType::I32);
+        let result = module.locals.add(ValType::I32);
+        let i = module.locals.add(ValType::I32);
+        run.func_body()
+            .loop_(None, |body| {
+                let id = body.id();
+                body.i32_const(0)
+                    .i32_const(0)
+                    .local_get(tid_ptr)
+                    .i32_const(0)
+                    .i32_const(0)
+                    .call(clone_instance)
+                    .local_set(result)
+                    .local_get(i)
+                    .i32_const(1)
+                    .binop(BinaryOp::I32Add)
+                    .local_tee(i)
+                    .i32_const(threads)
+                    .binop(BinaryOp::I32LtS)
+                    .br_if(id);
+            })
+            .local_get(result);
+        let run = run.finish(vec![tid_ptr], &mut module.funcs);
+        module.exports.add("run", run);
+
+        let mut count = FunctionBuilder::new(&mut module.types, &[], &[ValType::I32]);
+        count
+            .func_body()
+            .i32_const(0)
+            .load(memory, LoadKind::I32 { atomic: true }, counter);
+        let count = count.finish(vec![], &mut module.funcs);
+        module.exports.add("count", count);
+
+        module
+    }
+
+    /// Creates a module like [`spawning_module`], whose spawned threads spin forever.
+    fn spinning_thread_module() -> Result<walrus::Module, Error> {
+        let mut module = spawning_module(1);
+        let thread_start = module.exports.get_func("__wasmgrind_thread_start")?;
+        module
+            .funcs
+            .get_mut(thread_start)
+            .kind
+            .unwrap_local_mut()
+            .builder_mut()
+            .func_body()
+            .loop_(None, |body| {
+                let id = body.id();
+                body.br(id);
+            });
+
+        Ok(module)
+    }
+
+    #[test]
+    fn interrupt_running_instance() -> Result<(), Error> {
+        let wasm = spinning_thread_module()?.emit_wasm();
+        let (provider, _) = StandaloneCtxProvider::with_engine_config(&wasm, &Config::new())?;
+
+        let mut linker = Linker::new(provider.engine());
+        let mut store = provider.create_store(provider.create_ctx());
+        provider.add_to_linker(&mut linker, &store)?;
+        let instance = linker.instantiate(&mut store, provider.module())?;
+        provider.finalize(linker)?;
+
+        let run = instance.get_typed_func::<i32, i32>(&mut store, "run")?;
+        assert_eq!(run.call(&mut store, 4)?, 0);
+        let tids = provider.running_threads();
+        assert_eq!(tids.len(), 1);
+
+        let interrupter = provider.interrupt_handle();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            interrupter.interrupt();
+        });
+
+        // Interrupted threads do not count as failed
+        provider.shutdown_timeout(Duration::from_secs(10))?;
+        assert!(store.data().is_interrupted());
+        assert_eq!(provider.thread_state(tids[0]), Some(ThreadState::Joined));
+
+        // The main instance traps as soon as it is entered again
+        let err = run.call(&mut store, 4).unwrap_err();
+        assert_eq!(err.downcast_ref::<Trap>(), Some(&Trap::Interrupt));
+
+        Ok(())
+    }
+
+    #[test]
+    fn interrupt_spawned_thread_after_deadline() -> Result<(), Error> {
+        let wasm = spinning_thread_module()?.emit_wasm();
+        let (provider, _) = StandaloneCtxProvider::with_engine_config(&wasm, &Config::new())?;
+        let provider = provider.with_deadline(Duration::from_millis(50));
+
+        let mut linker = Linker::new(provider.engine());
+        let mut store = provider.create_store(provider.create_ctx());
+        provider.add_to_linker(&mut linker, &store)?;
+        let instance = linker.instantiate(&mut store, provider.module())?;
+        provider.finalize(linker)?;
+
+        let start = Instant::now();
+        let _deadline = provider
+            .deadline()
+            .map(|timeout| provider.interrupt_handle().interrupt_after(timeout));
+        let run = instance.get_typed_func::<i32, i32>(&mut store, "run")?;
+        assert_eq!(run.call(&mut store, 4)?, 0);
+        let tids = provider.running_threads();
+        assert_eq!(tids.len(), 1);
+
+        // The spinning thread only terminates once the deadline has passed
+        provider.shutdown_timeout(Duration::from_secs(10))?;
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(provider.thread_state(tids[0]), Some(ThreadState::Joined));
+
+        // The main instance traps as well after the deadline
+        let err = run.call(&mut store, 4).unwrap_err();
+        assert_eq!(err.downcast_ref::<Trap>(), Some(&Trap::Interrupt));
+
+        Ok(())
+    }
+
+    #[test]
+    fn complete_with_bounded_threads() -> Result<(), Error> {
+        let wasm = spawning_module(200).emit_wasm();
+        let (provider, _) = StandaloneCtxProvider::with_engine_config(&wasm, &Config::new())?;
+        let provider = provider.with_max_threads(8);
+
+        let mut linker = Linker::new(provider.engine());
+        let mut store = provider.create_store(provider.create_ctx());
+        provider.add_to_linker(&mut linker, &store)?;
+        let instance = linker.instantiate(&mut store, provider.module())?;
+        provider.finalize(linker)?;
+
+        let result = instance
+            .get_typed_func::<i32, i32>(&mut store, "run")?
+            .call(&mut store, 4)?;
+        assert_eq!(result, 0);
+        let peak = provider.peak_thread_count();
+        assert!(matches!(peak, Some(1..=8)), "{peak:?} threads ran at once");
+        provider.shutdown()?;
+
+        let count = instance
+            .get_typed_func::<(), i32>(&mut store, "count")?
+            .call(&mut store, ())?;
+        assert_eq!(count, 200);
+
+        Ok(())
+    }
+
+    #[test]
+    fn spawn_preinstantiated_threads() -> Result<(), Error> {
+        let wasm = spawning_module(200).emit_wasm();
+
+        for preinstantiate in [false, true] {
+            let (provider, _) = StandaloneCtxProvider::with_engine_config(&wasm, &Config::new())?;
+            let provider = provider
+                .with_max_threads(8)
+                .with_preinstantiation(preinstantiate);
+
+            let mut linker = Linker::new(provider.engine());
+            let mut store = provider.create_store(provider.create_ctx());
+            provider.add_to_linker(&mut linker, &store)?;
+            let instance = linker.instantiate(&mut store, provider.module())?;
+            provider.finalize(linker)?;
+            assert_eq!(provider.instance_pre.get().is_some(), preinstantiate);
+
+            let result = instance
+                .get_typed_func::<i32, i32>(&mut store, "run")?
+                .call(&mut store, 4)?;
+            provider.shutdown()?;
+            assert_eq!(result, 0);
+
+            let count = instance
+                .get_typed_func::<(), i32>(&mut store, "count")?
+                .call(&mut store, ())?;
+            assert_eq!(count, 200);
+
+            // Pre-instantiation resolves the imports once when finalizing, whereas
+            // a linker without the imports only fails to spawn threads otherwise
+            let (provider, _) =
+                StandaloneCtxProvider::<()>::with_engine_config(&wasm, &Config::new())?;
+            let provider = provider.with_preinstantiation(preinstantiate);
+            let finalized = provider.finalize(Linker::new(provider.engine()));
+            assert_eq!(finalized.is_err(), preinstantiate);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn run_with_runtime_options() -> Result<(), Error> {
+        let options = RuntimeOptions {
+            pooling_allocator: Some(16),
+            parallel_compilation: false,
+            opt_level: OptLevel::None,
+        };
+        let wasm = spawning_module(20).emit_wasm();
+        let (provider, _) = StandaloneCtxProvider::with_engine_config(&wasm, &options.to_config())?;
+        let provider = provider.with_max_threads(4);
+
+        let mut linker = Linker::new(provider.engine());
+        let mut store = provider.create_store(provider.create_ctx());
+        provider.add_to_linker(&mut linker, &store)?;
+        let instance = linker.instantiate(&mut store, provider.module())?;
+        provider.finalize(linker)?;
+
+        let result = instance
+            .get_typed_func::<i32, i32>(&mut store, "run")?
+            .call(&mut store, 4)?;
+        assert_eq!(result, 0);
+        provider.shutdown()?;
+
+        let count = instance
+            .get_typed_func::<(), i32>(&mut store, "count")?
+            .call(&mut store, ())?;
+        assert_eq!(count, 20);
+
+        Ok(())
+    }
+
+    #[test]
+    fn return_patch_artifacts() -> Result<(), Error> {
+        let wasm = spawning_module(1).emit_wasm();
+        let engine = Engine::new(&Config::new())?;
+
+        let (_, artifacts) =
+            StandaloneCtxProvider::<()>::from_binary_with_artifacts(&engine, &wasm, None)?;
+        assert!(artifacts.instrumented.is_none());
+        let patched = walrus::Module::from_buffer(&artifacts.patched)?;
+        assert!(
+            patched
+                .exports
+                .get_func("__wasmgrind_instance_entry")
+                .is_ok()
+        );
+
+        let (_, artifacts) = StandaloneCtxProvider::<()>::from_binary_with_artifacts(
+            &engine,
+            &wasm,
+            Some(&InstrumentOptions::default()),
+        )?;
+        let instrumented = walrus::Module::from_buffer(
+            artifacts
+                .instrumented
+                .as_deref()
+                .expect("Instrumented binary is missing"),
+        )?;
+        assert!(
+            instrumented
+                .imports
+                .iter()
+                .any(|import| import.module == "wasmgrind_tracing")
+        );
+        // The instrumented binary is returned before it is patched
+        assert!(
+            instrumented
+                .exports
+                .get_func("__wasmgrind_instance_entry")
+                .is_err()
+        );
+        let patched = walrus::Module::from_buffer(&artifacts.patched)?;
+        assert!(
+            patched
+                .exports
+                .get_func("__wasmgrind_instance_entry")
+                .is_ok()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn query_memory_limits() -> Result<(), Error> {
+        let wasm = spawning_module(1).emit_wasm();
+        let (provider, _) = StandaloneCtxProvider::with_engine_config(&wasm, &Config::new())?;
+
+        let declared = provider
+            .module()
+            .imports()
+            .find_map(|import| import.ty().memory().cloned())
+            .expect("Patched module does not import a memory");
+        let (min, max) = provider.memory_limits();
+        assert_eq!(u64::from(min), declared.minimum());
+        assert_eq!(Some(u64::from(max)), declared.maximum());
+        assert_eq!(provider.current_memory_pages(), None);
+
+        let mut linker = Linker::new(provider.engine());
+        let store = provider.create_store(provider.create_ctx());
+        provider.add_to_linker(&mut linker, &store)?;
+        assert_eq!(provider.current_memory_pages(), Some(u64::from(min)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn grow_into_memory_headroom() -> Result<(), Error> {
+        let engine = Engine::new(&Config::new())?;
+        let create_instance = |headroom_pages| -> Result<_, Error> {
+            let mut module = spawning_module(1);
+            let memory = module.memories.iter_mut().next().unwrap();
+            memory.maximum = Some(memory.initial);
+            let memory = memory.id();
+
+            let mut grow =
+                FunctionBuilder::new(&mut module.types, &[ValType::I32], &[ValType::I32]);
+            let pages = module.locals.add(ValType::I32);
+            grow.func_body().local_get(pages).memory_grow(memory);
+            let grow = grow.finish(vec![pages], &mut module.funcs);
+            module.exports.add("grow", grow);
+
+            let provider = StandaloneCtxProvider::from_walrus_with_headroom(
+                &engine,
+                &mut module,
+                None,
+                headroom_pages,
+            )?;
+            let mut linker = Linker::new(provider.engine());
+            let mut store = provider.create_store(provider.create_ctx());
+            provider.add_to_linker(&mut linker, &store)?;
+            let instance = linker.instantiate(&mut store, provider.module())?;
+            let grow = instance.get_typed_func::<i32, i32>(&mut store, "grow")?;
+            Ok((provider, store, grow))
+        };
+
+        let (provider, mut store, grow) = create_instance(0)?;
+        assert_eq!(provider.memory_limits(), (1, 1));
+        assert_eq!(grow.call(&mut store, 1)?, -1);
+
+        let (provider, mut store, grow) = create_instance(2)?;
+        assert_eq!(provider.memory_limits(), (1, 3));
+        assert_eq!(grow.call(&mut store, 2)?, 1);
+        assert_eq!(provider.current_memory_pages(), Some(3));
+        assert_eq!(grow.call(&mut store, 1)?, -1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn expose_thread_ids() -> Result<(), Error> {
+        let wasm = spawning_module(1).emit_wasm();
+        let (provider, _) = StandaloneCtxProvider::with_engine_config(&wasm, &Config::new())?;
+
+        let mut linker = Linker::new(provider.engine());
+        let mut store = provider.create_store(provider.create_ctx());
+        provider.add_to_linker(&mut linker, &store)?;
+        let instance = linker.instantiate(&mut store, provider.module())?;
+        provider.finalize(linker)?;
+
+        let thread_id = instance.get_typed_func::<(), u32>(&mut store, "__wasmgrind_thread_id")?;
+        assert_eq!(
+            thread_id.call(&mut store, ())?,
+            WasmgrindStandaloneCtx::MAIN_TID
+        );
+        // The TID of the main instance is never handed out to spawned threads
+        assert_ne!(
+            store.data().next_available_tid(),
+            WasmgrindStandaloneCtx::MAIN_TID
+        );
+
+        // Spawned instances are entered with the TID assigned by the runtime
+        instance
+            .get_typed_func::<(u32, u32, u32, u32, u32), ()>(
+                &mut store,
+                "__wasmgrind_instance_entry",
+            )?
+            .call(&mut store, (0, 0, 1024, 0, 5))?;
+        assert_eq!(thread_id.call(&mut store, ())?, 5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_out_of_bounds_tid_pointer() -> Result<(), Error> {
+        const PAGE_SIZE: i32 = 0x10000;
+
+        let wasm = spawning_module(1).emit_wasm();
+        let (provider, _) = StandaloneCtxProvider::with_engine_config(&wasm, &Config::new())?;
+
+        let mut linker = Linker::new(provider.engine());
+        let mut store = provider.create_store(provider.create_ctx());
+        provider.add_to_linker(&mut linker, &store)?;
+        let instance = linker.instantiate(&mut store, provider.module())?;
+        provider.finalize(linker)?;
+
+        let run = instance.get_typed_func::<i32, i32>(&mut store, "run")?;
+        for tid_ptr in [PAGE_SIZE - 2, PAGE_SIZE, -1] {
+            assert_eq!(run.call(&mut store, tid_ptr)?, -1);
+        }
+        assert!(provider.thread_ids().is_empty());
+
+        // The runtime remains usable afterwards
+        assert_eq!(run.call(&mut store, PAGE_SIZE - 4)?, 0);
+        provider.shutdown()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn report_failed_threads() -> Result<(), Error> {
+        let wasm = spawning_module(1).emit_wasm();
+        let (provider, _) = StandaloneCtxProvider::with_engine_config(&wasm, &Config::new())?;
+        let failed = Arc::new(Mutex::new(Vec::new()));
+        let sink = failed.clone();
+        let provider = provider
+            .on_thread_error(move |tid, error| sink.lock().unwrap().push((tid, error.to_string())));
+
+        let mut linker = Linker::new(provider.engine());
+        let mut store = provider.create_store(provider.create_ctx());
+        provider.add_to_linker(&mut linker, &store)?;
+        let instance = linker.instantiate(&mut store, provider.module())?;
+        provider.finalize(linker)?;
+
+        let run = instance.get_typed_func::<i32, i32>(&mut store, "run")?;
+        assert_eq!(run.call(&mut store, -1)?, -1);
+
+        let failed = failed.lock().unwrap().clone();
+        assert_eq!(failed.len(), 1);
+        let (tid, _) = failed[0];
+        assert_eq!(provider.thread_state(tid), Some(ThreadState::Failed));
+        provider.shutdown()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn propagate_thread_traps() -> Result<(), Error> {
+        let mut module = spawning_module(1);
+        let thread_start = module
+            .exports
+            .iter()
+            .find_map(|export| match export.item {
+                walrus::ExportItem::Function(func) if export.name == "__wasmgrind_thread_start" => {
+                    Some(func)
+                }
+                _ => None,
+            })
+            .expect("Module does not export a thread start");
+        module
+            .funcs
+            .get_mut(thread_start)
+            .kind
+            .unwrap_local_mut()
+            .builder_mut()
+            .func_body()
+            .unreachable();
+
+        let (provider, _) =
+            StandaloneCtxProvider::with_engine_config(&module.emit_wasm(), &Config::new())?;
+        let mut linker = Linker::new(provider.engine());
+        let mut store = provider.create_store(provider.create_ctx());
+        provider.add_to_linker(&mut linker, &store)?;
+        let instance = linker.instantiate(&mut store, provider.module())?;
+        provider.finalize(linker)?;
+
+        let run = instance.get_typed_func::<i32, i32>(&mut store, "run")?;
+        assert_eq!(run.call(&mut store, 4)?, 0);
+
+        let err = provider.shutdown().unwrap_err();
+        assert_eq!(err.to_string(), "Spawned thread 1 trapped");
+        assert_eq!(
+            err.downcast_ref::<Trap>(),
+            Some(&Trap::UnreachableCodeReached)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn trap_on_hooked_exit() -> Result<(), Error> {
+        let mut module = spawning_module(1);
+        let exit_ty = module.types.add(&[ValType::I32], &[]);
+        let (exit, _) =
+            module.add_import_func(WasmgrindStandaloneCtx::MODULE_NAME, "exit", exit_ty);
+        let mut fail = FunctionBuilder::new(&mut module.types, &[], &[]);
+        fail.func_body().i32_const(42).call(exit);
+        let fail = fail.finish(vec![], &mut module.funcs);
+        module.exports.add("fail", fail);
+
+        let (provider, _) =
+            StandaloneCtxProvider::with_engine_config(&module.emit_wasm(), &Config::new())?;
+        let exit_codes = Arc::new(Mutex::new(Vec::new()));
+        let sink = exit_codes.clone();
+        let provider = provider.on_wasm_exit(move |code| sink.lock().unwrap().push(code));
+
+        let mut linker = Linker::new(provider.engine());
+        let mut store = provider.create_store(provider.create_ctx());
+        provider.add_to_linker(&mut linker, &store)?;
+        let instance = linker.instantiate(&mut store, provider.module())?;
+        provider.finalize(linker)?;
+
+        let err = instance
+            .get_typed_func::<(), ()>(&mut store, "fail")?
+            .call(&mut store, ())
+            .unwrap_err();
+        assert!(format!("{err:?}").contains("Module exited with raw error code 42"));
+        assert_eq!(*exit_codes.lock().unwrap(), vec![42]);
+
+        // The host and the store remain usable afterwards
+        let run = instance.get_typed_func::<i32, i32>(&mut store, "run")?;
+        assert_eq!(run.call(&mut store, 4)?, 0);
+        provider.shutdown()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn refuse_threads_after_shutdown() -> Result<(), Error> {
+        let wasm = spawning_module(1).emit_wasm();
+        let (provider, _) = StandaloneCtxProvider::with_engine_config(&wasm, &Config::new())?;
+
+        let mut linker = Linker::new(provider.engine());
+        let mut store = provider.create_store(provider.create_ctx());
+        provider.add_to_linker(&mut linker, &store)?;
+        let instance = linker.instantiate(&mut store, provider.module())?;
+        provider.finalize(linker)?;
+
+        let run = instance.get_typed_func::<i32, i32>(&mut store, "run")?;
+        assert_eq!(run.call(&mut store, 0)?, 0);
+        provider.shutdown_timeout(Duration::from_secs(10))?;
+        assert!(provider.running_threads().is_empty());
+
+        assert_eq!(run.call(&mut store, 0)?, -2);
+        assert!(provider.thread_ids().is_empty());
+        provider.shutdown()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn write_within_memory_bounds() -> Result<(), Error> {
+        let mut config = Config::new();
+        config.wasm_threads(true);
+        let engine = Engine::new(&config)?;
+        let memory = SharedMemory::new(&engine, MemoryType::shared(1, 1))?;
+        let read = |address: usize, len: usize| -> Vec<u8> {
+            memory.data()[address..address + len]
+                .iter()
+                .map(|cell| unsafe { *cell.get() })
+                .collect()
+        };
+        let end = memory.data().len();
+
+        write_to_memory(&memory, 8, &[1, 2, 3, 4])?;
+        assert_eq!(read(8, 4), [1, 2, 3, 4]);
+        write_to_memory(&memory, 13, &[5, 6, 7, 8])?;
+        assert_eq!(read(13, 4), [5, 6, 7, 8]);
+
+        write_to_memory(&memory, end - 4, &[9; 4])?;
+        assert_eq!(read(end - 4, 4), [9; 4]);
+        write_to_memory(&memory, end, &[])?;
+
+        assert!(write_to_memory(&memory, end - 2, &[10; 4]).is_err());
+        assert!(write_to_memory(&memory, end, &[10]).is_err());
+        assert!(write_to_memory(&memory, usize::MAX, &[10]).is_err());
+        assert_eq!(read(end - 4, 4), [9; 4]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,463 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        Arc, Condvar, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use anyhow::Error;
+
+/// Bounds the number of spawned threads that run at the same time.
+struct ThreadLimit {
+    max: usize,
+    running: Mutex<usize>,
+    /// The highest number of threads that have been running at the same time
+    peak: AtomicUsize,
+    released: Condvar,
+}
+
+/// Permission to run a spawned thread, which is given back when dropped.
+pub(crate) struct ThreadSlot {
+    limit: Option<Arc<ThreadLimit>>,
+}
+
+impl Drop for ThreadSlot {
+    fn drop(&mut self) {
+        if let Some(limit) = &self.limit {
+            *limit.running.lock().expect("Could not lock thread limit!") -= 1;
+            limit.released.notify_one();
+        }
+    }
+}
+
+/// The state of a spawned WebAssembly thread.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThreadState {
+    /// The thread is still executing
+    Running,
+    /// The thread has terminated but has not been joined by the runtime yet
+    Finished,
+    /// The thread has terminated and its OS thread has been joined
+    Joined,
+    /// The thread trapped or could not be started
+    Failed,
+}
+
+/// Keeps track of the OS threads backing spawned WebAssembly threads.
+///
+/// Spawned threads may outlive the function that was invoked on the main
+/// instance. The registry allows to wait until all of them have terminated.
+#[derive(Clone, Default)]
+pub(crate) struct ThreadRegistry {
+    handles: Arc<Mutex<HashMap<u32, JoinHandle<()>>>>,
+    joined: Arc<Mutex<HashSet<u32>>>,
+    failed: Arc<Mutex<HashSet<u32>>>,
+    /// The threads whose TIDs have been released for reuse
+    released: Arc<Mutex<HashSet<u32>>>,
+    /// The threads that panicked and have been joined before their TID was reused
+    panicked: Arc<Mutex<Vec<u32>>>,
+    /// The errors of threads that trapped, which have not been taken yet
+    errors: Arc<Mutex<Vec<(u32, Error)>>>,
+    limit: Option<Arc<ThreadLimit>>,
+}
+
+impl ThreadRegistry {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry that allows at most `max` spawned threads to run at the same time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero, as no thread could ever be spawned.
+    pub(crate) fn with_limit(max: usize) -> Self {
+        assert!(
+            max > 0,
+            "At least one spawned thread must be allowed to run!"
+        );
+        Self {
+            handles: Default::default(),
+            joined: Default::default(),
+            failed: Default::default(),
+            released: Default::default(),
+            panicked: Default::default(),
+            errors: Default::default(),
+            limit: Some(Arc::new(ThreadLimit {
+                max,
+                running: Mutex::new(0),
+                peak: AtomicUsize::new(0),
+                released: Condvar::new(),
+            })),
+        }
+    }
+
+    /// Waits until another spawned thread may run.
+    ///
+    /// The returned slot has to be kept alive until the spawned thread terminates.
+    /// Waiting is aborted once `cancelled` returns true, in which case `None` is returned.
+    pub(crate) fn acquire_slot(&self, cancelled: impl Fn() -> bool) -> Option<ThreadSlot> {
+        const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+        let Some(limit) = &self.limit else {
+            return Some(ThreadSlot { limit: None });
+        };
+
+        let mut running = limit.running.lock().expect("Could not lock thread limit!");
+        while *running >= limit.max {
+            if cancelled() {
+                return None;
+            }
+            running = limit
+                .released
+                .wait_timeout(running, POLL_INTERVAL)
+                .expect("Could not lock thread limit!")
+                .0;
+        }
+        *running += 1;
+        limit.peak.fetch_max(*running, Ordering::Relaxed);
+
+        Some(ThreadSlot {
+            limit: Some(limit.clone()),
+        })
+    }
+
+    /// Returns the highest number of spawned threads that have been running at the same time.
+    ///
+    /// Returns `None` if the number of running threads is not limited.
+    pub(crate) fn peak_running(&self) -> Option<usize> {
+        self.limit
+            .as_ref()
+            .map(|limit| limit.peak.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn register(&self, tid: u32, handle: JoinHandle<()>) {
+        for set in [&self.joined, &self.failed, &self.released] {
+            set.lock()
+                .expect("Could not lock thread registry!")
+                .remove(&tid);
+        }
+        let prev = self
+            .handles
+            .lock()
+            .expect("Could not lock thread registry!")
+            .insert(tid, handle);
+
+        // A TID is only reused after the guest has seen the previous thread
+        // terminate, so joining it does not block for long.
+        if let Some(prev) = prev
+            && prev.join().is_err()
+        {
+            log::error!("Spawned thread {tid} panicked before its TID was reused");
+            self.panicked
+                .lock()
+                .expect("Could not lock thread registry!")
+                .push(tid);
+        }
+    }
+
+    /// Marks the TID of the given thread as released and returns whether it has
+    /// not been released before.
+    pub(crate) fn release(&self, tid: u32) -> bool {
+        self.released
+            .lock()
+            .expect("Could not lock thread registry!")
+            .insert(tid)
+    }
+
+    /// Records that the thread with the given TID trapped or could not be started.
+    pub(crate) fn record_failure(&self, tid: u32) {
+        self.failed
+            .lock()
+            .expect("Could not lock thread registry!")
+            .insert(tid);
+    }
+
+    /// Keeps the error of the thread with the given TID, which trapped.
+    pub(crate) fn record_error(&self, tid: u32, error: Error) {
+        self.errors
+            .lock()
+            .expect("Could not lock thread registry!")
+            .push((tid, error));
+    }
+
+    /// Returns the errors of all threads that trapped since the last call, ordered by their TID.
+    pub(crate) fn take_errors(&self) -> Vec<(u32, Error)> {
+        let mut errors =
+            std::mem::take(&mut *self.errors.lock().expect("Could not lock thread registry!"));
+        errors.sort_by_key(|(tid, _)| *tid);
+        errors
+    }
+
+    /// Returns the state of the thread with the given TID.
+    ///
+    /// Returns `None` if no thread has been registered under this TID.
+    pub(crate) fn thread_state(&self, tid: u32) -> Option<ThreadState> {
+        if self
+            .failed
+            .lock()
+            .expect("Could not lock thread registry!")
+            .contains(&tid)
+        {
+            return Some(ThreadState::Failed);
+        }
+
+        let handles = self
+            .handles
+            .lock()
+            .expect("Could not lock thread registry!");
+        match handles.get(&tid) {
+            Some(handle) if handle.is_finished() => Some(ThreadState::Finished),
+            Some(_) => Some(ThreadState::Running),
+            None => self
+                .joined
+                .lock()
+                .expect("Could not lock thread registry!")
+                .contains(&tid)
+                .then_some(ThreadState::Joined),
+        }
+    }
+
+    /// Returns the IDs of all spawned threads that have not been joined yet.
+    pub(crate) fn thread_ids(&self) -> Vec<u32> {
+        let mut tids: Vec<u32> = self
+            .handles
+            .lock()
+            .expect("Could not lock thread registry!")
+            .keys()
+            .copied()
+            .collect();
+        tids.sort_unstable();
+        tids
+    }
+
+    /// Returns the IDs of all spawned threads that have not terminated yet.
+    pub(crate) fn running_threads(&self) -> Vec<u32> {
+        let mut running: Vec<u32> = self
+            .handles
+            .lock()
+            .expect("Could not lock thread registry!")
+            .iter()
+            .filter(|(_, handle)| !handle.is_finished())
+            .map(|(tid, _)| *tid)
+            .collect();
+        running.sort_unstable();
+        running
+    }
+
+    /// Joins all registered threads like [`ThreadRegistry::join_all`], but gives up
+    /// at the `deadline`.
+    ///
+    /// Returns the IDs of the threads that panicked or, if the deadline has
+    /// passed, the IDs of the threads that are still running.
+    pub(crate) fn join_all_until(&self, deadline: Instant) -> Result<Vec<u32>, Vec<u32>> {
+        const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+        let mut panicked = self.take_panicked();
+        loop {
+            let finished: Vec<(u32, JoinHandle<()>)> = {
+                let mut handles = self
+                    .handles
+                    .lock()
+                    .expect("Could not lock thread registry!");
+                if handles.is_empty() {
+                    return Ok(panicked);
+                }
+
+                let tids: Vec<u32> = handles
+                    .iter()
+                    .filter(|(_, handle)| handle.is_finished())
+                    .map(|(tid, _)| *tid)
+                    .collect();
+                tids.into_iter()
+                    .filter_map(|tid| handles.remove_entry(&tid))
+                    .collect()
+            };
+
+            for (tid, handle) in finished {
+                if handle.join().is_err() {
+                    panicked.push(tid);
+                }
+                self.joined
+                    .lock()
+                    .expect("Could not lock thread registry!")
+                    .insert(tid);
+            }
+
+            let running = self.running_threads();
+            if !running.is_empty() && Instant::now() >= deadline {
+                // Keep the panicked threads for the next attempt to join
+                self.panicked
+                    .lock()
+                    .expect("Could not lock thread registry!")
+                    .extend(panicked);
+                return Err(running);
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Returns the threads that panicked and have already been joined by
+    /// [`ThreadRegistry::register`].
+    fn take_panicked(&self) -> Vec<u32> {
+        std::mem::take(
+            &mut *self
+                .panicked
+                .lock()
+                .expect("Could not lock thread registry!"),
+        )
+    }
+
+    /// Joins all registered threads, including the ones that are spawned
+    /// while joining.
+    ///
+    /// Returns the IDs of the threads that panicked.
+    pub(crate) fn join_all(&self) -> Vec<u32> {
+        let mut panicked = self.take_panicked();
+        loop {
+            let handles = std::mem::take(
+                &mut *self
+                    .handles
+                    .lock()
+                    .expect("Could not lock thread registry!"),
+            );
+            if handles.is_empty() {
+                return panicked;
+            }
+
+            for (tid, handle) in handles {
+                if handle.join().is_err() {
+                    panicked.push(tid);
+                }
+                self.joined
+                    .lock()
+                    .expect("Could not lock thread registry!")
+                    .insert(tid);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+        },
+        time::{Duration, Instant},
+    };
+
+    use super::{ThreadRegistry, ThreadState};
+
+    #[test]
+    fn join_detached_threads() {
+        let registry = ThreadRegistry::new();
+        let finished = Arc::new(AtomicBool::new(false));
+
+        let inner_registry = registry.clone();
+        let inner_finished = finished.clone();
+        registry.register(
+            1,
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                inner_registry.register(
+                    2,
+                    std::thread::spawn(move || {
+                        std::thread::sleep(Duration::from_millis(50));
+                        inner_finished.store(true, Ordering::SeqCst);
+                    }),
+                );
+            }),
+        );
+
+        assert_eq!(registry.running_threads(), vec![1]);
+        assert_eq!(registry.thread_state(1), Some(ThreadState::Running));
+        assert_eq!(registry.thread_state(3), None);
+        assert!(registry.join_all().is_empty());
+        assert_eq!(registry.thread_state(1), Some(ThreadState::Joined));
+        assert_eq!(registry.thread_state(2), Some(ThreadState::Joined));
+        assert!(registry.thread_ids().is_empty());
+        assert!(finished.load(Ordering::SeqCst));
+        assert!(registry.running_threads().is_empty());
+    }
+
+    #[test]
+    fn join_until_deadline() {
+        let registry = ThreadRegistry::new();
+        let release = Arc::new(AtomicBool::new(false));
+
+        registry.register(1, std::thread::spawn(|| {}));
+        let inner_release = release.clone();
+        registry.register(
+            2,
+            std::thread::spawn(move || {
+                while !inner_release.load(Ordering::SeqCst) {
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+            }),
+        );
+
+        let deadline = Instant::now() + Duration::from_millis(20);
+        assert_eq!(registry.join_all_until(deadline), Err(vec![2]));
+        assert_eq!(registry.thread_state(1), Some(ThreadState::Joined));
+        assert_eq!(registry.thread_state(2), Some(ThreadState::Running));
+
+        release.store(true, Ordering::SeqCst);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        assert_eq!(registry.join_all_until(deadline), Ok(vec![]));
+        assert_eq!(registry.thread_state(2), Some(ThreadState::Joined));
+    }
+
+    #[test]
+    fn report_panics_of_reused_tids() {
+        let registry = ThreadRegistry::new();
+        let panicking = std::thread::spawn(|| panic!("Spawned thread panicked"));
+        while !panicking.is_finished() {
+            std::thread::yield_now();
+        }
+
+        registry.register(1, panicking);
+        registry.register(1, std::thread::spawn(|| {}));
+        assert_ne!(registry.thread_state(1), Some(ThreadState::Failed));
+        assert_eq!(registry.join_all(), vec![1]);
+        assert!(registry.join_all().is_empty());
+    }
+
+    #[test]
+    fn bound_running_threads() {
+        let registry = ThreadRegistry::with_limit(4);
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        for tid in 0..32 {
+            let slot = registry.acquire_slot(|| false).unwrap();
+            let running = running.clone();
+            let peak = peak.clone();
+            registry.register(
+                tid,
+                std::thread::spawn(move || {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(5));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    drop(slot);
+                }),
+            );
+        }
+
+        assert!(registry.join_all().is_empty());
+        assert!(peak.load(Ordering::SeqCst) <= 4);
+
+        // Waiting for a slot can be cancelled
+        let slots: Vec<_> = (0..4)
+            .map(|_| registry.acquire_slot(|| false).unwrap())
+            .collect();
+        assert!(registry.acquire_slot(|| true).is_none());
+        drop(slots);
+        assert!(registry.acquire_slot(|| true).is_some());
+    }
+}
@@ -1,33 +1,163 @@
 use std::{path::Path, sync::Arc};
 
-use anyhow::Error;
-use wasmgrind_core::tracing::{Tid, Tracing, metadata::WasmgrindTraceMetadata};
-use wasmtime::{Caller, Linker};
+use anyhow::{Error, bail};
+use trace_tools::{Codec, RapidBinEncoder, analysis::DeadlockReport, generic::Encoder};
+use wasmgrind_core::{
+    instrumentation::HookCategories,
+    tracing::{
+        MonotonicClock, RecordingStats, ReplayScheduler, Tid, TraceFilter, Tracing,
+        metadata::WasmgrindTraceMetadata,
+    },
+};
+use wasmtime::{Caller, Extern, Linker};
 
 use crate::tracing::TracingView;
 
 pub struct WasmgrindTracingCtx {
     tracing: Arc<Tracing>,
+    memory_name: Arc<str>,
 }
 
 impl Clone for WasmgrindTracingCtx {
     fn clone(&self) -> Self {
         Self {
             tracing: self.tracing.clone(),
+            memory_name: self.memory_name.clone(),
+        }
+    }
+}
+
+/// Wraps a [`Tracing`] configured by its builder methods, e.g., to combine options
+/// that have no dedicated constructor.
+impl From<Tracing> for WasmgrindTracingCtx {
+    fn from(tracing: Tracing) -> Self {
+        Self {
+            tracing: Arc::new(tracing),
+            memory_name: Arc::from(Self::DEFAULT_MEMORY_NAME),
         }
     }
 }
 
 impl WasmgrindTracingCtx {
     const MODULE_NAME: &str = "wasmgrind_tracing";
+    const DEFAULT_MEMORY_NAME: &str = "memory";
 
     pub fn new<P: AsRef<Path>>(tracing_cache_dir: P) -> Self {
-        Self {
-            tracing: Arc::new(Tracing::new(tracing_cache_dir)),
-        }
+        Self::from(Tracing::new(tracing_cache_dir))
+    }
+
+    /// Creates a new context that additionally detects deadlocks while tracing.
+    ///
+    /// See [`Tracing::with_deadlock_detection`].
+    pub fn with_deadlock_detection<P: AsRef<Path>>(tracing_cache_dir: P) -> Self {
+        Self::from(Tracing::new(tracing_cache_dir).with_deadlock_detection())
+    }
+
+    /// Creates a new context that only records events matching `filter`.
+    ///
+    /// See [`Tracing::with_filter`].
+    pub fn with_trace_filter<P: AsRef<Path>>(tracing_cache_dir: P, filter: TraceFilter) -> Self {
+        Self::from(Tracing::new(tracing_cache_dir).with_filter(filter))
+    }
+
+    /// Creates a new context whose generated trace only keeps the most recent `capacity` events.
+    ///
+    /// See [`Tracing::with_output_window`].
+    pub fn with_output_window<P: AsRef<Path>>(tracing_cache_dir: P, capacity: u64) -> Self {
+        Self::from(Tracing::new(tracing_cache_dir).with_output_window(capacity))
+    }
+
+    /// Creates a new context that attaches a timestamp to every recorded event.
+    ///
+    /// The timestamps count the nanoseconds since the creation of the context.
+    /// See [`Tracing::with_timestamps`].
+    pub fn with_timestamps<P: AsRef<Path>>(tracing_cache_dir: P) -> Self {
+        Self::from(Tracing::new(tracing_cache_dir).with_timestamps(MonotonicClock::new()))
+    }
+
+    /// Creates a new context whose trace is written to `outfile` as events arrive.
+    ///
+    /// The trace has to be completed by [`WasmgrindTracingCtx::finalize`].
+    /// See [`Tracing::to_file`].
+    pub fn to_file<P: AsRef<Path>>(outfile: P) -> Result<Self, Error> {
+        Ok(Self::from(Tracing::to_file(outfile)?))
+    }
+
+    /// Creates a new context that replays the synchronization order recorded by `scheduler`.
+    ///
+    /// See [`Tracing::with_replay`].
+    pub fn with_replay<P: AsRef<Path>>(tracing_cache_dir: P, scheduler: ReplayScheduler) -> Self {
+        Self::from(Tracing::new(tracing_cache_dir).with_replay(scheduler))
+    }
+
+    /// Reads strings passed by the guest, e.g., thread names, from the memory exported
+    /// as `name` instead of `memory`.
+    ///
+    /// Modules with multiple memories have to pass the name of the memory that has been
+    /// designated via [`StandaloneCtxProvider::from_walrus_with_memory`].
+    ///
+    /// [`StandaloneCtxProvider::from_walrus_with_memory`]: crate::standalone::ctx::StandaloneCtxProvider::from_walrus_with_memory
+    pub fn with_memory_name(mut self, name: &str) -> Self {
+        self.memory_name = Arc::from(name);
+        self
+    }
+
+    /// Returns the number of recorded synchronization events that have not been replayed yet.
+    pub fn replay_remaining(&self) -> Option<usize> {
+        self.tracing.replay_remaining()
+    }
+
+    /// Returns the number of events recorded so far.
+    ///
+    /// See [`Tracing::event_count`].
+    pub fn event_count(&self) -> u64 {
+        self.tracing.event_count()
+    }
+
+    /// Returns whether no event has been recorded so far.
+    pub fn is_empty(&self) -> bool {
+        self.tracing.is_empty()
+    }
+
+    /// Returns the number of events of each kind recorded so far.
+    ///
+    /// See [`Tracing::stats`].
+    pub fn stats(&self) -> RecordingStats {
+        self.tracing.stats()
+    }
+
+    /// Returns all deadlocks that have been detected so far.
+    pub fn deadlock_report(&self) -> Vec<DeadlockReport<(u32, u32)>> {
+        self.tracing.deadlock_report()
+    }
+
+    /// Registers the current host thread as an additional runner of the traced module.
+    ///
+    /// See [`Tracing::runner_begin`].
+    pub fn runner_begin(&self) -> Option<Tid> {
+        self.tracing.runner_begin()
+    }
+
+    /// Unregisters a runner thread registered by [`WasmgrindTracingCtx::runner_begin`].
+    ///
+    /// See [`Tracing::runner_end`].
+    pub fn runner_end(&self, tid: Tid) {
+        self.tracing.runner_end(tid)
     }
 
     pub fn add_to_linker<T: TracingView + 'static>(linker: &mut Linker<T>) -> Result<(), Error> {
+        Self::add_to_linker_with_hooks(linker, HookCategories::ALL)
+    }
+
+    /// Registers the tracing hooks like [`WasmgrindTracingCtx::add_to_linker`], but only
+    /// provides the memory access and function call hooks of the selected categories.
+    ///
+    /// The categories that remain in an instrumented module can be obtained from its
+    /// custom section via [`HookCategories::from_module`].
+    pub fn add_to_linker_with_hooks<T: TracingView + 'static>(
+        linker: &mut Linker<T>,
+        hooks: HookCategories,
+    ) -> Result<(), Error> {
         linker
             .func_wrap(Self::MODULE_NAME, "initialize", |caller: Caller<'_, T>| {
                 caller.data().ctx().tracing.initialize();
@@ -136,6 +266,34 @@ impl WasmgrindTracingCtx {
                         .mutex_unlock(lock_id, (fidx, iidx));
                 },
             )?
+            .func_wrap(
+                Self::MODULE_NAME,
+                "condvar_wait",
+                |caller: Caller<'_, T>, cond_id: u32, fidx: u32, iidx: u32| {
+                    caller.data().ctx().tracing.cond_wait(cond_id, (fidx, iidx));
+                },
+            )?
+            .func_wrap(
+                Self::MODULE_NAME,
+                "condvar_notify",
+                |caller: Caller<'_, T>, cond_id: u32, fidx: u32, iidx: u32| {
+                    caller
+                        .data()
+                        .ctx()
+                        .tracing
+                        .cond_notify(cond_id, (fidx, iidx));
+                },
+            )?
+            .func_wrap(
+                Self::MODULE_NAME,
+                "thread_set_name",
+                |mut caller: Caller<'_, T>, ptr: u32, len: u32| -> Result<(), Error> {
+                    let memory_name = caller.data().ctx().memory_name.clone();
+                    let name = Self::read_guest_string(&mut caller, &memory_name, ptr, len)?;
+                    caller.data().ctx().tracing.thread_set_name(name);
+                    Ok(())
+                },
+            )?
             .func_wrap(
                 Self::MODULE_NAME,
                 "mutex_repair",
@@ -149,8 +307,10 @@ impl WasmgrindTracingCtx {
                 |caller: Caller<'_, T>, lock_id: u32| {
                     caller.data().ctx().tracing.mutex_invalid_access(lock_id);
                 },
-            )?
-            .func_wrap(
+            )?;
+
+        if hooks.reads {
+            linker.func_wrap(
                 Self::MODULE_NAME,
                 "read_hook",
                 |caller: Caller<'_, T>,
@@ -166,8 +326,11 @@ impl WasmgrindTracingCtx {
                         (fidx, iidx),
                     );
                 },
-            )?
-            .func_wrap(
+            )?;
+        }
+
+        if hooks.writes {
+            linker.func_wrap(
                 Self::MODULE_NAME,
                 "write_hook",
                 |caller: Caller<'_, T>,
@@ -184,19 +347,274 @@ impl WasmgrindTracingCtx {
                     );
                 },
             )?;
+        }
+
+        if hooks.calls {
+            linker
+                .func_wrap(
+                    Self::MODULE_NAME,
+                    "call_hook",
+                    |caller: Caller<'_, T>, callee: u32, fidx: u32, iidx: u32| {
+                        caller
+                            .data()
+                            .ctx()
+                            .tracing
+                            .function_call(callee, (fidx, iidx));
+                    },
+                )?
+                .func_wrap(
+                    Self::MODULE_NAME,
+                    "return_hook",
+                    |caller: Caller<'_, T>, callee: u32, fidx: u32, iidx: u32| {
+                        caller
+                            .data()
+                            .ctx()
+                            .tracing
+                            .function_return(callee, (fidx, iidx));
+                    },
+                )?;
+        }
 
         Ok(())
     }
 
+    fn read_guest_string<T>(
+        caller: &mut Caller<'_, T>,
+        memory_name: &str,
+        ptr: u32,
+        len: u32,
+    ) -> Result<String, Error> {
+        let range = ptr as usize..ptr as usize + len as usize;
+        let bytes = match caller.get_export(memory_name) {
+            Some(Extern::Memory(memory)) => match memory.data(&caller).get(range) {
+                Some(bytes) => bytes.to_vec(),
+                None => bail!("String at {ptr:#x} with length {len} is out of bounds"),
+            },
+            Some(Extern::SharedMemory(memory)) => match memory.data().get(range) {
+                Some(cells) => cells
+                    .iter()
+                    // SAFETY: The cells belong to a live shared memory, which wasmtime
+                    // allows to access through raw pointers. Other threads may write the
+                    // string concurrently, but reading a `u8` can not tear and a garbled
+                    // string is rejected as invalid UTF-8 or merely yields a wrong name.
+                    .map(|cell| unsafe { *cell.get() })
+                    .collect(),
+                None => bail!("String at {ptr:#x} with length {len} is out of bounds"),
+            },
+            _ => bail!("Module does not export a linear memory named '{memory_name}'"),
+        };
+
+        Ok(String::from_utf8(bytes)?)
+    }
+
+    pub fn generate_trace<E: Encoder, P: AsRef<Path>>(
+        self,
+        encoder: &mut E,
+        outfile: P,
+    ) -> Result<Result<WasmgrindTraceMetadata, Error>, WasmgrindTracingCtx> {
+        match Arc::try_unwrap(self.tracing) {
+            Ok(tracing) => Ok(tracing.generate_trace(encoder, outfile)),
+            Err(arc_tracing) => Err(Self {
+                tracing: arc_tracing,
+                memory_name: self.memory_name,
+            }),
+        }
+    }
+
     pub fn generate_binary_trace<P: AsRef<Path>>(
         self,
         outfile: P,
+    ) -> Result<Result<WasmgrindTraceMetadata, Error>, WasmgrindTracingCtx> {
+        self.generate_trace(&mut RapidBinEncoder::new(), outfile)
+    }
+
+    /// Completes the trace file of a context created by [`WasmgrindTracingCtx::to_file`].
+    ///
+    /// Returns the context unchanged if any other reference to the trace still exists.
+    pub fn finalize(self) -> Result<Result<WasmgrindTraceMetadata, Error>, WasmgrindTracingCtx> {
+        match Arc::try_unwrap(self.tracing) {
+            Ok(tracing) => Ok(tracing.finalize()),
+            Err(arc_tracing) => Err(Self {
+                tracing: arc_tracing,
+                memory_name: self.memory_name,
+            }),
+        }
+    }
+
+    /// Generates a binary trace that is compressed with `codec`.
+    ///
+    /// See [`wasmgrind_core::tracing::Tracing::generate_binary_trace_compressed`].
+    pub fn generate_binary_trace_compressed<P: AsRef<Path>>(
+        self,
+        outfile: P,
+        codec: Codec,
     ) -> Result<Result<WasmgrindTraceMetadata, Error>, WasmgrindTracingCtx> {
         match Arc::try_unwrap(self.tracing) {
-            Ok(tracing) => Ok(tracing.generate_binary_trace(outfile)),
+            Ok(tracing) => Ok(tracing.generate_binary_trace_compressed(outfile, codec)),
             Err(arc_tracing) => Err(Self {
                 tracing: arc_tracing,
+                memory_name: self.memory_name,
             }),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use tempfile::tempdir;
+    use walrus::{
+        ConstExpr, DataKind, FunctionBuilder, ValType,
+        ir::{LoadKind, MemArg, StoreKind, Value},
+    };
+    use wasmgrind_core::instrumentation::{self, HookCategories};
+    use wasmtime::{Engine, Linker, Module, Store};
+
+    use super::WasmgrindTracingCtx;
+
+    /// Creates a module whose `run` export writes and reads memory while holding a lock.
+    fn locking_module() -> walrus::Module {
+        let mut module = walrus::Module::default();
+        let memory = module.memories.add_local(false, false, 1, None, None);
+        let lock_ty = module.types.add(&[ValType::I32], &[]);
+        let lock_hooks = ["mutex_start_lock", "mutex_finish_lock", "mutex_unlock"]
+            .map(|name| module.add_import_func("wasmgrind_tracing", name, lock_ty).0);
+
+        let mut builder = FunctionBuilder::new(&mut module.types, &[], &[]);
+        let memarg = MemArg {
+            align: 4,
+            offset: 0,
+        };
+        builder
+            .func_body()
+            .i32_const(0)
+            .call(lock_hooks[0])
+            .i32_const(0)
+            .call(lock_hooks[1])
+            .i32_const(16)
+            .i32_const(42)
+            .store(memory, StoreKind::I32 { atomic: false }, memarg)
+            .i32_const(16)
+            .load(memory, LoadKind::I32 { atomic: false }, memarg)
+            .drop()
+            .i32_const(0)
+            .call(lock_hooks[2]);
+        let run = builder.finish(vec![], &mut module.funcs);
+        module.exports.add("run", run);
+
+        module
+    }
+
+    #[test]
+    fn trace_stripped_module() -> Result<(), Error> {
+        let cachedir = tempdir()?;
+
+        // Instruction locations are only available for parsed modules
+        let mut module = walrus::Module::from_buffer(&locking_module().emit_wasm())?;
+        instrumentation::instrument(&mut module)?;
+        let stripped = instrumentation::strip_hooks(&module.emit_wasm(), HookCategories::MEMORY)?;
+
+        let hooks = HookCategories::from_module(&walrus::Module::from_buffer(&stripped)?)
+            .expect("Instrumented modules list their hooks");
+        assert_eq!(
+            hooks,
+            HookCategories {
+                locks: true,
+                ..HookCategories::default()
+            }
+        );
+
+        let engine = Engine::default();
+        let mut linker = Linker::new(&engine);
+        WasmgrindTracingCtx::add_to_linker_with_hooks(&mut linker, hooks)?;
+        let ctx = WasmgrindTracingCtx::new(cachedir.path());
+        let mut store = Store::new(&engine, ctx.clone());
+        assert!(
+            linker
+                .get(&mut store, "wasmgrind_tracing", "read_hook")
+                .is_none()
+        );
+        assert!(
+            linker
+                .get(&mut store, "wasmgrind_tracing", "write_hook")
+                .is_none()
+        );
+
+        let instance = linker.instantiate(&mut store, &Module::new(&engine, &stripped)?)?;
+        instance
+            .get_typed_func::<(), ()>(&mut store, "run")?
+            .call(&mut store, ())?;
+
+        let stats = ctx.stats();
+        assert_eq!(stats.n_reads, 0);
+        assert_eq!(stats.n_writes, 0);
+        assert_eq!(stats.n_requests, 1);
+        assert_eq!(stats.n_aquires, 1);
+        assert_eq!(stats.n_releases, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn name_threads_from_designated_memory() -> Result<(), Error> {
+        let cachedir = tempdir()?;
+
+        let mut module = walrus::Module::default();
+        let memory = module.memories.add_local(false, false, 1, None, None);
+        module.exports.add("heap", memory);
+        let name = b"worker";
+        module.data.add(
+            DataKind::Active {
+                memory,
+                offset: ConstExpr::Value(Value::I32(64)),
+            },
+            name.to_vec(),
+        );
+        let set_name_ty = module.types.add(&[ValType::I32, ValType::I32], &[]);
+        let (set_name, _) =
+            module.add_import_func("wasmgrind_tracing", "thread_set_name", set_name_ty);
+
+        let mut builder = FunctionBuilder::new(&mut module.types, &[], &[]);
+        builder
+            .func_body()
+            .i32_const(64)
+            .i32_const(name.len() as i32)
+            .call(set_name)
+            .i32_const(16)
+            .i32_const(42)
+            .store(
+                memory,
+                StoreKind::I32 { atomic: false },
+                MemArg {
+                    align: 4,
+                    offset: 0,
+                },
+            );
+        let run = builder.finish(vec![], &mut module.funcs);
+        module.exports.add("run", run);
+
+        // Instruction locations are only available for parsed modules
+        let mut module = walrus::Module::from_buffer(&module.emit_wasm())?;
+        let wasm = instrumentation::instrument(&mut module)?.emit_wasm();
+
+        let engine = Engine::default();
+        let mut linker = Linker::new(&engine);
+        WasmgrindTracingCtx::add_to_linker(&mut linker)?;
+
+        let ctx = WasmgrindTracingCtx::new(cachedir.path()).with_memory_name("heap");
+        let mut store = Store::new(&engine, ctx.clone());
+        let instance = linker.instantiate(&mut store, &Module::new(&engine, &wasm)?)?;
+        instance
+            .get_typed_func::<(), ()>(&mut store, "run")?
+            .call(&mut store, ())?;
+        drop(store);
+
+        let metadata = match ctx.generate_binary_trace(cachedir.path().join("trace.data")) {
+            Ok(metadata) => metadata?,
+            Err(_) => panic!("The store has been dropped"),
+        };
+        assert_eq!(metadata.thread_name(0), Some("worker"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use std::{
+    path::Path,
+    process::{Command, Output},
+};
+
+use anyhow::Error;
+use tempfile::tempdir;
+use walrus::{FunctionBuilder, InstrSeqBuilder, ValType};
+use wasmgrind_core::testing::{AbiModule, abi_module};
+
+/// Creates a module whose `run` export spawns a thread that executes `thread_body`.
+fn spawning_binary(
+    path: &Path,
+    thread_body: impl FnOnce(&mut InstrSeqBuilder),
+) -> Result<(), Error> {
+    let AbiModule {
+        mut module,
+        thread_start,
+        ..
+    } = abi_module();
+
+    thread_body(
+        &mut module
+            .funcs
+            .get_mut(thread_start)
+            .kind
+            .unwrap_local_mut()
+            .builder_mut()
+            .func_body(),
+    );
+
+    let clone_instance_ty = module.types.add(&[ValType::I32; 5], &[ValType::I32]);
+    let (clone_instance, _) =
+        module.add_import_func("wasmgrind_standalone", "clone_instance", clone_instance_ty);
+    let mut run = FunctionBuilder::new(&mut module.types, &[], &[]);
+    run.func_body()
+        .i32_const(0)
+        .i32_const(0)
+        .i32_const(2048)
+        .i32_const(0)
+        .i32_const(0)
+        .call(clone_instance)
+        .drop();
+    let run = run.finish(vec![], &mut module.funcs);
+    module.exports.add("run", run);
+
+    module.emit_wasm_file(path)?;
+    Ok(())
+}
+
+/// Runs the `run` export of `binary` with the standalone interface.
+fn run(binary: &Path, options: &[&str]) -> Result<Output, Error> {
+    let output = Command::new(env!("CARGO_BIN_EXE_wasmgrind"))
+        .env("RUST_BACKTRACE", "0")
+        .arg("run")
+        .arg(binary)
+        .arg("standalone")
+        .args(options)
+        .arg("run")
+        .output()?;
+
+    Ok(output)
+}
+
+#[test]
+fn fail_on_trapped_threads() -> Result<(), Error> {
+    let tmp = tempdir()?;
+    let binary = tmp.path().join("trap.wasm");
+    spawning_binary(&binary, |body| {
+        body.unreachable();
+    })?;
+
+    let output = run(&binary, &[])?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Spawned thread 1 trapped"), "{stderr}");
+
+    Ok(())
+}
+
+#[test]
+fn interrupt_threads_after_timeout() -> Result<(), Error> {
+    let tmp = tempdir()?;
+    let binary = tmp.path().join("spin.wasm");
+    spawning_binary(&binary, |body| {
+        body.loop_(None, |body| {
+            let id = body.id();
+            body.br(id);
+        });
+    })?;
+
+    let output = run(&binary, &["--timeout", "1"])?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Spawned threads did not terminate within 1s"),
+        "{stderr}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn emit_patched_binary() -> Result<(), Error> {
+    let tmp = tempdir()?;
+    let binary = tmp.path().join("spawn.wasm");
+    spawning_binary(&binary, |_| {})?;
+
+    let emit_dir = tmp.path().join("emit");
+    let emit_dir = emit_dir.to_str().expect("Temporary path is not UTF-8");
+    let output = run(&binary, &["--emit-dir", emit_dir, "--emit-patched"])?;
+    assert!(output.status.success());
+    let patched = walrus::Module::from_file(Path::new(emit_dir).join("patched.wasm"))?;
+    assert!(
+        patched
+            .exports
+            .get_func("__wasmgrind_instance_entry")
+            .is_ok()
+    );
+    assert!(Path::new(emit_dir).join("patched.wat").exists());
+
+    let emit_dir = tmp.path().join("emit-wasm");
+    let emit_dir = emit_dir.to_str().expect("Temporary path is not UTF-8");
+    let output = run(
+        &binary,
+        &["--emit-dir", emit_dir, "--no-wat", "--emit-patched"],
+    )?;
+    assert!(output.status.success());
+    assert!(Path::new(emit_dir).join("patched.wasm").exists());
+    assert!(!Path::new(emit_dir).join("patched.wat").exists());
+
+    // Failing to emit a binary is an error
+    let output = run(
+        &binary,
+        &["--emit-dir", binary.to_str().unwrap(), "--emit-patched"],
+    )?;
+    assert!(!output.status.success());
+
+    Ok(())
+}
@@ -0,0 +1,330 @@
+use std::{collections::HashMap, path::Path, process::Command};
+
+use anyhow::{Error, ensure};
+use tempfile::tempdir;
+use walrus::{
+    FunctionBuilder, Module, ValType,
+    ir::{BinaryOp, LoadKind, MemArg, StoreKind},
+};
+use wasmgrind_core::testing::{AbiModule, abi_module, attach_line_info};
+
+/// Creates a module whose `run` export writes and reads a word of memory.
+fn example_binary(path: &Path) -> Result<(), Error> {
+    let AbiModule {
+        mut module, memory, ..
+    } = abi_module();
+
+    let memarg = MemArg {
+        align: 4,
+        offset: 0,
+    };
+    let mut run = FunctionBuilder::new(&mut module.types, &[], &[]);
+    run.func_body()
+        .i32_const(2048)
+        .i32_const(42)
+        .store(memory, StoreKind::I32 { atomic: false }, memarg)
+        .i32_const(2048)
+        .load(memory, LoadKind::I32 { atomic: false }, memarg)
+        .drop();
+    let run = run.finish(vec![], &mut module.funcs);
+    module.exports.add("run", run);
+
+    module.emit_wasm_file(path)?;
+    Ok(())
+}
+
+/// Creates a module whose `run` export leaves the `leave_early` function in three ways.
+///
+/// `leave_early(0)` falls off the end of its body, `leave_early(1)` returns early and
+/// `leave_early(2)` branches to the label of the function body.
+fn leave_early_binary(path: &Path) -> Result<(), Error> {
+    let AbiModule { mut module, .. } = abi_module();
+
+    let mut leave_early = FunctionBuilder::new(&mut module.types, &[ValType::I32], &[]);
+    let arg = module.locals.add(ValType::I32);
+    let mut body = leave_early.func_body();
+    let body_id = body.id();
+    body.local_get(arg)
+        .i32_const(1)
+        .binop(BinaryOp::I32Eq)
+        .if_else(
+            None,
+            |then| {
+                then.return_();
+            },
+            |_| {},
+        )
+        .local_get(arg)
+        .br_if(body_id);
+    let leave_early = leave_early.finish(vec![arg], &mut module.funcs);
+    module.exports.add("leave_early", leave_early);
+
+    let mut run = FunctionBuilder::new(&mut module.types, &[], &[]);
+    for arg in 0..3 {
+        run.func_body().i32_const(arg).call(leave_early);
+    }
+    let run = run.finish(vec![], &mut module.funcs);
+    module.exports.add("run", run);
+
+    module.emit_wasm_file(path)?;
+    Ok(())
+}
+
+/// Creates a module whose `run` export calls `keep` and `skip_me`, which both write a
+/// word of memory. `skip_me` additionally locks and unlocks a mutex.
+fn selective_binary(path: &Path) -> Result<(), Error> {
+    let AbiModule {
+        mut module, memory, ..
+    } = abi_module();
+
+    let memarg = MemArg {
+        align: 4,
+        offset: 0,
+    };
+    let lock_ty = module.types.add(&[ValType::I32], &[]);
+    let [start_lock, finish_lock, unlock] =
+        ["mutex_start_lock", "mutex_finish_lock", "mutex_unlock"]
+            .map(|name| module.add_import_func("wasmgrind_tracing", name, lock_ty).0);
+
+    let mut keep = FunctionBuilder::new(&mut module.types, &[], &[]);
+    keep.name("keep".to_string())
+        .func_body()
+        .i32_const(2048)
+        .i32_const(42)
+        .store(memory, StoreKind::I32 { atomic: false }, memarg)
+        .i32_const(2048)
+        .load(memory, LoadKind::I32 { atomic: false }, memarg)
+        .drop();
+    let keep = keep.finish(vec![], &mut module.funcs);
+
+    let mut skip_me = FunctionBuilder::new(&mut module.types, &[], &[]);
+    skip_me
+        .name("skip_me".to_string())
+        .func_body()
+        .i32_const(16)
+        .call(start_lock)
+        .i32_const(16)
+        .call(finish_lock)
+        .i32_const(2048)
+        .i32_const(7)
+        .store(memory, StoreKind::I32 { atomic: false }, memarg)
+        .i32_const(16)
+        .call(unlock);
+    let skip_me = skip_me.finish(vec![], &mut module.funcs);
+
+    let mut run = FunctionBuilder::new(&mut module.types, &[], &[]);
+    run.func_body().call(keep).call(skip_me);
+    let run = run.finish(vec![], &mut module.funcs);
+    module.exports.add("run", run);
+
+    module.emit_wasm_file(path)?;
+    Ok(())
+}
+
+/// Traces the `run` export of `binary` and writes the trace in `format` to `output`.
+fn trace(binary: &Path, format: &str, output: &Path, options: &[&str]) -> Result<(), Error> {
+    let dir = output.parent().expect("Output has a parent directory");
+    let status = Command::new(env!("CARGO_BIN_EXE_wasmgrind"))
+        .arg("--emit-dir")
+        .arg(dir.join("emit"))
+        .arg("trace")
+        .arg(binary)
+        .arg("--cachedir")
+        .arg(dir.join("cache"))
+        .arg("--format")
+        .arg(format)
+        .arg("--outdir")
+        .arg(dir)
+        .arg("--output")
+        .arg(output.file_name().expect("Output has a file name"))
+        .args(options)
+        .args(["standalone", "run"])
+        .status()?;
+    ensure!(status.success(), "Tracing failed with {status}");
+
+    Ok(())
+}
+
+#[test]
+fn trace_in_all_formats() -> Result<(), Error> {
+    let tmp = tempdir()?;
+    let binary = tmp.path().join("example.wasm");
+    example_binary(&binary)?;
+
+    let output = tmp.path().join("trace");
+    trace(&binary, "rapidbin", &output, &[])?;
+    assert!(output.with_extension("data").exists());
+    assert!(output.with_extension("json").exists());
+
+    trace(&binary, "std", &output, &[])?;
+    let std_trace = std::fs::read_to_string(output.with_extension("std"))?;
+    assert_eq!(std_trace.lines().count(), 2);
+    assert!(!std_trace.contains('@'));
+    // The intermediate RapidBin trace is removed
+    assert!(!output.with_extension("std.data").exists());
+
+    trace(&binary, "json", &output, &[])?;
+    let json_trace = std::fs::read_to_string(output.with_extension("events.json"))?;
+    let events = json_trace
+        .lines()
+        .map(serde_json::from_str::<serde_json::Value>)
+        .collect::<Result<Vec<_>, _>>()?;
+    let ops = events
+        .iter()
+        .map(|event| event["op"].as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(ops, [Some("write"), Some("read")]);
+    // The metadata is written next to the trace
+    assert!(output.with_extension("json").exists());
+
+    Ok(())
+}
+
+#[test]
+fn trace_with_timestamps() -> Result<(), Error> {
+    let tmp = tempdir()?;
+    let binary = tmp.path().join("example.wasm");
+    example_binary(&binary)?;
+
+    let output = tmp.path().join("trace");
+    trace(&binary, "std", &output, &["--timestamps"])?;
+    let std_trace = std::fs::read_to_string(output.with_extension("std"))?;
+    let timestamps = std_trace
+        .lines()
+        .map(|line| line.rsplit_once('@').map(|(_, nanos)| nanos.parse::<u64>()))
+        .collect::<Option<Result<Vec<_>, _>>>()
+        .expect("Every event has a timestamp")?;
+    assert_eq!(timestamps.len(), 2);
+    assert!(timestamps[0] <= timestamps[1]);
+    assert!(!output.with_extension("std.data.timestamps").exists());
+
+    trace(&binary, "rapidbin", &output, &["--timestamps"])?;
+    assert!(output.with_extension("data.timestamps").exists());
+
+    Ok(())
+}
+
+#[test]
+fn trace_source_positions() -> Result<(), Error> {
+    let tmp = tempdir()?;
+    let binary = tmp.path().join("example.wasm");
+    example_binary(&binary)?;
+    let mut wasm = std::fs::read(&binary)?;
+    attach_line_info(&mut wasm)?;
+    std::fs::write(&binary, wasm)?;
+
+    let output = tmp.path().join("trace");
+    trace(&binary, "std", &output, &[])?;
+    let std_trace = std::fs::read_to_string(output.with_extension("std"))?;
+    let locations = std_trace
+        .lines()
+        .map(|line| line.rsplit('|').next())
+        .collect::<Vec<_>>();
+    assert_eq!(locations.len(), 2);
+    for location in locations {
+        let line = location
+            .and_then(|location| location.strip_prefix("src/lib.rs:"))
+            .expect("Every event is named by its source position");
+        line.parse::<u32>()?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn trace_function_calls() -> Result<(), Error> {
+    let tmp = tempdir()?;
+    let binary = tmp.path().join("calls.wasm");
+    leave_early_binary(&binary)?;
+
+    // The instrumentation identifies functions by their index in the original binary
+    let module = Module::from_file(&binary)?;
+    let fidx = |name: &str| -> Result<String, Error> {
+        let func = module.exports.get_func(name)?;
+        Ok(func.index().to_string())
+    };
+    let (run, leave_early) = (fidx("run")?, fidx("leave_early")?);
+
+    let output = tmp.path().join("trace");
+    trace(&binary, "std", &output, &["--trace-calls"])?;
+    let std_trace = std::fs::read_to_string(output.with_extension("std"))?;
+
+    // Every return leaves the function that has been entered last by the same thread
+    let mut stacks = HashMap::<&str, Vec<&str>>::new();
+    let mut calls = Vec::new();
+    for line in std_trace.lines() {
+        let mut fields = line.split('|');
+        let (Some(thread), Some(op)) = (fields.next(), fields.next()) else {
+            panic!("Malformed STD line '{line}'");
+        };
+        if let Some(callee) = op
+            .strip_prefix("call(F")
+            .and_then(|op| op.strip_suffix(')'))
+        {
+            stacks.entry(thread).or_default().push(callee);
+            calls.push(("call", callee));
+        } else if let Some(callee) = op.strip_prefix("ret(F").and_then(|op| op.strip_suffix(')')) {
+            let entered = stacks.entry(thread).or_default().pop();
+            assert_eq!(entered, Some(callee), "Unbalanced return in '{line}'");
+            calls.push(("ret", callee));
+        }
+    }
+    assert!(stacks.values().all(Vec::is_empty));
+
+    let (run, leave_early) = (run.as_str(), leave_early.as_str());
+    let calls = calls
+        .into_iter()
+        .filter(|(_, callee)| [run, leave_early].contains(callee))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        calls,
+        [
+            ("call", run),
+            // Falling off the end
+            ("call", leave_early),
+            ("ret", leave_early),
+            // Returning early
+            ("call", leave_early),
+            ("ret", leave_early),
+            // Branching to the function label
+            ("call", leave_early),
+            ("ret", leave_early),
+            ("ret", run),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn trace_only_selected_functions() -> Result<(), Error> {
+    let tmp = tempdir()?;
+    let binary = tmp.path().join("selective.wasm");
+    selective_binary(&binary)?;
+
+    let output = tmp.path().join("trace");
+    trace(&binary, "json", &output, &["--instrument-only", "keep"])?;
+    let json_trace = std::fs::read_to_string(output.with_extension("events.json"))?;
+    let events = json_trace
+        .lines()
+        .map(serde_json::from_str::<serde_json::Value>)
+        .collect::<Result<Vec<_>, _>>()?;
+    let ops = events
+        .iter()
+        .map(|event| event["op"].as_str())
+        .collect::<Vec<_>>();
+
+    // The write of `skip_me` is not recorded, but its lock operations are
+    assert_eq!(
+        ops,
+        [
+            Some("write"),
+            Some("read"),
+            Some("request"),
+            Some("acquire"),
+            Some("release"),
+        ]
+    );
+
+    Ok(())
+}